@@ -10,7 +10,8 @@
 struct CommsMessage {
   uint16_t from_address;
   uint16_t to_address;
-  uint16_t data_len;
+  // Same bitfield as the first 4 bytes of a CAN-FD frame, length plus fragment info
+  uint32_t metadata;
   uint8_t data[MAX_SERIALIZE_LENGTH];
 };
 
@@ -22,9 +23,22 @@ byte mac[] = {
 IPAddress ip(10, 0, 0, 5);
 unsigned int localPort = 8888;
 
-IPAddress mcctrlIP(10, 0, 0, 4);
+IPAddress broadcastIP(10, 0, 0, 255);
 unsigned int mcctrlPort = 25565;
 
+// Everywhere mission control has announced itself from. ctrl-view and ctrl-send listen on
+// different ports, so the port is kept along with the IP. Until anyone announces themselves,
+// packets are broadcast to mcctrlPort.
+#define MAX_MCCTRL_ENDPOINTS 4
+
+struct Endpoint {
+  IPAddress ip;
+  uint16_t port;
+};
+
+Endpoint mcctrlEndpoints[MAX_MCCTRL_ENDPOINTS];
+int mcctrlEndpointCount = 0;
+
 EthernetUDP udp;
 
 void onReceive(const CANFD_message_t &msg);
@@ -82,7 +96,7 @@ void setup() {
     panic();
   }
 
-  if (sizeof(CommsMessage) != MAX_SERIALIZE_LENGTH + 6) {
+  if (sizeof(CommsMessage) != MAX_SERIALIZE_LENGTH + 8) {
     Serial.print("Size is incorrect: ");
     Serial.println(sizeof(CommsMessage));
 
@@ -92,12 +106,44 @@ void setup() {
   Serial.println("Done!");
 }
 
-void sendPacket(CommsMessage &msg) {
-  udp.beginPacket(IPAddress(10, 0, 0, 255), mcctrlPort);
+void sendPacketTo(CommsMessage &msg, IPAddress ip, uint16_t port) {
+  udp.beginPacket(ip, port);
   udp.write(reinterpret_cast<const uint8_t*>(&msg), sizeof(CommsMessage));
   udp.endPacket(); 
 }
 
+void sendPacket(CommsMessage &msg) {
+  if (mcctrlEndpointCount == 0) {
+    sendPacketTo(msg, broadcastIP, mcctrlPort);
+    return;
+  }
+
+  for (int i = 0; i < mcctrlEndpointCount; i++) {
+    sendPacketTo(msg, mcctrlEndpoints[i].ip, mcctrlEndpoints[i].port);
+  }
+}
+
+void addMissionControlEndpoint(IPAddress ip, uint16_t port) {
+  for (int i = 0; i < mcctrlEndpointCount; i++) {
+    if (mcctrlEndpoints[i].ip == ip && mcctrlEndpoints[i].port == port) {
+      return;
+    }
+  }
+
+  // Forget the oldest to make room
+  if (mcctrlEndpointCount == MAX_MCCTRL_ENDPOINTS) {
+    for (int i = 1; i < MAX_MCCTRL_ENDPOINTS; i++) {
+      mcctrlEndpoints[i - 1] = mcctrlEndpoints[i];
+    }
+
+    mcctrlEndpointCount--;
+  }
+
+  mcctrlEndpoints[mcctrlEndpointCount].ip = ip;
+  mcctrlEndpoints[mcctrlEndpointCount].port = port;
+  mcctrlEndpointCount++;
+}
+
 #define FROM_ADDRESS_SHIFT 6
 #define ID_5_BIT_MASK 0x1F
 #define TRUE_LENGTH_MASK 0x3F
@@ -111,7 +157,8 @@ void onReceive(const CANFD_message_t &msg) {
   CommsMessage send = {};
   send.from_address = (msg.id >> FROM_ADDRESS_SHIFT) & ID_5_BIT_MASK;
   send.to_address = msg.id & ID_5_BIT_MASK;
-  send.data_len = constrain(metadata & TRUE_LENGTH_MASK, 1, MAX_SERIALIZE_LENGTH);
+  send.metadata = (metadata & ~TRUE_LENGTH_MASK)
+    + constrain(metadata & TRUE_LENGTH_MASK, 1, MAX_SERIALIZE_LENGTH);
   
   memcpy(send.data, &msg.buf[4], sizeof(send.data));
 
@@ -152,25 +199,27 @@ void loop() {
     memcpy(&recv, incomingBuffer, sizeof(recv));
 
     if (recv.from_address == 65535 && recv.to_address == 65535) {
-      mcctrlIP = udp.remoteIP();
-      Serial.print("Set mission control (remote IP) to: ");
-      mcctrlIP.printTo(Serial);
-      Serial.print("\n");
+      addMissionControlEndpoint(udp.remoteIP(), udp.remotePort());
+      Serial.print("Added mission control endpoint: ");
+      udp.remoteIP().printTo(Serial);
+      Serial.print(":");
+      Serial.println(udp.remotePort());
       continue;
     }
 
-    if (recv.data_len == 0) {
+    uint32_t data_len = recv.metadata & TRUE_LENGTH_MASK;
+    if (data_len == 0) {
       continue;
     }
 
-    uint32_t ctrl = 0;
-    ctrl += constrain(recv.data_len, 1, MAX_SERIALIZE_LENGTH);
+    uint32_t ctrl = recv.metadata & ~TRUE_LENGTH_MASK;
+    ctrl += constrain(data_len, 1, MAX_SERIALIZE_LENGTH);
 
     CANFD_message_t msg;
     msg.id = recv.to_address & ID_5_BIT_MASK;
     msg.id += (recv.from_address & ID_5_BIT_MASK) << FROM_ADDRESS_SHIFT;
-    msg.id += (recv.data_len + 4 > 32 ? 1 : 0) << 5;
-    msg.len = max(recv.data_len + 4, 8);
+    msg.id += (data_len + 4 > 32 ? 1 : 0) << 5;
+    msg.len = max(data_len + 4, 8);
     memcpy(&msg.buf[0], &ctrl, sizeof(ctrl));
     memcpy(&msg.buf[4], recv.data, sizeof(recv.data));
 
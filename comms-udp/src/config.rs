@@ -0,0 +1,247 @@
+use std::{
+    fmt, fs, io,
+    net::SocketAddr,
+    path::{Path, PathBuf},
+};
+
+use hal::comms_hal::{
+    comms_bridge::{BRIDGE_PORT, MISSION_CONTROL_PORT},
+    NetworkAddress,
+};
+use serde::Deserialize;
+
+use crate::{UDPCommsConfig, DEFAULT_BRIDGE_IP};
+
+/// Loaded from the working directory when no `--config` is given, if it exists
+pub const DEFAULT_CONFIG_PATH: &str = "network.toml";
+
+/// What's on the test stand network and where the ground tools listen. Anything left out of the
+/// file keeps its default, which matches the test stand.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct NetworkConfig {
+    /// Where the CAN-FD/Ethernet bridge listens
+    pub bridge: SocketAddr,
+    /// Port ctrl-view listens on, the bridge sends everything for mission control here
+    pub ctrl_view_port: u16,
+    /// Port ctrl-send listens on. It announces itself to the bridge, so replies reach it here while
+    /// ctrl-view is running on the same machine.
+    pub ctrl_send_port: u16,
+    /// Every controller on the bus. The first is the one talked to unless told otherwise.
+    pub controllers: Vec<ControllerConfig>,
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ControllerConfig {
+    /// Shown by the ground tools and used to pick the controller on the command line
+    pub name: String,
+    pub address: NetworkAddress,
+}
+
+#[derive(Debug)]
+pub enum ConfigError {
+    Io(PathBuf, io::Error),
+    Parse(toml::de::Error),
+    NoControllers,
+    /// Controllers can only be at `NetworkAddress::EngineController` addresses
+    NotAController(String),
+    /// Two controllers share an address or a name
+    Duplicate(String),
+    UnknownController(String),
+    /// A command line option without a value, or with one that can't be parsed
+    BadOption(String),
+}
+
+impl NetworkConfig {
+    /// # Errors
+    /// Returns a `ConfigError` if the file can't be read or isn't a valid config
+    pub fn load(path: &Path) -> Result<NetworkConfig, ConfigError> {
+        let text =
+            fs::read_to_string(path).map_err(|err| ConfigError::Io(path.to_path_buf(), err))?;
+
+        NetworkConfig::parse(&text)
+    }
+
+    /// # Errors
+    /// Returns a `ConfigError` if `text` isn't a valid config
+    pub fn parse(text: &str) -> Result<NetworkConfig, ConfigError> {
+        let config: NetworkConfig = toml::from_str(text).map_err(ConfigError::Parse)?;
+
+        config.validate()?;
+
+        Ok(config)
+    }
+
+    /// Mission control listening on `port` and talking through the configured bridge
+    pub fn mission_control(&self, port: u16) -> UDPCommsConfig {
+        UDPCommsConfig {
+            address: NetworkAddress::MissionControl,
+            local: SocketAddr::from(([0, 0, 0, 0], port)),
+            remote: self.bridge,
+            read_timeout: None,
+        }
+    }
+
+    /// Looks a controller up by name, or returns the first one when `name` is `None`
+    ///
+    /// # Errors
+    /// Returns `ConfigError::UnknownController` if no controller has that name
+    pub fn select_controller(&self, name: Option<&str>) -> Result<&ControllerConfig, ConfigError> {
+        match name {
+            Some(name) => self
+                .controllers
+                .iter()
+                .find(|controller| controller.name == name)
+                .ok_or_else(|| ConfigError::UnknownController(name.to_string())),
+            None => self.controllers.first().ok_or(ConfigError::NoControllers),
+        }
+    }
+
+    fn validate(&self) -> Result<(), ConfigError> {
+        if self.controllers.is_empty() {
+            return Err(ConfigError::NoControllers);
+        }
+
+        for (index, controller) in self.controllers.iter().enumerate() {
+            if !matches!(controller.address, NetworkAddress::EngineController(_)) {
+                return Err(ConfigError::NotAController(controller.name.clone()));
+            }
+
+            let duplicate = self.controllers[0..index]
+                .iter()
+                .any(|other| other.name == controller.name || other.address == controller.address);
+
+            if duplicate {
+                return Err(ConfigError::Duplicate(controller.name.clone()));
+            }
+        }
+
+        Ok(())
+    }
+}
+
+impl Default for NetworkConfig {
+    /// The test stand, with a single ECU
+    fn default() -> Self {
+        NetworkConfig {
+            bridge: SocketAddr::from((DEFAULT_BRIDGE_IP, BRIDGE_PORT)),
+            ctrl_view_port: MISSION_CONTROL_PORT,
+            ctrl_send_port: MISSION_CONTROL_PORT + 1,
+            controllers: vec![ControllerConfig {
+                name: "ECU(0)".to_string(),
+                address: NetworkAddress::EngineController(0),
+            }],
+        }
+    }
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ConfigError::Io(path, err) => {
+                write!(f, "Couldn't read config {}: {}", path.display(), err)
+            }
+            ConfigError::Parse(err) => write!(f, "Couldn't parse config: {}", err),
+            ConfigError::NoControllers => write!(f, "Config doesn't list any controllers"),
+            ConfigError::NotAController(name) => {
+                write!(f, "{} needs an EngineController address", name)
+            }
+            ConfigError::Duplicate(name) => {
+                write!(
+                    f,
+                    "{} has the same name or address as another controller",
+                    name
+                )
+            }
+            ConfigError::UnknownController(name) => write!(f, "No controller named {}", name),
+            ConfigError::BadOption(option) => write!(f, "Bad value for {}", option),
+        }
+    }
+}
+
+/// Command line options every ground tool takes, overriding the config file
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct NetworkOptions {
+    /// `--config <file>`
+    pub config: Option<PathBuf>,
+    /// `--bridge <address:port>`
+    pub bridge: Option<SocketAddr>,
+    /// `--port <port>`, the port this tool listens on
+    pub port: Option<u16>,
+    /// `--controller <name>`
+    pub controller: Option<String>,
+}
+
+impl NetworkOptions {
+    /// Removes the network options from `args`, leaving everything else for the tool to parse
+    ///
+    /// # Errors
+    /// Returns `ConfigError::BadOption` if an option is missing its value or it can't be parsed
+    pub fn take_from(args: &mut Vec<String>) -> Result<NetworkOptions, ConfigError> {
+        let mut options = NetworkOptions::default();
+        let mut index = 0;
+
+        while index < args.len() {
+            let option = args[index].clone();
+
+            if !matches!(
+                option.as_str(),
+                "--config" | "--bridge" | "--port" | "--controller"
+            ) {
+                index += 1;
+                continue;
+            }
+
+            if index + 1 >= args.len() {
+                return Err(ConfigError::BadOption(option));
+            }
+
+            let value = args.remove(index + 1);
+            args.remove(index);
+
+            match option.as_str() {
+                "--config" => options.config = Some(PathBuf::from(value)),
+                "--bridge" => {
+                    options.bridge = Some(
+                        value
+                            .parse()
+                            .map_err(|_| ConfigError::BadOption(option.clone()))?,
+                    )
+                }
+                "--port" => {
+                    options.port = Some(
+                        value
+                            .parse()
+                            .map_err(|_| ConfigError::BadOption(option.clone()))?,
+                    )
+                }
+                _ => options.controller = Some(value),
+            }
+        }
+
+        Ok(options)
+    }
+
+    /// Loads `--config`, or `DEFAULT_CONFIG_PATH` if it exists, or falls back to the defaults, then
+    /// applies `--bridge`. `--port` and `--controller` are left for the tool, since which port and
+    /// controller they replace depends on the tool.
+    ///
+    /// # Errors
+    /// Returns a `ConfigError` if the config file can't be loaded
+    pub fn load_config(&self) -> Result<NetworkConfig, ConfigError> {
+        let mut config = match &self.config {
+            Some(path) => NetworkConfig::load(path)?,
+            None if Path::new(DEFAULT_CONFIG_PATH).exists() => {
+                NetworkConfig::load(Path::new(DEFAULT_CONFIG_PATH))?
+            }
+            None => NetworkConfig::default(),
+        };
+
+        if let Some(bridge) = self.bridge {
+            config.bridge = bridge;
+        }
+
+        Ok(config)
+    }
+}
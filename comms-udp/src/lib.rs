@@ -0,0 +1,317 @@
+#![forbid(unsafe_code)]
+
+pub mod config;
+pub mod peer_stats;
+
+use std::{
+    io::{self, ErrorKind},
+    net::{SocketAddr, UdpSocket},
+    time::{Duration, Instant},
+};
+
+use hal::comms_hal::{
+    comms_bridge::{
+        self, Bridge, BRIDGE_PORT, MAX_MISSION_CONTROL_ENDPOINTS, MISSION_CONTROL_PORT,
+    },
+    comms_ethernet_hal::{self, ETHERNET_BUFFER_SIZE},
+    fragmentation::{Fragmenter, Reassembler, MAX_MESSAGE_LENGTH},
+    CommsInterface, NetworkAddress, Packet, TransferError,
+};
+use peer_stats::{PeerStats, PeerStatsTable};
+
+/// Where the CAN-FD/Ethernet bridge lives on the test stand network
+pub const DEFAULT_BRIDGE_IP: [u8; 4] = [10, 0, 0, 5];
+
+pub struct UDPCommsConfig {
+    /// The address packets are sent from. Packets addressed to anyone else are dropped.
+    pub address: NetworkAddress,
+    pub local: SocketAddr,
+    /// Where every packet is sent, normally the bridge
+    pub remote: SocketAddr,
+    /// How long `receive()` blocks waiting for a datagram. `None` never blocks, which is what a
+    /// controller's update loop needs.
+    pub read_timeout: Option<Duration>,
+}
+
+impl UDPCommsConfig {
+    /// Mission control listening on its usual port and talking through the bridge
+    pub fn mission_control() -> UDPCommsConfig {
+        UDPCommsConfig {
+            address: NetworkAddress::MissionControl,
+            local: SocketAddr::from(([0, 0, 0, 0], MISSION_CONTROL_PORT)),
+            remote: SocketAddr::from((DEFAULT_BRIDGE_IP, BRIDGE_PORT)),
+            read_timeout: None,
+        }
+    }
+}
+
+/// A `CommsInterface` that sends packets over UDP in the same framing the bridge uses, so host
+/// programs can talk to controllers through the bridge, or to each other over localhost.
+pub struct UDPComms {
+    socket: UdpSocket,
+    address: NetworkAddress,
+    remotes: Vec<SocketAddr>,
+    fragmenter: Fragmenter,
+    reassembler: Reassembler,
+    last_update: Instant,
+    peers: PeerStatsTable,
+    dropped_datagrams: usize,
+    last_cet_pulse: Option<Instant>,
+    follow_announcements: bool,
+    /// Whether anyone has announced themselves since `follow_announcements` was called
+    announced: bool,
+}
+
+impl UDPComms {
+    /// Binds to `config.local`.
+    ///
+    /// # Errors
+    /// Returns whatever binding or configuring the socket failed with
+    pub fn new(config: UDPCommsConfig) -> io::Result<UDPComms> {
+        let socket = UdpSocket::bind(config.local)?;
+
+        match config.read_timeout {
+            Some(timeout) => socket.set_read_timeout(Some(timeout))?,
+            None => socket.set_nonblocking(true)?,
+        }
+
+        Ok(UDPComms {
+            socket,
+            address: config.address,
+            remotes: vec![config.remote],
+            fragmenter: Fragmenter::new(),
+            reassembler: Reassembler::new(),
+            last_update: Instant::now(),
+            peers: PeerStatsTable::new(),
+            dropped_datagrams: 0,
+            last_cet_pulse: None,
+            follow_announcements: false,
+            announced: false,
+        })
+    }
+
+    /// Another handle to the same socket, so packets can be sent from another thread. The clone
+    /// keeps its own statistics and reassembly state.
+    ///
+    /// # Errors
+    /// Returns whatever cloning the socket failed with
+    pub fn try_clone(&self) -> io::Result<UDPComms> {
+        Ok(UDPComms {
+            socket: self.socket.try_clone()?,
+            address: self.address,
+            remotes: self.remotes.clone(),
+            fragmenter: Fragmenter::new(),
+            reassembler: Reassembler::new(),
+            last_update: Instant::now(),
+            peers: PeerStatsTable::new(),
+            dropped_datagrams: 0,
+            last_cet_pulse: None,
+            follow_announcements: self.follow_announcements,
+            announced: self.announced,
+        })
+    }
+
+    /// # Errors
+    /// Returns whatever getting the socket's address failed with
+    pub fn local_addr(&self) -> io::Result<SocketAddr> {
+        self.socket.local_addr()
+    }
+
+    pub fn get_remotes(&self) -> &[SocketAddr] {
+        &self.remotes
+    }
+
+    /// Replaces every remote with just `remote`
+    pub fn set_remote(&mut self, remote: SocketAddr) {
+        self.remotes.clear();
+        self.remotes.push(remote);
+    }
+
+    /// Sends everything to `remote` as well, for when there's more than one program listening
+    pub fn add_remote(&mut self, remote: SocketAddr) {
+        if !self.remotes.contains(&remote) {
+            self.remotes.push(remote);
+        }
+    }
+
+    /// Sends to wherever mission control announces itself from, like the bridge does, for programs
+    /// standing in for the bridge. The remotes are only used until the first announcement.
+    pub fn follow_announcements(&mut self) {
+        self.follow_announcements = true;
+    }
+
+    /// Asks the bridge to send packets for mission control straight to this socket, along with
+    /// anywhere else that has announced itself, rather than broadcasting them.
+    ///
+    /// # Errors
+    /// Returns whatever sending the datagram failed with
+    pub fn announce(&self) -> io::Result<()> {
+        let mut buffer = [0_u8; ETHERNET_BUFFER_SIZE];
+        let len = comms_bridge::announce(&mut buffer);
+
+        self.send_to_remotes(&buffer[0..len])
+    }
+
+    /// Sends the bridge's pulse, for programs standing in for the bridge.
+    ///
+    /// # Errors
+    /// Returns whatever sending the datagram failed with
+    pub fn send_cet_pulse(&self) -> io::Result<()> {
+        let mut buffer = [0_u8; ETHERNET_BUFFER_SIZE];
+        let (len, _endpoint) = Bridge::default().cet_pulse(&mut buffer);
+
+        self.send_to_remotes(&buffer[0..len])
+    }
+
+    /// When the bridge's pulse was last received, if it ever was
+    pub fn get_last_cet_pulse(&self) -> Option<Instant> {
+        self.last_cet_pulse
+    }
+
+    pub fn get_peer_stats(&self, address: NetworkAddress) -> Option<&PeerStats> {
+        self.peers.get(address)
+    }
+
+    pub fn peer_stats(&self) -> impl Iterator<Item = &(NetworkAddress, PeerStats)> {
+        self.peers.iter()
+    }
+
+    /// Datagrams that couldn't be attributed to any peer, because their header didn't parse
+    pub fn get_dropped_datagrams(&self) -> usize {
+        self.dropped_datagrams
+    }
+
+    /// Handles one datagram, returning the packet it completed if any
+    fn process_datagram(
+        &mut self,
+        buffer: &[u8; ETHERNET_BUFFER_SIZE],
+        source: SocketAddr,
+    ) -> Option<(Packet, NetworkAddress)> {
+        if comms_bridge::is_cet_pulse(buffer) {
+            self.last_cet_pulse = Some(Instant::now());
+            return None;
+        }
+
+        // Only means something to the bridge, or whatever's standing in for it
+        if comms_bridge::is_announce(buffer) {
+            if self.follow_announcements && !self.remotes.contains(&source) {
+                if !self.announced {
+                    self.remotes.clear();
+                    self.announced = true;
+                } else if self.remotes.len() >= MAX_MISSION_CONTROL_ENDPOINTS {
+                    self.remotes.remove(0);
+                }
+
+                self.remotes.push(source);
+            }
+
+            return None;
+        }
+
+        let (fragment, from, to) = match comms_ethernet_hal::deserialize_fragment(buffer) {
+            Ok(fragment) => fragment,
+            Err(_) => {
+                self.dropped_datagrams += 1;
+                return None;
+            }
+        };
+
+        if to != self.address && to != NetworkAddress::Broadcast {
+            return None;
+        }
+
+        let stats = self.peers.get_mut(from);
+        stats.bytes_received += fragment.data.len();
+        stats.last_received = Some(Instant::now());
+
+        match self.reassembler.receive(from, &fragment) {
+            Ok(Some(packet)) => {
+                stats.packets_received += 1;
+                Some((packet, from))
+            }
+            Ok(None) => None,
+            Err(_) => {
+                stats.receive_errors += 1;
+                None
+            }
+        }
+    }
+
+    fn send_to_remotes(&self, data: &[u8]) -> io::Result<()> {
+        for remote in self.remotes.iter() {
+            self.socket.send_to(data, remote)?;
+        }
+
+        Ok(())
+    }
+}
+
+impl CommsInterface for UDPComms {
+    fn transmit(
+        &mut self,
+        packet: &Packet,
+        address: NetworkAddress,
+    ) -> nb::Result<(), TransferError> {
+        let mut message_buffer = [0_u8; MAX_MESSAGE_LENGTH];
+        let mut buffer = [0_u8; ETHERNET_BUFFER_SIZE];
+
+        let fragments = self
+            .fragmenter
+            .fragment(packet, &mut message_buffer)
+            .map_err(|err| nb::Error::Other(TransferError::Serialization(err)))?;
+
+        let mut bytes_transmitted = 0;
+
+        for fragment in fragments {
+            let len = comms_ethernet_hal::serialize_fragment(
+                &fragment,
+                self.address,
+                address,
+                &mut buffer,
+            )?;
+
+            match self.send_to_remotes(&buffer[0..len]) {
+                Ok(()) => bytes_transmitted += fragment.data.len(),
+                Err(err) if err.kind() == ErrorKind::WouldBlock => {
+                    return Err(nb::Error::WouldBlock)
+                }
+                Err(_) => return Err(nb::Error::Other(TransferError::Unknown)),
+            }
+        }
+
+        let stats = self.peers.get_mut(address);
+        stats.packets_transmitted += 1;
+        stats.bytes_transmitted += bytes_transmitted;
+
+        Ok(())
+    }
+
+    fn receive(&mut self) -> Option<(Packet, NetworkAddress)> {
+        self.reassembler
+            .update(self.last_update.elapsed().as_secs_f32());
+        self.last_update = Instant::now();
+
+        let mut buffer = [0_u8; ETHERNET_BUFFER_SIZE];
+
+        // Keep going until a whole packet turns up or there's nothing left to read
+        loop {
+            match self.socket.recv_from(&mut buffer) {
+                Ok((len, source)) => {
+                    // A short datagram shouldn't be padded out with whatever the last one left
+                    buffer[len..].fill(0);
+
+                    if let Some(received) = self.process_datagram(&buffer, source) {
+                        return Some(received);
+                    }
+                }
+                Err(err) => {
+                    if err.kind() != ErrorKind::WouldBlock && err.kind() != ErrorKind::TimedOut {
+                        log::error!("Failed to receive datagram, got {:?}", err);
+                    }
+
+                    return None;
+                }
+            }
+        }
+    }
+}
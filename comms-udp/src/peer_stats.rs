@@ -0,0 +1,69 @@
+use std::time::Instant;
+
+use hal::comms_hal::NetworkAddress;
+
+/// Traffic to and from a single controller
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PeerStats {
+    pub packets_received: usize,
+    pub packets_transmitted: usize,
+    pub bytes_received: usize,
+    pub bytes_transmitted: usize,
+    /// Fragments from the peer that couldn't be reassembled or decoded
+    pub receive_errors: usize,
+    pub last_received: Option<Instant>,
+}
+
+impl PeerStats {
+    pub fn new() -> PeerStats {
+        PeerStats {
+            packets_received: 0,
+            packets_transmitted: 0,
+            bytes_received: 0,
+            bytes_transmitted: 0,
+            receive_errors: 0,
+            last_received: None,
+        }
+    }
+}
+
+impl Default for PeerStats {
+    fn default() -> Self {
+        PeerStats::new()
+    }
+}
+
+/// `PeerStats` for every controller that's been talked to. There are only ever a handful, so
+/// they're kept in a list.
+pub(crate) struct PeerStatsTable {
+    peers: Vec<(NetworkAddress, PeerStats)>,
+}
+
+impl PeerStatsTable {
+    pub fn new() -> PeerStatsTable {
+        PeerStatsTable { peers: Vec::new() }
+    }
+
+    pub fn get(&self, address: NetworkAddress) -> Option<&PeerStats> {
+        self.peers
+            .iter()
+            .find(|(peer, _)| *peer == address)
+            .map(|(_, stats)| stats)
+    }
+
+    pub fn get_mut(&mut self, address: NetworkAddress) -> &mut PeerStats {
+        let index = match self.peers.iter().position(|(peer, _)| *peer == address) {
+            Some(index) => index,
+            None => {
+                self.peers.push((address, PeerStats::new()));
+                self.peers.len() - 1
+            }
+        };
+
+        &mut self.peers[index].1
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &(NetworkAddress, PeerStats)> {
+        self.peers.iter()
+    }
+}
@@ -0,0 +1,133 @@
+use std::{net::SocketAddr, path::PathBuf};
+
+use comms_udp::config::{ConfigError, ControllerConfig, NetworkConfig, NetworkOptions};
+use hal::comms_hal::NetworkAddress;
+
+#[test]
+fn example_config_matches_defaults() {
+    let config = NetworkConfig::parse(include_str!("../../network.toml")).unwrap();
+
+    assert!(config == NetworkConfig::default());
+}
+
+#[test]
+fn missing_fields_keep_defaults() {
+    let config = NetworkConfig::parse(
+        r#"
+        bridge = "127.0.0.1:8888"
+
+        [[controllers]]
+        name = "ECU(0)"
+        address = { EngineController = 0 }
+
+        [[controllers]]
+        name = "ECU(1)"
+        address = { EngineController = 1 }
+        "#,
+    )
+    .unwrap();
+
+    assert!(config.bridge == SocketAddr::from(([127, 0, 0, 1], 8888)));
+    assert!(config.ctrl_view_port == NetworkConfig::default().ctrl_view_port);
+    assert!(
+        config.select_controller(Some("ECU(1)")).unwrap()
+            == &ControllerConfig {
+                name: "ECU(1)".to_string(),
+                address: NetworkAddress::EngineController(1),
+            }
+    );
+    assert!(config.select_controller(None).unwrap().name == "ECU(0)");
+    assert!(matches!(
+        config.select_controller(Some("ECU(2)")),
+        Err(ConfigError::UnknownController(_))
+    ));
+}
+
+#[test]
+fn invalid_controllers_are_rejected() {
+    assert!(matches!(
+        NetworkConfig::parse("controllers = []"),
+        Err(ConfigError::NoControllers)
+    ));
+
+    assert!(matches!(
+        NetworkConfig::parse(
+            r#"
+            [[controllers]]
+            name = "Ground"
+            address = "MissionControl"
+            "#
+        ),
+        Err(ConfigError::NotAController(_))
+    ));
+
+    assert!(matches!(
+        NetworkConfig::parse(
+            r#"
+            [[controllers]]
+            name = "ECU(0)"
+            address = { EngineController = 0 }
+
+            [[controllers]]
+            name = "Also ECU(0)"
+            address = { EngineController = 0 }
+            "#
+        ),
+        Err(ConfigError::Duplicate(_))
+    ));
+
+    assert!(matches!(
+        NetworkConfig::parse("brige = \"10.0.0.5:8888\""),
+        Err(ConfigError::Parse(_))
+    ));
+}
+
+#[test]
+fn options_are_taken_from_args() {
+    let mut args: Vec<String> = [
+        "ctrl-send",
+        "--bridge",
+        "127.0.0.1:8888",
+        "valve",
+        "--controller",
+        "ECU(1)",
+        "FuelVent",
+        "--config",
+        "stand.toml",
+        "255",
+        "--port",
+        "30000",
+    ]
+    .iter()
+    .map(|arg| arg.to_string())
+    .collect();
+
+    let options = NetworkOptions::take_from(&mut args).unwrap();
+
+    assert!(args == ["ctrl-send", "valve", "FuelVent", "255"]);
+    assert!(
+        options
+            == NetworkOptions {
+                config: Some(PathBuf::from("stand.toml")),
+                bridge: Some(SocketAddr::from(([127, 0, 0, 1], 8888))),
+                port: Some(30000),
+                controller: Some("ECU(1)".to_string()),
+            }
+    );
+
+    let mut missing_value = vec!["ctrl-view".to_string(), "--port".to_string()];
+    assert!(matches!(
+        NetworkOptions::take_from(&mut missing_value),
+        Err(ConfigError::BadOption(_))
+    ));
+
+    let mut bad_value = vec![
+        "ctrl-view".to_string(),
+        "--bridge".to_string(),
+        "bridge".to_string(),
+    ];
+    assert!(matches!(
+        NetworkOptions::take_from(&mut bad_value),
+        Err(ConfigError::BadOption(_))
+    ));
+}
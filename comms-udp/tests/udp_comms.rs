@@ -0,0 +1,222 @@
+use std::{
+    net::{SocketAddr, UdpSocket},
+    time::Duration,
+};
+
+use comms_udp::{UDPComms, UDPCommsConfig};
+use ecu::{Ecu, HALs};
+use hal::{
+    comms_hal::{
+        comms_bridge::Bridge,
+        comms_ethernet_hal::{self, ETHERNET_BUFFER_SIZE},
+        CommsInterface, ControllerKind, NetworkAddress, Packet, PROTOCOL_VERSION,
+        RECORDED_DATA_BATCH_LENGTH,
+    },
+    ecu_hal::{ECUDataFrame, IgniterState, MAX_ECU_SENSORS, MAX_ECU_VALVES},
+    ecu_mock::ECUHardwareMock,
+};
+
+const READ_TIMEOUT: Duration = Duration::from_millis(100);
+
+#[test]
+fn packets_round_trip() {
+    let (mut mission_control, mut ecu) = connected_pair();
+
+    mission_control
+        .transmit(&Packet::Heartbeat, NetworkAddress::EngineController(0))
+        .unwrap();
+    assert!(ecu.receive() == Some((Packet::Heartbeat, NetworkAddress::MissionControl)));
+
+    ecu.transmit(&Packet::Ack { seq: 42 }, NetworkAddress::MissionControl)
+        .unwrap();
+    assert!(
+        mission_control.receive()
+            == Some((Packet::Ack { seq: 42 }, NetworkAddress::EngineController(0)))
+    );
+}
+
+#[test]
+fn fragmented_packets_round_trip() {
+    let (mut mission_control, mut ecu) = connected_pair();
+
+    let packet = Packet::RecordedDataBatch(
+        [ECUDataFrame {
+            igniter_state: IgniterState::Firing,
+            sensor_states: [4095_u16; MAX_ECU_SENSORS],
+            valve_states: [255_u8; MAX_ECU_VALVES],
+            sparking: true,
+        }; RECORDED_DATA_BATCH_LENGTH],
+    );
+
+    ecu.transmit(&packet, NetworkAddress::MissionControl)
+        .unwrap();
+
+    assert!(mission_control.receive() == Some((packet, NetworkAddress::EngineController(0))));
+}
+
+#[test]
+fn packets_for_others_are_dropped() {
+    let (mut mission_control, mut ecu) = connected_pair();
+
+    mission_control
+        .transmit(&Packet::Heartbeat, NetworkAddress::EngineController(1))
+        .unwrap();
+    assert!(ecu.receive().is_none());
+
+    mission_control
+        .transmit(&Packet::Heartbeat, NetworkAddress::Broadcast)
+        .unwrap();
+    assert!(ecu.receive() == Some((Packet::Heartbeat, NetworkAddress::MissionControl)));
+}
+
+#[test]
+fn cet_pulse_is_recorded() {
+    let mut comms = UDPComms::new(config(NetworkAddress::MissionControl)).unwrap();
+    let socket = UdpSocket::bind("127.0.0.1:0").unwrap();
+
+    let mut buffer = [0_u8; ETHERNET_BUFFER_SIZE];
+    let (len, _endpoint) = Bridge::default().cet_pulse(&mut buffer);
+    socket
+        .send_to(&buffer[0..len], comms.local_addr().unwrap())
+        .unwrap();
+
+    assert!(comms.get_last_cet_pulse().is_none());
+    assert!(comms.receive().is_none());
+    assert!(comms.get_last_cet_pulse().is_some());
+    assert!(comms.get_dropped_datagrams() == 0);
+}
+
+#[test]
+fn announcements_are_followed() {
+    let mut ecu = UDPComms::new(config(NetworkAddress::EngineController(0))).unwrap();
+    ecu.follow_announcements();
+
+    let ctrl_view = UDPComms::new(config(NetworkAddress::MissionControl)).unwrap();
+    let ctrl_send = UDPComms::new(config(NetworkAddress::MissionControl)).unwrap();
+
+    for mission_control in [&ctrl_view, &ctrl_send] {
+        let mut mission_control = mission_control.try_clone().unwrap();
+        mission_control.set_remote(ecu.local_addr().unwrap());
+        mission_control.announce().unwrap();
+        assert!(ecu.receive().is_none());
+    }
+
+    // The fallback remote is dropped for whoever announced
+    assert!(
+        ecu.get_remotes()
+            == [
+                ctrl_view.local_addr().unwrap(),
+                ctrl_send.local_addr().unwrap()
+            ]
+    );
+}
+
+#[test]
+fn stats_are_kept_per_peer() {
+    let (mut mission_control, mut ecu) = connected_pair();
+    let socket = UdpSocket::bind("127.0.0.1:0").unwrap();
+
+    for _ in 0..3 {
+        mission_control
+            .transmit(&Packet::Heartbeat, NetworkAddress::EngineController(0))
+            .unwrap();
+        ecu.receive().unwrap();
+    }
+
+    // A corrupted packet still counts towards the peer it came from
+    let mut buffer = [0_u8; ETHERNET_BUFFER_SIZE];
+    let len = comms_ethernet_hal::serialize_packet(
+        &Packet::Heartbeat,
+        NetworkAddress::MissionControl,
+        NetworkAddress::EngineController(0),
+        &mut buffer,
+    )
+    .unwrap();
+    buffer[len - 2] ^= 0xFE;
+    socket
+        .send_to(&buffer[0..len], ecu.local_addr().unwrap())
+        .unwrap();
+
+    // Garbage can't be attributed to anyone
+    socket
+        .send_to(&[42_u8; 8], ecu.local_addr().unwrap())
+        .unwrap();
+
+    assert!(ecu.receive().is_none());
+
+    let sent = mission_control
+        .get_peer_stats(NetworkAddress::EngineController(0))
+        .unwrap();
+    assert!(sent.packets_transmitted == 3);
+    assert!(sent.packets_received == 0);
+
+    let received = ecu.get_peer_stats(NetworkAddress::MissionControl).unwrap();
+    assert!(received.packets_received == 3);
+    assert!(received.receive_errors == 1);
+    assert!(received.last_received.is_some());
+    assert!(ecu.get_dropped_datagrams() == 1);
+    assert!(ecu.peer_stats().count() == 1);
+}
+
+#[test]
+fn ecu_runs_over_udp() {
+    let (mut mission_control, mut ecu_comms) = connected_pair();
+    let mut ecu_hardware = ECUHardwareMock::new();
+
+    let mut ecu = Ecu::new(
+        0,
+        &mut HALs {
+            hardware: &mut ecu_hardware,
+            comms: &mut ecu_comms,
+        },
+    );
+
+    match mission_control.receive() {
+        Some((
+            Packet::Hello {
+                protocol_version, ..
+            },
+            NetworkAddress::EngineController(0),
+        )) => assert!(protocol_version == PROTOCOL_VERSION),
+        other => panic!("Expected a hello, got {:?}", other),
+    }
+
+    mission_control
+        .transmit(
+            &Packet::hello(ControllerKind::MissionControl, "test"),
+            NetworkAddress::EngineController(0),
+        )
+        .unwrap();
+
+    ecu.update(
+        &mut HALs {
+            hardware: &mut ecu_hardware,
+            comms: &mut ecu_comms,
+        },
+        0.001,
+    );
+
+    assert!(ecu.get_mission_control_version() == Some(PROTOCOL_VERSION));
+}
+
+/// Mission control and ECU 0 talking to each other directly over localhost
+fn connected_pair() -> (UDPComms, UDPComms) {
+    let mut mission_control = UDPComms::new(config(NetworkAddress::MissionControl)).unwrap();
+
+    let mut ecu_config = config(NetworkAddress::EngineController(0));
+    ecu_config.remote = mission_control.local_addr().unwrap();
+    let ecu = UDPComms::new(ecu_config).unwrap();
+
+    mission_control.set_remote(ecu.local_addr().unwrap());
+
+    (mission_control, ecu)
+}
+
+fn config(address: NetworkAddress) -> UDPCommsConfig {
+    UDPCommsConfig {
+        address,
+        local: SocketAddr::from(([127, 0, 0, 1], 0)),
+        remote: SocketAddr::from(([127, 0, 0, 1], 0)),
+        read_timeout: Some(READ_TIMEOUT),
+    }
+}
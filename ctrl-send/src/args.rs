@@ -0,0 +1,178 @@
+use std::{fmt, str::FromStr};
+
+use hal::ecu_hal::{ECUSensor, ECUValve, ECU_SENSORS, ECU_VALVES};
+
+/// Names ctrl-send has always accepted, kept working alongside the enum names
+const VALVE_ALIASES: [(&str, ECUValve); 4] = [
+    ("fuel_main", ECUValve::IgniterFuelMain),
+    ("gox_main", ECUValve::IgniterGOxMain),
+    ("fuel_press", ECUValve::FuelPress),
+    ("fuel_vent", ECUValve::FuelVent),
+];
+
+#[derive(Debug)]
+pub enum ArgError {
+    /// Fewer args than the command needs, holds its usage
+    Missing(&'static str),
+    UnknownCommand(String),
+    UnknownValve(String),
+    UnknownSensor(String),
+    /// An arg that should've been a number or a bool but couldn't be parsed as one
+    BadValue {
+        name: &'static str,
+        value: String,
+    },
+}
+
+impl fmt::Display for ArgError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ArgError::Missing(usage) => write!(f, "Not enough args. Format: {}", usage),
+            ArgError::UnknownCommand(command) => {
+                write!(f, "Unknown command {}, type help for a list", command)
+            }
+            ArgError::UnknownValve(name) => {
+                write!(
+                    f,
+                    "Unknown valve {}. Valves are: {}",
+                    name,
+                    valve_names().join(", ")
+                )
+            }
+            ArgError::UnknownSensor(name) => write!(
+                f,
+                "Unknown sensor {}. Sensors are: {}",
+                name,
+                sensor_names().join(", ")
+            ),
+            ArgError::BadValue { name, value } => write!(f, "Invalid {}: {}", name, value),
+        }
+    }
+}
+
+pub fn valve_names() -> Vec<String> {
+    ECU_VALVES
+        .iter()
+        .map(|valve| format!("{:?}", valve))
+        .collect()
+}
+
+pub fn sensor_names() -> Vec<String> {
+    ECU_SENSORS
+        .iter()
+        .map(|sensor| format!("{:?}", sensor))
+        .collect()
+}
+
+/// Accepts a valve's enum name in any case, or one of the old short names
+///
+/// # Errors
+/// Returns `ArgError::UnknownValve` if `name` isn't any valve
+pub fn parse_valve(name: &str) -> Result<ECUValve, ArgError> {
+    ECU_VALVES
+        .iter()
+        .find(|valve| format!("{:?}", valve).eq_ignore_ascii_case(name))
+        .copied()
+        .or_else(|| {
+            VALVE_ALIASES
+                .iter()
+                .find(|(alias, _)| *alias == name)
+                .map(|(_, valve)| *valve)
+        })
+        .ok_or_else(|| ArgError::UnknownValve(name.to_string()))
+}
+
+/// Accepts a sensor's enum name in any case
+///
+/// # Errors
+/// Returns `ArgError::UnknownSensor` if `name` isn't any sensor
+pub fn parse_sensor(name: &str) -> Result<ECUSensor, ArgError> {
+    ECU_SENSORS
+        .iter()
+        .find(|sensor| format!("{:?}", sensor).eq_ignore_ascii_case(name))
+        .copied()
+        .ok_or_else(|| ArgError::UnknownSensor(name.to_string()))
+}
+
+/// # Errors
+/// Returns `ArgError::BadValue` naming the arg if `value` can't be parsed
+pub fn parse_value<T: FromStr>(name: &'static str, value: &str) -> Result<T, ArgError> {
+    value.parse().map_err(|_| ArgError::BadValue {
+        name,
+        value: value.to_string(),
+    })
+}
+
+/// Parses on/off style switches, as well as plain true/false
+///
+/// # Errors
+/// Returns `ArgError::BadValue` naming the arg if `value` isn't a switch
+pub fn parse_switch(name: &'static str, value: &str) -> Result<bool, ArgError> {
+    match value.to_ascii_lowercase().as_str() {
+        "on" | "true" | "1" => Ok(true),
+        "off" | "false" | "0" => Ok(false),
+        _ => Err(ArgError::BadValue {
+            name,
+            value: value.to_string(),
+        }),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn valves_parse_by_name_or_alias() {
+        assert!(matches!(
+            parse_valve("igniterfuelmain"),
+            Ok(ECUValve::IgniterFuelMain)
+        ));
+        assert!(matches!(
+            parse_valve("gox_main"),
+            Ok(ECUValve::IgniterGOxMain)
+        ));
+
+        let err = parse_valve("lox_main").unwrap_err();
+        assert!(matches!(&err, ArgError::UnknownValve(name) if name == "lox_main"));
+        assert!(err.to_string()
+            == "Unknown valve lox_main. Valves are: IgniterFuelMain, IgniterGOxMain, FuelPress, FuelVent");
+    }
+
+    #[test]
+    fn unknown_sensors_are_rejected() {
+        assert!(matches!(
+            parse_sensor("FUELTANKPRESSURE"),
+            Ok(ECUSensor::FuelTankPressure)
+        ));
+
+        let err = parse_sensor("tank").unwrap_err();
+        assert!(matches!(&err, ArgError::UnknownSensor(name) if name == "tank"));
+        assert!(err
+            .to_string()
+            .starts_with("Unknown sensor tank. Sensors are: IgniterThroatTemp"));
+    }
+
+    #[test]
+    fn bad_values_are_rejected() {
+        assert!(matches!(parse_value::<u8>("valve state", "255"), Ok(255)));
+
+        for value in ["256", "-1", "open", ""] {
+            let err = parse_value::<u8>("valve state", value).unwrap_err();
+            assert!(err.to_string() == format!("Invalid valve state: {}", value));
+        }
+    }
+
+    #[test]
+    fn switches_parse() {
+        assert!(matches!(parse_switch("recording", "ON"), Ok(true)));
+        assert!(matches!(parse_switch("recording", "0"), Ok(false)));
+        assert!(matches!(
+            parse_switch("recording", "maybe"),
+            Err(ArgError::BadValue {
+                name: "recording",
+                ..
+            })
+        ));
+    }
+}
@@ -0,0 +1,580 @@
+use std::{
+    io::{stdout, Write},
+    time::{Duration, Instant},
+};
+
+use comms_udp::UDPComms;
+use crossterm::{
+    event::{self, Event, KeyCode, KeyEvent, KeyModifiers},
+    queue, terminal,
+};
+use hal::{
+    comms_hal::{Command, CommsInterface, ECUTelemtryData, Packet, ARM_CONFIRMATION_CODE},
+    ecu_hal::ECU_SENSORS,
+};
+
+use crate::{
+    args::{self, ArgError},
+    transmit_command, CommandError, MAX_ATTEMPTS,
+};
+
+const PROMPT: &str = "ecu> ";
+
+const COMMANDS: [&str; 12] = [
+    "abort", "arm", "disarm", "fire", "help", "history", "quit", "record", "sensor", "spark",
+    "transfer", "valve",
+];
+
+const HELP: &str = "\
+valve <name> <state>   Sets a valve, 0 is closed and 255 is fully open
+spark <seconds>        Sparks for this long
+sensor [name]          Shows the last raw reading of a sensor, or of all of them
+fire [seconds]         Counts down from 5s (or the given time), arming at T-2s, then fires.
+                       h holds, r resumes
+record <on|off>        Starts or stops recording
+arm / disarm           Allows or stops hazardous commands
+transfer               Transfers recorded data
+abort                  Aborts the ECU
+history                Lists the commands entered so far
+quit                   Leaves the console, as does Ctrl-D
+
+Tab completes commands, valves and sensors. Up and down go through the history.
+Esc aborts the ECU at any time, even in the middle of a line or a countdown.";
+
+/// How long the countdown runs when `fire` isn't given a time, same as `ctrl-send fire`
+const DEFAULT_COUNTDOWN: f32 = 5.0;
+
+/// Recording starts and the ECU is armed this long before the igniter fires
+const RECORDING_LEAD: f32 = 2.0;
+
+/// How long to wait for a key before checking the network. The network's read timeout does most
+/// of the waiting.
+const KEY_POLL_TIMEOUT: Duration = Duration::from_millis(1);
+
+/// Longest spent handling packets between checks for a key. Telemetry arrives about as often as
+/// the read timeout, so the socket is rarely empty and there has to be a limit for the abort key
+/// to stay responsive.
+const COMMS_POLL_TIME: Duration = Duration::from_millis(20);
+
+/// An interactive console that keeps the connection to the ECU open between commands
+pub struct Console<'a> {
+    comms: &'a mut UDPComms,
+    history: Vec<String>,
+    telemetry: Option<ECUTelemtryData>,
+}
+
+enum Input {
+    Line(String),
+    Abort,
+    Quit,
+}
+
+enum Countdown {
+    Fired,
+    Aborted(String),
+}
+
+/// Raw mode is only on while reading keys, so everything else can print as usual. Turned off
+/// again when dropped, even if the console bails out with an error.
+struct RawMode;
+
+impl RawMode {
+    fn enable() -> crossterm::Result<RawMode> {
+        terminal::enable_raw_mode()?;
+        Ok(RawMode)
+    }
+}
+
+impl Drop for RawMode {
+    fn drop(&mut self) {
+        let _ = terminal::disable_raw_mode();
+    }
+}
+
+impl<'a> Console<'a> {
+    pub fn new(comms: &'a mut UDPComms) -> Console<'a> {
+        Console {
+            comms,
+            history: Vec::new(),
+            telemetry: None,
+        }
+    }
+
+    /// Runs until the operator quits
+    ///
+    /// # Errors
+    /// Returns an error if the terminal can't be read from or written to
+    pub fn run(&mut self) -> crossterm::Result<()> {
+        println!("Type help for a list of commands. Esc aborts the ECU.");
+
+        loop {
+            match self.read_line()? {
+                Input::Line(line) => {
+                    if line.is_empty() {
+                        continue;
+                    }
+
+                    if self.history.last() != Some(&line) {
+                        self.history.push(line.clone());
+                    }
+
+                    if line == "quit" || line == "exit" {
+                        return Ok(());
+                    }
+
+                    if let Err(err) = self.execute(&line) {
+                        println!("{}", err);
+                    }
+                }
+                Input::Abort => self.abort(),
+                Input::Quit => return Ok(()),
+            }
+        }
+    }
+
+    fn execute(&mut self, line: &str) -> Result<(), ArgError> {
+        let words: Vec<&str> = line.split_whitespace().collect();
+
+        match words[0] {
+            "valve" => {
+                if words.len() < 3 {
+                    return Err(ArgError::Missing("valve <name> <state>"));
+                }
+
+                let valve = args::parse_valve(words[1])?;
+                let state = args::parse_value("valve state", words[2])?;
+
+                self.send(Command::SetValve { valve, state });
+            }
+            "spark" => {
+                if words.len() < 2 {
+                    return Err(ArgError::Missing("spark <seconds>"));
+                }
+
+                let duration: f32 = args::parse_value("spark duration", words[1])?;
+
+                if !duration.is_finite() || duration < 0.0 {
+                    return Err(ArgError::BadValue {
+                        name: "spark duration",
+                        value: words[1].to_string(),
+                    });
+                }
+
+                self.send(Command::SetSparking(duration));
+            }
+            "sensor" => {
+                let sensors = match words.get(1) {
+                    Some(name) => vec![args::parse_sensor(name)?],
+                    None => ECU_SENSORS.to_vec(),
+                };
+
+                match &self.telemetry {
+                    Some(telemetry) => {
+                        for sensor in sensors {
+                            println!(
+                                "{:?}: {}",
+                                sensor, telemetry.ecu_data.sensor_states[sensor as usize]
+                            );
+                        }
+                    }
+                    None => println!("No telemetry received yet"),
+                }
+            }
+            "fire" => {
+                let seconds = match words.get(1) {
+                    Some(value) => args::parse_value("countdown", value)?,
+                    None => DEFAULT_COUNTDOWN,
+                };
+
+                if !seconds.is_finite() || seconds < 0.0 {
+                    return Err(ArgError::BadValue {
+                        name: "countdown",
+                        value: words[1].to_string(),
+                    });
+                }
+
+                match self.countdown(seconds) {
+                    Ok(Countdown::Fired) => println!("Fired!"),
+                    Ok(Countdown::Aborted(reason)) => {
+                        println!("Countdown aborted: {}", reason);
+                        self.abort();
+                    }
+                    Err(err) => {
+                        println!("Countdown aborted, terminal error: {}", err);
+                        self.abort();
+                    }
+                }
+            }
+            "record" => {
+                if words.len() < 2 {
+                    return Err(ArgError::Missing("record <on|off>"));
+                }
+
+                let recording = args::parse_switch("recording", words[1])?;
+
+                self.send(Command::SetRecording(recording));
+            }
+            "arm" => {
+                println!("Arming! Hazardous commands will be accepted until disarmed or timed out");
+                self.send(Command::Arm {
+                    code: ARM_CONFIRMATION_CODE,
+                });
+            }
+            "disarm" => {
+                self.send(Command::Disarm);
+            }
+            "transfer" => {
+                self.send(Command::TransferData);
+            }
+            "abort" => self.abort(),
+            "history" => {
+                for (index, line) in self.history.iter().enumerate() {
+                    println!("{:>4}  {}", index + 1, line);
+                }
+            }
+            "help" => println!("{}", HELP),
+            command => return Err(ArgError::UnknownCommand(command.to_string())),
+        }
+
+        Ok(())
+    }
+
+    /// Sends a command, reporting rather than exiting if it fails
+    fn send(&mut self, command: Command) -> bool {
+        match transmit_command(self.comms, command) {
+            Ok(()) => true,
+            Err(CommandError::Nak(reason)) => {
+                println!("Command rejected: {:?}", reason);
+                false
+            }
+            Err(CommandError::NoReply) => {
+                println!("No reply after {} attempts", MAX_ATTEMPTS);
+                false
+            }
+        }
+    }
+
+    fn abort(&mut self) {
+        println!("ABORTING");
+
+        if !self.send(Command::Abort) {
+            println!("ECU didn't acknowledge the abort!");
+        }
+    }
+
+    /// Counts down to zero on a single line, then fires. The ECU is armed and recording starts
+    /// `RECORDING_LEAD` seconds before that, so a long hold can't outlast the arming.
+    fn countdown(&mut self, seconds: f32) -> crossterm::Result<Countdown> {
+        let mut remaining = seconds;
+        let mut held = false;
+        let mut recording = false;
+        let mut last_tick = Instant::now();
+
+        let mut raw_mode = Some(RawMode::enable()?);
+
+        loop {
+            if !held {
+                remaining -= last_tick.elapsed().as_secs_f32();
+            }
+            last_tick = Instant::now();
+
+            if !recording && remaining <= RECORDING_LEAD {
+                // Commands print, so let them print normally
+                drop(raw_mode.take());
+                println!();
+
+                println!("Arming");
+                if !self.send(Command::Arm {
+                    code: ARM_CONFIRMATION_CODE,
+                }) {
+                    return Ok(Countdown::Aborted("arming failed".to_string()));
+                }
+
+                if !self.send(Command::SetRecording(true)) {
+                    return Ok(Countdown::Aborted("recording didn't start".to_string()));
+                }
+
+                recording = true;
+                raw_mode = Some(RawMode::enable()?);
+            }
+
+            if remaining <= 0.0 {
+                drop(raw_mode);
+                println!();
+
+                return if self.send(Command::FireIgniter) {
+                    Ok(Countdown::Fired)
+                } else {
+                    Ok(Countdown::Aborted("fire command failed".to_string()))
+                };
+            }
+
+            let status = if held {
+                format!(
+                    "HOLD at T-{:.1}s (r resumes, Esc aborts)",
+                    remaining.max(0.0)
+                )
+            } else {
+                format!("T-{:.1}s (h holds, Esc aborts)", remaining.max(0.0))
+            };
+            redraw(&status, "")?;
+
+            if event::poll(KEY_POLL_TIMEOUT)? {
+                if let Event::Key(key) = event::read()? {
+                    match key.code {
+                        KeyCode::Esc => return Ok(Countdown::Aborted("operator".to_string())),
+                        KeyCode::Char('c') if key.modifiers.contains(KeyModifiers::CONTROL) => {
+                            return Ok(Countdown::Aborted("operator".to_string()))
+                        }
+                        KeyCode::Char('h') | KeyCode::Char('H') => held = true,
+                        KeyCode::Char('r') | KeyCode::Char('R') => held = false,
+                        _ => {}
+                    }
+                }
+            }
+
+            if let Some(reason) = self.poll_comms() {
+                return Ok(Countdown::Aborted(reason));
+            }
+        }
+    }
+
+    /// Reads a line with history and tab completion, checking the network while the operator
+    /// types
+    fn read_line(&mut self) -> crossterm::Result<Input> {
+        let _raw_mode = RawMode::enable()?;
+
+        let mut line = String::new();
+        // Where the operator is in the history, `history.len()` being the line being typed
+        let mut history_index = self.history.len();
+        let mut draft = String::new();
+
+        redraw(PROMPT, &line)?;
+
+        loop {
+            if event::poll(KEY_POLL_TIMEOUT)? {
+                if let Event::Key(KeyEvent { code, modifiers }) = event::read()? {
+                    let control = modifiers.contains(KeyModifiers::CONTROL);
+
+                    match code {
+                        KeyCode::Esc => {
+                            print!("\r\n");
+                            return Ok(Input::Abort);
+                        }
+                        KeyCode::Enter => {
+                            print!("\r\n");
+                            return Ok(Input::Line(line.trim().to_string()));
+                        }
+                        KeyCode::Char('c') | KeyCode::Char('d') if control => {
+                            if line.is_empty() {
+                                print!("\r\n");
+                                return Ok(Input::Quit);
+                            }
+
+                            line.clear();
+                        }
+                        KeyCode::Char(character) if !control => line.push(character),
+                        KeyCode::Backspace => {
+                            line.pop();
+                        }
+                        KeyCode::Tab => {
+                            let candidates = complete(&mut line);
+
+                            if candidates.len() > 1 {
+                                print!("\r\n{}\r\n", candidates.join("  "));
+                            }
+                        }
+                        KeyCode::Up if history_index > 0 => {
+                            if history_index == self.history.len() {
+                                draft = line.clone();
+                            }
+
+                            history_index -= 1;
+                            line = self.history[history_index].clone();
+                        }
+                        KeyCode::Down if history_index < self.history.len() => {
+                            history_index += 1;
+
+                            line = if history_index == self.history.len() {
+                                draft.clone()
+                            } else {
+                                self.history[history_index].clone()
+                            };
+                        }
+                        _ => {}
+                    }
+
+                    redraw(PROMPT, &line)?;
+                }
+            }
+
+            if let Some(reason) = self.poll_comms() {
+                print!("\r\n{}\r\n", reason);
+                redraw(PROMPT, &line)?;
+            }
+        }
+    }
+
+    /// Keeps the latest telemetry, returning why if the ECU reported aborting
+    fn poll_comms(&mut self) -> Option<String> {
+        let start = Instant::now();
+
+        while start.elapsed() < COMMS_POLL_TIME {
+            match self.comms.receive() {
+                Some((Packet::ECUTelemtry(telemetry), _)) => self.telemetry = Some(telemetry),
+                Some((Packet::ControllerAborted { reason, .. }, _)) => {
+                    return Some(format!("ECU aborted: {:?}", reason));
+                }
+                Some(_) => {}
+                None => break,
+            }
+        }
+
+        None
+    }
+}
+
+/// Completes the last word of `line` in place, returning every candidate it could've been
+fn complete(line: &mut String) -> Vec<String> {
+    let word_start = line.rfind(' ').map_or(0, |index| index + 1);
+    let word = &line[word_start..];
+
+    let candidates: Vec<String> = if word_start == 0 {
+        COMMANDS.iter().map(|command| command.to_string()).collect()
+    } else {
+        let words: Vec<&str> = line[0..word_start].split_whitespace().collect();
+
+        match words.as_slice() {
+            ["valve"] => args::valve_names(),
+            ["sensor"] => args::sensor_names(),
+            ["record"] => vec!["on".to_string(), "off".to_string()],
+            _ => Vec::new(),
+        }
+    };
+
+    let matches: Vec<String> = candidates
+        .into_iter()
+        .filter(|candidate| {
+            candidate.len() >= word.len() && candidate[0..word.len()].eq_ignore_ascii_case(word)
+        })
+        .collect();
+
+    match matches.as_slice() {
+        [] => {}
+        [only] => {
+            line.truncate(word_start);
+            line.push_str(only);
+            line.push(' ');
+        }
+        [first, rest @ ..] => {
+            let common = rest.iter().fold(first.len(), |common, candidate| {
+                first
+                    .chars()
+                    .zip(candidate.chars())
+                    .take(common)
+                    .take_while(|(a, b)| a.eq_ignore_ascii_case(b))
+                    .count()
+            });
+
+            if common > word.len() {
+                line.truncate(word_start);
+                line.push_str(&first[0..common]);
+            }
+        }
+    }
+
+    matches
+}
+
+fn redraw(prompt: &str, line: &str) -> crossterm::Result<()> {
+    let mut stdout = stdout();
+
+    queue!(stdout, terminal::Clear(terminal::ClearType::CurrentLine))?;
+    write!(stdout, "\r{}{}", prompt, line)?;
+    stdout.flush()?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::SocketAddr;
+
+    use comms_udp::UDPCommsConfig;
+    use hal::comms_hal::NetworkAddress;
+
+    use super::*;
+
+    /// Nothing is listening on the other end, so this only works for lines that never get as far
+    /// as sending anything
+    fn execute(line: &str) -> Result<(), ArgError> {
+        let mut comms = UDPComms::new(UDPCommsConfig {
+            address: NetworkAddress::MissionControl,
+            local: SocketAddr::from(([127, 0, 0, 1], 0)),
+            remote: SocketAddr::from(([127, 0, 0, 1], 0)),
+            read_timeout: Some(Duration::from_millis(1)),
+        })
+        .unwrap();
+
+        Console::new(&mut comms).execute(line)
+    }
+
+    #[test]
+    fn bad_lines_are_rejected() {
+        for (line, message) in [
+            ("valve", "Not enough args. Format: valve <name> <state>"),
+            (
+                "valve FuelPress",
+                "Not enough args. Format: valve <name> <state>",
+            ),
+            ("valve lox_main 255", "Unknown valve lox_main"),
+            ("valve FuelPress 256", "Invalid valve state: 256"),
+            ("spark", "Not enough args. Format: spark <seconds>"),
+            ("spark -1", "Invalid spark duration: -1"),
+            ("spark inf", "Invalid spark duration: inf"),
+            ("sensor tank", "Unknown sensor tank"),
+            ("fire -5", "Invalid countdown: -5"),
+            ("record maybe", "Invalid recording: maybe"),
+            ("launch now", "Unknown command launch, type help for a list"),
+        ] {
+            match execute(line) {
+                Err(err) => assert!(err.to_string().starts_with(message), "{}: {}", line, err),
+                Ok(()) => panic!("{} wasn't rejected", line),
+            }
+        }
+    }
+
+    #[test]
+    fn commands_complete() {
+        let mut line = "va".to_string();
+        assert!(complete(&mut line) == ["valve"]);
+        assert!(line == "valve ");
+
+        let mut line = "launch".to_string();
+        assert!(complete(&mut line).is_empty());
+        assert!(line == "launch");
+    }
+
+    #[test]
+    fn valves_and_sensors_complete() {
+        let mut line = "valve ".to_string();
+        assert!(complete(&mut line) == args::valve_names());
+        assert!(line == "valve ");
+
+        // Completes as far as every match agrees, ignoring case
+        let mut line = "valve i".to_string();
+        assert!(complete(&mut line) == ["IgniterFuelMain", "IgniterGOxMain"]);
+        assert!(line == "valve Igniter");
+
+        let mut line = "sensor fuelt".to_string();
+        assert!(complete(&mut line) == ["FuelTankPressure"]);
+        assert!(line == "sensor FuelTankPressure ");
+
+        let mut line = "sensor ".to_string();
+        assert!(complete(&mut line).len() == ECU_SENSORS.len());
+
+        // Only the first argument completes
+        let mut line = "valve FuelPress 2".to_string();
+        assert!(complete(&mut line).is_empty());
+    }
+}
@@ -1,90 +1,180 @@
-use std::{env, net::UdpSocket, thread, time::Duration};
+use std::{
+    env, process,
+    sync::{
+        atomic::{AtomicU16, Ordering},
+        Mutex, OnceLock,
+    },
+    thread,
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
 
+use comms_udp::{config::NetworkOptions, UDPComms, UDPCommsConfig};
 use hal::{
     comms_hal::{
-        comms_ethernet_hal::{self, ETHERNET_BUFFER_SIZE},
-        NetworkAddress, Packet,
+        Command, CommsInterface, ControllerKind, NakReason, NetworkAddress, Packet,
+        ARM_CONFIRMATION_CODE, HEARTBEAT_RATE, PROTOCOL_VERSION,
     },
-    ecu_hal::{ECUValve, ECU_VALVES},
+    ecu_hal::ECU_VALVES,
 };
+use sequence::{Runner, Sequence};
+
+use crate::{args::ArgError, console::Console};
+
+mod args;
+mod console;
+mod sequence;
+
+/// How long to wait for an `Ack` or `Nak` before sending the command again
+const REPLY_TIMEOUT: Duration = Duration::from_millis(250);
+const MAX_ATTEMPTS: u32 = 4;
+
+const EXIT_USAGE: i32 = 2;
+const EXIT_NAK: i32 = 3;
+const EXIT_NO_REPLY: i32 = 4;
+const EXIT_PROTOCOL_MISMATCH: i32 = 5;
+const EXIT_SEQUENCE_ABORTED: i32 = 6;
+
+static NEXT_SEQ: AtomicU16 = AtomicU16::new(0);
+
+/// The controller everything's sent to, picked from the network config at startup
+static TARGET: OnceLock<NetworkAddress> = OnceLock::new();
+
+/// What the heartbeats are sent through. Taken when saying goodbye, so no heartbeat can follow it
+/// and start the ECU's comms loss watchdog again.
+static HEARTBEAT_COMMS: Mutex<Option<UDPComms>> = Mutex::new(None);
+
+enum CommandError {
+    Nak(NakReason),
+    NoReply,
+}
 
 fn main() {
-    let args: Vec<String> = env::args().collect();
+    let mut args: Vec<String> = env::args().collect();
+
+    let network = NetworkOptions::take_from(&mut args).unwrap_or_else(|err| {
+        println!("{}", err);
+        exit(EXIT_USAGE);
+    });
 
     if args.len() < 2 {
         println!("No args!");
-        return;
+        exit(EXIT_USAGE);
+    }
+
+    let sequence = match args[1].as_str() {
+        "run" | "fire" => Some(load_sequence(&args)),
+        _ => None,
+    };
+
+    // Dry runs never touch the network
+    if let Some(sequence) = &sequence {
+        if args.iter().any(|arg| arg == "--dry-run") {
+            sequence.print_timeline();
+            return;
+        }
     }
 
-    let socket = UdpSocket::bind("0.0.0.0:25566").unwrap();
+    let config = network.load_config().unwrap_or_else(|err| {
+        println!("{}", err);
+        exit(EXIT_USAGE);
+    });
+
+    let controller = config
+        .select_controller(network.controller.as_deref())
+        .unwrap_or_else(|err| {
+            println!("{}", err);
+            exit(EXIT_USAGE);
+        });
+    if TARGET.set(controller.address).is_err() {
+        println!("Target controller was already picked");
+        exit(EXIT_USAGE);
+    }
+
+    let port = network.port.unwrap_or(config.ctrl_send_port);
+    let mut comms = UDPComms::new(UDPCommsConfig {
+        read_timeout: Some(Duration::from_millis(10)),
+        ..config.mission_control(port)
+    })
+    .unwrap_or_else(|err| {
+        println!("Couldn't bind to port {}: {}", port, err);
+        exit(EXIT_USAGE);
+    });
+
+    // The bridge only sends to ctrl-view's port until we tell it where we are
+    if let Err(err) = comms.announce() {
+        println!("Couldn't announce to the bridge: {}", err);
+        exit(EXIT_NO_REPLY);
+    }
+
+    // Start somewhere different every run, otherwise the ECU could take our first command for a
+    // retry of the last one the previous run sent. A clock set before the epoch still varies.
+    let nanos = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_else(|err| err.duration())
+        .subsec_nanos();
+    // Always fits after the modulo
+    NEXT_SEQ.store((nanos % u32::from(u16::MAX)) as u16, Ordering::Relaxed);
+
+    // Keep the ECU's comms loss watchdog fed for as long as we're connected
+    let heartbeat_comms = comms.try_clone().unwrap_or_else(|err| {
+        println!(
+            "Couldn't share the socket with the heartbeat thread: {}",
+            err
+        );
+        exit(EXIT_USAGE);
+    });
+    *HEARTBEAT_COMMS.lock().unwrap() = Some(heartbeat_comms);
+    thread::spawn(|| loop {
+        if let Some(heartbeat_comms) = HEARTBEAT_COMMS.lock().unwrap().as_mut() {
+            // A missed heartbeat or two is survivable, the main thread exits if sending keeps failing
+            if let Err(err) = heartbeat_comms.transmit(&Packet::Heartbeat, target()) {
+                println!("Couldn't send heartbeat, got {:?}", err);
+            }
+        }
+        thread::sleep(Duration::from_secs_f32(HEARTBEAT_RATE));
+    });
+
+    handshake(&mut comms, &controller.name);
 
     match args[1].as_str() {
         "valve" => {
             if args.len() < 4 {
-                println!("Not enough args. Format: valve <name> <value>");
-                return;
+                usage_error(ArgError::Missing("valve <name> <value>"));
             }
 
-            let valve = match args[2].as_str() {
-                "fuel_main" => Some(ECUValve::IgniterFuelMain),
-                "gox_main" => Some(ECUValve::IgniterGOxMain),
-                "fuel_press" => Some(ECUValve::FuelPress),
-                "fuel_vent" => Some(ECUValve::FuelVent),
-                _ => None,
-            };
-
-            send_packet(
-                &socket,
-                Packet::SetValve {
-                    valve: valve.unwrap(),
-                    state: args[3].parse::<u8>().unwrap(),
-                },
-            );
+            let valve = args::parse_valve(&args[2]).unwrap_or_else(|err| usage_error(err));
+            let state =
+                args::parse_value("valve state", &args[3]).unwrap_or_else(|err| usage_error(err));
+
+            send_command(&mut comms, Command::SetValve { valve, state });
         }
         "testvalve" => {
             if args.len() < 4 {
-                println!("Not enough args. Format: testvalve <name> <delay>");
-                return;
+                usage_error(ArgError::Missing("testvalve <name> <delay>"));
             }
 
-            let valve = match args[2].as_str() {
-                "fuel_main" => Some(ECUValve::IgniterFuelMain),
-                "gox_main" => Some(ECUValve::IgniterGOxMain),
-                "fuel_press" => Some(ECUValve::FuelPress),
-                "fuel_vent" => Some(ECUValve::FuelVent),
-                _ => None,
-            };
-
-            send_packet(
-                &socket,
-                Packet::SetValve {
-                    valve: valve.unwrap(),
-                    state: 255,
-                },
-            );
+            let valve = args::parse_valve(&args[2]).unwrap_or_else(|err| usage_error(err));
+            let delay = args::parse_value("delay", &args[3]).unwrap_or_else(|err| usage_error(err));
 
-            std::thread::sleep(std::time::Duration::from_secs_f32(args[3].parse().unwrap()));
+            send_command(&mut comms, Command::SetValve { valve, state: 255 });
 
-            send_packet(
-                &socket,
-                Packet::SetValve {
-                    valve: valve.unwrap(),
-                    state: 0,
-                },
-            );
+            std::thread::sleep(std::time::Duration::from_secs_f32(delay));
+
+            send_command(&mut comms, Command::SetValve { valve, state: 0 });
         }
         "testvalves" => {
             if args.len() != 2 {
                 println!("Too many args. Format: testvalves");
+                exit(EXIT_USAGE);
             }
 
             for valve in &ECU_VALVES {
                 println!("Testing {:?}", valve);
                 std::thread::sleep(std::time::Duration::from_secs_f32(1.0));
 
-                send_packet(
-                    &socket,
-                    Packet::SetValve {
+                send_command(
+                    &mut comms,
+                    Command::SetValve {
                         valve: *valve,
                         state: 255,
                     },
@@ -92,9 +182,9 @@ fn main() {
 
                 std::thread::sleep(std::time::Duration::from_secs_f32(1.0));
 
-                send_packet(
-                    &socket,
-                    Packet::SetValve {
+                send_command(
+                    &mut comms,
+                    Command::SetValve {
                         valve: *valve,
                         state: 0,
                     },
@@ -103,48 +193,241 @@ fn main() {
         }
         "testspark" => {
             if args.len() < 3 {
-                println!("Not enough args. Format: testspark <duration>");
-                return;
+                usage_error(ArgError::Missing("testspark <duration>"));
             }
 
-            send_packet(&socket, Packet::SetSparking(args[2].parse().unwrap()));
-        }
-        "fire" => {
-            println!("Firing in T-5s");
-
-            for i in 0..5 {
-                thread::sleep(Duration::from_secs(1));
-                println!("T-{}s", 4 - i);
+            let duration =
+                args::parse_value("duration", &args[2]).unwrap_or_else(|err| usage_error(err));
 
-                if 4 - i == 2 {
-                    send_packet(&socket, Packet::SetRecording(true));
-                }
+            send_command(&mut comms, Command::SetSparking(duration));
+        }
+        "fire" | "run" => run_sequence(&mut comms, &sequence.unwrap()),
+        "console" => {
+            if let Err(err) = Console::new(&mut comms).run() {
+                println!("Console stopped: {}", err);
             }
-
-            send_packet(&socket, Packet::FireIgniter);
+        }
+        "arm" => {
+            println!("Arming! Hazardous commands will be accepted until disarmed or timed out");
+            send_command(
+                &mut comms,
+                Command::Arm {
+                    code: ARM_CONFIRMATION_CODE,
+                },
+            );
+        }
+        "disarm" => {
+            println!("Disarming");
+            send_command(&mut comms, Command::Disarm);
         }
         "transfer" => {
             println!("Transfering!");
-            send_packet(&socket, Packet::TransferData);
+            send_command(&mut comms, Command::TransferData);
         }
         _ => {
             println!("Unknown command");
+            exit(EXIT_USAGE);
+        }
+    }
+
+    exit(0);
+}
+
+/// Says goodbye first if the ECU has been sent heartbeats, otherwise it would safe itself once
+/// they stop
+fn exit(code: i32) -> ! {
+    let heartbeat_comms = HEARTBEAT_COMMS
+        .lock()
+        .ok()
+        .and_then(|mut heartbeat_comms| heartbeat_comms.take());
+
+    if let Some(mut comms) = heartbeat_comms {
+        // Already exiting, so there's nothing more to do if it fails
+        let _ = comms.transmit(&Packet::Goodbye, target());
+    }
+
+    process::exit(code);
+}
+
+fn load_sequence(args: &[String]) -> Sequence {
+    let sequence = if args[1] == "fire" {
+        Sequence::parse(sequence::FIRE_SEQUENCE)
+    } else {
+        match args.get(2) {
+            Some(path) if path != "--dry-run" => Sequence::load(path),
+            _ => {
+                println!("Not enough args. Format: run <sequence file> [--dry-run]");
+                exit(EXIT_USAGE);
+            }
+        }
+    };
+
+    sequence.unwrap_or_else(|err| {
+        println!("{}", err);
+        exit(EXIT_USAGE);
+    })
+}
+
+fn usage_error(err: ArgError) -> ! {
+    println!("{}", err);
+    exit(EXIT_USAGE);
+}
+
+/// Runs a sequence, aborting the ECU if anything goes wrong along the way
+fn run_sequence(comms: &mut UDPComms, sequence: &Sequence) {
+    let result = Runner::new(comms).run(sequence);
+
+    if let Err(abort) = result {
+        println!("Sequence aborted: {}", abort);
+
+        if transmit_command(comms, Command::Abort).is_err() {
+            println!("ECU didn't acknowledge the abort!");
+        }
+
+        exit(EXIT_SEQUENCE_ABORTED);
+    }
+
+    println!("Sequence complete");
+}
+
+/// Sends a command and waits for the ECU to answer it, exiting if it's rejected or never answered
+fn send_command(comms: &mut UDPComms, command: Command) {
+    match transmit_command(comms, command) {
+        Ok(()) => {}
+        Err(CommandError::Nak(reason)) => {
+            println!("Command rejected: {:?}", reason);
+            exit(EXIT_NAK);
+        }
+        Err(CommandError::NoReply) => {
+            println!("No reply after {} attempts", MAX_ATTEMPTS);
+            exit(EXIT_NO_REPLY);
         }
     }
 }
 
-fn send_packet(socket: &UdpSocket, packet: Packet) {
-    let mut buffer = [0_u8; ETHERNET_BUFFER_SIZE];
+fn transmit_command(comms: &mut UDPComms, command: Command) -> Result<(), CommandError> {
+    transmit_command_with(comms, command, |_| {})
+}
+
+/// Same as `transmit_command`, but hands every packet that isn't the reply to `on_other` rather
+/// than dropping it
+fn transmit_command_with<F>(
+    comms: &mut UDPComms,
+    command: Command,
+    mut on_other: F,
+) -> Result<(), CommandError>
+where
+    F: FnMut(&Packet),
+{
+    let seq = NEXT_SEQ.fetch_add(1, Ordering::Relaxed);
+    let packet = Packet::Command { seq, command };
+
+    // Retries reuse the same seq, so the ECU only runs the command once
+    for attempt in 0..MAX_ATTEMPTS {
+        transmit_packet(comms, &packet);
 
-    let len = comms_ethernet_hal::serialize_packet(
-        &packet,
-        NetworkAddress::MissionControl,
-        NetworkAddress::EngineController(0),
-        &mut buffer,
-    )
-    .unwrap();
+        if attempt == 0 {
+            println!("Sending packet {:?}", packet);
+        } else {
+            println!("Retrying seq {}", seq);
+        }
+
+        if let Some(result) = wait_for_reply(comms, seq, &mut on_other) {
+            return result;
+        }
+    }
+
+    Err(CommandError::NoReply)
+}
 
-    println!("Sending packet {:?} {:?}", packet, &buffer[6..len]);
+/// Makes sure the ECU speaks the same protocol before sending it anything it could misread
+fn handshake(comms: &mut UDPComms, name: &str) {
+    let hello = Packet::hello(ControllerKind::MissionControl, env!("CARGO_PKG_VERSION"));
+
+    for _ in 0..MAX_ATTEMPTS {
+        transmit_packet(comms, &hello);
+
+        let reply = wait_for_packet(comms, |packet| match packet {
+            Packet::Hello {
+                protocol_version,
+                build_id,
+                ..
+            } => Some((*protocol_version, *build_id)),
+            _ => None,
+        });
+
+        if let Some((protocol_version, build_id)) = reply {
+            if protocol_version != PROTOCOL_VERSION {
+                println!(
+                    "ECU uses protocol version {}, expected {}. Update ctrl-send or the ECU",
+                    protocol_version, PROTOCOL_VERSION
+                );
+                exit(EXIT_PROTOCOL_MISMATCH);
+            }
+
+            println!(
+                "Connected to {} {}",
+                name,
+                String::from_utf8_lossy(&build_id).trim_end_matches('\0')
+            );
+            return;
+        }
+    }
+
+    println!(
+        "{} didn't answer hello after {} attempts",
+        name, MAX_ATTEMPTS
+    );
+    exit(EXIT_NO_REPLY);
+}
+
+fn wait_for_reply<F>(
+    comms: &mut UDPComms,
+    seq: u16,
+    on_other: &mut F,
+) -> Option<Result<(), CommandError>>
+where
+    F: FnMut(&Packet),
+{
+    wait_for_packet(comms, |packet| match packet {
+        Packet::Ack { seq: ack_seq } if *ack_seq == seq => Some(Ok(())),
+        Packet::Nak {
+            seq: nak_seq,
+            reason,
+        } if *nak_seq == seq => Some(Err(CommandError::Nak(*reason))),
+        _ => {
+            on_other(packet);
+            None
+        }
+    })
+}
+
+/// Listens for up to `REPLY_TIMEOUT` for a packet `matcher` picks out
+fn wait_for_packet<T, F>(comms: &mut UDPComms, mut matcher: F) -> Option<T>
+where
+    F: FnMut(&Packet) -> Option<T>,
+{
+    let start = Instant::now();
+
+    while start.elapsed() < REPLY_TIMEOUT {
+        if let Some((packet, _)) = comms.receive() {
+            if let Some(result) = matcher(&packet) {
+                return Some(result);
+            }
+        }
+    }
+
+    None
+}
+
+/// Sends a packet to the target controller, exiting if it can't be sent
+fn transmit_packet(comms: &mut UDPComms, packet: &Packet) {
+    if let Err(err) = comms.transmit(packet, target()) {
+        println!("Couldn't send {:?}, got {:?}", packet, err);
+        exit(EXIT_NO_REPLY);
+    }
+}
 
-    socket.send_to(&buffer[0..len], "10.0.0.5:8888").unwrap();
+fn target() -> NetworkAddress {
+    *TARGET.get().unwrap_or(&NetworkAddress::EngineController(0))
 }
@@ -0,0 +1,433 @@
+use std::{
+    fmt, fs,
+    io::{self, Write},
+    sync::mpsc::{self, Receiver},
+    thread,
+    time::{Duration, Instant},
+};
+
+use comms_udp::UDPComms;
+use hal::{
+    comms_hal::{AbortReason, Command, CommsInterface, ECUTelemtryData, Packet},
+    ecu_hal::{ECUSensor, IgnitionResult},
+};
+use serde::Deserialize;
+
+use crate::{transmit_command_with, CommandError};
+
+/// The countdown `ctrl-send fire` runs
+pub const FIRE_SEQUENCE: &str = include_str!("../sequences/fire.ron");
+
+/// A list of steps run one after the other. Loaded from RON files like `sequences/fire.ron`.
+#[derive(Debug, Clone, Deserialize)]
+pub struct Sequence {
+    pub name: String,
+    pub steps: Vec<Step>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub enum Step {
+    /// Sends a command and waits for the ECU to acknowledge it
+    Send(Command),
+    /// Waits this many seconds before moving on
+    Wait(f32),
+    /// Prints a message, for calling out the countdown
+    Print(String),
+    /// Stops the clock until the operator presses enter
+    Hold(String),
+    /// Asks the operator a yes or no question, aborting the sequence unless they answer yes
+    Confirm(String),
+    /// From this step on, aborts the sequence as soon as the condition is met
+    AbortIf(AbortCondition),
+}
+
+#[derive(Debug, Clone, Copy, Deserialize)]
+pub enum AbortCondition {
+    /// The ECU aborted on its own
+    ControllerAborted,
+    /// The ECU's telemetry reports this ignition result
+    IgnitionResult(IgnitionResult),
+    /// A raw sensor reading went above `raw`
+    SensorAbove { sensor: ECUSensor, raw: u16 },
+    /// A raw sensor reading went below `raw`
+    SensorBelow { sensor: ECUSensor, raw: u16 },
+    /// No telemetry arrived for this many seconds
+    TelemetryLoss(f32),
+}
+
+#[derive(Debug)]
+pub enum SequenceError {
+    Io(io::Error),
+    Parse(ron::Error),
+    /// A step's parameters don't make sense, e.g. a negative wait
+    InvalidStep {
+        index: usize,
+        step: Step,
+    },
+}
+
+/// Why a running sequence stopped early
+pub enum SequenceAbort {
+    Condition(AbortCondition),
+    OperatorDeclined(String),
+    CommandFailed(Command, CommandError),
+}
+
+impl Sequence {
+    /// # Errors
+    /// Returns a `SequenceError` if the file can't be read or isn't a valid sequence
+    pub fn load(path: &str) -> Result<Sequence, SequenceError> {
+        let text = fs::read_to_string(path).map_err(SequenceError::Io)?;
+
+        Sequence::parse(&text)
+    }
+
+    /// # Errors
+    /// Returns a `SequenceError` if `text` isn't a valid sequence
+    pub fn parse(text: &str) -> Result<Sequence, SequenceError> {
+        let sequence: Sequence = ron::from_str(text).map_err(SequenceError::Parse)?;
+
+        for (index, step) in sequence.steps.iter().enumerate() {
+            let valid = match step {
+                Step::Wait(duration) => duration.is_finite() && *duration >= 0.0,
+                Step::AbortIf(AbortCondition::TelemetryLoss(timeout)) => {
+                    timeout.is_finite() && *timeout > 0.0
+                }
+                _ => true,
+            };
+
+            if !valid {
+                return Err(SequenceError::InvalidStep {
+                    index,
+                    step: step.clone(),
+                });
+            }
+        }
+
+        Ok(sequence)
+    }
+
+    /// Prints when each step runs, assuming commands are acknowledged straight away and the
+    /// operator never keeps the clock held
+    pub fn print_timeline(&self) {
+        // Nothing useful can be done if stdout is gone
+        let _ = self.write_timeline(&mut io::stdout());
+    }
+
+    fn write_timeline<W: Write>(&self, out: &mut W) -> io::Result<()> {
+        writeln!(out, "Sequence: {}", self.name)?;
+
+        let mut time = 0.0;
+
+        for step in self.steps.iter() {
+            match step {
+                Step::Wait(duration) => {
+                    time += duration;
+                    continue;
+                }
+                Step::Send(command) => writeln!(out, "  T+{:>8.3}s  Send {:?}", time, command)?,
+                Step::Print(message) => writeln!(out, "  T+{:>8.3}s  Print \"{}\"", time, message)?,
+                Step::Hold(message) => {
+                    writeln!(out, "  T+{:>8.3}s  Hold \"{}\" until enter", time, message)?
+                }
+                Step::Confirm(question) => {
+                    writeln!(out, "  T+{:>8.3}s  Confirm \"{}\"", time, question)?
+                }
+                Step::AbortIf(condition) => {
+                    writeln!(out, "  T+{:>8.3}s  Abort if {:?}", time, condition)?
+                }
+            }
+        }
+
+        writeln!(out, "  T+{:>8.3}s  End", time)
+    }
+}
+
+impl fmt::Display for SequenceError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SequenceError::Io(err) => write!(f, "Couldn't read sequence: {}", err),
+            SequenceError::Parse(err) => write!(f, "Couldn't parse sequence: {}", err),
+            SequenceError::InvalidStep { index, step } => {
+                write!(f, "Step {} is invalid: {:?}", index + 1, step)
+            }
+        }
+    }
+}
+
+impl fmt::Display for SequenceAbort {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SequenceAbort::Condition(condition) => write!(f, "{:?}", condition),
+            SequenceAbort::OperatorDeclined(question) => {
+                write!(f, "Operator declined \"{}\"", question)
+            }
+            SequenceAbort::CommandFailed(command, CommandError::Nak(reason)) => {
+                write!(f, "{:?} rejected: {:?}", command, reason)
+            }
+            SequenceAbort::CommandFailed(command, CommandError::NoReply) => {
+                write!(f, "{:?} wasn't acknowledged", command)
+            }
+        }
+    }
+}
+
+/// Runs sequences against the ECU, keeping an eye on its telemetry for abort conditions whenever
+/// it isn't busy sending a command
+pub struct Runner<'a> {
+    comms: &'a mut UDPComms,
+    operator: Receiver<String>,
+    conditions: Vec<AbortCondition>,
+    ecu: EcuObserver,
+}
+
+/// What's been heard from the ECU, kept apart from the comms so it can be updated while a command
+/// is waiting for its reply
+struct EcuObserver {
+    telemetry: Option<ECUTelemtryData>,
+    last_telemetry: Instant,
+    controller_aborted: Option<AbortReason>,
+}
+
+impl<'a> Runner<'a> {
+    pub fn new(comms: &'a mut UDPComms) -> Runner<'a> {
+        let (operator_tx, operator) = mpsc::channel();
+
+        // Read lines on their own thread so telemetry keeps being checked while the operator
+        // makes up their mind
+        thread::spawn(move || loop {
+            let mut line = String::new();
+
+            match io::stdin().read_line(&mut line) {
+                Ok(0) | Err(_) => break,
+                Ok(_) => {
+                    if operator_tx.send(line.trim().to_string()).is_err() {
+                        break;
+                    }
+                }
+            }
+        });
+
+        Runner {
+            comms,
+            operator,
+            conditions: Vec::new(),
+            ecu: EcuObserver {
+                telemetry: None,
+                last_telemetry: Instant::now(),
+                controller_aborted: None,
+            },
+        }
+    }
+
+    /// # Errors
+    /// Returns why the sequence was aborted, after which the caller should safe the ECU
+    pub fn run(&mut self, sequence: &Sequence) -> Result<(), SequenceAbort> {
+        println!("Running sequence: {}", sequence.name);
+
+        let start = Instant::now();
+
+        for step in sequence.steps.iter() {
+            self.check_conditions()?;
+
+            match step {
+                Step::Send(command) => {
+                    println!(
+                        "T+{:.3}s Sending {:?}",
+                        start.elapsed().as_secs_f32(),
+                        command
+                    );
+
+                    let ecu = &mut self.ecu;
+                    let result = transmit_command_with(self.comms, command.clone(), |packet| {
+                        ecu.on_packet(packet)
+                    });
+
+                    if let Err(err) = result {
+                        // The ECU aborting is the better explanation for why it rejected this
+                        self.check_conditions()?;
+
+                        return Err(SequenceAbort::CommandFailed(command.clone(), err));
+                    }
+                }
+                Step::Wait(duration) => {
+                    let wait_start = Instant::now();
+
+                    while wait_start.elapsed().as_secs_f32() < *duration {
+                        self.poll()?;
+                    }
+                }
+                Step::Print(message) => println!("{}", message),
+                Step::Hold(message) => {
+                    println!("HOLD: {} (press enter to continue)", message);
+                    self.wait_for_operator()?;
+                }
+                Step::Confirm(question) => {
+                    println!("{} [y/N]", question);
+                    let answer = self.wait_for_operator()?;
+
+                    if !answer.eq_ignore_ascii_case("y") && !answer.eq_ignore_ascii_case("yes") {
+                        return Err(SequenceAbort::OperatorDeclined(question.clone()));
+                    }
+                }
+                Step::AbortIf(condition) => {
+                    // Telemetry loss counts from when the condition is armed
+                    if let AbortCondition::TelemetryLoss(_) = condition {
+                        self.ecu.last_telemetry = Instant::now();
+                    }
+
+                    self.conditions.push(*condition);
+                }
+            }
+        }
+
+        self.check_conditions()
+    }
+
+    fn wait_for_operator(&mut self) -> Result<String, SequenceAbort> {
+        // Anything typed before the prompt was shown doesn't count
+        while self.operator.try_recv().is_ok() {}
+
+        loop {
+            if let Ok(line) = self.operator.try_recv() {
+                return Ok(line);
+            }
+
+            self.poll()?;
+        }
+    }
+
+    /// Handles at most one incoming packet, then checks the abort conditions
+    fn poll(&mut self) -> Result<(), SequenceAbort> {
+        if let Some((packet, _)) = self.comms.receive() {
+            self.ecu.on_packet(&packet);
+        }
+
+        self.check_conditions()
+    }
+
+    fn check_conditions(&self) -> Result<(), SequenceAbort> {
+        for condition in self.conditions.iter() {
+            if self.ecu.is_met(condition) {
+                return Err(SequenceAbort::Condition(*condition));
+            }
+        }
+
+        Ok(())
+    }
+}
+
+impl EcuObserver {
+    fn on_packet(&mut self, packet: &Packet) {
+        match packet {
+            Packet::ECUTelemtry(telemetry) => {
+                self.telemetry = Some(telemetry.clone());
+                self.last_telemetry = Instant::now();
+            }
+            Packet::ControllerAborted { reason, .. } => {
+                self.controller_aborted = Some(*reason);
+            }
+            _ => {}
+        }
+    }
+
+    fn is_met(&self, condition: &AbortCondition) -> bool {
+        match condition {
+            AbortCondition::ControllerAborted => self.controller_aborted.is_some(),
+            AbortCondition::IgnitionResult(result) => self
+                .telemetry
+                .as_ref()
+                .is_some_and(|telemetry| telemetry.ignition_result == *result),
+            AbortCondition::SensorAbove { sensor, raw } => self
+                .telemetry
+                .as_ref()
+                .is_some_and(|telemetry| telemetry.ecu_data.sensor_states[*sensor as usize] > *raw),
+            AbortCondition::SensorBelow { sensor, raw } => self
+                .telemetry
+                .as_ref()
+                .is_some_and(|telemetry| telemetry.ecu_data.sensor_states[*sensor as usize] < *raw),
+            AbortCondition::TelemetryLoss(timeout) => {
+                self.last_telemetry.elapsed() > Duration::from_secs_f32(*timeout)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn bundled_sequences_parse() {
+        let fire = Sequence::parse(FIRE_SEQUENCE).unwrap();
+        assert!(fire
+            .steps
+            .iter()
+            .any(|step| matches!(step, Step::Send(Command::FireIgniter))));
+
+        Sequence::parse(include_str!("../sequences/hot-fire.ron")).unwrap();
+    }
+
+    #[test]
+    fn fire_sequence_arms_before_firing() {
+        let fire = Sequence::parse(FIRE_SEQUENCE).unwrap();
+
+        let position = |matcher: fn(&Step) -> bool| fire.steps.iter().position(matcher).unwrap();
+        let arm = position(|step| matches!(step, Step::Send(Command::Arm { .. })));
+        let fire_igniter = position(|step| matches!(step, Step::Send(Command::FireIgniter)));
+
+        assert!(arm < fire_igniter);
+    }
+
+    #[test]
+    fn invalid_steps_are_rejected() {
+        for (steps, index) in [
+            ("[Wait(-1.0)]", 0),
+            ("[Print(\"T-0s\"), Wait(inf)]", 1),
+            ("[AbortIf(TelemetryLoss(0.0))]", 0),
+        ] {
+            let text = format!("Sequence(name: \"Bad\", steps: {})", steps);
+
+            match Sequence::parse(&text) {
+                Err(SequenceError::InvalidStep {
+                    index: invalid_index,
+                    ..
+                }) => assert!(invalid_index == index, "{}", steps),
+                _ => panic!("{} wasn't rejected", steps),
+            }
+        }
+
+        assert!(matches!(
+            Sequence::parse("Sequence(name: \"Bad\", steps: [Jump(1)])"),
+            Err(SequenceError::Parse(_))
+        ));
+    }
+
+    #[test]
+    fn timeline_adds_up_waits() {
+        let sequence = Sequence::parse(
+            "Sequence(name: \"Test\", steps: [
+                AbortIf(ControllerAborted),
+                Wait(1.5),
+                Send(SetRecording(true)),
+                Wait(0.25),
+                Hold(\"Ready?\"),
+            ])",
+        )
+        .unwrap();
+
+        let mut out = Vec::new();
+        sequence.write_timeline(&mut out).unwrap();
+
+        assert!(
+            String::from_utf8(out).unwrap()
+                == "\
+Sequence: Test
+  T+   0.000s  Abort if ControllerAborted
+  T+   1.500s  Send SetRecording(true)
+  T+   1.750s  Hold \"Ready?\" until enter
+  T+   1.750s  End
+"
+        );
+    }
+}
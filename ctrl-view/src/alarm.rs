@@ -0,0 +1,588 @@
+use std::{fmt, fs, io, time::Instant};
+
+use hal::{
+    comms_hal::{AbortReason, ECUTelemtryData, NetworkAddress},
+    ecu_hal::{ECUSensor, ECUValve, IgniterState, ECU_SENSORS, ECU_VALVES},
+};
+use serde::{Deserialize, Serialize};
+
+use crate::calibration::Calibration;
+
+/// The alarms used unless ctrl-view is given `--alarms`
+pub const DEFAULT_ALARMS: &str = include_str!("../alarms.ron");
+
+/// How often the bell rings again while a critical alarm hasn't been acknowledged
+const BELL_PERIOD: f32 = 2.0;
+
+/// Limits and expected states that raise alarms. Loaded from RON files like `alarms.ron`.
+#[derive(Debug, Clone, Deserialize)]
+pub struct AlarmConfig {
+    #[serde(default)]
+    pub sensors: Vec<SensorLimits>,
+    #[serde(default)]
+    pub max_loop_time: Option<LoopTimeLimits>,
+    #[serde(default)]
+    pub valves: Vec<ValveRule>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct SensorLimits {
+    pub sensor: ECUSensor,
+    #[serde(default)]
+    pub warning: Limits,
+    #[serde(default)]
+    pub critical: Limits,
+}
+
+/// A reading below `min` or above `max` is outside the limits
+#[derive(Debug, Clone, Copy, Default, Deserialize)]
+pub struct Limits {
+    #[serde(default)]
+    pub min: Option<f32>,
+    #[serde(default)]
+    pub max: Option<f32>,
+}
+
+/// In seconds
+#[derive(Debug, Clone, Copy, Deserialize)]
+pub struct LoopTimeLimits {
+    pub warning: f32,
+    pub critical: f32,
+}
+
+/// The valve has to be open (or closed) whenever the igniter is in one of `states`
+#[derive(Debug, Clone, Deserialize)]
+pub struct ValveRule {
+    pub valve: ECUValve,
+    pub states: Vec<IgniterState>,
+    pub open: bool,
+}
+
+#[derive(Debug)]
+pub enum AlarmConfigError {
+    Io(io::Error),
+    Parse(ron::Error),
+    /// The sensor has limits more than once
+    Duplicate(ECUSensor),
+    /// The sensor's limits aren't finite numbers
+    Invalid(ECUSensor),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+pub enum Severity {
+    Warning,
+    Critical,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum AlarmId {
+    Sensor(ECUSensor),
+    LoopTime,
+    Valve(ECUValve),
+    Watchdog(String),
+    Aborted(NetworkAddress),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum AlarmTransition {
+    Raised,
+    Cleared,
+    Acknowledged,
+}
+
+/// An alarm changing state, which goes in the session
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct AlarmRecord {
+    pub transition: AlarmTransition,
+    pub severity: Severity,
+    pub message: String,
+}
+
+/// An alarm on the annunciator panel. Alarms stay there until they've both cleared and been
+/// acknowledged, so short ones can't be missed.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Alarm {
+    pub id: AlarmId,
+    pub severity: Severity,
+    pub message: String,
+    pub active: bool,
+    pub acknowledged: bool,
+    pub raised_at: Instant,
+}
+
+pub struct AlarmPanel {
+    config: AlarmConfig,
+    alarms: Vec<Alarm>,
+    /// Waiting to be taken with `take_records`
+    records: Vec<AlarmRecord>,
+    ring: bool,
+    last_bell: Option<Instant>,
+}
+
+impl AlarmConfig {
+    /// # Errors
+    /// Returns an `AlarmConfigError` if the file can't be read or isn't a valid config
+    pub fn load(path: &str) -> Result<AlarmConfig, AlarmConfigError> {
+        let text = fs::read_to_string(path).map_err(AlarmConfigError::Io)?;
+
+        AlarmConfig::parse(&text)
+    }
+
+    /// # Errors
+    /// Returns an `AlarmConfigError` if `text` isn't a valid config
+    pub fn parse(text: &str) -> Result<AlarmConfig, AlarmConfigError> {
+        let config: AlarmConfig = ron::from_str(text).map_err(AlarmConfigError::Parse)?;
+
+        for (index, limits) in config.sensors.iter().enumerate() {
+            if config.sensors[0..index]
+                .iter()
+                .any(|other| other.sensor == limits.sensor)
+            {
+                return Err(AlarmConfigError::Duplicate(limits.sensor));
+            }
+
+            let finite = [limits.warning, limits.critical]
+                .iter()
+                .flat_map(|limits| [limits.min, limits.max])
+                .flatten()
+                .all(f32::is_finite);
+
+            if !finite {
+                return Err(AlarmConfigError::Invalid(limits.sensor));
+            }
+        }
+
+        Ok(config)
+    }
+}
+
+impl Limits {
+    /// Describes how `value` is outside the limits, if it is
+    fn check(&self, value: f32) -> Option<String> {
+        match (self.min, self.max) {
+            (Some(min), _) if value < min => Some(format!("below {:.1}", min)),
+            (_, Some(max)) if value > max => Some(format!("above {:.1}", max)),
+            _ => None,
+        }
+    }
+}
+
+impl AlarmPanel {
+    pub fn new(config: AlarmConfig) -> AlarmPanel {
+        AlarmPanel {
+            config,
+            alarms: Vec::new(),
+            records: Vec::new(),
+            ring: false,
+            last_bell: None,
+        }
+    }
+
+    /// Checks the sensors, loop time and valves of the controller on display
+    pub fn check_telemetry(&mut self, data: &ECUTelemtryData, calibration: &Calibration) {
+        for limits in self.config.sensors.clone().iter() {
+            let raw = ECU_SENSORS
+                .iter()
+                .position(|sensor| *sensor == limits.sensor)
+                .map(|index| data.ecu_data.sensor_states[index]);
+
+            let condition = raw.and_then(|raw| {
+                let value = calibration.convert(limits.sensor, raw);
+                let units = calibration.units(limits.sensor);
+
+                let describe = |severity, outside| {
+                    (
+                        severity,
+                        format!("{:?} {:.1} {} {}", limits.sensor, value, units, outside),
+                    )
+                };
+
+                match limits.critical.check(value) {
+                    Some(outside) => Some(describe(Severity::Critical, outside)),
+                    None => limits
+                        .warning
+                        .check(value)
+                        .map(|outside| describe(Severity::Warning, outside)),
+                }
+            });
+
+            self.update(AlarmId::Sensor(limits.sensor), condition);
+        }
+
+        if let Some(limits) = self.config.max_loop_time {
+            let severity = if data.max_loop_time > limits.critical {
+                Some(Severity::Critical)
+            } else if data.max_loop_time > limits.warning {
+                Some(Severity::Warning)
+            } else {
+                None
+            };
+
+            self.update(
+                AlarmId::LoopTime,
+                severity.map(|severity| {
+                    (
+                        severity,
+                        format!("ECU loop took {:.1} ms", data.max_loop_time * 1000.0),
+                    )
+                }),
+            );
+        }
+
+        let state = data.ecu_data.igniter_state;
+        for (valve, valve_state) in ECU_VALVES.iter().zip(data.ecu_data.valve_states.iter()) {
+            let open = *valve_state > 0;
+
+            let unexpected = self.config.valves.iter().any(|rule| {
+                rule.valve == *valve && rule.states.contains(&state) && rule.open != open
+            });
+
+            self.update(
+                AlarmId::Valve(*valve),
+                if unexpected {
+                    Some((
+                        Severity::Critical,
+                        format!(
+                            "{:?} {} during {:?}",
+                            valve,
+                            if open { "open" } else { "closed" },
+                            state
+                        ),
+                    ))
+                } else {
+                    None
+                },
+            );
+        }
+    }
+
+    pub fn check_watchdog(&mut self, name: &str, alive: bool) {
+        self.update(
+            AlarmId::Watchdog(name.to_string()),
+            if alive {
+                None
+            } else {
+                Some((Severity::Critical, format!("Lost {}", name)))
+            },
+        );
+    }
+
+    /// Aborts are over as soon as they're received, so the alarm stays latched until acknowledged
+    pub fn controller_aborted(&mut self, name: &str, address: NetworkAddress, reason: AbortReason) {
+        let id = AlarmId::Aborted(address);
+
+        self.update(
+            id.clone(),
+            Some((
+                Severity::Critical,
+                format!("{} aborted: {:?}", name, reason),
+            )),
+        );
+        self.update(id, None);
+    }
+
+    /// Raises, escalates or clears an alarm. `condition` is the severity and description while
+    /// it's active, and `None` once it isn't.
+    pub fn update(&mut self, id: AlarmId, condition: Option<(Severity, String)>) {
+        let existing = self.alarms.iter().position(|alarm| alarm.id == id);
+
+        match (existing, condition) {
+            (None, Some((severity, message))) => {
+                self.ring = true;
+                self.record(AlarmTransition::Raised, severity, &message);
+                self.alarms.push(Alarm {
+                    id,
+                    severity,
+                    message,
+                    active: true,
+                    acknowledged: false,
+                    raised_at: Instant::now(),
+                });
+            }
+            (Some(index), Some((severity, message))) => {
+                let alarm = &mut self.alarms[index];
+                let raised = !alarm.active || severity > alarm.severity;
+
+                // The value keeps changing, but only a new alarm or a worse one goes in the log
+                alarm.message = message;
+
+                if raised {
+                    // A flapping alarm only rings again once someone's seen it
+                    self.ring |= alarm.acknowledged || severity > alarm.severity;
+
+                    alarm.severity = severity;
+                    alarm.active = true;
+                    alarm.acknowledged = false;
+                    alarm.raised_at = Instant::now();
+
+                    let (severity, message) = (alarm.severity, alarm.message.clone());
+                    self.record(AlarmTransition::Raised, severity, &message);
+                }
+            }
+            (Some(index), None) if self.alarms[index].active => {
+                let alarm = &mut self.alarms[index];
+                alarm.active = false;
+
+                let (severity, message) = (alarm.severity, alarm.message.clone());
+                self.record(AlarmTransition::Cleared, severity, &message);
+
+                if self.alarms[index].acknowledged {
+                    self.alarms.remove(index);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    /// Acknowledges the most recent unacknowledged alarm
+    pub fn acknowledge_latest(&mut self) {
+        let latest = self
+            .alarms
+            .iter()
+            .enumerate()
+            .filter(|(_, alarm)| !alarm.acknowledged)
+            .max_by_key(|(_, alarm)| alarm.raised_at)
+            .map(|(index, _)| index);
+
+        if let Some(index) = latest {
+            self.acknowledge(index);
+        }
+    }
+
+    pub fn acknowledge_all(&mut self) {
+        // Backwards, since cleared alarms are removed once they're acknowledged
+        for index in (0..self.alarms.len()).rev() {
+            if !self.alarms[index].acknowledged {
+                self.acknowledge(index);
+            }
+        }
+    }
+
+    /// Everything on the panel, most severe first and then newest first
+    pub fn get_alarms(&self) -> Vec<&Alarm> {
+        let mut alarms: Vec<&Alarm> = self.alarms.iter().collect();
+        alarms.sort_by(|a, b| {
+            b.severity
+                .cmp(&a.severity)
+                .then(b.raised_at.cmp(&a.raised_at))
+        });
+
+        alarms
+    }
+
+    /// Whether the sensor's reading is within its limits
+    pub fn is_sensor_ok(&self, sensor: ECUSensor) -> bool {
+        !self
+            .alarms
+            .iter()
+            .any(|alarm| alarm.active && alarm.id == AlarmId::Sensor(sensor))
+    }
+
+    /// Every change since this was last called, oldest first
+    pub fn take_records(&mut self) -> Vec<AlarmRecord> {
+        std::mem::take(&mut self.records)
+    }
+
+    /// Whether the bell should ring now. It rings for every new alarm, and keeps ringing while a
+    /// critical alarm is active and unacknowledged.
+    pub fn take_bell(&mut self) -> bool {
+        let critical_unacknowledged = self.alarms.iter().any(|alarm| {
+            alarm.active && !alarm.acknowledged && alarm.severity == Severity::Critical
+        });
+
+        let repeat = critical_unacknowledged
+            && self
+                .last_bell
+                .is_none_or(|last| last.elapsed().as_secs_f32() >= BELL_PERIOD);
+
+        if self.ring || repeat {
+            self.ring = false;
+            self.last_bell = Some(Instant::now());
+
+            true
+        } else {
+            false
+        }
+    }
+
+    fn acknowledge(&mut self, index: usize) {
+        let alarm = &mut self.alarms[index];
+        alarm.acknowledged = true;
+
+        let (severity, message) = (alarm.severity, alarm.message.clone());
+        self.record(AlarmTransition::Acknowledged, severity, &message);
+
+        if !self.alarms[index].active {
+            self.alarms.remove(index);
+        }
+    }
+
+    fn record(&mut self, transition: AlarmTransition, severity: Severity, message: &str) {
+        self.records.push(AlarmRecord {
+            transition,
+            severity,
+            message: message.to_string(),
+        });
+    }
+}
+
+impl fmt::Display for AlarmConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AlarmConfigError::Io(err) => write!(f, "Couldn't read alarms: {}", err),
+            AlarmConfigError::Parse(err) => write!(f, "Couldn't parse alarms: {}", err),
+            AlarmConfigError::Duplicate(sensor) => {
+                write!(f, "{:?} has limits more than once", sensor)
+            }
+            AlarmConfigError::Invalid(sensor) => {
+                write!(f, "{:?} has limits that aren't numbers", sensor)
+            }
+        }
+    }
+}
+
+impl fmt::Display for AlarmRecord {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{:?} {:?}: {}",
+            self.severity, self.transition, self.message
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ID: AlarmId = AlarmId::LoopTime;
+
+    #[test]
+    fn raised_cleared_then_acknowledged_is_removed() {
+        let mut panel = panel();
+
+        panel.update(ID, Some((Severity::Warning, String::from("slow"))));
+        assert!(panel.take_bell());
+
+        panel.update(ID, None);
+        assert!(panel.get_alarms().len() == 1);
+        assert!(!panel.get_alarms()[0].active);
+
+        panel.acknowledge_latest();
+        assert!(panel.get_alarms().is_empty());
+
+        assert!(
+            transitions(&mut panel)
+                == [
+                    AlarmTransition::Raised,
+                    AlarmTransition::Cleared,
+                    AlarmTransition::Acknowledged
+                ]
+        );
+    }
+
+    #[test]
+    fn acknowledged_then_cleared_is_removed() {
+        let mut panel = panel();
+
+        panel.update(ID, Some((Severity::Warning, String::from("slow"))));
+        panel.acknowledge_all();
+        assert!(panel.get_alarms().len() == 1);
+        assert!(panel.get_alarms()[0].acknowledged);
+
+        panel.update(ID, None);
+        assert!(panel.get_alarms().is_empty());
+
+        assert!(
+            transitions(&mut panel)
+                == [
+                    AlarmTransition::Raised,
+                    AlarmTransition::Acknowledged,
+                    AlarmTransition::Cleared
+                ]
+        );
+    }
+
+    #[test]
+    fn escalation_rings_again() {
+        let mut panel = panel();
+
+        panel.update(ID, Some((Severity::Warning, String::from("slow"))));
+        panel.acknowledge_latest();
+        assert!(panel.take_bell());
+
+        // Staying at the same severity is old news
+        panel.update(ID, Some((Severity::Warning, String::from("still slow"))));
+        assert!(!panel.take_bell());
+
+        panel.update(ID, Some((Severity::Critical, String::from("very slow"))));
+        assert!(panel.take_bell());
+
+        let alarms = panel.get_alarms();
+        assert!(alarms.len() == 1);
+        assert!(alarms[0].severity == Severity::Critical);
+        assert!(!alarms[0].acknowledged);
+
+        assert!(
+            transitions(&mut panel)
+                == [
+                    AlarmTransition::Raised,
+                    AlarmTransition::Acknowledged,
+                    AlarmTransition::Raised
+                ]
+        );
+    }
+
+    #[test]
+    fn duplicate_limits_are_rejected() {
+        let text = "AlarmConfig(sensors: [
+            (sensor: FuelTankPressure, warning: (max: Some(250.0))),
+            (sensor: FuelTankPressure, critical: (max: Some(280.0))),
+        ])";
+
+        assert!(matches!(
+            AlarmConfig::parse(text),
+            Err(AlarmConfigError::Duplicate(ECUSensor::FuelTankPressure))
+        ));
+    }
+
+    #[test]
+    fn non_finite_limits_are_rejected() {
+        for limit in ["inf", "-inf", "NaN"] {
+            let text = format!(
+                "AlarmConfig(sensors: [(sensor: IgniterThroatTemp, critical: (min: Some({})))])",
+                limit
+            );
+
+            assert!(
+                matches!(
+                    AlarmConfig::parse(&text),
+                    Err(AlarmConfigError::Invalid(ECUSensor::IgniterThroatTemp))
+                ),
+                "{}",
+                limit
+            );
+        }
+    }
+
+    #[test]
+    fn default_alarms_parse() {
+        AlarmConfig::parse(DEFAULT_ALARMS).unwrap();
+    }
+
+    fn panel() -> AlarmPanel {
+        AlarmPanel::new(AlarmConfig {
+            sensors: Vec::new(),
+            max_loop_time: None,
+            valves: Vec::new(),
+        })
+    }
+
+    fn transitions(panel: &mut AlarmPanel) -> Vec<AlarmTransition> {
+        panel
+            .take_records()
+            .iter()
+            .map(|record| record.transition)
+            .collect()
+    }
+}
@@ -0,0 +1,288 @@
+use std::{fmt, fs, io};
+
+use hal::{
+    ecu_hal::{raw_to_voltage, ECUSensor, ADC_MAX_READING},
+    SensorConfig,
+};
+use serde::{Deserialize, Serialize};
+
+/// The sensors on the test stand, used unless ctrl-view is given `--calibration`
+pub const DEFAULT_CALIBRATION: &str = include_str!("../calibration.ron");
+
+/// Units shown for sensors without a calibration, which are displayed as raw readings
+pub const RAW_UNITS: &str = "counts";
+
+/// How to turn each sensor's raw ADC readings into engineering units. Loaded from RON files like
+/// `calibration.ron`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Calibration {
+    pub sensors: Vec<SensorCalibration>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SensorCalibration {
+    pub sensor: ECUSensor,
+    pub units: String,
+    pub transfer: TransferFunction,
+    /// Subtracted after the transfer function, e.g. to tare a transducer that reads a few psi
+    /// while vented
+    #[serde(default)]
+    pub zero_offset: f32,
+}
+
+/// Turns the sensor's output voltage into its value
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum TransferFunction {
+    Linear {
+        slope: f32,
+        intercept: f32,
+    },
+    /// Coefficients from the constant term up, so `[a, b, c]` is a + bV + cV²
+    Polynomial(Vec<f32>),
+    /// `(volts, value)` points in increasing order of volts. Values in between are interpolated,
+    /// and values outside are extrapolated from the closest two points.
+    Table(Vec<(f32, f32)>),
+}
+
+#[derive(Debug)]
+pub enum CalibrationError {
+    Io(io::Error),
+    Parse(ron::Error),
+    /// The sensor has more than one calibration
+    Duplicate(ECUSensor),
+    /// The sensor's transfer function is empty, not in order, or has numbers that aren't finite
+    Invalid(ECUSensor),
+}
+
+impl Calibration {
+    /// # Errors
+    /// Returns a `CalibrationError` if the file can't be read or isn't a valid calibration
+    pub fn load(path: &str) -> Result<Calibration, CalibrationError> {
+        let text = fs::read_to_string(path).map_err(CalibrationError::Io)?;
+
+        Calibration::parse(&text)
+    }
+
+    /// # Errors
+    /// Returns a `CalibrationError` if `text` isn't a valid calibration
+    pub fn parse(text: &str) -> Result<Calibration, CalibrationError> {
+        let calibration: Calibration = ron::from_str(text).map_err(CalibrationError::Parse)?;
+
+        for (index, sensor) in calibration.sensors.iter().enumerate() {
+            if calibration.sensors[0..index]
+                .iter()
+                .any(|other| other.sensor == sensor.sensor)
+            {
+                return Err(CalibrationError::Duplicate(sensor.sensor));
+            }
+
+            if !sensor.transfer.is_valid() || !sensor.zero_offset.is_finite() {
+                return Err(CalibrationError::Invalid(sensor.sensor));
+            }
+        }
+
+        Ok(calibration)
+    }
+
+    pub fn get(&self, sensor: ECUSensor) -> Option<&SensorCalibration> {
+        self.sensors
+            .iter()
+            .find(|calibration| calibration.sensor == sensor)
+    }
+
+    /// The reading in engineering units, or the raw reading if the sensor isn't calibrated
+    pub fn convert(&self, sensor: ECUSensor, raw: u16) -> f32 {
+        self.get(sensor)
+            .map_or(f32::from(raw), |calibration| calibration.convert(raw))
+    }
+
+    pub fn units(&self, sensor: ECUSensor) -> &str {
+        self.get(sensor)
+            .map_or(RAW_UNITS, |calibration| calibration.units.as_str())
+    }
+}
+
+impl SensorCalibration {
+    pub fn convert(&self, raw: u16) -> f32 {
+        self.transfer.apply(raw_to_voltage(raw)) - self.zero_offset
+    }
+
+    /// The `SensorConfig` that makes the ECU report this sensor in the same units, so redlines
+    /// and ignition verification can be set in them. The ECU only maps readings linearly, so
+    /// non-linear transfer functions are approximated by the line through their values at either
+    /// end of the ADC's range.
+    pub fn to_sensor_config(&self) -> SensorConfig {
+        SensorConfig {
+            premin: 0.0,
+            premax: f32::from(ADC_MAX_READING),
+            postmin: self.convert(0),
+            postmax: self.convert(ADC_MAX_READING),
+        }
+    }
+}
+
+impl TransferFunction {
+    pub fn apply(&self, volts: f32) -> f32 {
+        match self {
+            TransferFunction::Linear { slope, intercept } => slope * volts + intercept,
+            TransferFunction::Polynomial(coefficients) => coefficients
+                .iter()
+                .rev()
+                .fold(0.0, |value, coefficient| value * volts + coefficient),
+            TransferFunction::Table(points) => {
+                // Validation makes sure there are at least two points
+                let segment = points
+                    .windows(2)
+                    .find(|segment| volts <= segment[1].0)
+                    .unwrap_or(&points[points.len() - 2..]);
+
+                let (v0, value0) = segment[0];
+                let (v1, value1) = segment[1];
+
+                value0 + (volts - v0) / (v1 - v0) * (value1 - value0)
+            }
+        }
+    }
+
+    fn is_valid(&self) -> bool {
+        match self {
+            TransferFunction::Linear { slope, intercept } => {
+                slope.is_finite() && intercept.is_finite()
+            }
+            TransferFunction::Polynomial(coefficients) => {
+                !coefficients.is_empty() && coefficients.iter().all(|value| value.is_finite())
+            }
+            TransferFunction::Table(points) => {
+                points.len() >= 2
+                    && points
+                        .iter()
+                        .all(|(volts, value)| volts.is_finite() && value.is_finite())
+                    && points.windows(2).all(|segment| segment[0].0 < segment[1].0)
+            }
+        }
+    }
+}
+
+impl fmt::Display for CalibrationError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CalibrationError::Io(err) => write!(f, "Couldn't read calibration: {}", err),
+            CalibrationError::Parse(err) => write!(f, "Couldn't parse calibration: {}", err),
+            CalibrationError::Duplicate(sensor) => {
+                write!(f, "{:?} is calibrated more than once", sensor)
+            }
+            CalibrationError::Invalid(sensor) => {
+                write!(f, "{:?} has an invalid transfer function", sensor)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use hal::ecu_hal::ADC_REFERENCE_VOLTAGE;
+
+    use super::*;
+
+    #[test]
+    fn linear() {
+        let transfer = TransferFunction::Linear {
+            slope: 75.0,
+            intercept: -37.5,
+        };
+
+        assert!(close(transfer.apply(0.5), 0.0));
+        assert!(close(transfer.apply(4.5), 300.0));
+    }
+
+    #[test]
+    fn polynomial() {
+        // 1 + 2V + 3V²
+        let transfer = TransferFunction::Polynomial(vec![1.0, 2.0, 3.0]);
+
+        assert!(close(transfer.apply(0.0), 1.0));
+        assert!(close(transfer.apply(2.0), 17.0));
+    }
+
+    #[test]
+    fn table_interpolates_and_extrapolates() {
+        let transfer = TransferFunction::Table(vec![(1.0, 0.0), (2.0, 100.0), (4.0, 200.0)]);
+
+        assert!(close(transfer.apply(1.5), 50.0));
+        assert!(close(transfer.apply(3.0), 150.0));
+        assert!(close(transfer.apply(4.0), 200.0));
+        // From the closest two points
+        assert!(close(transfer.apply(0.0), -100.0));
+        assert!(close(transfer.apply(5.0), 250.0));
+    }
+
+    #[test]
+    fn zero_offset_is_subtracted_after_transfer() {
+        let calibration = sensor(
+            TransferFunction::Linear {
+                slope: 10.0,
+                intercept: 0.0,
+            },
+            2.0,
+        );
+
+        assert!(close(calibration.convert(0), -2.0));
+        assert!(close(
+            calibration.convert(ADC_MAX_READING),
+            10.0 * ADC_REFERENCE_VOLTAGE - 2.0
+        ));
+    }
+
+    #[test]
+    fn sensor_config_spans_adc_range() {
+        let calibration = sensor(TransferFunction::Polynomial(vec![0.0, 0.0, 4.0]), 1.0);
+        let config = calibration.to_sensor_config();
+
+        assert!(config.premin == 0.0);
+        assert!(config.premax == f32::from(ADC_MAX_READING));
+        assert!(close(config.postmin, -1.0));
+        assert!(close(config.postmax, 99.0));
+    }
+
+    #[test]
+    fn invalid_calibrations_are_rejected() {
+        for transfer in [
+            "Polynomial([])",
+            "Table([(1.0, 0.0)])",
+            "Table([(2.0, 0.0), (1.0, 100.0)])",
+            "Linear(slope: inf, intercept: 0.0)",
+        ] {
+            let text = format!(
+                "Calibration(sensors: [(sensor: FuelTankPressure, units: \"psi\", transfer: {})])",
+                transfer
+            );
+
+            assert!(
+                matches!(
+                    Calibration::parse(&text),
+                    Err(CalibrationError::Invalid(ECUSensor::FuelTankPressure))
+                ),
+                "{}",
+                transfer
+            );
+        }
+    }
+
+    #[test]
+    fn default_calibration_parses() {
+        Calibration::parse(DEFAULT_CALIBRATION).unwrap();
+    }
+
+    fn sensor(transfer: TransferFunction, zero_offset: f32) -> SensorCalibration {
+        SensorCalibration {
+            sensor: ECUSensor::IgniterChamberPressure,
+            units: String::from("psi"),
+            transfer,
+            zero_offset,
+        }
+    }
+
+    fn close(value: f32, expected: f32) -> bool {
+        (value - expected).abs() < 1e-3
+    }
+}
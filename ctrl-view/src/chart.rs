@@ -0,0 +1,439 @@
+use std::collections::VecDeque;
+
+use hal::ecu_hal::{ECUDataFrame, ECUSensor, ECUValve, ECU_SENSORS, ECU_VALVES};
+
+use crate::calibration::Calibration;
+
+pub const DEFAULT_WINDOW: f64 = 10.0;
+pub const MIN_WINDOW: f64 = 1.0;
+/// History older than this is dropped, so it's also the widest the window can get
+pub const MAX_WINDOW: f64 = 300.0;
+
+/// Glyphs for the lowest to the highest value in the window
+const LEVELS: [char; 8] = ['▁', '▂', '▃', '▄', '▅', '▆', '▇', '█'];
+
+/// How many of the latest events are listed under the charts
+const LISTED_EVENTS: usize = 4;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ChartEvent {
+    ValveOpened(ECUValve),
+    ValveClosed(ECUValve),
+    SparkOn,
+    SparkOff,
+}
+
+/// One column of a chart
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ChartCell {
+    pub glyph: char,
+    /// The latest event in the column, drawn over the chart
+    pub event: Option<ChartEvent>,
+}
+
+/// A chart ready to be drawn, `cells` runs from the start of the window to now
+#[derive(Debug, Clone, PartialEq)]
+pub struct ChartRow {
+    pub label: String,
+    pub cells: Vec<ChartCell>,
+    pub summary: String,
+}
+
+struct Channel {
+    sensor: ECUSensor,
+    label: String,
+    units: String,
+    shown: bool,
+    /// Seconds since the Unix epoch and the calibrated value, oldest first
+    samples: VecDeque<(f64, f32)>,
+}
+
+/// Scrolling strip charts of the sensors, with valve and spark events marked on them
+pub struct Charts {
+    channels: Vec<Channel>,
+    events: VecDeque<(f64, ChartEvent)>,
+    /// What the valves and spark were in the last frame, to spot events
+    last_frame: Option<ECUDataFrame>,
+    window: f64,
+}
+
+impl Charts {
+    /// Charts `sensors` in the given order, all shown to begin with
+    pub fn new(sensors: &[(ECUSensor, &str)], calibration: &Calibration, window: f64) -> Charts {
+        Charts {
+            channels: sensors
+                .iter()
+                .map(|(sensor, label)| Channel {
+                    sensor: *sensor,
+                    label: (*label).to_string(),
+                    units: calibration.units(*sensor).to_string(),
+                    shown: true,
+                    samples: VecDeque::new(),
+                })
+                .collect(),
+            events: VecDeque::new(),
+            last_frame: None,
+            window: window.clamp(MIN_WINDOW, MAX_WINDOW),
+        }
+    }
+
+    /// Adds a frame received at `time` seconds since the Unix epoch
+    pub fn push(&mut self, time: f64, frame: &ECUDataFrame, calibration: &Calibration) {
+        for channel in self.channels.iter_mut() {
+            if let Some(index) = ECU_SENSORS
+                .iter()
+                .position(|sensor| *sensor == channel.sensor)
+            {
+                let value = calibration.convert(channel.sensor, frame.sensor_states[index]);
+                channel.samples.push_back((time, value));
+            }
+
+            while matches!(channel.samples.front(), Some((oldest, _)) if *oldest < time - MAX_WINDOW)
+            {
+                channel.samples.pop_front();
+            }
+        }
+
+        if let Some(last_frame) = self.last_frame {
+            for ((valve, state), last_state) in ECU_VALVES
+                .iter()
+                .zip(frame.valve_states.iter())
+                .zip(last_frame.valve_states.iter())
+            {
+                if (*state > 0) != (*last_state > 0) {
+                    self.events.push_back((
+                        time,
+                        if *state > 0 {
+                            ChartEvent::ValveOpened(*valve)
+                        } else {
+                            ChartEvent::ValveClosed(*valve)
+                        },
+                    ));
+                }
+            }
+
+            if frame.sparking != last_frame.sparking {
+                self.events.push_back((
+                    time,
+                    if frame.sparking {
+                        ChartEvent::SparkOn
+                    } else {
+                        ChartEvent::SparkOff
+                    },
+                ));
+            }
+        }
+
+        while matches!(self.events.front(), Some((oldest, _)) if *oldest < time - MAX_WINDOW) {
+            self.events.pop_front();
+        }
+
+        self.last_frame = Some(*frame);
+    }
+
+    /// Shows or hides the chart at `index`, in the order the sensors were given
+    pub fn toggle(&mut self, index: usize) {
+        if let Some(channel) = self.channels.get_mut(index) {
+            channel.shown = !channel.shown;
+        }
+    }
+
+    /// Multiplies the window by `factor`, within `MIN_WINDOW` and `MAX_WINDOW`
+    pub fn zoom(&mut self, factor: f64) {
+        self.window = (self.window * factor).clamp(MIN_WINDOW, MAX_WINDOW);
+    }
+
+    pub fn get_window(&self) -> f64 {
+        self.window
+    }
+
+    /// The shown charts `width` columns wide, ending at `now`. Each column shows the latest
+    /// value in it, scaled between the lowest and highest values in the window.
+    pub fn rows(&self, now: f64, width: usize) -> Vec<ChartRow> {
+        let start = now - self.window;
+        let column_of = |time: f64| -> Option<usize> {
+            if time < start || time > now || width == 0 {
+                return None;
+            }
+
+            Some((((time - start) / self.window * width as f64) as usize).min(width - 1))
+        };
+
+        let mut event_columns: Vec<Option<ChartEvent>> = vec![None; width];
+        for (time, event) in self.events.iter() {
+            if let Some(column) = column_of(*time) {
+                event_columns[column] = Some(*event);
+            }
+        }
+
+        self.channels
+            .iter()
+            .filter(|channel| channel.shown)
+            .map(|channel| {
+                let mut columns: Vec<Option<f32>> = vec![None; width];
+                for (time, value) in channel.samples.iter() {
+                    if let Some(column) = column_of(*time) {
+                        columns[column] = Some(*value);
+                    }
+                }
+
+                let visible = columns.iter().flatten();
+                let min = visible.clone().copied().fold(f32::INFINITY, f32::min);
+                let max = visible.copied().fold(f32::NEG_INFINITY, f32::max);
+
+                let cells = columns
+                    .iter()
+                    .zip(event_columns.iter())
+                    .map(|(value, event)| ChartCell {
+                        glyph: value.map_or(' ', |value| level_glyph(value, min, max)),
+                        event: *event,
+                    })
+                    .collect();
+
+                let summary = match channel.samples.back() {
+                    Some((_, latest)) if min <= max => {
+                        format!("{:.1} {} [{:.1}, {:.1}]", latest, channel.units, min, max)
+                    }
+                    _ => String::from("No data"),
+                };
+
+                ChartRow {
+                    label: channel.label.clone(),
+                    cells,
+                    summary,
+                }
+            })
+            .collect()
+    }
+
+    /// The latest events in the window, described with how long ago they were
+    pub fn recent_events(&self, now: f64) -> String {
+        let events: Vec<String> = self
+            .events
+            .iter()
+            .rev()
+            .take_while(|(time, _)| *time >= now - self.window)
+            .take(LISTED_EVENTS)
+            .map(|(time, event)| format!("-{:.1}s {}", now - time, describe(*event)))
+            .collect();
+
+        if events.is_empty() {
+            String::from("None")
+        } else {
+            events.join(", ")
+        }
+    }
+}
+
+fn level_glyph(value: f32, min: f32, max: f32) -> char {
+    // A flat line sits in the middle rather than at the bottom
+    if max - min <= f32::EPSILON {
+        return LEVELS[LEVELS.len() / 2];
+    }
+
+    let level = ((value - min) / (max - min) * (LEVELS.len() - 1) as f32).round() as usize;
+
+    LEVELS[level.min(LEVELS.len() - 1)]
+}
+
+fn describe(event: ChartEvent) -> String {
+    match event {
+        ChartEvent::ValveOpened(valve) => format!("{:?} open", valve),
+        ChartEvent::ValveClosed(valve) => format!("{:?} closed", valve),
+        ChartEvent::SparkOn => String::from("spark on"),
+        ChartEvent::SparkOff => String::from("spark off"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use hal::ecu_hal::{IgniterState, MAX_ECU_SENSORS, MAX_ECU_VALVES};
+
+    use super::*;
+
+    const SENSORS: [(ECUSensor, &str); 2] = [
+        (ECUSensor::IgniterChamberPressure, "Chamber"),
+        (ECUSensor::FuelTankPressure, "Fuel tank"),
+    ];
+
+    fn uncalibrated() -> Calibration {
+        Calibration {
+            sensors: Vec::new(),
+        }
+    }
+
+    fn frame(reading: u16, valve_states: [u8; MAX_ECU_VALVES], sparking: bool) -> ECUDataFrame {
+        ECUDataFrame {
+            igniter_state: IgniterState::Idle,
+            sensor_states: [reading; MAX_ECU_SENSORS],
+            valve_states,
+            sparking,
+        }
+    }
+
+    fn glyphs(row: &ChartRow) -> String {
+        row.cells.iter().map(|cell| cell.glyph).collect()
+    }
+
+    #[test]
+    fn columns_show_their_latest_sample_scaled_to_the_window() {
+        let calibration = uncalibrated();
+        let mut charts = Charts::new(&SENSORS, &calibration, 10.0);
+
+        // Two samples in the first column, none in the third
+        for (time, reading) in [(0.2, 9), (0.7, 0), (1.5, 7), (3.5, 1), (9.9, 14)] {
+            charts.push(
+                time,
+                &frame(reading, [0; MAX_ECU_VALVES], false),
+                &calibration,
+            );
+        }
+
+        let rows = charts.rows(10.0, 10);
+        assert!(rows.len() == 2);
+        assert!(rows[0].label == "Chamber");
+        assert!(glyphs(&rows[0]) == "▁▅ ▂     █");
+        assert!(rows[0].summary == "14.0 counts [0.0, 14.0]");
+    }
+
+    #[test]
+    fn flat_lines_sit_in_the_middle() {
+        let calibration = uncalibrated();
+        let mut charts = Charts::new(&SENSORS, &calibration, 4.0);
+
+        for time in [0.5, 1.5, 2.5, 3.5] {
+            charts.push(time, &frame(100, [0; MAX_ECU_VALVES], false), &calibration);
+        }
+
+        assert!(glyphs(&charts.rows(4.0, 4)[0]) == "▅▅▅▅");
+    }
+
+    #[test]
+    fn samples_outside_the_window_are_left_out() {
+        let calibration = uncalibrated();
+        let mut charts = Charts::new(&SENSORS, &calibration, 5.0);
+
+        charts.push(1.0, &frame(1000, [0; MAX_ECU_VALVES], false), &calibration);
+        charts.push(8.0, &frame(10, [0; MAX_ECU_VALVES], false), &calibration);
+
+        // The old reading doesn't stretch the scale
+        let rows = charts.rows(10.0, 5);
+        assert!(glyphs(&rows[0]) == "   ▅ ");
+        assert!(rows[0].summary == "10.0 counts [10.0, 10.0]");
+
+        assert!(charts.rows(100.0, 5)[0].summary == "No data");
+    }
+
+    #[test]
+    fn history_past_the_widest_window_is_dropped() {
+        let calibration = uncalibrated();
+        let mut charts = Charts::new(&SENSORS, &calibration, MAX_WINDOW);
+
+        charts.push(0.0, &frame(0, [0; MAX_ECU_VALVES], false), &calibration);
+        charts.push(1.0, &frame(0, [255, 0, 0, 0], false), &calibration);
+        charts.push(
+            MAX_WINDOW + 0.5,
+            &frame(0, [255, 0, 0, 0], false),
+            &calibration,
+        );
+
+        assert!(charts.channels[0].samples.len() == 2);
+        assert!(charts.events.len() == 1);
+
+        charts.push(
+            MAX_WINDOW + 2.0,
+            &frame(0, [255, 0, 0, 0], false),
+            &calibration,
+        );
+
+        assert!(charts.channels[0].samples.len() == 2);
+        assert!(charts.events.is_empty());
+    }
+
+    #[test]
+    fn valve_and_spark_changes_are_overlaid() {
+        let calibration = uncalibrated();
+        let mut charts = Charts::new(&SENSORS, &calibration, 10.0);
+
+        // The first frame has nothing to compare against
+        charts.push(0.5, &frame(0, [255, 0, 0, 0], true), &calibration);
+        charts.push(2.5, &frame(0, [1, 0, 0, 0], false), &calibration);
+        charts.push(5.5, &frame(0, [0, 255, 0, 0], false), &calibration);
+
+        let expected = [
+            None,
+            None,
+            Some(ChartEvent::SparkOff),
+            None,
+            None,
+            Some(ChartEvent::ValveOpened(ECUValve::IgniterGOxMain)),
+            None,
+            None,
+            None,
+            None,
+        ];
+
+        for row in charts.rows(10.0, 10) {
+            let events: Vec<Option<ChartEvent>> = row.cells.iter().map(|cell| cell.event).collect();
+            assert!(events == expected, "{:?}", events);
+        }
+
+        assert!(
+            charts.recent_events(6.0)
+                == "-0.5s IgniterGOxMain open, -0.5s IgniterFuelMain closed, -3.5s spark off"
+        );
+        assert!(charts.recent_events(20.0) == "None");
+    }
+
+    #[test]
+    fn only_the_latest_events_are_listed() {
+        let calibration = uncalibrated();
+        let mut charts = Charts::new(&SENSORS, &calibration, 10.0);
+
+        for (time, sparking) in [
+            (0.0, false),
+            (1.0, true),
+            (2.0, false),
+            (3.0, true),
+            (4.0, false),
+            (5.0, true),
+        ] {
+            charts.push(time, &frame(0, [0; MAX_ECU_VALVES], sparking), &calibration);
+        }
+
+        assert!(
+            charts.recent_events(5.0)
+                == "-0.0s spark on, -1.0s spark off, -2.0s spark on, -3.0s spark off"
+        );
+    }
+
+    #[test]
+    fn hidden_charts_are_skipped() {
+        let calibration = uncalibrated();
+        let mut charts = Charts::new(&SENSORS, &calibration, 10.0);
+
+        charts.toggle(0);
+        charts.toggle(SENSORS.len());
+
+        let rows = charts.rows(10.0, 10);
+        assert!(rows.len() == 1);
+        assert!(rows[0].label == "Fuel tank");
+
+        charts.toggle(0);
+        assert!(charts.rows(10.0, 10).len() == 2);
+    }
+
+    #[test]
+    fn zoom_is_clamped() {
+        let mut charts = Charts::new(&SENSORS, &uncalibrated(), DEFAULT_WINDOW);
+
+        charts.zoom(0.5);
+        assert!(charts.get_window() == DEFAULT_WINDOW / 2.0);
+
+        charts.zoom(1000.0);
+        assert!(charts.get_window() == MAX_WINDOW);
+
+        charts.zoom(0.0);
+        assert!(charts.get_window() == MIN_WINDOW);
+    }
+}
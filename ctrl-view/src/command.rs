@@ -0,0 +1,523 @@
+use std::{
+    sync::mpsc::Sender,
+    time::{Duration, Instant},
+};
+
+use crossterm::event::KeyCode;
+use hal::{
+    comms_hal::{Command, NetworkAddress, Packet, ARM_CONFIRMATION_CODE},
+    ecu_hal::{ECUValve, ECU_VALVES},
+};
+
+/// Aborts the ECU whatever mode ctrl-view is in
+pub const ABORT_KEY: char = 'x';
+
+/// How long to wait for an `Ack` or `Nak` before sending a command again, same as ctrl-send
+const REPLY_TIMEOUT: Duration = Duration::from_millis(250);
+const MAX_ATTEMPTS: u32 = 4;
+
+/// Sends commands to the controller on display from the keyboard. Commands go out through the
+/// recv thread's comms and are sent again until they're answered, like ctrl-send does.
+pub struct Commander {
+    send_thread_tx: Sender<(Packet, NetworkAddress)>,
+    target: NetworkAddress,
+    next_seq: u16,
+    /// The valves the number keys toggle, in order
+    valves: Vec<ECUValve>,
+    /// Whether each of `ECU_VALVES` was open in the last telemetry
+    valves_open: Option<Vec<bool>>,
+    active: bool,
+    /// A hazardous command waiting for the operator to confirm it
+    confirming: Option<Command>,
+    pending: Vec<PendingCommand>,
+    status: String,
+}
+
+struct PendingCommand {
+    seq: u16,
+    command: Command,
+    attempts: u32,
+    sent_at: Instant,
+}
+
+impl Commander {
+    /// `first_seq` should be different every run, so the ECU doesn't take a command for a retry
+    /// of one the last run sent
+    pub fn new(
+        send_thread_tx: Sender<(Packet, NetworkAddress)>,
+        target: NetworkAddress,
+        first_seq: u16,
+        valves: &[ECUValve],
+    ) -> Commander {
+        Commander {
+            send_thread_tx,
+            target,
+            next_seq: first_seq,
+            valves: valves.to_vec(),
+            valves_open: None,
+            active: false,
+            confirming: None,
+            pending: Vec::new(),
+            status: String::from("None"),
+        }
+    }
+
+    /// Handles a key press, returning whether it was used. Outside command mode only the abort key
+    /// and tab, which enters command mode, are used.
+    pub fn on_key(&mut self, code: KeyCode) -> bool {
+        if code == KeyCode::Char(ABORT_KEY) {
+            self.confirming = None;
+            self.send(Command::Abort);
+            return true;
+        }
+
+        // Anything but a yes cancels, so a stray key can't confirm
+        if let Some(command) = self.confirming.take() {
+            if code == KeyCode::Char('y') {
+                self.send(command);
+            } else {
+                self.status = format!("{} cancelled", describe(&command));
+            }
+            return true;
+        }
+
+        if code == KeyCode::Tab {
+            self.active = !self.active;
+            return true;
+        }
+
+        if !self.active {
+            return false;
+        }
+
+        let command = match code {
+            KeyCode::Esc => {
+                self.active = false;
+                return true;
+            }
+            KeyCode::Char(digit @ '1'..='9') => {
+                match self.toggle_valve(digit as usize - '1' as usize) {
+                    Some(command) => command,
+                    None => return true,
+                }
+            }
+            KeyCode::Char('r') => Command::SetRecording(true),
+            KeyCode::Char('s') => Command::SetRecording(false),
+            KeyCode::Char('t') => Command::TransferData,
+            KeyCode::Char('m') => Command::Arm {
+                code: ARM_CONFIRMATION_CODE,
+            },
+            KeyCode::Char('d') => Command::Disarm,
+            _ => return false,
+        };
+
+        if needs_confirmation(&command) {
+            self.confirming = Some(command);
+        } else {
+            self.send(command);
+        }
+
+        true
+    }
+
+    /// Keeps track of the valves, so the number keys know which way to toggle them
+    pub fn on_valve_states(&mut self, valve_states: &[u8]) {
+        self.valves_open = Some(valve_states.iter().map(|state| *state > 0).collect());
+    }
+
+    /// Takes an `Ack` or `Nak` for one of the commands sent from here
+    pub fn on_reply(&mut self, packet: &Packet) {
+        let (seq, outcome) = match packet {
+            Packet::Ack { seq } => (*seq, String::from("acknowledged")),
+            Packet::Nak { seq, reason } => (*seq, format!("rejected {:?}", reason)),
+            _ => return,
+        };
+
+        if let Some(index) = self.pending.iter().position(|pending| pending.seq == seq) {
+            let pending = self.pending.remove(index);
+            self.status = format!("{} {}", describe(&pending.command), outcome);
+        }
+    }
+
+    /// Sends unanswered commands again, and gives up on them after `MAX_ATTEMPTS`
+    pub fn update(&mut self) {
+        let mut index = 0;
+        while index < self.pending.len() {
+            if self.pending[index].sent_at.elapsed() < REPLY_TIMEOUT {
+                index += 1;
+                continue;
+            }
+
+            if self.pending[index].attempts >= MAX_ATTEMPTS {
+                let pending = self.pending.remove(index);
+                self.status = format!("{} got no reply", describe(&pending.command));
+                continue;
+            }
+
+            // Retries reuse the same seq, so the ECU only runs the command once
+            let pending = &mut self.pending[index];
+            pending.attempts += 1;
+            pending.sent_at = Instant::now();
+
+            let packet = Packet::Command {
+                seq: pending.seq,
+                command: pending.command.clone(),
+            };
+            let _ = self.send_thread_tx.send((packet, self.target));
+
+            index += 1;
+        }
+    }
+
+    /// A seq no other command has used, for commands sent from elsewhere
+    pub fn take_seq(&mut self) -> u16 {
+        let seq = self.next_seq;
+        self.next_seq = self.next_seq.wrapping_add(1);
+
+        seq
+    }
+
+    /// The key help while in command mode or confirming a command
+    pub fn get_help(&self) -> Option<String> {
+        match &self.confirming {
+            Some(command) => Some(format!(
+                "Press 'y' to confirm {}, any other key cancels",
+                describe(command)
+            )),
+            None if self.active => Some(format!(
+                "Tab or 'ESC' leaves. '{}' aborts, 1-{} toggle valves, 'r'/'s' start/stop ECU \
+                 recording, 't' transfers, 'm'/'d' arm/disarm",
+                ABORT_KEY,
+                self.valves.len()
+            )),
+            None => None,
+        }
+    }
+
+    /// What happened to the last command
+    pub fn get_status(&self) -> &str {
+        &self.status
+    }
+
+    fn toggle_valve(&mut self, index: usize) -> Option<Command> {
+        let valve = *self.valves.get(index)?;
+
+        let open = self.valves_open.as_ref().and_then(|valves_open| {
+            ECU_VALVES
+                .iter()
+                .position(|other| *other == valve)
+                .and_then(|index| valves_open.get(index).copied())
+        });
+
+        match open {
+            Some(open) => Some(Command::SetValve {
+                valve,
+                state: if open { 0 } else { 255 },
+            }),
+            None => {
+                self.status = format!("{:?} state unknown", valve);
+                None
+            }
+        }
+    }
+
+    fn send(&mut self, command: Command) {
+        let seq = self.take_seq();
+
+        // Only fails once ctrl-view is exiting
+        let _ = self.send_thread_tx.send((
+            Packet::Command {
+                seq,
+                command: command.clone(),
+            },
+            self.target,
+        ));
+
+        self.status = format!("{} sent", describe(&command));
+        self.pending.push(PendingCommand {
+            seq,
+            command,
+            attempts: 1,
+            sent_at: Instant::now(),
+        });
+    }
+}
+
+/// The ECU's hazardous commands, which it only takes while armed, and arming itself
+fn needs_confirmation(command: &Command) -> bool {
+    command.is_hazardous() || matches!(command, Command::Arm { .. })
+}
+
+/// Short enough to fit in the display's misc values
+fn describe(command: &Command) -> String {
+    match command {
+        Command::SetValve { valve, state } if *state > 0 => format!("Open {:?}", valve),
+        Command::SetValve { valve, .. } => format!("Close {:?}", valve),
+        Command::SetRecording(true) => String::from("Start recording"),
+        Command::SetRecording(false) => String::from("Stop recording"),
+        Command::TransferData => String::from("Transfer"),
+        Command::Abort => String::from("Abort"),
+        Command::Arm { .. } => String::from("Arm"),
+        Command::Disarm => String::from("Disarm"),
+        _ => String::from("Command"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::mpsc::{self, Receiver};
+
+    use ecu::{Ecu, HALs};
+    use hal::{
+        comms_hal::{CommsInterface, NakReason},
+        comms_mock::CommsLoopback,
+        ecu_hal::{ECUHardware, MAX_ECU_VALVES},
+        ecu_mock::ECUHardwareMock,
+    };
+
+    use super::*;
+
+    const ECU: NetworkAddress = NetworkAddress::EngineController(0);
+
+    fn commander() -> (Commander, Receiver<(Packet, NetworkAddress)>) {
+        let (tx, rx) = mpsc::channel();
+        let mut commander = Commander::new(tx, ECU, 100, &ECU_VALVES);
+        commander.on_valve_states(&[0; MAX_ECU_VALVES]);
+
+        (commander, rx)
+    }
+
+    /// Every command sent since last checked, with its seq
+    fn sent(rx: &Receiver<(Packet, NetworkAddress)>) -> Vec<(u16, Command)> {
+        rx.try_iter()
+            .map(|(packet, target)| {
+                assert!(target == ECU);
+
+                match packet {
+                    Packet::Command { seq, command } => (seq, command),
+                    packet => panic!("Sent something other than a command: {:?}", packet),
+                }
+            })
+            .collect()
+    }
+
+    fn press(commander: &mut Commander, keys: &str) {
+        for key in keys.chars() {
+            let code = if key == '\t' {
+                KeyCode::Tab
+            } else {
+                KeyCode::Char(key)
+            };
+
+            assert!(commander.on_key(code), "{:?} wasn't used", key);
+        }
+    }
+
+    #[test]
+    fn abort_key_works_in_any_mode() {
+        let (mut commander, rx) = commander();
+
+        press(&mut commander, "x");
+        assert!(sent(&rx) == [(100, Command::Abort)]);
+
+        // Even while a hazardous command is waiting to be confirmed, which it cancels
+        press(&mut commander, "\t1x");
+        assert!(sent(&rx) == [(101, Command::Abort)]);
+        assert!(commander
+            .get_help()
+            .unwrap()
+            .starts_with("Tab or 'ESC' leaves"));
+    }
+
+    #[test]
+    fn keys_are_ignored_outside_command_mode() {
+        let (mut commander, rx) = commander();
+
+        assert!(!commander.on_key(KeyCode::Char('1')));
+        assert!(!commander.on_key(KeyCode::Char('m')));
+        assert!(commander.get_help().is_none());
+
+        press(&mut commander, "\t");
+        assert!(commander.on_key(KeyCode::Esc));
+        assert!(!commander.on_key(KeyCode::Char('r')));
+
+        assert!(sent(&rx).is_empty());
+    }
+
+    #[test]
+    fn hazardous_commands_wait_for_confirmation() {
+        let (mut commander, rx) = commander();
+
+        press(&mut commander, "\tm");
+        assert!(sent(&rx).is_empty());
+        assert!(commander.get_help().unwrap() == "Press 'y' to confirm Arm, any other key cancels");
+
+        press(&mut commander, "y");
+        assert!(
+            sent(&rx)
+                == [(
+                    100,
+                    Command::Arm {
+                        code: ARM_CONFIRMATION_CODE
+                    }
+                )]
+        );
+
+        press(&mut commander, "1");
+        assert!(sent(&rx).is_empty());
+
+        press(&mut commander, "y");
+        assert!(
+            sent(&rx)
+                == [(
+                    101,
+                    Command::SetValve {
+                        valve: ECUValve::IgniterFuelMain,
+                        state: 255
+                    }
+                )]
+        );
+
+        // Safe commands go straight out, closing a valve included
+        commander.on_valve_states(&[255; MAX_ECU_VALVES]);
+        press(&mut commander, "1rd");
+        assert!(
+            sent(&rx)
+                == [
+                    (
+                        102,
+                        Command::SetValve {
+                            valve: ECUValve::IgniterFuelMain,
+                            state: 0
+                        }
+                    ),
+                    (103, Command::SetRecording(true)),
+                    (104, Command::Disarm),
+                ]
+        );
+    }
+
+    #[test]
+    fn any_other_key_cancels_confirmation() {
+        let (mut commander, rx) = commander();
+        press(&mut commander, "\t");
+
+        // Even keys that would otherwise do something
+        for key in [
+            KeyCode::Char('n'),
+            KeyCode::Char('m'),
+            KeyCode::Tab,
+            KeyCode::Esc,
+        ] {
+            press(&mut commander, "m");
+            assert!(commander.on_key(key));
+            assert!(commander.get_status() == "Arm cancelled");
+        }
+
+        assert!(sent(&rx).is_empty());
+        assert!(commander.get_help().is_some());
+    }
+
+    #[test]
+    fn unanswered_commands_are_retried_then_given_up_on() {
+        let (mut commander, rx) = commander();
+
+        press(&mut commander, "\tt");
+        assert!(sent(&rx) == [(100, Command::TransferData)]);
+
+        // Not due yet
+        commander.update();
+        assert!(sent(&rx).is_empty());
+
+        for _ in 1..MAX_ATTEMPTS {
+            commander.pending[0].sent_at -= REPLY_TIMEOUT;
+            commander.update();
+            assert!(sent(&rx) == [(100, Command::TransferData)]);
+        }
+
+        commander.pending[0].sent_at -= REPLY_TIMEOUT;
+        commander.update();
+        assert!(sent(&rx).is_empty());
+        assert!(commander.pending.is_empty());
+        assert!(commander.get_status() == "Transfer got no reply");
+    }
+
+    #[test]
+    fn replies_settle_their_command() {
+        let (mut commander, rx) = commander();
+
+        press(&mut commander, "\trs");
+        assert!(sent(&rx).len() == 2);
+
+        // Replies to commands sent from elsewhere are left alone
+        commander.on_reply(&Packet::Ack { seq: 99 });
+        assert!(commander.get_status() == "Stop recording sent");
+
+        commander.on_reply(&Packet::Nak {
+            seq: 101,
+            reason: NakReason::WrongState,
+        });
+        assert!(commander.get_status() == "Stop recording rejected WrongState");
+
+        commander.on_reply(&Packet::Ack { seq: 100 });
+        assert!(commander.get_status() == "Start recording acknowledged");
+        assert!(commander.pending.is_empty());
+
+        // Nothing left to retry
+        commander.update();
+        assert!(sent(&rx).is_empty());
+    }
+
+    #[test]
+    fn ecu_answers_commands_over_loopback() {
+        let (mut commander, rx) = commander();
+
+        let link = CommsLoopback::new(NetworkAddress::MissionControl, ECU);
+        let mut mission_control = link.endpoint_a();
+        let mut comms = link.endpoint_b();
+        let mut hardware = ECUHardwareMock::new();
+        let mut ecu = Ecu::new(
+            0,
+            &mut HALs {
+                hardware: &mut hardware,
+                comms: &mut comms,
+            },
+        );
+
+        let mut exchange = |commander: &mut Commander, keys: &str| {
+            press(commander, keys);
+
+            for (packet, target) in rx.try_iter() {
+                mission_control.transmit(&packet, target).unwrap();
+            }
+
+            ecu.update(
+                &mut HALs {
+                    hardware: &mut hardware,
+                    comms: &mut comms,
+                },
+                0.001,
+            );
+
+            while let Some((packet, _)) = mission_control.receive() {
+                commander.on_reply(&packet);
+            }
+        };
+
+        exchange(&mut commander, "\t1y");
+        assert!(commander.get_status() == "Open IgniterFuelMain rejected NotArmed");
+
+        exchange(&mut commander, "my");
+        assert!(commander.get_status() == "Arm acknowledged");
+
+        exchange(&mut commander, "1y");
+        assert!(commander.get_status() == "Open IgniterFuelMain acknowledged");
+
+        exchange(&mut commander, "x");
+        assert!(commander.get_status() == "Abort acknowledged");
+        assert!(commander.pending.is_empty());
+
+        // The abort closed the valve opened before it
+        assert!(hardware.get_valve_states()[ECUValve::IgniterFuelMain as usize] == 0);
+    }
+}
@@ -4,18 +4,36 @@ use std::io::{Stdout, Write};
 
 use std::convert::TryFrom;
 
-use crossterm::style::Colorize;
+use crossterm::style::{Attribute, Color, Colorize};
 use crossterm::{cursor, execute, queue, style, terminal};
 
+use crate::{
+    alarm::{Alarm, Severity},
+    chart::{ChartEvent, ChartRow},
+};
+
 const SECOND_COL_POS: u16 = 32;
 
+/// Columns either side of a chart for its label and summary
+const CHART_LABEL_WIDTH: usize = 14;
+const CHART_SUMMARY_WIDTH: usize = 32;
+
+/// Alarms past this are summed up in a line, so the panel doesn't push everything off screen
+const MAX_SHOWN_ALARMS: usize = 6;
+
 #[allow(clippy::module_name_repetitions)]
 pub struct ConsoleDisplay {
     stdout: Stdout,
+    help: String,
     watchdogs: Vec<(String, bool)>,
     sensors: Vec<(String, String, f32, String, bool)>,
     valves: Vec<(String, String, bool)>,
     misc: Vec<(String, String)>,
+    chart_title: String,
+    charts: Vec<ChartRow>,
+    chart_events: String,
+    alarms: Vec<Alarm>,
+    ring_bell: bool,
 }
 
 impl ConsoleDisplay {
@@ -25,19 +43,29 @@ impl ConsoleDisplay {
 
         Self {
             stdout,
+            help: String::from(
+                "Press 'ESC' to exit. Press 'r' to toggle telemetry recording. \
+                 Press 'c' to send the sensor calibration to the ECU",
+            ),
             watchdogs: Vec::new(),
             sensors: Vec::new(),
             valves: Vec::new(),
             misc: Vec::new(),
+            chart_title: String::new(),
+            charts: Vec::new(),
+            chart_events: String::new(),
+            alarms: Vec::new(),
+            ring_bell: false,
         }
     }
 
     pub fn render(&mut self) {
         self.clear_terminal();
+        let help = format!(" - {}", self.help);
         queue!(
             self.stdout,
             style::Print("ctrl-view".blue()),
-            style::Print(" - Press 'ESC' to exit. Press 'r' to toggle telemetry recording")
+            style::Print(help)
         )
         .unwrap();
         queue!(self.stdout, cursor::MoveToNextLine(1)).unwrap();
@@ -146,9 +174,49 @@ impl ConsoleDisplay {
             row_num += 1;
         }
 
+        self.render_alarms();
+
+        if !self.chart_title.is_empty() {
+            self.render_charts();
+        }
+
+        if self.ring_bell {
+            queue!(self.stdout, style::Print('\u{7}')).unwrap();
+            self.ring_bell = false;
+        }
+
         self.stdout.flush().unwrap();
     }
 
+    /// The alarms on the annunciator panel, in the order they're shown
+    pub fn set_alarms(&mut self, alarms: Vec<Alarm>) {
+        self.alarms = alarms;
+    }
+
+    /// Rings the terminal bell on the next render
+    pub fn ring_bell(&mut self) {
+        self.ring_bell = true;
+    }
+
+    /// Charts are left out until this is called
+    pub fn set_charts(&mut self, title: &str, charts: Vec<ChartRow>, events: &str) {
+        self.chart_title = String::from(title);
+        self.charts = charts;
+        self.chart_events = String::from(events);
+    }
+
+    /// How many columns charts can take up without wrapping
+    pub fn get_chart_width(&self) -> usize {
+        let (columns, _) = terminal::size().unwrap_or((80, 24));
+
+        usize::from(columns).saturating_sub(CHART_LABEL_WIDTH + CHART_SUMMARY_WIDTH)
+    }
+
+    /// Replaces the key help shown next to the title
+    pub fn set_help(&mut self, help: &str) {
+        self.help = String::from(help);
+    }
+
     pub fn set_watchdog(&mut self, name: &str, value: bool) {
         let mut found = false;
         for watchdog in self.watchdogs.iter_mut() {
@@ -250,6 +318,101 @@ impl ConsoleDisplay {
         }
     }
 
+    fn render_alarms(&mut self) {
+        queue!(
+            self.stdout,
+            cursor::MoveToNextLine(2),
+            style::Print("Alarms".blue()),
+            style::Print(" - Press 'a' to acknowledge the latest, 'A' to acknowledge all")
+        )
+        .unwrap();
+
+        if self.alarms.is_empty() {
+            queue!(
+                self.stdout,
+                cursor::MoveToNextLine(1),
+                style::Print("None".green())
+            )
+            .unwrap();
+        }
+
+        for alarm in self.alarms.clone().iter().take(MAX_SHOWN_ALARMS) {
+            let (tag, color) = match alarm.severity {
+                Severity::Critical => ("CRIT", Color::Red),
+                Severity::Warning => ("WARN", Color::Yellow),
+            };
+
+            let mut text = style::style(format!(
+                "[{}] {}{}",
+                tag,
+                alarm.message,
+                if alarm.active { "" } else { " (cleared)" }
+            ))
+            .with(color);
+
+            // Unacknowledged alarms stand out until someone's seen them
+            if !alarm.acknowledged {
+                text = text.attribute(Attribute::Reverse);
+            } else if !alarm.active {
+                text = text.attribute(Attribute::Dim);
+            }
+
+            queue!(
+                self.stdout,
+                cursor::MoveToNextLine(1),
+                style::PrintStyledContent(text)
+            )
+            .unwrap();
+        }
+
+        if self.alarms.len() > MAX_SHOWN_ALARMS {
+            let more = format!("{} more", self.alarms.len() - MAX_SHOWN_ALARMS);
+            queue!(self.stdout, cursor::MoveToNextLine(1), style::Print(more)).unwrap();
+        }
+    }
+
+    fn render_charts(&mut self) {
+        let title = self.chart_title.clone();
+        queue!(
+            self.stdout,
+            cursor::MoveToNextLine(2),
+            style::Print(title.blue())
+        )
+        .unwrap();
+
+        for chart in &self.charts.clone() {
+            queue!(
+                self.stdout,
+                cursor::MoveToNextLine(1),
+                style::Print(format!(
+                    "{:width$}",
+                    format!("{}: ", chart.label),
+                    width = CHART_LABEL_WIDTH
+                ))
+            )
+            .unwrap();
+
+            for cell in chart.cells.iter() {
+                let glyph = style::style(cell.glyph).with(Color::Cyan);
+
+                // Valves are red when open, same as above
+                let glyph = match cell.event {
+                    Some(ChartEvent::ValveOpened(_)) => glyph.on(Color::DarkRed),
+                    Some(ChartEvent::ValveClosed(_)) => glyph.on(Color::DarkGreen),
+                    Some(ChartEvent::SparkOn | ChartEvent::SparkOff) => glyph.on(Color::DarkYellow),
+                    None => glyph,
+                };
+
+                queue!(self.stdout, style::PrintStyledContent(glyph)).unwrap();
+            }
+
+            queue!(self.stdout, style::Print(format!(" {}", chart.summary))).unwrap();
+        }
+
+        let events = format!("Events: {}", self.chart_events);
+        queue!(self.stdout, cursor::MoveToNextLine(1), style::Print(events)).unwrap();
+    }
+
     fn clear_terminal(&mut self) {
         queue!(
             self.stdout,
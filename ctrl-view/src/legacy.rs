@@ -0,0 +1,146 @@
+use std::{fmt, fs, io, path::Path};
+
+use hal::{comms_hal::NetworkAddress, ecu_hal::ECUDataFrame};
+use serde::Deserialize;
+
+use crate::record::{FrameData, SessionFrame, ECU_RECORD_RATE};
+
+/// A line of a `telem-data.log`, which is an `ECUTelemtryData` written with `{:?}`. Only frames
+/// transferred from the ECU were ever logged, so everything but `ecu_data` was made up by ctrl-view
+/// and gets ignored, along with fields older builds had.
+#[derive(Debug, Deserialize)]
+#[serde(rename = "ECUTelemtryData")]
+struct LegacyTelemetry {
+    ecu_data: ECUDataFrame,
+}
+
+#[derive(Debug)]
+pub enum LegacyError {
+    Io(io::Error),
+    /// The line with this number isn't a logged frame
+    BadLine(usize),
+}
+
+/// Reads every frame from a legacy `telem-data.log`, in the order they were logged
+///
+/// # Errors
+/// Returns a `LegacyError` if the file can't be read or has a line that isn't a frame
+pub fn load_log(path: &Path) -> Result<Vec<ECUDataFrame>, LegacyError> {
+    let text = fs::read_to_string(path).map_err(LegacyError::Io)?;
+
+    parse_log(&text)
+}
+
+/// # Errors
+/// Returns `LegacyError::BadLine` for the first line that isn't a frame
+pub fn parse_log(text: &str) -> Result<Vec<ECUDataFrame>, LegacyError> {
+    text.lines()
+        .enumerate()
+        .filter(|(_, line)| !line.trim().is_empty())
+        .map(|(index, line)| parse_line(line).ok_or(LegacyError::BadLine(index + 1)))
+        .collect()
+}
+
+/// `Debug` writes structs as `Name { field: value }` and arrays as `[a, b]`, which are RON's
+/// `Name(field: value)` and `(a, b)` with different brackets. Some builds added the calibrated
+/// readings after the frame, which are worked out again from the raw ones.
+pub fn parse_line(line: &str) -> Option<ECUDataFrame> {
+    let frame = line.split(" calibrated: ").next()?.trim();
+    let ron = frame
+        .replace(" { ", "(")
+        .replace(" }", ")")
+        .replace('[', "(")
+        .replace(']', ")");
+
+    ron::from_str::<LegacyTelemetry>(&ron)
+        .ok()
+        .map(|telemetry| telemetry.ecu_data)
+}
+
+/// Turns logged frames into a session's recorded frames. The log doesn't say when anything
+/// happened, so the first frame is at `started_us` and each one after is `ECU_RECORD_RATE` later,
+/// the same as the ECU recorded them.
+pub fn to_session_frames(
+    frames: &[ECUDataFrame],
+    source: NetworkAddress,
+    started_us: u64,
+) -> Vec<SessionFrame> {
+    frames
+        .iter()
+        .enumerate()
+        .map(|(index, ecu_data)| SessionFrame {
+            received_us: started_us + (index as f64 * ECU_RECORD_RATE * 1e6).round() as u64,
+            source,
+            data: FrameData::Recorded(*ecu_data),
+        })
+        .collect()
+}
+
+impl fmt::Display for LegacyError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LegacyError::Io(err) => write!(f, "Couldn't read log: {}", err),
+            LegacyError::BadLine(line) => write!(f, "Line {} isn't a logged frame", line),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use hal::{
+        comms_hal::ECUTelemtryData,
+        ecu_hal::{IgniterState, IgnitionResult},
+    };
+
+    use super::*;
+
+    /// The example line at the top of view.py, from a build that still logged the frame time and
+    /// the average loop time
+    const OLD_LINE: &str = "ECUTelemtryData { ecu_data: ECUDataFrame { time: 0.0, igniter_state: \
+                            Idle, sensor_states: [0, 0, 0, 398, 0], valve_states: [0, 0, 0, 0], \
+                            sparking: false }, avg_loop_time: 0.00009406, max_loop_time: \
+                            0.000000000000000000000000000000000000000023704 }";
+
+    #[test]
+    fn old_line_is_parsed() {
+        let frame = parse_line(OLD_LINE).unwrap();
+
+        assert!(frame.igniter_state == IgniterState::Idle);
+        assert!(frame.sensor_states == [0, 0, 0, 398, 0]);
+        assert!(frame.valve_states == [0, 0, 0, 0]);
+        assert!(!frame.sparking);
+    }
+
+    #[test]
+    fn current_line_is_parsed() {
+        let ecu_data = ECUDataFrame {
+            igniter_state: IgniterState::Firing,
+            sensor_states: [1, 2, 3, 4, 5],
+            valve_states: [255, 0, 255, 0],
+            sparking: true,
+        };
+        let line = format!(
+            "{:?}",
+            ECUTelemtryData {
+                ecu_data,
+                max_loop_time: 0.001,
+                ignition_result: IgnitionResult::Ignited,
+                armed: true,
+            }
+        );
+
+        assert!(parse_line(&line) == Some(ecu_data));
+        assert!(parse_line(&format!("{} calibrated: [1.0, 2.0]", line)) == Some(ecu_data));
+    }
+
+    #[test]
+    fn malformed_line_is_a_bad_line() {
+        let text = format!(
+            "{}\n\nECUTelemtryData {{ ecu_data: ECUDataFrame {{ igniter_st",
+            OLD_LINE
+        );
+
+        assert!(matches!(parse_log(&text), Err(LegacyError::BadLine(3))));
+        assert!(parse_log(OLD_LINE).unwrap().len() == 1);
+    }
+}
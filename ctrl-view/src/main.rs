@@ -1,42 +1,71 @@
 use std::{
+    env,
     io::stdout,
+    net::SocketAddr,
+    path::PathBuf,
+    process,
     sync::{
         atomic::{AtomicBool, Ordering},
-        mpsc,
+        mpsc::{self, Sender},
     },
-    time::Instant,
+    time::{Instant, SystemTime, UNIX_EPOCH},
 };
 
+use alarm::{AlarmConfig, AlarmPanel};
+use calibration::Calibration;
+use chart::Charts;
+use command::Commander;
+
+use comms_udp::config::{ControllerConfig, NetworkOptions};
 use crossterm::event::{self, Event, KeyCode, KeyEvent};
 use display::ConsoleDisplay;
 use hal::{
-    comms_hal::{ECUTelemtryData, Packet},
-    ecu_hal::{ECUSensor, ECUValve, IgniterState, ECU_SENSORS, ECU_VALVES},
+    comms_hal::{Command, ECUTelemtryData, NetworkAddress, Packet, PROTOCOL_VERSION},
+    ecu_hal::{ECUSensor, ECUValve, IgniterState, IgnitionResult, ECU_SENSORS, ECU_VALVES},
 };
+use record::{FrameData, RecordFormat, Session, SessionFrame, SessionWriter};
 use recv::RecvOutput;
+use replay::Replay;
 
+pub mod alarm;
+pub mod calibration;
+pub mod chart;
+pub mod command;
 pub mod display;
+pub mod legacy;
 pub mod record;
 pub mod recv;
+pub mod replay;
 
 pub(crate) static RUNNING: AtomicBool = AtomicBool::new(true);
 
 pub const WATCHDOG_TIMEOUT_MS: usize = 250;
 
-const DISPLAY_SENSORS: [(ECUSensor, &str, &str); 5] = [
-    (ECUSensor::FuelTankPressure, "Fuel Tank", "psi"),
-    (ECUSensor::IgniterThroatTemp, "IGN Throat", "\u{b0}C"),
-    (
-        ECUSensor::IgniterFuelInjectorPressure,
-        "IGN Fuel-Inj",
-        "psi",
-    ),
-    (ECUSensor::IgniterGOxInjectorPressure, "IGN GOx-Inj", "psi"),
-    (ECUSensor::IgniterChamberPressure, "IGN Chamber", "psi"),
+const EXIT_USAGE: i32 = 2;
+const EXIT_CONVERT: i32 = 3;
+
+const USAGE: &str = "Format: ctrl-view [--config <file>] [--bridge <address>] [--port <port>] \
+                     [--controller <name>] [--calibration <file>] [--alarms <file>] \
+                     [--record-dir <directory>] [--record-format <csv|binary|both>] \
+                     [--chart-window <seconds>] [--replay <session> [--emit <address>]]
+        ctrl-view convert <telem-data.log>... [--controller <name>] [--calibration <file>] \
+                     [--record-dir <directory>] [--record-format <csv|binary|both>]";
+
+const LIVE_HELP: &str =
+    "Press 'ESC' to exit, 'x' to abort the ECU and tab for commands. Press 'r' to \
+                         toggle telemetry recording and 'c' to send the sensor calibration";
+
+const REPLAY_HELP: &str = "Press 'ESC' to exit. Press space to pause, left/right to seek, \
+                           up/down to change speed and home to restart";
+
+const DISPLAY_SENSORS: [(ECUSensor, &str); 5] = [
+    (ECUSensor::FuelTankPressure, "Fuel Tank"),
+    (ECUSensor::IgniterThroatTemp, "IGN Throat"),
+    (ECUSensor::IgniterFuelInjectorPressure, "IGN Fuel-Inj"),
+    (ECUSensor::IgniterGOxInjectorPressure, "IGN GOx-Inj"),
+    (ECUSensor::IgniterChamberPressure, "IGN Chamber"),
 ];
 
-const SENSORS_CONFIG: [f32; 5] = [4096.0, 300.0, 200.0, 200.0, 300.0];
-
 const DISPLAY_VALVES: [(ECUValve, &str); 4] = [
     (ECUValve::FuelPress, "Fuel Press"),
     (ECUValve::FuelVent, "Fuel Vent"),
@@ -44,14 +73,137 @@ const DISPLAY_VALVES: [(ECUValve, &str); 4] = [
     (ECUValve::IgniterGOxMain, "IGN GOx Main"),
 ];
 
+struct Options {
+    calibration: Option<String>,
+    alarms: Option<String>,
+    /// Defaults to the working directory, or next to each log when converting
+    record_dir: Option<PathBuf>,
+    record_format: RecordFormat,
+    replay: Option<PathBuf>,
+    emit: Option<SocketAddr>,
+    /// Seconds of history the charts start out showing
+    chart_window: f64,
+    /// Legacy logs to convert, for `ctrl-view convert`
+    convert: Option<Vec<PathBuf>>,
+}
+
 fn main() {
+    let mut args: Vec<String> = env::args().collect();
+
+    let network = NetworkOptions::take_from(&mut args).unwrap_or_else(|err| {
+        println!("{}", err);
+        process::exit(EXIT_USAGE);
+    });
+
+    let options = parse_args(&args[1..]);
+
+    let replay_session = options.replay.as_ref().map(|path| {
+        Session::load(path).unwrap_or_else(|err| {
+            println!("{}", err);
+            process::exit(EXIT_USAGE);
+        })
+    });
+
+    let calibration = match (
+        &options.calibration,
+        replay_session
+            .as_ref()
+            .and_then(|session| session.calibration.clone()),
+    ) {
+        (Some(path), _) => Calibration::load(path),
+        // Shows the session how it was seen while it was recorded
+        (None, Some(session_calibration)) => Ok(session_calibration),
+        (None, None) => Calibration::parse(calibration::DEFAULT_CALIBRATION),
+    }
+    .unwrap_or_else(|err| {
+        println!("{}", err);
+        process::exit(EXIT_USAGE);
+    });
+
+    let alarm_config = match &options.alarms {
+        Some(path) => AlarmConfig::load(path),
+        None => AlarmConfig::parse(alarm::DEFAULT_ALARMS),
+    }
+    .unwrap_or_else(|err| {
+        println!("{}", err);
+        process::exit(EXIT_USAGE);
+    });
+
+    let config = network.load_config().unwrap_or_else(|err| {
+        println!("{}", err);
+        process::exit(EXIT_USAGE);
+    });
+
+    // Every controller gets a watchdog, but only this one's data is shown
+    let shown = config
+        .select_controller(network.controller.as_deref())
+        .unwrap_or_else(|err| {
+            println!("{}", err);
+            process::exit(EXIT_USAGE);
+        })
+        .address;
+
+    if let Some(logs) = &options.convert {
+        let converted = convert_logs(logs, &options, &calibration, shown);
+
+        process::exit(if converted { 0 } else { EXIT_CONVERT });
+    }
+
+    let comms_config = config.mission_control(network.port.unwrap_or(config.ctrl_view_port));
+    let controllers: Vec<NetworkAddress> = config
+        .controllers
+        .iter()
+        .map(|controller| controller.address)
+        .collect();
+
+    // Created before the display takes over the terminal, so problems can be printed
+    let replay = replay_session.map(|session| {
+        Replay::new(&session.frames, options.emit).unwrap_or_else(|err| {
+            println!("Couldn't set up emitting the replay: {}", err);
+            process::exit(EXIT_USAGE);
+        })
+    });
+
+    // Replays aren't recorded again
+    let session = if replay.is_none() {
+        let record_dir = options
+            .record_dir
+            .clone()
+            .unwrap_or_else(|| PathBuf::from("."));
+
+        Some(
+            SessionWriter::create(&record_dir, options.record_format, calibration.clone())
+                .unwrap_or_else(|err| {
+                    println!(
+                        "Couldn't create a session in {}: {}",
+                        record_dir.display(),
+                        err
+                    );
+                    process::exit(EXIT_USAGE);
+                }),
+        )
+    } else {
+        None
+    };
+    let session_paths = session
+        .as_ref()
+        .map_or_else(Vec::new, |session| session.get_paths().to_vec());
+
     let mut display = ConsoleDisplay::new(stdout());
 
-    display.set_watchdog("ECU(0)", false);
+    for controller in config.controllers.iter() {
+        display.set_watchdog(&controller.name, false);
+    }
     display.set_watchdog("CET", false);
 
-    for (sensor, name, units) in &DISPLAY_SENSORS {
-        display.set_sensor_full(&format!("{:?}", sensor), *name, -42.0, *units, false);
+    for (sensor, name) in &DISPLAY_SENSORS {
+        display.set_sensor_full(
+            &format!("{:?}", sensor),
+            name,
+            -42.0,
+            calibration.units(*sensor),
+            false,
+        );
     }
 
     for (valve, name) in &DISPLAY_VALVES {
@@ -59,118 +211,112 @@ fn main() {
     }
 
     display.set_misc("IGN State", &format!("{:?}", IgniterState::Idle));
+    display.set_misc("IGN Result", &format!("{:?}", IgnitionResult::NotFired));
     display.set_misc("Spark", "Off");
+    display.set_misc("Armed", "Safe");
     display.set_misc("ECU Max Δt", "0.0 ms");
     display.set_misc("ECU Loop Freq", "0 Hz");
     display.set_misc("Recv ECU Freq", "0 Hz");
-    display.set_misc("Recording", "false");
+    // Just the name, the paths are printed on exit and too long for the display
+    let session_name = match (&options.replay, session_paths.first()) {
+        (Some(path), _) => path.file_name(),
+        (None, Some(path)) => path.file_stem(),
+        (None, None) => None,
+    }
+    .map_or(String::new(), |name| name.to_string_lossy().into_owned());
+
+    if replay.is_some() {
+        display.set_help(REPLAY_HELP);
+        display.set_misc("Replay", "Starting");
+    } else {
+        display.set_misc("Recording", "false");
+        display.set_misc("Command", "None");
+    }
+    display.set_misc("Session", &session_name);
     display.set_misc("counter", "0");
+    display.set_misc("Protocol", "Unknown");
+    display.set_misc("Calibration", "Not sent");
 
     let (recv_thread_tx, recv_thread_rx) = mpsc::channel();
-    let (record_thread_tx, record_thread_rx) = mpsc::channel();
-
-    let recv_thread = std::thread::spawn(move || {
-        recv::packet_loop(recv_thread_tx);
-    });
-
-    let record_thread = std::thread::spawn(move || {
-        record::record_loop(record_thread_rx);
-    });
-
-    let mut ecu_timer_ms: usize = WATCHDOG_TIMEOUT_MS;
-    let mut cet_timer_ms: usize = WATCHDOG_TIMEOUT_MS;
-    let mut ecu_telem_counter: usize = 0;
+    let (send_thread_tx, send_thread_rx) = mpsc::channel();
+    // Replays stand in for the controllers, so there's nothing to talk to
+    let replay_tx = recv_thread_tx.clone();
+    let recv_thread = if replay.is_none() {
+        Some(std::thread::spawn(move || {
+            recv::packet_loop(recv_thread_tx, send_thread_rx, comms_config, controllers);
+        }))
+    } else {
+        None
+    };
+
+    let (record_thread_tx, record_thread) = match session {
+        Some(session) => {
+            let (record_thread_tx, record_thread_rx) = mpsc::channel();
+            let record_thread =
+                std::thread::spawn(move || record::record_loop(record_thread_rx, session));
+
+            (Some(record_thread_tx), Some(record_thread))
+        }
+        None => (None, None),
+    };
+
+    // Same as ctrl-send, so the ECU doesn't take a command for a retry of an older one. Nothing
+    // gets sent while replaying.
+    let commander = Commander::new(
+        send_thread_tx.clone(),
+        shown,
+        SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap()
+            .subsec_nanos() as u16,
+        &DISPLAY_VALVES.map(|(valve, _)| valve),
+    );
+
+    let charts = Charts::new(&DISPLAY_SENSORS, &calibration, options.chart_window);
+
+    let mut dashboard = Dashboard {
+        display,
+        controllers: config.controllers.clone(),
+        shown,
+        calibration,
+        commander,
+        calibration_pending: Vec::new(),
+        send_thread_tx,
+        record_thread_tx,
+        replay,
+        controller_timers_ms: vec![WATCHDOG_TIMEOUT_MS; config.controllers.len()],
+        controllers_seen: vec![false; config.controllers.len()],
+        cet_timer_ms: WATCHDOG_TIMEOUT_MS,
+        cet_seen: false,
+        ecu_telem_counter: 0,
+        counter: 0,
+        recording: false,
+        charts,
+        alarms: AlarmPanel::new(alarm_config),
+    };
     let mut timer: f64 = 0.0;
-    let mut counter = 0;
-    let mut recording = false;
 
     loop {
         let start_time = Instant::now();
 
-        display.render();
+        dashboard.render();
 
         if event::poll(std::time::Duration::from_millis(1)).unwrap() {
             if let Ok(Event::Key(KeyEvent { code, .. })) = event::read() {
-                if code == KeyCode::Esc {
+                if !dashboard.handle_key(code) {
                     break;
-                } else if code == KeyCode::Char('r') {
-                    recording = !recording;
-                    display.set_misc("Recording", &format!("{}", recording));
                 }
             }
         }
 
+        dashboard.update_commands(&replay_tx);
+
         loop {
             match recv_thread_rx.try_recv() {
-                Ok(recv_output) => {
-                    if let RecvOutput::Packet(packet) = &recv_output {
-                        match packet {
-                            Packet::RecordedData(data) => {
-                                record_thread_tx
-                                    .send(ECUTelemtryData {
-                                        ecu_data: *data,
-                                        max_loop_time: -42.0,
-                                    })
-                                    .unwrap();
-                                counter += 1;
-                            }
-                            Packet::ECUTelemtry(data) => {
-                                ecu_timer_ms = 0;
-                                ecu_telem_counter += 1;
-
-                                for ((value, sensor), range) in data
-                                    .ecu_data
-                                    .sensor_states
-                                    .iter()
-                                    .zip(ECU_SENSORS.iter())
-                                    .zip(SENSORS_CONFIG.iter())
-                                {
-                                    let voltage = 5.0 * ((*value as f32) / 4095.0);
-                                    let lerp = (voltage - 0.5) / 4.0;
-
-                                    display.set_sensor_value(
-                                        &format!("{:?}", *sensor),
-                                        lerp * (*range),
-                                        true,
-                                    )
-                                }
-
-                                for (value, valve) in
-                                    data.ecu_data.valve_states.iter().zip(ECU_VALVES.iter())
-                                {
-                                    display.set_valve_value(&format!("{:?}", *valve), *value > 0);
-                                }
-
-                                display.set_misc(
-                                    "IGN State",
-                                    &format!("{:?}", data.ecu_data.igniter_state),
-                                );
-                                display.set_misc(
-                                    "Spark",
-                                    if data.ecu_data.sparking {
-                                        "Sparking"
-                                    } else {
-                                        "Off"
-                                    },
-                                );
-                                display.set_misc(
-                                    "ECU Max Δt",
-                                    &format!("{:.3} ms", data.max_loop_time * 1000.0),
-                                );
-                                display.set_misc(
-                                    "ECU Loop Freq",
-                                    &format!("{:.1} kHz", 1e-3 / data.max_loop_time),
-                                );
-                                display.set_misc("counter", &format!("{}", counter));
-                            }
-                            _ => {}
-                        }
-                    }
-
-                    if let RecvOutput::CETPulse = &recv_output {
-                        cet_timer_ms = 0;
-                    }
+                Ok(RecvOutput::Packet(packet, from, received_at)) => {
+                    dashboard.handle_packet(&packet, from, received_at)
                 }
+                Ok(RecvOutput::CETPulse) => dashboard.cet_timer_ms = 0,
                 Err(err) => {
                     if err == mpsc::TryRecvError::Empty {
                         break;
@@ -179,25 +325,18 @@ fn main() {
             }
         }
 
-        if ecu_timer_ms >= WATCHDOG_TIMEOUT_MS {
-            display.set_watchdog("ECU(0)", false);
-        } else {
-            display.set_watchdog("ECU(0)", true);
-        }
-
-        if cet_timer_ms >= WATCHDOG_TIMEOUT_MS {
-            display.set_watchdog("CET", false);
-        } else {
-            display.set_watchdog("CET", true);
-        }
+        dashboard.update_watchdogs();
+        dashboard.update_alarms();
 
         std::thread::sleep(std::time::Duration::from_millis(10));
-        ecu_timer_ms += 10;
-        cet_timer_ms += 10;
+        dashboard.cet_timer_ms += 10;
 
         if timer >= 1.0 {
-            display.set_misc("Recv ECU Freq", &format!("{:?} Hz", ecu_telem_counter));
-            ecu_telem_counter = 0;
+            dashboard.display.set_misc(
+                "Recv ECU Freq",
+                &format!("{:?} Hz", dashboard.ecu_telem_counter),
+            );
+            dashboard.ecu_telem_counter = 0;
             timer = 0.0;
         }
 
@@ -207,7 +346,510 @@ fn main() {
 
     RUNNING.store(false, Ordering::Relaxed);
 
-    display.quit();
-    recv_thread.join().unwrap();
-    record_thread.join().unwrap();
+    dashboard.display.quit();
+    if let Some(recv_thread) = recv_thread {
+        recv_thread.join().unwrap();
+    }
+
+    match record_thread.map(|record_thread| record_thread.join().unwrap()) {
+        Some(Ok(())) => {
+            for path in session_paths.iter() {
+                println!("Session saved to {}", path.display());
+            }
+        }
+        Some(Err(err)) => println!("Couldn't write the session: {}", err),
+        None => {}
+    }
+}
+
+/// Everything the live display and replays keep track of between loops
+struct Dashboard {
+    display: ConsoleDisplay,
+    controllers: Vec<ControllerConfig>,
+    /// The controller whose data is shown
+    shown: NetworkAddress,
+    calibration: Calibration,
+    commander: Commander,
+    /// Seqs of the ConfigureSensor commands the ECU hasn't acknowledged yet
+    calibration_pending: Vec<u16>,
+    send_thread_tx: Sender<(Packet, NetworkAddress)>,
+    /// Nothing is recorded while replaying
+    record_thread_tx: Option<Sender<SessionFrame>>,
+    replay: Option<Replay>,
+    controller_timers_ms: Vec<usize>,
+    /// Watchdogs only alarm once they've been fed, so nothing goes off before everything's up
+    controllers_seen: Vec<bool>,
+    cet_timer_ms: usize,
+    cet_seen: bool,
+    ecu_telem_counter: usize,
+    counter: usize,
+    recording: bool,
+    charts: Charts,
+    alarms: AlarmPanel,
+}
+
+impl Dashboard {
+    fn render(&mut self) {
+        let now = record::unix_time_us(SystemTime::now()) as f64 / 1e6;
+        self.display.set_charts(
+            &format!(
+                "Last {} s - Press 1-{} to toggle charts, +/- to change the window",
+                self.charts.get_window(),
+                DISPLAY_SENSORS.len()
+            ),
+            self.charts.rows(now, self.display.get_chart_width()),
+            &self.charts.recent_events(now),
+        );
+
+        self.display.render();
+    }
+
+    /// Returns false once the operator wants to leave
+    fn handle_key(&mut self, code: KeyCode) -> bool {
+        if self.replay.is_none() && self.commander.on_key(code) {
+            // Commands come first, so the abort key always works and command mode can use keys
+            // that mean something else outside it
+        } else if code == KeyCode::Esc {
+            return false;
+        } else if let KeyCode::Char(digit @ '1'..='9') = code {
+            self.charts.toggle(digit as usize - '1' as usize);
+        } else if code == KeyCode::Char('a') {
+            self.alarms.acknowledge_latest();
+        } else if code == KeyCode::Char('A') {
+            self.alarms.acknowledge_all();
+        } else if code == KeyCode::Char('+') || code == KeyCode::Char('=') {
+            self.charts.zoom(2.0);
+        } else if code == KeyCode::Char('-') {
+            self.charts.zoom(0.5);
+        } else if let Some(replay) = &mut self.replay {
+            match code {
+                KeyCode::Char(' ') => replay.toggle_pause(),
+                KeyCode::Left => replay.seek(-replay::SEEK_STEP),
+                KeyCode::Right => replay.seek(replay::SEEK_STEP),
+                KeyCode::Up => replay.change_speed(2.0),
+                KeyCode::Down => replay.change_speed(0.5),
+                KeyCode::Home => replay.seek_to(0.0),
+                _ => {}
+            }
+        } else if code == KeyCode::Char('r') {
+            self.recording = !self.recording;
+            self.display
+                .set_misc("Recording", &format!("{}", self.recording));
+        } else if code == KeyCode::Char('c') {
+            self.send_calibration();
+        }
+
+        true
+    }
+
+    fn send_calibration(&mut self) {
+        self.calibration_pending.clear();
+
+        for sensor_calibration in self.calibration.sensors.iter() {
+            let seq = self.commander.take_seq();
+
+            let command = Command::ConfigureSensor {
+                sensor: sensor_calibration.sensor,
+                config: sensor_calibration.to_sensor_config(),
+            };
+
+            self.send_thread_tx
+                .send((Packet::Command { seq, command }, self.shown))
+                .unwrap();
+            self.calibration_pending.push(seq);
+        }
+
+        self.display.set_misc(
+            "Calibration",
+            &format!("Sent {}", self.calibration_pending.len()),
+        );
+    }
+
+    /// Retries commands, or moves the replay along
+    fn update_commands(&mut self, replay_tx: &Sender<RecvOutput>) {
+        if self.replay.is_none() {
+            self.commander.update();
+
+            self.display.set_help(
+                &self
+                    .commander
+                    .get_help()
+                    .unwrap_or_else(|| LIVE_HELP.to_string()),
+            );
+            self.display
+                .set_misc("Command", self.commander.get_status());
+        }
+
+        if let Some(replay) = &mut self.replay {
+            replay.update(replay_tx);
+
+            self.display.set_misc(
+                "Replay",
+                &format!(
+                    "{:.1}/{:.1} s {}x{}",
+                    replay.get_position(),
+                    replay.get_duration(),
+                    replay.get_speed(),
+                    if replay.is_finished() {
+                        " Finished"
+                    } else if replay.is_paused() {
+                        " Paused"
+                    } else {
+                        ""
+                    }
+                ),
+            );
+        }
+    }
+
+    /// Only fails once the record thread stopped on a write error. Nothing is recorded while
+    /// replaying.
+    fn record(&self, data: FrameData, source: NetworkAddress, received_at: SystemTime) -> bool {
+        let frame = SessionFrame {
+            received_us: record::unix_time_us(received_at),
+            source,
+            data,
+        };
+
+        self.record_thread_tx
+            .as_ref()
+            .is_none_or(|record_thread_tx| record_thread_tx.send(frame).is_ok())
+    }
+
+    fn handle_packet(&mut self, packet: &Packet, from: NetworkAddress, received_at: SystemTime) {
+        if let Some(index) = self
+            .controllers
+            .iter()
+            .position(|controller| controller.address == from)
+        {
+            match packet {
+                Packet::ECUTelemtry(_) => self.controller_timers_ms[index] = 0,
+                // Whichever controller aborted, the operator needs to know
+                Packet::ControllerAborted { address, reason } => {
+                    self.alarms.controller_aborted(
+                        &self.controllers[index].name,
+                        *address,
+                        *reason,
+                    );
+                }
+                _ => {}
+            }
+        }
+
+        if from != self.shown {
+            return;
+        }
+
+        match packet {
+            Packet::Ack { seq } if self.calibration_pending.contains(seq) => {
+                self.calibration_pending.retain(|pending| pending != seq);
+
+                self.display.set_misc(
+                    "Calibration",
+                    &if self.calibration_pending.is_empty() {
+                        "Acknowledged".to_string()
+                    } else {
+                        format!("{} unacknowledged", self.calibration_pending.len())
+                    },
+                );
+            }
+            Packet::Nak { seq, reason } if self.calibration_pending.contains(seq) => {
+                self.calibration_pending.retain(|pending| pending != seq);
+
+                self.display
+                    .set_misc("Calibration", &format!("Rejected: {:?}", reason));
+            }
+            // Transferred frames are always recorded, they're what the ECU saw during a firing
+            Packet::RecordedData(data) => {
+                if !self.record(FrameData::Recorded(*data), from, received_at) {
+                    self.display.set_misc("Recording", "FAILED");
+                }
+                self.counter += 1;
+            }
+            Packet::RecordedDataBatch(frames) => {
+                for data in frames.iter() {
+                    if !self.record(FrameData::Recorded(*data), from, received_at) {
+                        self.display.set_misc("Recording", "FAILED");
+                    }
+                    self.counter += 1;
+                }
+            }
+            Packet::Ack { .. } | Packet::Nak { .. } => self.commander.on_reply(packet),
+            Packet::ECUTelemtry(data) => self.show_telemetry(data, from, received_at),
+            Packet::Hello {
+                protocol_version,
+                build_id,
+                ..
+            } => {
+                let protocol = if *protocol_version == PROTOCOL_VERSION {
+                    format!(
+                        "v{} {}",
+                        protocol_version,
+                        String::from_utf8_lossy(build_id).trim_end_matches('\0')
+                    )
+                } else {
+                    format!(
+                        "MISMATCH v{}, expected v{}",
+                        protocol_version, PROTOCOL_VERSION
+                    )
+                };
+
+                self.display.set_misc("Protocol", &protocol);
+            }
+            _ => {}
+        }
+    }
+
+    fn show_telemetry(
+        &mut self,
+        data: &ECUTelemtryData,
+        from: NetworkAddress,
+        received_at: SystemTime,
+    ) {
+        self.ecu_telem_counter += 1;
+        self.commander.on_valve_states(&data.ecu_data.valve_states);
+
+        self.alarms.check_telemetry(data, &self.calibration);
+
+        if self.recording && !self.record(FrameData::Telemetry(data.clone()), from, received_at) {
+            self.display.set_misc("Recording", "FAILED");
+        }
+
+        self.charts.push(
+            record::unix_time_us(received_at) as f64 / 1e6,
+            &data.ecu_data,
+            &self.calibration,
+        );
+
+        for (value, sensor) in data.ecu_data.sensor_states.iter().zip(ECU_SENSORS.iter()) {
+            self.display.set_sensor_value(
+                &format!("{:?}", *sensor),
+                self.calibration.convert(*sensor, *value),
+                self.alarms.is_sensor_ok(*sensor),
+            )
+        }
+
+        for (value, valve) in data.ecu_data.valve_states.iter().zip(ECU_VALVES.iter()) {
+            self.display
+                .set_valve_value(&format!("{:?}", *valve), *value > 0);
+        }
+
+        self.display
+            .set_misc("IGN State", &format!("{:?}", data.ecu_data.igniter_state));
+        self.display
+            .set_misc("IGN Result", &format!("{:?}", data.ignition_result));
+        self.display.set_misc(
+            "Spark",
+            if data.ecu_data.sparking {
+                "Sparking"
+            } else {
+                "Off"
+            },
+        );
+        self.display
+            .set_misc("Armed", if data.armed { "ARMED" } else { "Safe" });
+        self.display.set_misc(
+            "ECU Max Δt",
+            &format!("{:.3} ms", data.max_loop_time * 1000.0),
+        );
+        self.display.set_misc(
+            "ECU Loop Freq",
+            &format!("{:.1} kHz", 1e-3 / data.max_loop_time),
+        );
+        self.display
+            .set_misc("counter", &format!("{}", self.counter));
+    }
+
+    fn update_watchdogs(&mut self) {
+        for ((controller, timer_ms), seen) in self
+            .controllers
+            .iter()
+            .zip(self.controller_timers_ms.iter_mut())
+            .zip(self.controllers_seen.iter_mut())
+        {
+            let alive = *timer_ms < WATCHDOG_TIMEOUT_MS;
+            self.display.set_watchdog(&controller.name, alive);
+            *timer_ms += 10;
+
+            // Pausing or seeking a replay starves the watchdogs, which isn't worth an alarm
+            *seen |= alive;
+            if *seen && self.replay.is_none() {
+                self.alarms.check_watchdog(&controller.name, alive);
+            }
+        }
+
+        let cet_alive = self.cet_timer_ms < WATCHDOG_TIMEOUT_MS;
+        self.display.set_watchdog("CET", cet_alive);
+
+        self.cet_seen |= cet_alive;
+        if self.cet_seen && self.replay.is_none() {
+            self.alarms.check_watchdog("CET", cet_alive);
+        }
+    }
+
+    fn update_alarms(&mut self) {
+        // The alarm history is always recorded, so the session shows what the operator was told
+        let now = SystemTime::now();
+        for record in self.alarms.take_records() {
+            if !self.record(
+                FrameData::Alarm(record),
+                NetworkAddress::MissionControl,
+                now,
+            ) {
+                self.display.set_misc("Recording", "FAILED");
+            }
+        }
+
+        self.display
+            .set_alarms(self.alarms.get_alarms().into_iter().cloned().collect());
+        if self.alarms.take_bell() {
+            self.display.ring_bell();
+        }
+    }
+}
+
+fn parse_args(args: &[String]) -> Options {
+    let mut options = Options {
+        calibration: None,
+        alarms: None,
+        record_dir: None,
+        record_format: RecordFormat::Csv,
+        replay: None,
+        emit: None,
+        chart_window: chart::DEFAULT_WINDOW,
+        convert: None,
+    };
+
+    let mut args = args.iter().peekable();
+
+    if args.peek().map(|arg| arg.as_str()) == Some("convert") {
+        args.next();
+        options.convert = Some(Vec::new());
+    }
+
+    while let Some(option) = args.next() {
+        if let (Some(logs), false) = (&mut options.convert, option.starts_with("--")) {
+            logs.push(PathBuf::from(option));
+            continue;
+        }
+
+        let value = match (option.as_str(), args.next()) {
+            (
+                "--calibration" | "--alarms" | "--record-dir" | "--record-format"
+                | "--chart-window" | "--replay" | "--emit",
+                Some(value),
+            ) => value,
+            _ => {
+                println!("Unknown args. {}", USAGE);
+                process::exit(EXIT_USAGE);
+            }
+        };
+
+        match option.as_str() {
+            "--calibration" => options.calibration = Some(value.clone()),
+            "--alarms" => options.alarms = Some(value.clone()),
+            "--record-dir" => options.record_dir = Some(PathBuf::from(value)),
+            "--record-format" => {
+                options.record_format = value.parse().unwrap_or_else(|err| {
+                    println!("{}. {}", err, USAGE);
+                    process::exit(EXIT_USAGE);
+                })
+            }
+            "--chart-window" => {
+                options.chart_window = value
+                    .parse()
+                    .ok()
+                    .filter(|window: &f64| window.is_finite() && *window > 0.0)
+                    .unwrap_or_else(|| {
+                        println!("Bad number of seconds for --chart-window. {}", USAGE);
+                        process::exit(EXIT_USAGE);
+                    })
+            }
+            "--replay" => options.replay = Some(PathBuf::from(value)),
+            _ => {
+                options.emit = Some(value.parse().unwrap_or_else(|_| {
+                    println!("Bad address for --emit. {}", USAGE);
+                    process::exit(EXIT_USAGE);
+                }))
+            }
+        }
+    }
+
+    if options.emit.is_some() && options.replay.is_none() {
+        println!("--emit only works with --replay. {}", USAGE);
+        process::exit(EXIT_USAGE);
+    }
+
+    match &options.convert {
+        Some(logs) if logs.is_empty() => {
+            println!("Nothing to convert. {}", USAGE);
+            process::exit(EXIT_USAGE);
+        }
+        Some(_) if options.replay.is_some() => {
+            println!("--replay doesn't work with convert. {}", USAGE);
+            process::exit(EXIT_USAGE);
+        }
+        _ => {}
+    }
+
+    options
+}
+
+/// Converts legacy `telem-data.log`s into sessions named after them, as if `source` had sent
+/// them. Returns whether every log was converted.
+fn convert_logs(
+    logs: &[PathBuf],
+    options: &Options,
+    calibration: &Calibration,
+    source: NetworkAddress,
+) -> bool {
+    let mut converted = true;
+
+    for log in logs {
+        let frames = match legacy::load_log(log) {
+            Ok(frames) => frames,
+            Err(err) => {
+                println!("Skipping {}: {}", log.display(), err);
+                converted = false;
+                continue;
+            }
+        };
+
+        let directory = match &options.record_dir {
+            Some(directory) => directory.clone(),
+            None => log.parent().map(PathBuf::from).unwrap_or_default(),
+        };
+        let name = directory.join(log.file_stem().unwrap_or_default());
+
+        // The logs don't say when they were recorded, so every converted session starts at 0
+        let result =
+            SessionWriter::create_named(&name, options.record_format, calibration.clone(), 0)
+                .and_then(|mut writer| {
+                    for frame in legacy::to_session_frames(&frames, source, 0).iter() {
+                        writer.write(frame)?;
+                    }
+
+                    let paths = writer.get_paths().to_vec();
+                    writer.finish().map(|()| paths)
+                });
+
+        match result {
+            Ok(paths) => {
+                for path in paths {
+                    println!(
+                        "Converted {} frames from {} to {}",
+                        frames.len(),
+                        log.display(),
+                        path.display()
+                    );
+                }
+            }
+            Err(err) => {
+                println!("Couldn't convert {}: {}", log.display(), err);
+                converted = false;
+            }
+        }
+    }
+
+    converted
 }
@@ -1,35 +1,693 @@
 use std::io::prelude::*;
 use std::{
-    fs::OpenOptions,
+    fmt,
+    fs::{self, File},
+    io::{self, BufWriter},
+    path::{Path, PathBuf},
+    str::FromStr,
     sync::{
         atomic::Ordering,
         mpsc::{Receiver, RecvTimeoutError},
     },
-    time::Duration,
+    time::{Duration, SystemTime, UNIX_EPOCH},
 };
 
-use hal::comms_hal::ECUTelemtryData;
+use hal::{
+    comms_hal::{ECUTelemtryData, NetworkAddress},
+    ecu_hal::{
+        ECUDataFrame, ECUSensor, ECUValve, ECU_SENSORS, ECU_VALVES, MAX_ECU_SENSORS, MAX_ECU_VALVES,
+    },
+};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
 
-use crate::RUNNING;
+use crate::{alarm::AlarmRecord, calibration::Calibration, RUNNING};
 
-pub fn record_loop(recv_telem: Receiver<ECUTelemtryData>) {
-    while RUNNING.load(Ordering::Relaxed) {
-        match recv_telem.recv_timeout(Duration::from_millis(10)) {
-            Ok(telem_data) => {
-                let mut file = OpenOptions::new()
-                    .create(true)
-                    .write(true)
-                    .append(true)
-                    .open("telem-data.log")
-                    .unwrap();
-
-                writeln!(file, "{:?}", telem_data).unwrap();
+/// Starts every binary session file, followed by the version
+pub const SESSION_MAGIC: [u8; 8] = *b"CTRLVIEW";
+pub const SESSION_VERSION: u16 = 1;
+
+/// Longest a single frame can be once serialized, with room to spare
+const MAX_FRAME_LENGTH: usize = 256;
+
+/// How often the ECU records a frame while recording, the same as its `RECORD_RATE`
+pub const ECU_RECORD_RATE: f64 = 0.001;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RecordFormat {
+    Csv,
+    Binary,
+    Both,
+}
+
+/// A frame of data as it was received, with where and when it came from
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SessionFrame {
+    /// Microseconds since the Unix epoch when ctrl-view received the packet
+    pub received_us: u64,
+    pub source: NetworkAddress,
+    pub data: FrameData,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum FrameData {
+    /// Live telemetry
+    Telemetry(ECUTelemtryData),
+    /// A frame the ECU recorded itself and transferred afterwards. These arrive in batches, so
+    /// frames are `RECORD_RATE` apart rather than when they were received.
+    Recorded(ECUDataFrame),
+    /// An operator alarm changing state, raised by ctrl-view rather than received
+    Alarm(AlarmRecord),
+}
+
+/// Describes everything needed to read a binary session back. It's written as RON text after the
+/// magic and version, so it can be read by eye too.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SessionHeader {
+    /// Microseconds since the Unix epoch when the session started
+    pub started_us: u64,
+    /// The order of `sensor_states` in every frame
+    pub sensors: Vec<ECUSensor>,
+    /// The order of `valve_states` in every frame
+    pub valves: Vec<ECUValve>,
+    /// How the raw sensor readings were turned into engineering units during the session
+    pub calibration: Calibration,
+    /// How each frame is laid out
+    pub frame_layout: String,
+}
+
+/// A session read back from a file
+#[derive(Debug, Clone)]
+pub struct Session {
+    /// Only binary sessions save their calibration
+    pub calibration: Option<Calibration>,
+    /// In the order they were received
+    pub frames: Vec<SessionFrame>,
+}
+
+#[derive(Debug)]
+pub enum SessionError {
+    Io(io::Error),
+    /// Not a session file, or one from a newer version of ctrl-view
+    BadHeader,
+    /// The binary frame at this index couldn't be read
+    BadFrame(usize),
+    /// The CSV row on this line couldn't be read
+    BadRow(usize),
+}
+
+/// Writes a session to CSV, binary or both. Files are opened once and buffered, so `finish()`
+/// has to be called for the last frames to make it to disk.
+pub struct SessionWriter {
+    csv: Option<BufWriter<File>>,
+    binary: Option<BufWriter<File>>,
+    calibration: Calibration,
+    paths: Vec<PathBuf>,
+}
+
+impl SessionWriter {
+    /// Creates `session-<unix time>.csv` and/or `.bin` in `directory`, and writes their headers
+    ///
+    /// # Errors
+    /// Returns whatever creating or writing to the files failed with
+    pub fn create(
+        directory: &Path,
+        format: RecordFormat,
+        calibration: Calibration,
+    ) -> io::Result<SessionWriter> {
+        let started_us = unix_time_us(SystemTime::now());
+        let name = directory.join(format!("session-{}", started_us / 1_000_000));
+
+        SessionWriter::create_named(&name, format, calibration, started_us)
+    }
+
+    /// Creates `<name>.csv` and/or `<name>.bin`, for a session that started at `started_us`
+    /// microseconds since the Unix epoch
+    ///
+    /// # Errors
+    /// Returns whatever creating or writing to the files failed with
+    pub fn create_named(
+        name: &Path,
+        format: RecordFormat,
+        calibration: Calibration,
+        started_us: u64,
+    ) -> io::Result<SessionWriter> {
+        let mut writer = SessionWriter {
+            csv: None,
+            binary: None,
+            calibration,
+            paths: Vec::new(),
+        };
+
+        if format != RecordFormat::Binary {
+            let path = with_suffix(name, ".csv");
+            let mut csv = BufWriter::new(File::create(&path)?);
+
+            writer.write_csv_header(&mut csv, started_us)?;
+            writer.csv = Some(csv);
+            writer.paths.push(path);
+        }
+
+        if format != RecordFormat::Csv {
+            let path = with_suffix(name, ".bin");
+            let mut binary = BufWriter::new(File::create(&path)?);
+
+            writer.write_binary_header(&mut binary, started_us)?;
+            writer.binary = Some(binary);
+            writer.paths.push(path);
+        }
+
+        Ok(writer)
+    }
+
+    pub fn get_paths(&self) -> &[PathBuf] {
+        &self.paths
+    }
+
+    /// # Errors
+    /// Returns whatever writing to the files failed with
+    pub fn write(&mut self, frame: &SessionFrame) -> io::Result<()> {
+        if let Some(csv) = &mut self.csv {
+            write_csv_row(csv, frame, &self.calibration)?;
+        }
+
+        if let Some(binary) = &mut self.binary {
+            let mut buffer = [0_u8; MAX_FRAME_LENGTH];
+            let serialized = postcard::to_slice(frame, &mut buffer)
+                .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, format!("{:?}", err)))?;
+
+            // Can't truncate, MAX_FRAME_LENGTH fits in a u16
+            binary.write_all(&(serialized.len() as u16).to_le_bytes())?;
+            binary.write_all(serialized)?;
+        }
+
+        Ok(())
+    }
+
+    /// Flushes everything to disk
+    ///
+    /// # Errors
+    /// Returns whatever flushing the files failed with
+    pub fn finish(mut self) -> io::Result<()> {
+        if let Some(csv) = &mut self.csv {
+            csv.flush()?;
+        }
+
+        if let Some(binary) = &mut self.binary {
+            binary.flush()?;
+        }
+
+        Ok(())
+    }
+
+    fn write_csv_header(&self, csv: &mut impl Write, started_us: u64) -> io::Result<()> {
+        writeln!(
+            csv,
+            "# ctrl-view session v{}, started {:.6}",
+            SESSION_VERSION,
+            started_us as f64 / 1e6
+        )?;
+
+        let mut columns = vec![
+            "received_s".to_string(),
+            "source".to_string(),
+            "kind".to_string(),
+            "igniter_state".to_string(),
+        ];
+        columns.extend(ECU_SENSORS.iter().map(|sensor| format!("{:?}_raw", sensor)));
+        columns.extend(
+            ECU_SENSORS
+                .iter()
+                .map(|sensor| format!("{:?} ({})", sensor, self.calibration.units(*sensor))),
+        );
+        columns.extend(ECU_VALVES.iter().map(|valve| format!("{:?}", valve)));
+        columns.extend(
+            ["sparking", "max_loop_time_s", "ignition_result", "armed"]
+                .iter()
+                .map(|column| column.to_string()),
+        );
+
+        writeln!(csv, "{}", columns.join(","))
+    }
+
+    fn write_binary_header(&self, binary: &mut impl Write, started_us: u64) -> io::Result<()> {
+        let header = SessionHeader {
+            started_us,
+            sensors: ECU_SENSORS.to_vec(),
+            valves: ECU_VALVES.to_vec(),
+            calibration: self.calibration.clone(),
+            frame_layout: "u16 LE length, then a postcard serialized SessionFrame".to_string(),
+        };
+
+        let text = ron::ser::to_string_pretty(&header, ron::ser::PrettyConfig::default())
+            .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err.to_string()))?;
+
+        binary.write_all(&SESSION_MAGIC)?;
+        binary.write_all(&SESSION_VERSION.to_le_bytes())?;
+        // Can't truncate, the header is only a few kilobytes
+        binary.write_all(&(text.len() as u32).to_le_bytes())?;
+        binary.write_all(text.as_bytes())
+    }
+}
+
+impl Session {
+    /// Reads a session written by `SessionWriter`, working out whether it's CSV or binary from
+    /// its contents. A binary session cut short by a crash is read up to its last whole frame.
+    ///
+    /// # Errors
+    /// Returns a `SessionError` if the file can't be read or isn't a session
+    pub fn load(path: &Path) -> Result<Session, SessionError> {
+        let contents = fs::read(path).map_err(SessionError::Io)?;
+
+        if contents.starts_with(&SESSION_MAGIC) {
+            Session::parse_binary(&contents)
+        } else {
+            let text = String::from_utf8(contents).map_err(|_| SessionError::BadHeader)?;
+
+            Session::parse_csv(&text)
+        }
+    }
+
+    /// # Errors
+    /// Returns a `SessionError` if `contents` isn't a binary session
+    pub fn parse_binary(contents: &[u8]) -> Result<Session, SessionError> {
+        let header_start = SESSION_MAGIC.len() + 6;
+
+        if contents.len() < header_start || !contents.starts_with(&SESSION_MAGIC) {
+            return Err(SessionError::BadHeader);
+        }
+
+        let version = u16::from_le_bytes([contents[8], contents[9]]);
+        let header_length =
+            u32::from_le_bytes([contents[10], contents[11], contents[12], contents[13]]) as usize;
+
+        if version != SESSION_VERSION || contents.len() < header_start + header_length {
+            return Err(SessionError::BadHeader);
+        }
+
+        let header: SessionHeader =
+            ron::de::from_bytes(&contents[header_start..header_start + header_length])
+                .map_err(|_| SessionError::BadHeader)?;
+
+        let mut frames = Vec::new();
+        let mut remaining = &contents[header_start + header_length..];
+
+        while remaining.len() >= 2 {
+            let length = usize::from(u16::from_le_bytes([remaining[0], remaining[1]]));
+
+            if remaining.len() < 2 + length {
+                break;
             }
-            Err(err) => {
-                if err != RecvTimeoutError::Timeout {
-                    panic!("record_loop: {:?}", err);
-                }
+
+            let frame = postcard::from_bytes(&remaining[2..2 + length])
+                .map_err(|_| SessionError::BadFrame(frames.len()))?;
+
+            frames.push(frame);
+            remaining = &remaining[2 + length..];
+        }
+
+        Ok(Session {
+            calibration: Some(header.calibration),
+            frames,
+        })
+    }
+
+    /// # Errors
+    /// Returns a `SessionError` if `text` isn't a CSV session
+    pub fn parse_csv(text: &str) -> Result<Session, SessionError> {
+        let mut lines = text.lines().enumerate();
+
+        match lines.next() {
+            Some((_, line)) if line.starts_with("# ctrl-view session v") => {}
+            _ => return Err(SessionError::BadHeader),
+        }
+
+        // The column names
+        if lines.next().is_none() {
+            return Err(SessionError::BadHeader);
+        }
+
+        let mut frames = Vec::new();
+
+        for (index, line) in lines {
+            // Alarms are written as comments
+            if line.is_empty() || line.starts_with('#') {
+                continue;
             }
+
+            let frame = parse_csv_row(line).ok_or(SessionError::BadRow(index + 1))?;
+            frames.push(frame);
+        }
+
+        Ok(Session {
+            calibration: None,
+            frames,
+        })
+    }
+}
+
+impl fmt::Display for SessionError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SessionError::Io(err) => write!(f, "Couldn't read session: {}", err),
+            SessionError::BadHeader => write!(f, "Not a ctrl-view v{} session", SESSION_VERSION),
+            SessionError::BadFrame(index) => write!(f, "Frame {} of the session is corrupt", index),
+            SessionError::BadRow(line) => write!(f, "Line {} of the session is corrupt", line),
+        }
+    }
+}
+
+impl FromStr for RecordFormat {
+    type Err = String;
+
+    fn from_str(text: &str) -> Result<Self, Self::Err> {
+        match text {
+            "csv" => Ok(RecordFormat::Csv),
+            "binary" => Ok(RecordFormat::Binary),
+            "both" => Ok(RecordFormat::Both),
+            _ => Err(format!(
+                "Unknown record format {}, expected csv, binary or both",
+                text
+            )),
+        }
+    }
+}
+
+impl fmt::Display for FrameData {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FrameData::Telemetry(_) => write!(f, "telemetry"),
+            FrameData::Recorded(_) => write!(f, "recorded"),
+            FrameData::Alarm(_) => write!(f, "alarm"),
+        }
+    }
+}
+
+/// Records frames until ctrl-view exits, then flushes the session to disk
+///
+/// # Errors
+/// Returns whatever writing the session failed with, after which nothing more is recorded
+pub fn record_loop(
+    recv_frames: Receiver<SessionFrame>,
+    mut writer: SessionWriter,
+) -> io::Result<()> {
+    while RUNNING.load(Ordering::Relaxed) {
+        match recv_frames.recv_timeout(Duration::from_millis(10)) {
+            Ok(frame) => writer.write(&frame)?,
+            Err(RecvTimeoutError::Timeout) => {}
+            Err(RecvTimeoutError::Disconnected) => break,
+        }
+    }
+
+    // Anything received before exiting still belongs in the session
+    while let Ok(frame) = recv_frames.try_recv() {
+        writer.write(&frame)?;
+    }
+
+    writer.finish()
+}
+
+/// `Path::with_extension` would replace anything after a dot in the name
+fn with_suffix(name: &Path, suffix: &str) -> PathBuf {
+    let mut path = name.as_os_str().to_owned();
+    path.push(suffix);
+
+    PathBuf::from(path)
+}
+
+pub fn unix_time_us(time: SystemTime) -> u64 {
+    time.duration_since(UNIX_EPOCH)
+        .map_or(0, |since_epoch| since_epoch.as_micros() as u64)
+}
+
+fn write_csv_row(
+    csv: &mut impl Write,
+    frame: &SessionFrame,
+    calibration: &Calibration,
+) -> io::Result<()> {
+    let ecu_data = match &frame.data {
+        FrameData::Telemetry(telemetry) => &telemetry.ecu_data,
+        FrameData::Recorded(ecu_data) => ecu_data,
+        // Alarms don't fit the columns, so they're comments that CSV readers can skip
+        FrameData::Alarm(record) => {
+            return writeln!(
+                csv,
+                "# {:.6} alarm {}",
+                frame.received_us as f64 / 1e6,
+                record
+            );
         }
+    };
+
+    let mut row = vec![
+        format!("{:.6}", frame.received_us as f64 / 1e6),
+        format!("{:?}", frame.source),
+        frame.data.to_string(),
+        format!("{:?}", ecu_data.igniter_state),
+    ];
+    row.extend(ecu_data.sensor_states.iter().map(|raw| raw.to_string()));
+    row.extend(
+        ECU_SENSORS
+            .iter()
+            .zip(ecu_data.sensor_states.iter())
+            .map(|(sensor, raw)| format!("{:.3}", calibration.convert(*sensor, *raw))),
+    );
+    row.extend(ecu_data.valve_states.iter().map(|state| state.to_string()));
+    row.push(ecu_data.sparking.to_string());
+
+    // Only live telemetry has these
+    match &frame.data {
+        FrameData::Telemetry(telemetry) => {
+            row.push(format!("{:.6}", telemetry.max_loop_time));
+            row.push(format!("{:?}", telemetry.ignition_result));
+            row.push(telemetry.armed.to_string());
+        }
+        FrameData::Recorded(_) | FrameData::Alarm(_) => row.extend(vec![String::new(); 3]),
+    }
+
+    writeln!(csv, "{}", row.join(","))
+}
+
+/// The inverse of `write_csv_row`. The calibrated columns are skipped, they're worked out from the
+/// raw readings again when needed.
+fn parse_csv_row(line: &str) -> Option<SessionFrame> {
+    let fields: Vec<&str> = line.split(',').collect();
+
+    let sensors = fields.get(4..4 + ECU_SENSORS.len())?;
+    let valves_start = 4 + 2 * ECU_SENSORS.len();
+    let valves = fields.get(valves_start..valves_start + ECU_VALVES.len())?;
+    let rest = fields.get(valves_start + ECU_VALVES.len()..)?;
+
+    if rest.len() != 4 {
+        return None;
+    }
+
+    let received_s: f64 = fields[0].parse().ok()?;
+
+    let mut ecu_data = ECUDataFrame {
+        igniter_state: parse_ron(fields[3])?,
+        sensor_states: [0; MAX_ECU_SENSORS],
+        valve_states: [0; MAX_ECU_VALVES],
+        sparking: rest[0].parse().ok()?,
+    };
+
+    for (state, field) in ecu_data.sensor_states.iter_mut().zip(sensors.iter()) {
+        *state = field.parse().ok()?;
+    }
+
+    for (state, field) in ecu_data.valve_states.iter_mut().zip(valves.iter()) {
+        *state = field.parse().ok()?;
+    }
+
+    let data = match fields[2] {
+        "telemetry" => FrameData::Telemetry(ECUTelemtryData {
+            ecu_data,
+            max_loop_time: rest[1].parse().ok()?,
+            ignition_result: parse_ron(rest[2])?,
+            armed: rest[3].parse().ok()?,
+        }),
+        "recorded" => FrameData::Recorded(ecu_data),
+        _ => return None,
+    };
+
+    Some(SessionFrame {
+        received_us: (received_s * 1e6).round() as u64,
+        source: parse_ron(fields[1])?,
+        data,
+    })
+}
+
+/// Enums are written to CSV with their `Debug` format, which RON reads back
+fn parse_ron<T: DeserializeOwned>(field: &str) -> Option<T> {
+    ron::from_str(field).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use hal::ecu_hal::{IgniterState, IgnitionResult};
+
+    use super::*;
+    use crate::{
+        alarm::{AlarmTransition, Severity},
+        calibration::DEFAULT_CALIBRATION,
+    };
+
+    const STARTED_US: u64 = 1_700_000_000_000_000;
+
+    #[test]
+    fn csv_round_trip() {
+        let dir = test_dir("csv_round_trip");
+        let path = write_session(&dir, RecordFormat::Csv, &frames());
+
+        let session = Session::load(&path.with_extension("csv")).unwrap();
+
+        // Alarms are only comments in CSV
+        let expected: Vec<SessionFrame> = frames()
+            .into_iter()
+            .filter(|frame| !matches!(frame.data, FrameData::Alarm(_)))
+            .collect();
+        assert!(session.frames == expected);
+        assert!(session.calibration.is_none());
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn binary_round_trip() {
+        let dir = test_dir("binary_round_trip");
+        let path = write_session(&dir, RecordFormat::Binary, &frames());
+
+        let session = Session::load(&path.with_extension("bin")).unwrap();
+
+        assert!(session.frames == frames());
+        assert!(session.calibration.unwrap().sensors.len() == calibration().sensors.len());
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn truncated_binary_is_read_up_to_last_whole_frame() {
+        let dir = test_dir("truncated_binary");
+        let path = write_session(&dir, RecordFormat::Binary, &frames());
+
+        let mut contents = fs::read(path.with_extension("bin")).unwrap();
+        contents.truncate(contents.len() - 3);
+
+        let session = Session::parse_binary(&contents).unwrap();
+        assert!(session.frames == frames()[..frames().len() - 1]);
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn bad_headers_are_rejected() {
+        assert!(matches!(
+            Session::parse_csv("received_s,source\n"),
+            Err(SessionError::BadHeader)
+        ));
+        assert!(matches!(
+            Session::parse_csv("# ctrl-view session v1, started 0.0\n"),
+            Err(SessionError::BadHeader)
+        ));
+
+        let mut newer = SESSION_MAGIC.to_vec();
+        newer.extend((SESSION_VERSION + 1).to_le_bytes());
+        newer.extend(0_u32.to_le_bytes());
+        assert!(matches!(
+            Session::parse_binary(&newer),
+            Err(SessionError::BadHeader)
+        ));
+        assert!(matches!(
+            Session::parse_binary(&SESSION_MAGIC),
+            Err(SessionError::BadHeader)
+        ));
+    }
+
+    #[test]
+    fn bad_rows_are_rejected() {
+        let dir = test_dir("bad_rows");
+        let path = write_session(&dir, RecordFormat::Csv, &frames());
+
+        let mut text = fs::read_to_string(path.with_extension("csv")).unwrap();
+        text.push_str("1.0,ECU(0),telemetry,Idle\n");
+        let bad_line = text.lines().count();
+
+        match Session::parse_csv(&text) {
+            Err(SessionError::BadRow(line)) => assert!(line == bad_line),
+            other => panic!(
+                "Expected a bad row, got {:?}",
+                other.map(|session| session.frames)
+            ),
+        }
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    fn frames() -> Vec<SessionFrame> {
+        let ecu_data = ECUDataFrame {
+            igniter_state: IgniterState::Firing,
+            sensor_states: [1234; MAX_ECU_SENSORS],
+            valve_states: [255, 0, 255, 0],
+            sparking: true,
+        };
+
+        vec![
+            SessionFrame {
+                received_us: STARTED_US + 123_456,
+                source: NetworkAddress::EngineController(0),
+                data: FrameData::Telemetry(ECUTelemtryData {
+                    ecu_data,
+                    max_loop_time: 0.0015,
+                    ignition_result: IgnitionResult::Ignited,
+                    armed: true,
+                }),
+            },
+            SessionFrame {
+                received_us: STARTED_US + 200_000,
+                source: NetworkAddress::MissionControl,
+                data: FrameData::Alarm(AlarmRecord {
+                    transition: AlarmTransition::Raised,
+                    severity: Severity::Critical,
+                    message: String::from("IGN Chamber high"),
+                }),
+            },
+            SessionFrame {
+                received_us: STARTED_US + 1_000_000,
+                source: NetworkAddress::EngineController(0),
+                data: FrameData::Recorded(ecu_data),
+            },
+            SessionFrame {
+                received_us: STARTED_US + 1_001_000,
+                source: NetworkAddress::EngineController(0),
+                data: FrameData::Recorded(ECUDataFrame {
+                    igniter_state: IgniterState::Shutdown,
+                    ..ecu_data
+                }),
+            },
+        ]
+    }
+
+    fn calibration() -> Calibration {
+        Calibration::parse(DEFAULT_CALIBRATION).unwrap()
+    }
+
+    /// Writes `frames` to `<dir>/session`, returning that name
+    fn write_session(dir: &Path, format: RecordFormat, frames: &[SessionFrame]) -> PathBuf {
+        let name = dir.join("session");
+        let mut writer =
+            SessionWriter::create_named(&name, format, calibration(), STARTED_US).unwrap();
+
+        for frame in frames {
+            writer.write(frame).unwrap();
+        }
+        writer.finish().unwrap();
+
+        name
+    }
+
+    fn test_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("ctrl-view-{}-{}", name, std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+
+        dir
     }
 }
@@ -1,47 +1,103 @@
 use core::time;
 use std::{
-    io::ErrorKind,
-    net::UdpSocket,
-    sync::{atomic::Ordering, mpsc::Sender},
+    sync::{
+        atomic::Ordering,
+        mpsc::{Receiver, Sender},
+    },
+    time::{Instant, SystemTime},
 };
 
-use hal::comms_hal::comms_ethernet_hal::ETHERNET_BUFFER_SIZE;
-use hal::comms_hal::{comms_ethernet_hal, Packet};
+use comms_udp::{UDPComms, UDPCommsConfig};
+use hal::comms_hal::{
+    CommsInterface, ControllerKind, NetworkAddress, Packet, HEARTBEAT_RATE, PROTOCOL_VERSION,
+};
 
 use crate::RUNNING;
 
 pub enum RecvOutput {
-    Packet(Packet),
+    /// A packet, who sent it and when it was received
+    Packet(Packet, NetworkAddress, SystemTime),
     CETPulse,
 }
 
-pub fn packet_loop(recv_thread_tx: Sender<RecvOutput>) {
-    let socket = UdpSocket::bind("0.0.0.0:25565").unwrap();
-    socket
-        .set_read_timeout(Some(time::Duration::from_millis(10)))
-        .unwrap();
+/// Receives packets for the main thread, and sends the packets it's given to their controller
+pub fn packet_loop(
+    recv_thread_tx: Sender<RecvOutput>,
+    send_thread_rx: Receiver<(Packet, NetworkAddress)>,
+    config: UDPCommsConfig,
+    controllers: Vec<NetworkAddress>,
+) {
+    let mut comms = UDPComms::new(UDPCommsConfig {
+        read_timeout: Some(time::Duration::from_millis(10)),
+        ..config
+    })
+    .unwrap();
+
+    let mut last_heartbeat = Instant::now();
+
+    // Has the bridge send packets for mission control straight here instead of broadcasting them
+    let _ = comms.announce();
+
+    send_packet(
+        &mut comms,
+        &controllers,
+        &Packet::hello(ControllerKind::MissionControl, env!("CARGO_PKG_VERSION")),
+    );
+    send_packet(&mut comms, &controllers, &Packet::Heartbeat);
+
+    // Controllers that announced a different protocol version, their packets can't be trusted
+    let mut incompatible: Vec<NetworkAddress> = Vec::new();
+
+    let mut last_cet_pulse = None;
 
-    let mut buffer = [0_u8; ETHERNET_BUFFER_SIZE];
     while RUNNING.load(Ordering::Relaxed) {
-        match socket.recv_from(&mut buffer) {
-            Ok((_packet_size, _addr)) => {
-                if buffer[0] == 255 && buffer[2] == 255 {
-                    recv_thread_tx.send(RecvOutput::CETPulse).unwrap();
-                } else {
-                    let packet = comms_ethernet_hal::deserialize_packet(&mut buffer);
-
-                    if packet.is_ok() {
-                        recv_thread_tx
-                            .send(RecvOutput::Packet(packet.unwrap().0))
-                            .unwrap();
-                    }
-                }
-            }
-            Err(err) => {
-                if err.kind() != ErrorKind::WouldBlock && err.kind() != ErrorKind::TimedOut {
-                    panic!("{:?}", err);
+        if last_heartbeat.elapsed().as_secs_f32() >= HEARTBEAT_RATE {
+            send_packet(&mut comms, &controllers, &Packet::Heartbeat);
+            last_heartbeat = Instant::now();
+        }
+
+        while let Ok((packet, to)) = send_thread_rx.try_recv() {
+            // Commands are acknowledged, so the main thread finds out if this was lost
+            let _ = comms.transmit(&packet, to);
+        }
+
+        let received = comms.receive();
+        // Taken straight away, so the main thread being busy doesn't skew recorded times
+        let received_at = SystemTime::now();
+
+        if comms.get_last_cet_pulse() != last_cet_pulse {
+            last_cet_pulse = comms.get_last_cet_pulse();
+            recv_thread_tx.send(RecvOutput::CETPulse).unwrap();
+        }
+
+        if let Some((packet, from)) = received {
+            if let Packet::Hello {
+                protocol_version, ..
+            } = packet
+            {
+                incompatible.retain(|address| *address != from);
+
+                if protocol_version != PROTOCOL_VERSION {
+                    incompatible.push(from);
                 }
+            } else if incompatible.contains(&from) {
+                continue;
             }
+
+            recv_thread_tx
+                .send(RecvOutput::Packet(packet, from, received_at))
+                .unwrap();
         }
     }
+
+    // Closing ctrl-view isn't a loss of comms, so the ECUs shouldn't safe themselves over it
+    send_packet(&mut comms, &controllers, &Packet::Goodbye);
+}
+
+/// Heartbeats let the ECUs know mission control is still connected so they don't safe themselves
+fn send_packet(comms: &mut UDPComms, controllers: &[NetworkAddress], packet: &Packet) {
+    for controller in controllers {
+        // The ECU might not be reachable yet, which the watchdogs already show
+        let _ = comms.transmit(packet, *controller);
+    }
 }
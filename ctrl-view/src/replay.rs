@@ -0,0 +1,448 @@
+use std::{
+    io,
+    net::SocketAddr,
+    sync::mpsc::Sender,
+    time::{Instant, SystemTime},
+};
+
+use comms_udp::{UDPComms, UDPCommsConfig};
+use hal::{
+    comms_hal::{CommsInterface, ECUTelemtryData, NetworkAddress, Packet},
+    ecu_hal::IgnitionResult,
+};
+
+use crate::{
+    record::{FrameData, SessionFrame, ECU_RECORD_RATE},
+    recv::RecvOutput,
+};
+
+/// How far the arrow keys seek, in seconds of the session
+pub const SEEK_STEP: f64 = 5.0;
+
+pub const MIN_SPEED: f64 = 1.0 / 16.0;
+pub const MAX_SPEED: f64 = 16.0;
+
+/// Plays a recorded session back as if it was being received, so it goes through the same
+/// handling as live packets
+pub struct Replay {
+    /// Seconds into the session each packet was received, in order
+    timeline: Vec<(f64, Packet, NetworkAddress)>,
+    /// Index into `timeline` of the next packet to play
+    next: usize,
+    position: f64,
+    speed: f64,
+    paused: bool,
+    last_update: Instant,
+    emitter: Option<Emitter>,
+}
+
+/// Sends replayed packets over UDP as if the controllers that recorded them were sending them.
+/// Recorded frames go out as the `ECUTelemtry` they're replayed as rather than the `RecordedData`
+/// they were transferred in, so whatever's listening shows them like live telemetry.
+struct Emitter {
+    sources: Vec<(NetworkAddress, UDPComms)>,
+}
+
+impl Replay {
+    /// Transferred frames are played `ECU_RECORD_RATE` apart from when their transfer arrived, and
+    /// like live telemetry so they're displayed. If `emit_to` is given, every packet is sent there
+    /// too.
+    ///
+    /// # Errors
+    /// Returns whatever binding the sockets to emit from failed with
+    pub fn new(frames: &[SessionFrame], emit_to: Option<SocketAddr>) -> io::Result<Replay> {
+        let start_us = frames.first().map_or(0, |frame| frame.received_us);
+
+        let mut timeline: Vec<(f64, Packet, NetworkAddress)> = Vec::with_capacity(frames.len());
+        let mut last_telemetry: Option<&ECUTelemtryData> = None;
+        let mut last_recorded: Option<f64> = None;
+
+        // Alarms are raised again from the replayed telemetry
+        for frame in frames {
+            let received = frame.received_us.saturating_sub(start_us) as f64 / 1e6;
+
+            let (time, telemetry) = match &frame.data {
+                FrameData::Telemetry(telemetry) => {
+                    last_telemetry = Some(telemetry);
+                    last_recorded = None;
+
+                    (received, telemetry.clone())
+                }
+                FrameData::Recorded(ecu_data) => {
+                    let time = last_recorded.map_or(received, |last| last + ECU_RECORD_RATE);
+                    last_recorded = Some(time);
+
+                    // Recorded frames only have the ECU's data, the rest is whatever the ECU
+                    // last reported
+                    let telemetry = ECUTelemtryData {
+                        ecu_data: *ecu_data,
+                        max_loop_time: last_telemetry.map_or(0.0, |last| last.max_loop_time),
+                        ignition_result: last_telemetry
+                            .map_or(IgnitionResult::NotFired, |last| last.ignition_result),
+                        armed: last_telemetry.is_some_and(|last| last.armed),
+                    };
+
+                    (time, telemetry)
+                }
+                FrameData::Alarm(_) => continue,
+            };
+
+            timeline.push((time, Packet::ECUTelemtry(telemetry), frame.source));
+        }
+
+        // A batch can run past telemetry received after it
+        timeline.sort_by(|a, b| a.0.total_cmp(&b.0));
+
+        let emitter = match emit_to {
+            Some(remote) => Some(Emitter::new(remote, &timeline)?),
+            None => None,
+        };
+
+        Ok(Replay {
+            timeline,
+            next: 0,
+            position: 0.0,
+            speed: 1.0,
+            paused: false,
+            last_update: Instant::now(),
+            emitter,
+        })
+    }
+
+    /// Plays every packet that's due to `output`
+    pub fn update(&mut self, output: &Sender<RecvOutput>) {
+        let elapsed = self.last_update.elapsed().as_secs_f64();
+        self.last_update = Instant::now();
+
+        self.advance(elapsed, output);
+    }
+
+    /// Moves `elapsed` seconds of real time on, scaled by the speed
+    fn advance(&mut self, elapsed: f64, output: &Sender<RecvOutput>) {
+        if self.paused {
+            return;
+        }
+
+        self.position = (self.position + elapsed * self.speed).min(self.get_duration());
+
+        while let Some((time, packet, source)) = self.timeline.get(self.next) {
+            if *time > self.position {
+                break;
+            }
+
+            if let Some(emitter) = &mut self.emitter {
+                emitter.emit(packet, *source);
+            }
+
+            // The main thread only goes away once it's stopped updating the replay
+            let _ = output.send(RecvOutput::Packet(
+                packet.clone(),
+                *source,
+                SystemTime::now(),
+            ));
+
+            self.next += 1;
+        }
+
+        if self.is_finished() {
+            self.paused = true;
+        }
+    }
+
+    /// Resumes from the start once the session has finished
+    pub fn toggle_pause(&mut self) {
+        if self.is_finished() {
+            self.seek_to(0.0);
+            self.paused = false;
+        } else {
+            self.paused = !self.paused;
+        }
+    }
+
+    /// Moves by `seconds` of the session, backwards if negative
+    pub fn seek(&mut self, seconds: f64) {
+        self.seek_to(self.position + seconds);
+    }
+
+    pub fn seek_to(&mut self, position: f64) {
+        self.position = position.max(0.0).min(self.get_duration());
+        self.next = self
+            .timeline
+            .partition_point(|(time, _, _)| *time < self.position);
+    }
+
+    /// Multiplies the playback speed by `factor`, within `MIN_SPEED` and `MAX_SPEED`
+    pub fn change_speed(&mut self, factor: f64) {
+        self.speed = (self.speed * factor).clamp(MIN_SPEED, MAX_SPEED);
+    }
+
+    pub fn get_position(&self) -> f64 {
+        self.position
+    }
+
+    pub fn get_duration(&self) -> f64 {
+        self.timeline.last().map_or(0.0, |(time, _, _)| *time)
+    }
+
+    pub fn get_speed(&self) -> f64 {
+        self.speed
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.paused
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.next >= self.timeline.len()
+    }
+}
+
+impl Emitter {
+    /// Binds a socket for every controller in the session up front, so failures show up before
+    /// the replay starts
+    fn new(remote: SocketAddr, timeline: &[(f64, Packet, NetworkAddress)]) -> io::Result<Emitter> {
+        let mut sources: Vec<(NetworkAddress, UDPComms)> = Vec::new();
+
+        for (_, _, source) in timeline {
+            if sources.iter().any(|(address, _)| address == source) {
+                continue;
+            }
+
+            let comms = UDPComms::new(UDPCommsConfig {
+                address: *source,
+                local: SocketAddr::from(([0, 0, 0, 0], 0)),
+                remote,
+                read_timeout: None,
+            })?;
+
+            sources.push((*source, comms));
+        }
+
+        Ok(Emitter { sources })
+    }
+
+    fn emit(&mut self, packet: &Packet, source: NetworkAddress) {
+        if let Some((_, comms)) = self
+            .sources
+            .iter_mut()
+            .find(|(address, _)| *address == source)
+        {
+            // Nobody has to be listening, same as the ECU sending telemetry
+            let _ = comms.transmit(packet, NetworkAddress::MissionControl);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{sync::mpsc, time::Duration};
+
+    use hal::ecu_hal::{ECUDataFrame, IgniterState, MAX_ECU_SENSORS, MAX_ECU_VALVES};
+
+    use super::*;
+    use crate::alarm::{AlarmRecord, AlarmTransition, Severity};
+
+    const ECU: NetworkAddress = NetworkAddress::EngineController(0);
+
+    fn data(sensor: u16) -> ECUDataFrame {
+        ECUDataFrame {
+            igniter_state: IgniterState::Idle,
+            sensor_states: [sensor; MAX_ECU_SENSORS],
+            valve_states: [0; MAX_ECU_VALVES],
+            sparking: false,
+        }
+    }
+
+    fn frame(received_us: u64, data: FrameData) -> SessionFrame {
+        SessionFrame {
+            received_us,
+            source: ECU,
+            data,
+        }
+    }
+
+    fn telemetry(sensor: u16, armed: bool) -> FrameData {
+        FrameData::Telemetry(ECUTelemtryData {
+            ecu_data: data(sensor),
+            max_loop_time: 0.002,
+            ignition_result: IgnitionResult::NotFired,
+            armed,
+        })
+    }
+
+    /// Live telemetry at 0s and 1.0015s, with three recorded frames transferred at 1s
+    fn session() -> Vec<SessionFrame> {
+        vec![
+            frame(10_000_000, telemetry(0, true)),
+            frame(
+                10_500_000,
+                FrameData::Alarm(AlarmRecord {
+                    transition: AlarmTransition::Raised,
+                    severity: Severity::Warning,
+                    message: String::from("slow"),
+                }),
+            ),
+            frame(11_000_000, FrameData::Recorded(data(1))),
+            frame(11_000_000, FrameData::Recorded(data(2))),
+            frame(11_000_000, FrameData::Recorded(data(3))),
+            frame(11_001_500, telemetry(4, false)),
+        ]
+    }
+
+    /// The first sensor reading of every packet sent, which tells the frames apart
+    fn sent(output: &mpsc::Receiver<RecvOutput>) -> Vec<u16> {
+        output
+            .try_iter()
+            .map(|sent| match sent {
+                RecvOutput::Packet(Packet::ECUTelemtry(telemetry), source, _) => {
+                    assert!(source == ECU);
+                    telemetry.ecu_data.sensor_states[0]
+                }
+                _ => panic!("Replayed something other than telemetry"),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn recorded_frames_are_spaced_at_the_record_rate() {
+        let replay = Replay::new(&session(), None).unwrap();
+
+        let times: Vec<f64> = replay.timeline.iter().map(|(time, _, _)| *time).collect();
+        let expected = [
+            0.0,
+            1.0,
+            1.0 + ECU_RECORD_RATE,
+            1.0015,
+            1.0 + 2.0 * ECU_RECORD_RATE,
+        ];
+
+        assert!(times.len() == expected.len());
+        for (time, expected) in times.iter().zip(expected.iter()) {
+            assert!((time - expected).abs() < 1e-9, "{:?}", times);
+        }
+
+        // Recorded frames fill in the rest from the telemetry before them
+        match &replay.timeline[1].1 {
+            Packet::ECUTelemtry(telemetry) => {
+                assert!(telemetry.armed);
+                assert!(telemetry.ecu_data.sensor_states[0] == 1);
+            }
+            packet => panic!("Expected telemetry, got {:?}", packet),
+        }
+    }
+
+    #[test]
+    fn due_packets_are_played_in_order() {
+        let (tx, rx) = mpsc::channel();
+        let mut replay = Replay::new(&session(), None).unwrap();
+
+        replay.advance(0.5, &tx);
+        assert!(sent(&rx) == [0]);
+
+        replay.advance(0.5015, &tx);
+        assert!(sent(&rx) == [1, 2, 4]);
+
+        replay.advance(1.0, &tx);
+        assert!(sent(&rx) == [3]);
+        assert!(replay.is_finished());
+        assert!(replay.is_paused());
+        assert!((replay.get_position() - replay.get_duration()).abs() < 1e-9);
+    }
+
+    #[test]
+    fn paused_replays_hold_position() {
+        let (tx, rx) = mpsc::channel();
+        let mut replay = Replay::new(&session(), None).unwrap();
+
+        replay.toggle_pause();
+        replay.advance(5.0, &tx);
+
+        assert!(sent(&rx).is_empty());
+        assert!(replay.get_position() == 0.0);
+
+        replay.toggle_pause();
+        replay.advance(0.0, &tx);
+        assert!(sent(&rx) == [0]);
+    }
+
+    #[test]
+    fn seeking_picks_the_next_packet() {
+        let (tx, rx) = mpsc::channel();
+        let mut replay = Replay::new(&session(), None).unwrap();
+
+        replay.seek_to(1.0 + ECU_RECORD_RATE);
+        replay.advance(0.0, &tx);
+        assert!(sent(&rx) == [2]);
+
+        // Seeking back plays packets again
+        replay.seek(-SEEK_STEP);
+        assert!(replay.get_position() == 0.0);
+        replay.advance(0.0, &tx);
+        assert!(sent(&rx) == [0]);
+
+        // Seeking forward skips everything in between
+        replay.seek(SEEK_STEP);
+        assert!((replay.get_position() - replay.get_duration()).abs() < 1e-9);
+        replay.advance(0.0, &tx);
+        assert!(sent(&rx) == [3]);
+    }
+
+    #[test]
+    fn speed_scales_and_is_clamped() {
+        let (tx, rx) = mpsc::channel();
+        let mut replay = Replay::new(&session(), None).unwrap();
+
+        replay.change_speed(2.0);
+        replay.advance(0.5, &tx);
+        assert!(sent(&rx) == [0, 1]);
+
+        replay.change_speed(1000.0);
+        assert!(replay.get_speed() == MAX_SPEED);
+
+        replay.change_speed(1e-6);
+        assert!(replay.get_speed() == MIN_SPEED);
+    }
+
+    #[test]
+    fn unpausing_at_the_end_starts_over() {
+        let (tx, rx) = mpsc::channel();
+        let mut replay = Replay::new(&session(), None).unwrap();
+
+        replay.advance(10.0, &tx);
+        assert!(sent(&rx).len() == 5);
+        assert!(replay.is_paused());
+
+        replay.toggle_pause();
+        assert!(!replay.is_paused());
+        assert!(replay.get_position() == 0.0);
+
+        replay.advance(0.0, &tx);
+        assert!(sent(&rx) == [0]);
+    }
+
+    #[test]
+    fn emitted_packets_are_telemetry() {
+        let mut mission_control = UDPComms::new(UDPCommsConfig {
+            address: NetworkAddress::MissionControl,
+            local: SocketAddr::from(([127, 0, 0, 1], 0)),
+            remote: SocketAddr::from(([127, 0, 0, 1], 0)),
+            read_timeout: Some(Duration::from_millis(100)),
+        })
+        .unwrap();
+
+        let (tx, _rx) = mpsc::channel();
+        let mut replay =
+            Replay::new(&session(), Some(mission_control.local_addr().unwrap())).unwrap();
+
+        replay.seek_to(1.0);
+        replay.advance(0.0, &tx);
+
+        match mission_control.receive() {
+            Some((Packet::ECUTelemtry(telemetry), source)) => {
+                assert!(source == ECU);
+                assert!(telemetry.ecu_data.sensor_states[0] == 1);
+            }
+            other => panic!("Expected telemetry, got {:?}", other),
+        }
+    }
+}
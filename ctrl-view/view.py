@@ -73,6 +73,10 @@ for i in range(1, end - start):
             color = 'orange'
         elif states[i + start] == 'Firing':
             color = 'red'
+        elif states[i + start] == 'Shutdown':
+            color = 'purple'
+        elif states[i + start] == 'Purge':
+            color = 'blue'
 
         ax.axvspan(xpoints[state_start], xpoints[i], facecolor=color, alpha=0.25)
 
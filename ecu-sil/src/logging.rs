@@ -0,0 +1,27 @@
+//! Prints the ECU's log messages to stderr, standing in for the Teensy's USB logging
+
+use log::{LevelFilter, Log, Metadata, Record, SetLoggerError};
+
+struct StderrLogger;
+
+static LOGGER: StderrLogger = StderrLogger;
+
+impl Log for StderrLogger {
+    fn enabled(&self, _metadata: &Metadata) -> bool {
+        true
+    }
+
+    fn log(&self, record: &Record) {
+        eprintln!("[{}] {}", record.level(), record.args());
+    }
+
+    fn flush(&self) {}
+}
+
+/// Should only be called once
+pub fn init(level: LevelFilter) -> Result<(), SetLoggerError> {
+    log::set_logger(&LOGGER)?;
+    log::set_max_level(level);
+
+    Ok(())
+}
@@ -0,0 +1,253 @@
+use std::{
+    env,
+    net::SocketAddr,
+    process, thread,
+    time::{Duration, Instant},
+};
+
+use comms_udp::{UDPComms, UDPCommsConfig};
+use ecu::{Ecu, HALs};
+use ecu_sim::{igniter_model::IgniterModelConfig, ECUHardwareSim};
+use hal::comms_hal::{
+    comms_bridge::{BRIDGE_PORT, MISSION_CONTROL_PORT},
+    NetworkAddress,
+};
+use log::LevelFilter;
+
+mod logging;
+
+/// The Teensy runs its loop as fast as it can, which works out to around this
+const DEFAULT_LOOP_RATE: f32 = 1000.0;
+
+/// Same rate the transceiver sends its pulse at, so ctrl-view's CET watchdog stays happy
+const CET_PULSE_PERIOD: f32 = 0.1;
+
+const USAGE: &str = "Usage: ecu-sil [--bind <address>] [--remote <address>]... [--rate <hz>]";
+
+const EXIT_USAGE: i32 = 2;
+const EXIT_BIND: i32 = 3;
+
+struct Options {
+    bind: SocketAddr,
+    remotes: Vec<SocketAddr>,
+    /// Whether to reply to whoever announces themselves, like the bridge
+    follow_announcements: bool,
+    loop_rate: f32,
+}
+
+fn main() {
+    let options = parse_args(env::args().skip(1).collect());
+
+    logging::init(LevelFilter::Info).unwrap();
+
+    let mut comms = match UDPComms::new(UDPCommsConfig {
+        address: NetworkAddress::EngineController(0),
+        local: options.bind,
+        remote: options.remotes[0],
+        read_timeout: None,
+    }) {
+        Ok(comms) => comms,
+        Err(err) => {
+            eprintln!("Couldn't bind to {}: {}", options.bind, err);
+            process::exit(EXIT_BIND);
+        }
+    };
+
+    for remote in options.remotes.iter().skip(1) {
+        comms.add_remote(*remote);
+    }
+
+    if options.follow_announcements {
+        comms.follow_announcements();
+    }
+
+    let mut hardware = ECUHardwareSim::new(IgniterModelConfig::default());
+    let mut ecu = Ecu::new(
+        0,
+        &mut HALs {
+            hardware: &mut hardware,
+            comms: &mut comms,
+        },
+    );
+
+    log::info!(
+        "Listening on {}, sending to {:?} at {} Hz",
+        options.bind,
+        comms.get_remotes(),
+        options.loop_rate
+    );
+
+    let period = Duration::from_secs_f32(1.0 / options.loop_rate);
+    let mut last_delta = period;
+    let mut next_tick = Instant::now();
+    let mut pulse_timer = 0.0;
+
+    loop {
+        let start = Instant::now();
+
+        tick(
+            &mut ecu,
+            &mut hardware,
+            &mut comms,
+            &mut pulse_timer,
+            last_delta.as_secs_f32(),
+        );
+
+        next_tick += period;
+        let now = Instant::now();
+
+        if next_tick > now {
+            thread::sleep(next_tick - now);
+        } else {
+            // Fell behind, carry on from here rather than running a burst of short loops
+            if now - next_tick > period {
+                log::warn!("Loop overran by {:?}", now - next_tick);
+            }
+
+            next_tick = now;
+        }
+
+        last_delta = start.elapsed();
+    }
+}
+
+/// Steps the simulated hardware and the ECU, and sends the bridge's pulse whenever `pulse_timer`
+/// has counted up to it
+fn tick(
+    ecu: &mut Ecu,
+    hardware: &mut ECUHardwareSim,
+    comms: &mut UDPComms,
+    pulse_timer: &mut f32,
+    delta: f32,
+) {
+    hardware.update(delta);
+
+    ecu.update(&mut HALs { hardware, comms }, delta);
+
+    if *pulse_timer >= CET_PULSE_PERIOD {
+        if let Err(err) = comms.send_cet_pulse() {
+            log::error!("Failed to send CET pulse, got {:?}", err);
+        }
+
+        *pulse_timer = 0.0;
+    }
+
+    *pulse_timer += delta;
+}
+
+/// Defaults to standing in for the bridge, which replies to ctrl-view's port on this machine until
+/// mission control announces itself
+fn parse_args(args: Vec<String>) -> Options {
+    let mut options = Options {
+        bind: SocketAddr::from(([0, 0, 0, 0], BRIDGE_PORT)),
+        remotes: Vec::new(),
+        follow_announcements: false,
+        loop_rate: DEFAULT_LOOP_RATE,
+    };
+
+    let mut args = args.iter();
+
+    while let Some(arg) = args.next() {
+        let value = match args.next() {
+            Some(value) => value,
+            None => usage(),
+        };
+
+        match arg.as_str() {
+            "--bind" => options.bind = value.parse().unwrap_or_else(|_| usage()),
+            "--remote" => options
+                .remotes
+                .push(value.parse().unwrap_or_else(|_| usage())),
+            "--rate" => {
+                options.loop_rate = value.parse().unwrap_or_else(|_| usage());
+
+                if options.loop_rate <= 0.0 {
+                    usage();
+                }
+            }
+            _ => usage(),
+        }
+    }
+
+    if options.remotes.is_empty() {
+        options.remotes = vec![SocketAddr::from(([127, 0, 0, 1], MISSION_CONTROL_PORT))];
+        options.follow_announcements = true;
+    }
+
+    options
+}
+
+fn usage() -> ! {
+    println!("{}", USAGE);
+    process::exit(EXIT_USAGE);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use ecu::DEFAULT_TELEMETRY_RATE;
+    use hal::comms_hal::{CommsInterface, ControllerKind, Packet, PROTOCOL_VERSION};
+
+    const READ_TIMEOUT: Duration = Duration::from_millis(100);
+
+    #[test]
+    fn tick_runs_the_ecu_over_udp() {
+        let mut mission_control = UDPComms::new(config(NetworkAddress::MissionControl)).unwrap();
+
+        let mut ecu_config = config(NetworkAddress::EngineController(0));
+        ecu_config.remote = mission_control.local_addr().unwrap();
+        let mut comms = UDPComms::new(ecu_config).unwrap();
+
+        mission_control.set_remote(comms.local_addr().unwrap());
+
+        let mut hardware = ECUHardwareSim::new(IgniterModelConfig::default());
+        let mut ecu = Ecu::new(
+            0,
+            &mut HALs {
+                hardware: &mut hardware,
+                comms: &mut comms,
+            },
+        );
+
+        mission_control
+            .transmit(
+                &Packet::hello(ControllerKind::MissionControl, "test"),
+                NetworkAddress::EngineController(0),
+            )
+            .unwrap();
+
+        let mut pulse_timer = CET_PULSE_PERIOD;
+        tick(
+            &mut ecu,
+            &mut hardware,
+            &mut comms,
+            &mut pulse_timer,
+            DEFAULT_TELEMETRY_RATE,
+        );
+
+        assert!(ecu.get_mission_control_version() == Some(PROTOCOL_VERSION));
+        assert!((pulse_timer - DEFAULT_TELEMETRY_RATE).abs() < f32::EPSILON);
+
+        let mut got_telemetry = false;
+        while let Some((packet, from)) = mission_control.receive() {
+            assert!(from == NetworkAddress::EngineController(0));
+
+            if let Packet::ECUTelemtry(_) = packet {
+                got_telemetry = true;
+            }
+        }
+
+        assert!(got_telemetry);
+        assert!(mission_control.get_last_cet_pulse().is_some());
+    }
+
+    fn config(address: NetworkAddress) -> UDPCommsConfig {
+        UDPCommsConfig {
+            address,
+            local: SocketAddr::from(([127, 0, 0, 1], 0)),
+            remote: SocketAddr::from(([127, 0, 0, 1], 0)),
+            read_timeout: Some(READ_TIMEOUT),
+        }
+    }
+}
@@ -0,0 +1,87 @@
+use hal::{
+    ecu_hal::{raw_to_voltage, ECUSensor, ADC_MAX_READING, ADC_REFERENCE_VOLTAGE, MAX_ECU_SENSORS},
+    SensorConfig,
+};
+
+/// Describes how a physical value shows up as a voltage on one of the ECU's analog inputs
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SensorEncoding {
+    /// A ratiometric pressure transducer that outputs 0.5V at 0 psi and 4.5V at `full_scale` psi
+    PressureTransducer { full_scale: f32 },
+    /// A thermocouple amplifier with a linear output of `volts_per_degree` and `offset_volts`
+    /// at 0 degrees C
+    Thermocouple {
+        volts_per_degree: f32,
+        offset_volts: f32,
+    },
+}
+
+impl SensorEncoding {
+    pub fn to_voltage(&self, value: f32) -> f32 {
+        match self {
+            SensorEncoding::PressureTransducer { full_scale } => 0.5 + 4.0 * (value / full_scale),
+            SensorEncoding::Thermocouple {
+                volts_per_degree,
+                offset_volts,
+            } => offset_volts + value * volts_per_degree,
+        }
+    }
+
+    pub fn from_voltage(&self, voltage: f32) -> f32 {
+        match self {
+            SensorEncoding::PressureTransducer { full_scale } => (voltage - 0.5) / 4.0 * full_scale,
+            SensorEncoding::Thermocouple {
+                volts_per_degree,
+                offset_volts,
+            } => (voltage - offset_volts) / volts_per_degree,
+        }
+    }
+
+    /// Converts a physical value into the raw reading the ADC would report for it, clamping to
+    /// the range of the ADC
+    pub fn encode(&self, value: f32) -> u16 {
+        voltage_to_raw(self.to_voltage(value))
+    }
+
+    pub fn decode(&self, raw: u16) -> f32 {
+        self.from_voltage(raw_to_voltage(raw))
+    }
+
+    /// The `SensorConfig` that makes the ECU report this sensor in its physical units
+    pub fn to_sensor_config(&self) -> SensorConfig {
+        SensorConfig {
+            premin: 0.0,
+            premax: f32::from(ADC_MAX_READING),
+            postmin: self.from_voltage(0.0),
+            postmax: self.from_voltage(ADC_REFERENCE_VOLTAGE),
+        }
+    }
+}
+
+/// The sensors currently installed on the test stand, indexed by `ECUSensor`
+pub const DEFAULT_SENSOR_ENCODINGS: [SensorEncoding; MAX_ECU_SENSORS] = [
+    // IgniterThroatTemp, AD8495 K-type amplifier
+    SensorEncoding::Thermocouple {
+        volts_per_degree: 0.005,
+        offset_volts: 1.25,
+    },
+    // IgniterFuelInjectorPressure
+    SensorEncoding::PressureTransducer { full_scale: 300.0 },
+    // IgniterGOxInjectorPressure
+    SensorEncoding::PressureTransducer { full_scale: 200.0 },
+    // IgniterChamberPressure
+    SensorEncoding::PressureTransducer { full_scale: 200.0 },
+    // FuelTankPressure
+    SensorEncoding::PressureTransducer { full_scale: 300.0 },
+];
+
+pub fn default_sensor_encoding(sensor: ECUSensor) -> SensorEncoding {
+    DEFAULT_SENSOR_ENCODINGS[sensor as usize]
+}
+
+#[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+pub fn voltage_to_raw(voltage: f32) -> u16 {
+    let normalized = (voltage / ADC_REFERENCE_VOLTAGE).clamp(0.0, 1.0);
+
+    (normalized * f32::from(ADC_MAX_READING)).round() as u16
+}
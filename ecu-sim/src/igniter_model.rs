@@ -0,0 +1,234 @@
+use hal::ecu_hal::{ECUSensor, ECUValve, MAX_ECU_VALVES};
+
+/// Largest step the model will integrate over at once, longer updates get split up
+pub const MAX_MODEL_TIMESTEP: f32 = 0.0005;
+
+/// Tunable parameters for the igniter model. Pressures are gauge psi, temperatures are degrees
+/// C, and flows are in arbitrary but consistent units.
+#[derive(Debug, Clone, PartialEq)]
+pub struct IgniterModelConfig {
+    /// Regulated GOx pressure upstream of `IgniterGOxMain`
+    pub gox_supply_pressure: f32,
+    /// Regulated pressurant pressure upstream of `FuelPress`
+    pub pressurant_supply_pressure: f32,
+    pub initial_fuel_tank_pressure: f32,
+    pub ambient_temp: f32,
+    pub gox_flow_coefficient: f32,
+    pub fuel_flow_coefficient: f32,
+    /// Chamber pressure produced per unit of total flow without combustion
+    pub cold_flow_pressure_gain: f32,
+    /// Chamber pressure produced per unit of total flow with combustion
+    pub combustion_pressure_gain: f32,
+    /// Fraction of the pressure drop from feed to chamber that occurs before the injector tap
+    pub injector_pressure_ratio: f32,
+    pub min_ignition_mixture_ratio: f32,
+    pub max_ignition_mixture_ratio: f32,
+    /// Lets tests model a bad spark plug or a wet chamber that won't light
+    pub ignition_enabled: bool,
+    pub fuel_press_rate: f32,
+    pub fuel_vent_rate: f32,
+    /// Tank pressure lost per unit of fuel flow, i.e. ullage expansion as fuel drains
+    pub fuel_tank_droop: f32,
+    pub flame_temp: f32,
+    pub chamber_time_constant: f32,
+    pub injector_time_constant: f32,
+    pub throat_heating_time_constant: f32,
+    pub throat_cooling_time_constant: f32,
+}
+
+impl Default for IgniterModelConfig {
+    fn default() -> IgniterModelConfig {
+        IgniterModelConfig {
+            gox_supply_pressure: 200.0,
+            pressurant_supply_pressure: 250.0,
+            initial_fuel_tank_pressure: 0.0,
+            ambient_temp: 20.0,
+            gox_flow_coefficient: 1.0,
+            fuel_flow_coefficient: 0.5,
+            cold_flow_pressure_gain: 3.0,
+            combustion_pressure_gain: 10.0,
+            injector_pressure_ratio: 0.6,
+            min_ignition_mixture_ratio: 0.5,
+            max_ignition_mixture_ratio: 5.0,
+            ignition_enabled: true,
+            fuel_press_rate: 2.0,
+            fuel_vent_rate: 4.0,
+            fuel_tank_droop: 0.5,
+            flame_temp: 1100.0,
+            chamber_time_constant: 0.01,
+            injector_time_constant: 0.005,
+            throat_heating_time_constant: 0.3,
+            throat_cooling_time_constant: 2.0,
+        }
+    }
+}
+
+/// A lumped-parameter model of the torch igniter and its fuel tank. Each volume is treated as a
+/// first order lag towards the pressure its inflows and outflows would settle at.
+#[derive(Debug, Clone)]
+pub struct IgniterModel {
+    pub config: IgniterModelConfig,
+    fuel_tank_pressure: f32,
+    fuel_injector_pressure: f32,
+    gox_injector_pressure: f32,
+    chamber_pressure: f32,
+    throat_temp: f32,
+    gox_flow: f32,
+    fuel_flow: f32,
+    ignited: bool,
+}
+
+impl IgniterModel {
+    pub fn new(config: IgniterModelConfig) -> IgniterModel {
+        IgniterModel {
+            fuel_tank_pressure: config.initial_fuel_tank_pressure,
+            fuel_injector_pressure: 0.0,
+            gox_injector_pressure: 0.0,
+            chamber_pressure: 0.0,
+            throat_temp: config.ambient_temp,
+            gox_flow: 0.0,
+            fuel_flow: 0.0,
+            ignited: false,
+            config,
+        }
+    }
+
+    /// Advances the model by `elapsed` seconds with the given valve states and spark state
+    pub fn update(&mut self, elapsed: f32, valve_states: &[u8; MAX_ECU_VALVES], sparking: bool) {
+        let mut remaining = elapsed;
+
+        while remaining > 0.0 {
+            let dt = remaining.min(MAX_MODEL_TIMESTEP);
+            self.step(dt, valve_states, sparking);
+            remaining -= dt;
+        }
+    }
+
+    /// The true physical value a sensor would be measuring, before any ADC encoding
+    pub fn get_sensor_value(&self, sensor: ECUSensor) -> f32 {
+        match sensor {
+            ECUSensor::IgniterThroatTemp => self.throat_temp,
+            ECUSensor::IgniterFuelInjectorPressure => self.fuel_injector_pressure,
+            ECUSensor::IgniterGOxInjectorPressure => self.gox_injector_pressure,
+            ECUSensor::IgniterChamberPressure => self.chamber_pressure,
+            ECUSensor::FuelTankPressure => self.fuel_tank_pressure,
+        }
+    }
+
+    pub fn is_ignited(&self) -> bool {
+        self.ignited
+    }
+
+    pub fn get_gox_flow(&self) -> f32 {
+        self.gox_flow
+    }
+
+    pub fn get_fuel_flow(&self) -> f32 {
+        self.fuel_flow
+    }
+
+    pub fn set_fuel_tank_pressure(&mut self, pressure: f32) {
+        self.fuel_tank_pressure = pressure;
+    }
+
+    fn step(&mut self, dt: f32, valve_states: &[u8; MAX_ECU_VALVES], sparking: bool) {
+        let config = &self.config;
+        let fuel_main = valve_fraction(valve_states[ECUValve::IgniterFuelMain as usize]);
+        let gox_main = valve_fraction(valve_states[ECUValve::IgniterGOxMain as usize]);
+        let fuel_press = valve_fraction(valve_states[ECUValve::FuelPress as usize]);
+        let fuel_vent = valve_fraction(valve_states[ECUValve::FuelVent as usize]);
+
+        self.gox_flow = gox_main
+            * config.gox_flow_coefficient
+            * orifice_flow(config.gox_supply_pressure, self.chamber_pressure);
+        self.fuel_flow = fuel_main
+            * config.fuel_flow_coefficient
+            * orifice_flow(self.fuel_tank_pressure, self.chamber_pressure);
+
+        self.ignited = if self.gox_flow > 0.0 && self.fuel_flow > 0.0 {
+            let mixture_ratio = self.gox_flow / self.fuel_flow;
+            let lightable = config.ignition_enabled
+                && (config.min_ignition_mixture_ratio..=config.max_ignition_mixture_ratio)
+                    .contains(&mixture_ratio);
+
+            // Once lit the flame holds itself until one of the propellants stops
+            self.ignited || (sparking && lightable)
+        } else {
+            false
+        };
+
+        let pressure_gain = if self.ignited {
+            config.combustion_pressure_gain
+        } else {
+            config.cold_flow_pressure_gain
+        };
+        let chamber_target = (self.gox_flow + self.fuel_flow) * pressure_gain;
+
+        let gox_injector_target = if gox_main > 0.0 {
+            self.chamber_pressure
+                + (config.gox_supply_pressure - self.chamber_pressure).max(0.0)
+                    * config.injector_pressure_ratio
+        } else {
+            self.chamber_pressure
+        };
+
+        let fuel_injector_target = if fuel_main > 0.0 {
+            self.chamber_pressure
+                + (self.fuel_tank_pressure - self.chamber_pressure).max(0.0)
+                    * config.injector_pressure_ratio
+        } else {
+            self.chamber_pressure
+        };
+
+        let tank_rate = fuel_press
+            * config.fuel_press_rate
+            * (config.pressurant_supply_pressure - self.fuel_tank_pressure).max(0.0)
+            - fuel_vent * config.fuel_vent_rate * self.fuel_tank_pressure
+            - config.fuel_tank_droop * self.fuel_flow;
+
+        let (throat_target, throat_time_constant) = if self.ignited {
+            (config.flame_temp, config.throat_heating_time_constant)
+        } else {
+            (config.ambient_temp, config.throat_cooling_time_constant)
+        };
+
+        self.chamber_pressure = lag(
+            self.chamber_pressure,
+            chamber_target,
+            config.chamber_time_constant,
+            dt,
+        );
+        self.gox_injector_pressure = lag(
+            self.gox_injector_pressure,
+            gox_injector_target,
+            config.injector_time_constant,
+            dt,
+        );
+        self.fuel_injector_pressure = lag(
+            self.fuel_injector_pressure,
+            fuel_injector_target,
+            config.injector_time_constant,
+            dt,
+        );
+        self.throat_temp = lag(self.throat_temp, throat_target, throat_time_constant, dt);
+        self.fuel_tank_pressure = (self.fuel_tank_pressure + tank_rate * dt).max(0.0);
+    }
+}
+
+/// The Teensy drives the valves on or off, so any state above 0 is fully open
+fn valve_fraction(state: u8) -> f32 {
+    if state > 0 {
+        1.0
+    } else {
+        0.0
+    }
+}
+
+/// Incompressible orifice flow, proportional to the square root of the pressure drop
+fn orifice_flow(upstream: f32, downstream: f32) -> f32 {
+    (upstream - downstream).max(0.0).sqrt()
+}
+
+fn lag(current: f32, target: f32, time_constant: f32, dt: f32) -> f32 {
+    current + (target - current) * (1.0 - (-dt / time_constant).exp())
+}
@@ -0,0 +1,117 @@
+#![forbid(unsafe_code)]
+
+pub mod adc;
+pub mod igniter_model;
+
+use adc::{SensorEncoding, DEFAULT_SENSOR_ENCODINGS};
+use hal::{
+    ecu_hal::{ECUHardware, ECUSensor, ECUValve, ECU_SENSORS, MAX_ECU_SENSORS, MAX_ECU_VALVES},
+    SensorConfig,
+};
+use igniter_model::{IgniterModel, IgniterModelConfig};
+
+/// An `ECUHardware` implementation backed by a physical model of the torch igniter rather than
+/// real hardware. Valve and spark commands feed the model, and the model's outputs are run
+/// through the same ADC encoding the Teensy produces so the ECU sees realistic raw readings.
+pub struct ECUHardwareSim {
+    model: IgniterModel,
+    sensor_encodings: [SensorEncoding; MAX_ECU_SENSORS],
+    sensor_configs: [SensorConfig; MAX_ECU_SENSORS],
+    valve_states: [u8; MAX_ECU_VALVES],
+    raw_sensor_readings: [u16; MAX_ECU_SENSORS],
+    sensor_readings: [f32; MAX_ECU_SENSORS],
+    sparking: bool,
+}
+
+impl ECUHardwareSim {
+    pub fn new(config: IgniterModelConfig) -> ECUHardwareSim {
+        let mut inst = ECUHardwareSim {
+            model: IgniterModel::new(config),
+            sensor_encodings: DEFAULT_SENSOR_ENCODINGS,
+            sensor_configs: [SensorConfig::default(); MAX_ECU_SENSORS],
+            valve_states: [0_u8; MAX_ECU_VALVES],
+            raw_sensor_readings: [0_u16; MAX_ECU_SENSORS],
+            sensor_readings: [0.0_f32; MAX_ECU_SENSORS],
+            sparking: false,
+        };
+
+        inst.read_sensors();
+
+        inst
+    }
+
+    /// Steps the physical model forwards and then samples every sensor, the same as the Teensy
+    /// does at the top of each loop
+    pub fn update(&mut self, elapsed: f32) {
+        self.model
+            .update(elapsed, &self.valve_states, self.sparking);
+        self.read_sensors();
+    }
+
+    pub fn read_sensors(&mut self) {
+        for (index, ecu_sensor) in ECU_SENSORS.iter().enumerate() {
+            let raw_reading =
+                self.sensor_encodings[index].encode(self.model.get_sensor_value(*ecu_sensor));
+            let config = &self.sensor_configs[index];
+            let reading = (f32::from(raw_reading) - config.premin)
+                / (config.premax - config.premin)
+                * (config.postmax - config.postmin)
+                + config.postmin;
+
+            self.raw_sensor_readings[index] = raw_reading;
+            self.sensor_readings[index] = reading;
+        }
+    }
+
+    pub fn set_sensor_encoding(&mut self, sensor: ECUSensor, encoding: SensorEncoding) {
+        self.sensor_encodings[sensor as usize] = encoding;
+    }
+
+    pub fn get_sensor_encoding(&self, sensor: ECUSensor) -> SensorEncoding {
+        self.sensor_encodings[sensor as usize]
+    }
+
+    pub fn model(&self) -> &IgniterModel {
+        &self.model
+    }
+
+    pub fn model_mut(&mut self) -> &mut IgniterModel {
+        &mut self.model
+    }
+}
+
+impl ECUHardware for ECUHardwareSim {
+    fn set_valve(&mut self, valve: ECUValve, state: u8) {
+        self.valve_states[valve as usize] = state;
+    }
+
+    fn set_sparking(&mut self, state: bool) {
+        self.sparking = state;
+    }
+
+    fn get_sensor_value(&self, sensor: ECUSensor) -> f32 {
+        self.sensor_readings[sensor as usize]
+    }
+
+    fn get_raw_sensor_readings(&self) -> &[u16] {
+        &self.raw_sensor_readings
+    }
+
+    fn get_valve_states(&self) -> &[u8] {
+        &self.valve_states
+    }
+
+    fn get_sparking(&self) -> bool {
+        self.sparking
+    }
+
+    /// Configs that can't scale readings are ignored, keeping the last good one
+    fn configure_sensor(&mut self, sensor: ECUSensor, config: &SensorConfig) {
+        if !config.is_valid() {
+            return;
+        }
+
+        self.sensor_configs[sensor as usize] = *config;
+        self.read_sensors();
+    }
+}
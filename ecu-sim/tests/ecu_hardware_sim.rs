@@ -0,0 +1,234 @@
+use ecu_sim::{
+    adc::{self, SensorEncoding},
+    igniter_model::IgniterModelConfig,
+    ECUHardwareSim,
+};
+use hal::{
+    ecu_hal::{ECUHardware, ECUSensor, ECUValve, ADC_MAX_READING, ECU_SENSORS},
+    SensorConfig,
+};
+
+#[test]
+fn adc_encoding_round_trip() {
+    for sensor in ECU_SENSORS.iter() {
+        let encoding = adc::default_sensor_encoding(*sensor);
+
+        for value in [0.0_f32, 20.0, 100.0, 150.0].iter() {
+            let decoded = encoding.decode(encoding.encode(*value));
+
+            println!("{:?}: {} -> {}", sensor, value, decoded);
+            assert!((decoded - *value).abs() < 0.5);
+        }
+    }
+}
+
+#[test]
+fn adc_encoding_clamps() {
+    let encoding = SensorEncoding::PressureTransducer { full_scale: 100.0 };
+
+    assert!(encoding.encode(-1000.0) == 0);
+    assert!(encoding.encode(1000.0) == ADC_MAX_READING);
+    // 0.5V at zero pressure
+    assert!(encoding.encode(0.0) == 410);
+}
+
+#[test]
+fn idle_readings() {
+    let mut hardware = ECUHardwareSim::new(IgniterModelConfig::default());
+
+    hardware.update(1.0);
+
+    assert!(approx_reading(
+        &hardware,
+        ECUSensor::IgniterThroatTemp,
+        20.0,
+        1.0
+    ));
+    assert!(approx_reading(
+        &hardware,
+        ECUSensor::IgniterChamberPressure,
+        0.0,
+        0.5
+    ));
+    assert!(approx_reading(
+        &hardware,
+        ECUSensor::FuelTankPressure,
+        0.0,
+        0.5
+    ));
+    assert!(!hardware.model().is_ignited());
+}
+
+#[test]
+fn fuel_tank_press_and_vent() {
+    let mut hardware = ECUHardwareSim::new(IgniterModelConfig::default());
+
+    hardware.set_valve(ECUValve::FuelPress, 255);
+    hardware.update(5.0);
+
+    let pressurant = hardware.model().config.pressurant_supply_pressure;
+    assert!(approx_reading(
+        &hardware,
+        ECUSensor::FuelTankPressure,
+        pressurant,
+        2.0
+    ));
+
+    hardware.set_valve(ECUValve::FuelPress, 0);
+    hardware.set_valve(ECUValve::FuelVent, 255);
+    hardware.update(5.0);
+
+    assert!(approx_reading(
+        &hardware,
+        ECUSensor::FuelTankPressure,
+        0.0,
+        1.0
+    ));
+}
+
+#[test]
+fn gox_cold_flow() {
+    let mut hardware = ECUHardwareSim::new(IgniterModelConfig::default());
+
+    hardware.set_valve(ECUValve::IgniterGOxMain, 255);
+    hardware.set_sparking(true);
+    hardware.update(0.5);
+
+    let chamber = sensor_reading(&hardware, ECUSensor::IgniterChamberPressure);
+    let injector = sensor_reading(&hardware, ECUSensor::IgniterGOxInjectorPressure);
+
+    assert!(chamber > 10.0);
+    assert!(injector > chamber);
+    assert!(!hardware.model().is_ignited());
+    assert!(approx_reading(
+        &hardware,
+        ECUSensor::IgniterThroatTemp,
+        20.0,
+        1.0
+    ));
+}
+
+#[test]
+fn partly_open_valves_flow_fully() {
+    let mut full = ECUHardwareSim::new(IgniterModelConfig::default());
+    let mut partial = ECUHardwareSim::new(IgniterModelConfig::default());
+
+    full.set_valve(ECUValve::IgniterGOxMain, 255);
+    partial.set_valve(ECUValve::IgniterGOxMain, 1);
+    full.update(0.5);
+    partial.update(0.5);
+
+    assert!(full.model().get_gox_flow() > 0.0);
+    assert_eq!(full.model().get_gox_flow(), partial.model().get_gox_flow());
+}
+
+#[test]
+fn ignition_and_shutdown() {
+    let mut hardware = ECUHardwareSim::new(IgniterModelConfig {
+        initial_fuel_tank_pressure: 250.0,
+        ..IgniterModelConfig::default()
+    });
+
+    hardware.set_valve(ECUValve::IgniterGOxMain, 255);
+    hardware.update(0.25);
+    let cold_chamber = sensor_reading(&hardware, ECUSensor::IgniterChamberPressure);
+
+    hardware.set_valve(ECUValve::IgniterFuelMain, 255);
+    hardware.set_sparking(true);
+    hardware.update(1.0);
+
+    let hot_chamber = sensor_reading(&hardware, ECUSensor::IgniterChamberPressure);
+    assert!(hardware.model().is_ignited());
+    assert!(hot_chamber > cold_chamber * 2.0);
+    assert!(sensor_reading(&hardware, ECUSensor::IgniterThroatTemp) > 500.0);
+    assert!(sensor_reading(&hardware, ECUSensor::FuelTankPressure) < 250.0);
+
+    hardware.set_sparking(false);
+    hardware.set_valve(ECUValve::IgniterFuelMain, 0);
+    hardware.set_valve(ECUValve::IgniterGOxMain, 0);
+    hardware.update(0.5);
+
+    assert!(!hardware.model().is_ignited());
+    assert!(approx_reading(
+        &hardware,
+        ECUSensor::IgniterChamberPressure,
+        0.0,
+        0.5
+    ));
+}
+
+#[test]
+fn no_ignition_when_disabled() {
+    let mut hardware = ECUHardwareSim::new(IgniterModelConfig {
+        initial_fuel_tank_pressure: 250.0,
+        ignition_enabled: false,
+        ..IgniterModelConfig::default()
+    });
+
+    hardware.set_valve(ECUValve::IgniterGOxMain, 255);
+    hardware.set_valve(ECUValve::IgniterFuelMain, 255);
+    hardware.set_sparking(true);
+    hardware.update(1.0);
+
+    assert!(!hardware.model().is_ignited());
+    assert!(approx_reading(
+        &hardware,
+        ECUSensor::IgniterThroatTemp,
+        20.0,
+        1.0
+    ));
+}
+
+#[test]
+fn degenerate_sensor_configs_ignored() {
+    let mut hardware = ECUHardwareSim::new(IgniterModelConfig::default());
+    let config = SensorConfig {
+        premin: 0.0,
+        premax: f32::from(ADC_MAX_READING),
+        postmin: 0.0,
+        postmax: 100.0,
+    };
+
+    hardware.configure_sensor(ECUSensor::FuelTankPressure, &config);
+    let reading = hardware.get_sensor_value(ECUSensor::FuelTankPressure);
+
+    for degenerate in [
+        SensorConfig {
+            premax: 0.0,
+            ..config
+        },
+        SensorConfig {
+            postmax: f32::NAN,
+            ..config
+        },
+    ]
+    .iter()
+    {
+        hardware.configure_sensor(ECUSensor::FuelTankPressure, degenerate);
+        hardware.update(0.1);
+
+        assert!(hardware
+            .get_sensor_value(ECUSensor::FuelTankPressure)
+            .is_finite());
+    }
+
+    assert!(hardware.get_sensor_value(ECUSensor::FuelTankPressure) == reading);
+}
+
+fn sensor_reading(hardware: &ECUHardwareSim, sensor: ECUSensor) -> f32 {
+    let raw = hardware.get_raw_sensor_readings()[sensor as usize];
+
+    hardware.get_sensor_encoding(sensor).decode(raw)
+}
+
+fn approx_reading(
+    hardware: &ECUHardwareSim,
+    sensor: ECUSensor,
+    value: f32,
+    tolerance: f32,
+) -> bool {
+    let reading = sensor_reading(hardware, sensor);
+    println!("{:?} reads {}, expected {}", sensor, reading, value);
+
+    (reading - value).abs() <= tolerance
+}
@@ -0,0 +1,69 @@
+use hal::comms_hal::{Command, NakReason, NetworkAddress, Packet};
+
+use crate::{Ecu, HALs};
+
+/// Seconds during which the same command with the same `seq` as the last one is treated as a retry.
+/// The reply is sent again without running the command twice.
+pub const COMMAND_RETRY_WINDOW: f32 = 1.0;
+
+/// Remembers the last command so retries from mission control, sent when an `Ack` or `Nak` gets
+/// lost, get the same answer as the original
+pub struct CommandAcknowledger {
+    last_command: Option<(u16, Command)>,
+    last_result: Result<(), NakReason>,
+    elapsed_since_last_command: f32,
+}
+
+impl Ecu {
+    pub(crate) fn ack_update(&mut self, elapsed: f32) {
+        self.acknowledger.elapsed_since_last_command += elapsed;
+    }
+
+    pub(crate) fn on_sequenced_command(&mut self, hals: &mut HALs, seq: u16, command: &Command) {
+        // A different command under the same seq is a new command from someone else, not a retry
+        let is_retry = matches!(
+            &self.acknowledger.last_command,
+            Some((last_seq, last_command)) if *last_seq == seq && last_command == command
+        ) && self.acknowledger.elapsed_since_last_command < COMMAND_RETRY_WINDOW;
+
+        let result = if is_retry {
+            self.acknowledger.last_result
+        } else {
+            let result = self.on_command(hals, command);
+
+            self.acknowledger.last_command = Some((seq, command.clone()));
+            self.acknowledger.last_result = result;
+            self.acknowledger.elapsed_since_last_command = 0.0;
+
+            result
+        };
+
+        let reply = match result {
+            Ok(()) => Packet::Ack { seq },
+            Err(reason) => {
+                log::warn!("Rejected {:?}, {:?}", command, reason);
+                Packet::Nak { seq, reason }
+            }
+        };
+
+        if let Err(err) = hals.comms.transmit(&reply, NetworkAddress::MissionControl) {
+            log::error!("Failed to send packet, got {:?}", err);
+        }
+    }
+}
+
+impl CommandAcknowledger {
+    pub fn new() -> CommandAcknowledger {
+        CommandAcknowledger {
+            last_command: None,
+            last_result: Ok(()),
+            elapsed_since_last_command: 0.0,
+        }
+    }
+}
+
+impl Default for CommandAcknowledger {
+    fn default() -> Self {
+        CommandAcknowledger::new()
+    }
+}
@@ -0,0 +1,77 @@
+use hal::comms_hal::{Command, NakReason, ARM_CONFIRMATION_CODE};
+
+use crate::Ecu;
+
+/// Seconds the ECU stays armed after `Command::Arm` before safing itself again
+pub const DEFAULT_ARM_TIMEOUT: f32 = 60.0;
+
+/// Hazardous commands are only accepted while armed. Disarming only blocks new commands, it doesn't
+/// interrupt a sequence that's already running, that's what `Command::Abort` is for.
+pub struct ArmingInterlock {
+    armed: bool,
+    timeout: f32,
+    elapsed_since_armed: f32,
+}
+
+impl Ecu {
+    pub(crate) fn arming_update(&mut self, elapsed: f32) {
+        if !self.arming.armed {
+            return;
+        }
+
+        self.arming.elapsed_since_armed += elapsed;
+
+        if self.arming.elapsed_since_armed >= self.arming.timeout {
+            log::info!("Arming timed out, safing");
+            self.disarm();
+        }
+    }
+
+    /// Checks whether a command is allowed through in the current mode
+    pub(crate) fn arming_check(&self, command: &Command) -> Result<(), NakReason> {
+        if self.arming.armed || !command.is_hazardous() {
+            Ok(())
+        } else {
+            Err(NakReason::NotArmed)
+        }
+    }
+
+    pub(crate) fn arm(&mut self, code: u32) -> Result<(), NakReason> {
+        if code != ARM_CONFIRMATION_CODE {
+            return Err(NakReason::InvalidArmCode);
+        }
+
+        self.arming.armed = true;
+        self.arming.elapsed_since_armed = 0.0;
+
+        Ok(())
+    }
+
+    pub fn disarm(&mut self) {
+        self.arming.armed = false;
+    }
+
+    pub fn is_armed(&self) -> bool {
+        self.arming.armed
+    }
+
+    pub fn set_arm_timeout(&mut self, timeout: f32) {
+        self.arming.timeout = timeout;
+    }
+}
+
+impl ArmingInterlock {
+    pub fn new() -> ArmingInterlock {
+        ArmingInterlock {
+            armed: false,
+            timeout: DEFAULT_ARM_TIMEOUT,
+            elapsed_since_armed: 0.0,
+        }
+    }
+}
+
+impl Default for ArmingInterlock {
+    fn default() -> Self {
+        ArmingInterlock::new()
+    }
+}
@@ -0,0 +1,92 @@
+use hal::{comms_hal::AbortReason, ecu_hal::ECUValve};
+
+use crate::{Ecu, HALs};
+
+pub const DEFAULT_COMMS_LOSS_TIMEOUT: f32 = 1.0;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct CommsLossConfig {
+    pub enabled: bool,
+    /// Seconds without any packet from mission control before the ECU safes itself
+    pub timeout: f32,
+    /// Whether to vent the fuel tank when safing, on top of closing `FuelPress`
+    pub open_fuel_vent: bool,
+}
+
+impl Default for CommsLossConfig {
+    fn default() -> CommsLossConfig {
+        CommsLossConfig {
+            enabled: true,
+            timeout: DEFAULT_COMMS_LOSS_TIMEOUT,
+            open_fuel_vent: true,
+        }
+    }
+}
+
+/// Watches for packets from mission control. The watchdog only arms once mission control has
+/// been heard from, disarms when it says `Packet::Goodbye`, and only trips once per loss of
+/// signal.
+pub struct CommsWatchdog {
+    config: CommsLossConfig,
+    elapsed_since_last_contact: f32,
+    armed: bool,
+}
+
+impl Ecu {
+    pub(crate) fn comms_watchdog_update(&mut self, elapsed: f32, hals: &mut HALs) {
+        if !self.comms_watchdog.config.enabled || !self.comms_watchdog.armed {
+            return;
+        }
+
+        self.comms_watchdog.elapsed_since_last_contact += elapsed;
+
+        if self.comms_watchdog.elapsed_since_last_contact >= self.comms_watchdog.config.timeout {
+            log::error!(
+                "No contact from mission control in {} seconds, safing",
+                self.comms_watchdog.elapsed_since_last_contact
+            );
+
+            self.comms_watchdog.armed = false;
+            self.abort(hals, AbortReason::CommsLoss);
+
+            hals.hardware.set_valve(ECUValve::FuelPress, 0);
+            if self.comms_watchdog.config.open_fuel_vent {
+                hals.hardware.set_valve(ECUValve::FuelVent, 255);
+            }
+        }
+    }
+
+    pub(crate) fn on_mission_control_contact(&mut self) {
+        self.comms_watchdog.armed = true;
+        self.comms_watchdog.elapsed_since_last_contact = 0.0;
+    }
+
+    /// Mission control left on purpose, so there's nothing to safe against until it's back
+    pub(crate) fn on_mission_control_goodbye(&mut self) {
+        self.comms_watchdog.armed = false;
+    }
+
+    pub fn set_comms_loss_config(&mut self, config: CommsLossConfig) {
+        self.comms_watchdog.config = config;
+    }
+
+    pub fn get_comms_loss_config(&self) -> CommsLossConfig {
+        self.comms_watchdog.config
+    }
+}
+
+impl CommsWatchdog {
+    pub fn new() -> CommsWatchdog {
+        CommsWatchdog {
+            config: CommsLossConfig::default(),
+            elapsed_since_last_contact: 0.0,
+            armed: false,
+        }
+    }
+}
+
+impl Default for CommsWatchdog {
+    fn default() -> Self {
+        CommsWatchdog::new()
+    }
+}
@@ -0,0 +1,76 @@
+use hal::comms_hal::{ControllerKind, NetworkAddress, Packet, PROTOCOL_VERSION};
+
+use crate::{Ecu, HALs};
+
+/// Sent in `Packet::Hello`. Set `ECU_BUILD_ID` when building firmware to tell builds apart,
+/// otherwise it's the crate version.
+pub const FIRMWARE_BUILD_ID: &str = match option_env!("ECU_BUILD_ID") {
+    Some(build_id) => build_id,
+    None => env!("CARGO_PKG_VERSION"),
+};
+
+/// Remembers which protocol version mission control announced. Until it says hello everything is
+/// accepted, after that packets are only accepted if the versions match.
+pub struct Handshake {
+    mission_control_version: Option<u16>,
+}
+
+impl Ecu {
+    pub(crate) fn send_hello(&self, hals: &mut HALs) {
+        let hello = Packet::hello(ControllerKind::EngineController, FIRMWARE_BUILD_ID);
+
+        if let Err(err) = hals.comms.transmit(&hello, NetworkAddress::MissionControl) {
+            log::error!("Failed to send packet, got {:?}", err);
+        }
+    }
+
+    pub(crate) fn on_hello(
+        &mut self,
+        hals: &mut HALs,
+        from: NetworkAddress,
+        protocol_version: u16,
+    ) {
+        if from != NetworkAddress::MissionControl {
+            return;
+        }
+
+        if protocol_version != PROTOCOL_VERSION {
+            log::error!(
+                "Mission control uses protocol version {}, expected {}. Ignoring it",
+                protocol_version,
+                PROTOCOL_VERSION
+            );
+        }
+
+        self.handshake.mission_control_version = Some(protocol_version);
+
+        // Always answer, so mission control can tell the versions don't match too
+        self.send_hello(hals);
+    }
+
+    /// Whether packets from `from` can be trusted to decode properly
+    pub fn is_compatible(&self, from: NetworkAddress) -> bool {
+        match (from, self.handshake.mission_control_version) {
+            (NetworkAddress::MissionControl, Some(version)) => version == PROTOCOL_VERSION,
+            _ => true,
+        }
+    }
+
+    pub fn get_mission_control_version(&self) -> Option<u16> {
+        self.handshake.mission_control_version
+    }
+}
+
+impl Handshake {
+    pub fn new() -> Handshake {
+        Handshake {
+            mission_control_version: None,
+        }
+    }
+}
+
+impl Default for Handshake {
+    fn default() -> Self {
+        Handshake::new()
+    }
+}
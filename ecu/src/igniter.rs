@@ -1,16 +1,24 @@
 use crate::HALs;
 use hal::{
-    comms_hal::Packet,
-    ecu_hal::{ECUValve, IgniterState, IgniterTimingConfig},
+    comms_hal::{Command, NakReason},
+    ecu_hal::{
+        ECUSensor, ECUValve, IgniterState, IgniterTimingConfig, IgnitionResult,
+        IgnitionVerificationConfig,
+    },
 };
 
 pub const DEFAULT_IGNITER_PREFIRE_DURATION_MS: u16 = 250;
 pub const DEFAULT_IGNITER_FIRE_DURATION_MS: u16 = 1000;
+pub const DEFAULT_IGNITER_SHUTDOWN_DURATION_MS: u16 = 50;
+pub const DEFAULT_IGNITER_PURGE_DURATION_MS: u16 = 500;
+pub const DEFAULT_IGNITION_WINDOW_MS: u16 = 250;
 
 pub struct Igniter {
     timing_config: IgniterTimingConfig,
+    verification_config: IgnitionVerificationConfig,
     current_state_enum: IgniterState,
     elapsed_since_state_transition: f32,
+    ignition_result: IgnitionResult,
 }
 
 impl Igniter {
@@ -19,43 +27,111 @@ impl Igniter {
             timing_config: IgniterTimingConfig {
                 prefire_duration_ms: DEFAULT_IGNITER_PREFIRE_DURATION_MS,
                 fire_duration_ms: DEFAULT_IGNITER_FIRE_DURATION_MS,
+                shutdown_duration_ms: DEFAULT_IGNITER_SHUTDOWN_DURATION_MS,
+                purge_duration_ms: DEFAULT_IGNITER_PURGE_DURATION_MS,
+            },
+            // Thresholds depend on the sensor calibration, so verification has to be configured
+            // from the ground before it can be enabled
+            verification_config: IgnitionVerificationConfig {
+                enabled: false,
+                window_ms: DEFAULT_IGNITION_WINDOW_MS,
+                chamber_pressure_threshold: 0.0,
+                throat_temp_threshold: None,
             },
             current_state_enum: IgniterState::Idle,
             elapsed_since_state_transition: 0.0,
+            ignition_result: IgnitionResult::NotFired,
         }
     }
 
-    pub(crate) fn update<'a>(&mut self, elapsed: f32, hals: &'a mut HALs) {
+    pub(crate) fn update(&mut self, elapsed: f32, hals: &mut HALs) {
         self.elapsed_since_state_transition += elapsed;
 
         match self.current_state_enum {
             IgniterState::Idle => {}
             IgniterState::Prefire => self.update_prefire_state(hals),
             IgniterState::Firing => self.update_firing_state(hals),
+            IgniterState::Shutdown => self.update_shutdown_state(hals),
+            IgniterState::Purge => self.update_purge_state(hals),
         }
     }
 
-    pub(crate) fn on_packet<'a>(&mut self, packet: &Packet, hals: &'a mut HALs) {
-        match packet {
-            Packet::FireIgniter => {
-                if self.current_state_enum == IgniterState::Idle {
-                    self.transition_state(IgniterState::Prefire, hals);
+    pub(crate) fn on_command(
+        &mut self,
+        command: &Command,
+        hals: &mut HALs,
+    ) -> Result<(), NakReason> {
+        // The sequence owns the igniter, so nothing about it can change until it's finished
+        if self.current_state_enum != IgniterState::Idle {
+            return Err(NakReason::WrongState);
+        }
+
+        match command {
+            Command::FireIgniter => self.transition_state(IgniterState::Prefire, hals),
+            Command::ConfigureIgniterTiming(timing) => {
+                if timing.fire_duration_ms == 0 {
+                    return Err(NakReason::InvalidParameter);
+                }
+
+                self.timing_config = timing.clone();
+            }
+            Command::ConfigureIgnitionVerification(config) => {
+                if config.enabled && config.window_ms == 0 {
+                    return Err(NakReason::InvalidParameter);
                 }
+
+                self.verification_config = *config;
             }
-            Packet::ConfigureIgniterTiming(timing) => self.timing_config = timing.clone(),
-            _ => {}
+            _ => return Err(NakReason::Unsupported),
+        }
+
+        Ok(())
+    }
+
+    /// Opening one of the igniter mains would fight the sequence while it's running. Closing them
+    /// is always allowed.
+    pub(crate) fn check_valve_command(&self, valve: ECUValve, state: u8) -> Result<(), NakReason> {
+        let igniter_valve = matches!(valve, ECUValve::IgniterFuelMain | ECUValve::IgniterGOxMain);
+
+        if igniter_valve && state > 0 && self.current_state_enum != IgniterState::Idle {
+            Err(NakReason::WrongState)
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Test sparks would turn the spark off partway through a firing
+    pub(crate) fn check_spark_command(&self) -> Result<(), NakReason> {
+        if self.current_state_enum == IgniterState::Idle {
+            Ok(())
+        } else {
+            Err(NakReason::WrongState)
         }
     }
 
-    pub(crate) fn on_abort<'a>(&mut self, hals: &'a mut HALs) {
-        self.transition_state(IgniterState::Idle, hals);
+    /// Propellant has been flowing since Prefire, so aborting from there on purges the chamber
+    /// before going idle. Aborting again, or from Purge, goes straight to Idle.
+    pub(crate) fn on_abort(&mut self, hals: &mut HALs) {
+        match self.current_state_enum {
+            IgniterState::Prefire | IgniterState::Firing | IgniterState::Shutdown => {
+                self.transition_state(IgniterState::Purge, hals);
+            }
+            IgniterState::Idle | IgniterState::Purge => {
+                self.transition_state(IgniterState::Idle, hals);
+            }
+        }
     }
 
     pub fn get_current_state(&self) -> IgniterState {
         self.current_state_enum
     }
 
-    fn transition_state<'a>(&mut self, new_state: IgniterState, hals: &'a mut HALs) {
+    /// Result of the most recent firing
+    pub fn get_ignition_result(&self) -> IgnitionResult {
+        self.ignition_result
+    }
+
+    fn transition_state(&mut self, new_state: IgniterState, hals: &mut HALs) {
         self.current_state_enum = new_state;
         self.elapsed_since_state_transition = 0.0;
 
@@ -63,6 +139,8 @@ impl Igniter {
             IgniterState::Idle => self.enter_idle_state(hals),
             IgniterState::Prefire => self.enter_prefire_state(hals),
             IgniterState::Firing => self.enter_firing_state(hals),
+            IgniterState::Shutdown => self.enter_shutdown_state(hals),
+            IgniterState::Purge => self.enter_purge_state(hals),
         }
     }
 
@@ -94,19 +172,95 @@ impl Igniter {
 
     // ----- Firing State ----- //
 
-    #[allow(clippy::unused_self)]
     fn enter_firing_state(&mut self, hals: &mut HALs) {
         hals.hardware.set_sparking(true);
         hals.hardware.set_valve(ECUValve::IgniterFuelMain, 255);
         hals.hardware.set_valve(ECUValve::IgniterGOxMain, 255);
+
+        self.ignition_result = if self.verification_config.enabled {
+            IgnitionResult::Pending
+        } else {
+            IgnitionResult::Unverified
+        };
     }
 
     fn update_firing_state(&mut self, hals: &mut HALs) {
+        if self.ignition_result == IgnitionResult::Pending {
+            if self.ignition_detected(hals) {
+                self.ignition_result = IgnitionResult::Ignited;
+            } else if self.elapsed_since_state_transition
+                > f32::from(self.verification_config.window_ms) * 1e-3
+            {
+                log::error!("Igniter failed to light, shutting down");
+
+                self.ignition_result = IgnitionResult::NoIgnition;
+                self.transition_state(IgniterState::Shutdown, hals);
+                return;
+            }
+        }
+
         if self.elapsed_since_state_transition
             > f32::from(self.timing_config.fire_duration_ms) * 1e-3
         {
-            hals.hardware.set_valve(ECUValve::FuelPress, 0);
+            self.transition_state(IgniterState::Shutdown, hals);
+        }
+    }
+
+    fn ignition_detected(&self, hals: &mut HALs) -> bool {
+        // Compared so that a NaN reading never counts as lit
+        let chamber_lit = hals
+            .hardware
+            .get_sensor_value(ECUSensor::IgniterChamberPressure)
+            >= self.verification_config.chamber_pressure_threshold;
+
+        let throat_lit = match self.verification_config.throat_temp_threshold {
+            Some(threshold) => {
+                hals.hardware.get_sensor_value(ECUSensor::IgniterThroatTemp) >= threshold
+            }
+            None => true,
+        };
+
+        chamber_lit && throat_lit
+    }
+
+    // ----- Shutdown State ----- //
+
+    #[allow(clippy::unused_self)]
+    fn enter_shutdown_state(&mut self, hals: &mut HALs) {
+        hals.hardware.set_sparking(false);
+        hals.hardware.set_valve(ECUValve::IgniterFuelMain, 0);
+        hals.hardware.set_valve(ECUValve::IgniterGOxMain, 255);
+        hals.hardware.set_valve(ECUValve::FuelPress, 0);
+    }
+
+    fn update_shutdown_state(&mut self, hals: &mut HALs) {
+        if self.elapsed_since_state_transition
+            > f32::from(self.timing_config.shutdown_duration_ms) * 1e-3
+        {
+            self.transition_state(IgniterState::Purge, hals);
+        }
+    }
+
+    // ----- Purge State ----- //
+
+    #[allow(clippy::unused_self)]
+    fn enter_purge_state(&mut self, hals: &mut HALs) {
+        hals.hardware.set_sparking(false);
+        hals.hardware.set_valve(ECUValve::IgniterFuelMain, 0);
+        hals.hardware.set_valve(ECUValve::IgniterGOxMain, 255);
+    }
+
+    fn update_purge_state(&mut self, hals: &mut HALs) {
+        if self.elapsed_since_state_transition
+            > f32::from(self.timing_config.purge_duration_ms) * 1e-3
+        {
             self.transition_state(IgniterState::Idle, hals);
         }
     }
 }
+
+impl Default for Igniter {
+    fn default() -> Self {
+        Igniter::new()
+    }
+}
@@ -1,17 +1,29 @@
 #![no_std]
 
+pub mod ack;
+pub mod arming;
+pub mod comms_watchdog;
+pub mod handshake;
 pub mod igniter;
 pub mod record;
+pub mod redline;
 
 #[macro_use]
 extern crate lazy_static;
 
+use ack::CommandAcknowledger;
+use arming::ArmingInterlock;
+use comms_watchdog::CommsWatchdog;
 use hal::{
-    comms_hal::{CommsInterface, ECUTelemtryData, NetworkAddress, Packet},
+    comms_hal::{
+        AbortReason, Command, CommsInterface, ECUTelemtryData, NakReason, NetworkAddress, Packet,
+    },
     ecu_hal::{ECUDataFrame, ECUHardware, ECUValve, MAX_ECU_SENSORS, MAX_ECU_VALVES},
 };
+use handshake::Handshake;
 use igniter::Igniter;
 use record::DataRecorder;
+use redline::RedlineMonitor;
 
 pub const DEFAULT_TELEMETRY_RATE: f32 = 0.01;
 
@@ -27,6 +39,11 @@ pub struct HALs<'a> {
 pub struct Ecu {
     igniter: Igniter,
     recorder: DataRecorder,
+    comms_watchdog: CommsWatchdog,
+    redline_monitor: RedlineMonitor,
+    arming: ArmingInterlock,
+    acknowledger: CommandAcknowledger,
+    handshake: Handshake,
     elapsed_since_last_telemetry: f32,
     enginer_controller_index: u8,
     telemetry_rate: f32,
@@ -41,15 +58,24 @@ impl Ecu {
         hals.hardware.set_valve(ECUValve::FuelPress, 0);
         hals.hardware.set_valve(ECUValve::FuelVent, 0);
 
-        Ecu {
+        let ecu = Ecu {
             igniter: Igniter::new(),
             recorder: DataRecorder::new(),
+            comms_watchdog: CommsWatchdog::new(),
+            redline_monitor: RedlineMonitor::new(),
+            arming: ArmingInterlock::new(),
+            acknowledger: CommandAcknowledger::new(),
+            handshake: Handshake::new(),
             elapsed_since_last_telemetry: 0.0,
             enginer_controller_index,
             telemetry_rate: DEFAULT_TELEMETRY_RATE,
             max_loop_time_per_telem: 0.0,
             test_spark_elapsed: -1.0,
-        }
+        };
+
+        ecu.send_hello(hals);
+
+        ecu
     }
 
     pub fn update(&mut self, hals: &mut HALs, elapsed: f32) {
@@ -70,46 +96,94 @@ impl Ecu {
             }
         }
 
-        loop {
-            match hals.comms.receive() {
-                Some((packet, _from)) => {
-                    self.on_packet(hals, &packet);
+        while let Some((packet, from)) = hals.comms.receive() {
+            if let Packet::Hello {
+                protocol_version, ..
+            } = packet
+            {
+                self.on_hello(hals, from, protocol_version);
+            }
+
+            if !self.is_compatible(from) {
+                continue;
+            }
+
+            if from == NetworkAddress::MissionControl {
+                if packet == Packet::Goodbye {
+                    self.on_mission_control_goodbye();
+                } else {
+                    self.on_mission_control_contact();
                 }
-                None => break,
             }
+
+            self.on_packet(hals, &packet);
         }
 
+        self.ack_update(elapsed);
+        self.arming_update(elapsed);
+        self.comms_watchdog_update(elapsed, hals);
+        self.redline_update(elapsed, hals);
         self.igniter.update(elapsed, hals);
         self.record_update(elapsed, hals);
     }
 
     pub fn on_packet(&mut self, hals: &mut HALs, packet: &Packet) {
-        match packet {
-            Packet::SetValve { valve, state } => hals.hardware.set_valve(*valve, *state),
-            Packet::SetSparking(sparking) => {
-                self.test_spark_elapsed = *sparking;
-                hals.hardware.set_sparking(true)
+        if let Packet::Command { seq, command } = packet {
+            self.on_sequenced_command(hals, *seq, command);
+        }
+    }
+
+    /// Runs a command without acknowledging it.
+    ///
+    /// # Errors
+    /// If the command can't be run right now, it will return the reason to send back in a
+    /// `Packet::Nak`
+    pub fn on_command(&mut self, hals: &mut HALs, command: &Command) -> Result<(), NakReason> {
+        self.arming_check(command)?;
+
+        match command {
+            Command::SetValve { valve, state } => {
+                self.igniter.check_valve_command(*valve, *state)?;
+                hals.hardware.set_valve(*valve, *state);
+            }
+            Command::SetSparking(duration) => {
+                if duration.is_nan() || *duration <= 0.0 {
+                    return Err(NakReason::InvalidParameter);
+                }
+                self.igniter.check_spark_command()?;
+
+                self.test_spark_elapsed = *duration;
+                hals.hardware.set_sparking(true);
             }
-            Packet::ConfigureSensor { sensor, config } => {
+            Command::ConfigureSensor { sensor, config } => {
                 hals.hardware.configure_sensor(*sensor, config)
             }
-            Packet::Abort => self.abort(hals),
-            Packet::SetRecording(recording) => self.set_recording(*recording),
-            Packet::TransferData => self.transfer_recorded_data(),
-            _ => {}
+            Command::Abort => self.abort(hals, AbortReason::Commanded),
+            Command::SetRecording(recording) => self.set_recording(*recording),
+            Command::TransferData => self.transfer_recorded_data(),
+            Command::ConfigureRedline { sensor, redline } => {
+                self.configure_redline(*sensor, *redline)
+            }
+            Command::Arm { code } => self.arm(*code)?,
+            Command::Disarm => self.disarm(),
+            Command::FireIgniter
+            | Command::ConfigureIgniterTiming(_)
+            | Command::ConfigureIgnitionVerification(_) => {
+                self.igniter.on_command(command, hals)?
+            }
         }
 
-        if !matches!(packet, Packet::Abort) {
-            self.igniter.on_packet(packet, hals);
-        }
+        Ok(())
     }
 
-    pub fn abort(&mut self, hals: &mut HALs) {
+    pub fn abort(&mut self, hals: &mut HALs, reason: AbortReason) {
         self.igniter.on_abort(hals);
+        self.disarm();
 
-        let packet = Packet::ControllerAborted(NetworkAddress::EngineController(
-            self.enginer_controller_index,
-        ));
+        let packet = Packet::ControllerAborted {
+            address: NetworkAddress::EngineController(self.enginer_controller_index),
+            reason,
+        };
 
         if let Err(err) = hals.comms.transmit(&packet, NetworkAddress::Broadcast) {
             log::error!("Failed to send abort message, got {:?}", err);
@@ -124,6 +198,8 @@ impl Ecu {
         let telem = ECUTelemtryData {
             ecu_data: self.get_ecu_data_frame(hals),
             max_loop_time: self.max_loop_time_per_telem,
+            ignition_result: self.igniter.get_ignition_result(),
+            armed: self.is_armed(),
         };
 
         self.max_loop_time_per_telem = 0.0;
@@ -1,17 +1,19 @@
 use atomic_queue::AtomicQueue;
 use hal::{
-    comms_hal::{NetworkAddress, Packet},
+    comms_hal::{NetworkAddress, Packet, RECORDED_DATA_BATCH_LENGTH},
     ecu_hal::{ECUDataFrame, MAX_ECU_SENSORS, MAX_ECU_VALVES},
 };
 
 use crate::{Ecu, HALs, RECORD_RATE, RECORD_STORAGE_SIZE, RECORD_TRANSMIT_RATE};
 
-static mut STORAGE: [ECUDataFrame; RECORD_STORAGE_SIZE] = [ECUDataFrame {
+const EMPTY_FRAME: ECUDataFrame = ECUDataFrame {
     igniter_state: hal::ecu_hal::IgniterState::Firing,
     sensor_states: [0_u16; MAX_ECU_SENSORS],
     valve_states: [0_u8; MAX_ECU_VALVES],
     sparking: false,
-}; RECORD_STORAGE_SIZE];
+};
+
+static mut STORAGE: [ECUDataFrame; RECORD_STORAGE_SIZE] = [EMPTY_FRAME; RECORD_STORAGE_SIZE];
 
 lazy_static! {
     static ref QUEUE: AtomicQueue<'static, ECUDataFrame> = {
@@ -47,18 +49,7 @@ impl Ecu {
 
             if self.recorder.elapsed_since_last_transfer >= RECORD_TRANSMIT_RATE {
                 self.recorder.elapsed_since_last_transfer -= RECORD_TRANSMIT_RATE;
-
-                if let Some(frame) = QUEUE.pop() {
-                    if let Err(err) = hals
-                        .comms
-                        .transmit(&Packet::RecordedData(frame), NetworkAddress::MissionControl)
-                    {
-                        log::error!("Couldn't send recorded data: {:?}", err);
-                    }
-                } else {
-                    self.recorder.state = RecorderState::Idle;
-                    self.recorder.elapsed_since_last_transfer = 0.0;
-                }
+                self.transfer_batch(hals);
             }
         }
     }
@@ -79,6 +70,34 @@ impl Ecu {
         self.recorder.state = RecorderState::Transferring;
     }
 
+    /// Sends the next `RECORDED_DATA_BATCH_LENGTH` recorded frames in one packet. The last few
+    /// frames, which don't fill a batch, are sent one at a time.
+    fn transfer_batch(&mut self, hals: &mut HALs) {
+        let mut batch = [EMPTY_FRAME; RECORDED_DATA_BATCH_LENGTH];
+        let mut len = 0;
+
+        while len < RECORDED_DATA_BATCH_LENGTH {
+            match QUEUE.pop() {
+                Some(frame) => {
+                    batch[len] = frame;
+                    len += 1;
+                }
+                None => break,
+            }
+        }
+
+        if len == 0 {
+            self.recorder.state = RecorderState::Idle;
+            self.recorder.elapsed_since_last_transfer = 0.0;
+        } else if len == RECORDED_DATA_BATCH_LENGTH {
+            transmit_recorded(hals, &Packet::RecordedDataBatch(batch));
+        } else {
+            for frame in batch[0..len].iter() {
+                transmit_recorded(hals, &Packet::RecordedData(*frame));
+            }
+        }
+    }
+
     fn record_data(&mut self, hals: &mut HALs) {
         if let Err(_) = QUEUE.push(self.get_ecu_data_frame(hals)) {
             self.recorder.state = RecorderState::Idle;
@@ -87,6 +106,12 @@ impl Ecu {
     }
 }
 
+fn transmit_recorded(hals: &mut HALs, packet: &Packet) {
+    if let Err(err) = hals.comms.transmit(packet, NetworkAddress::MissionControl) {
+        log::error!("Couldn't send recorded data: {:?}", err);
+    }
+}
+
 impl DataRecorder {
     pub fn new() -> DataRecorder {
         DataRecorder {
@@ -0,0 +1,84 @@
+use hal::{
+    comms_hal::AbortReason,
+    ecu_hal::{ECUSensor, SensorRedline, ECU_SENSORS, MAX_ECU_SENSORS},
+};
+
+use crate::{Ecu, HALs};
+
+/// Tracks how long each sensor has been outside of its armed limits
+pub struct RedlineMonitor {
+    redlines: [SensorRedline; MAX_ECU_SENSORS],
+    elapsed_out_of_limits: [f32; MAX_ECU_SENSORS],
+    tripped: [bool; MAX_ECU_SENSORS],
+}
+
+impl Ecu {
+    pub(crate) fn redline_update(&mut self, elapsed: f32, hals: &mut HALs) {
+        let igniter_state = self.igniter.get_current_state();
+
+        for (index, sensor) in ECU_SENSORS.iter().enumerate() {
+            let redline = self.redline_monitor.redlines[index];
+            let value = hals.hardware.get_sensor_value(*sensor);
+
+            // A failed or disconnected transducer can read NaN, which can't be trusted to be in
+            // limits either
+            let below_min = redline.min.is_armed(igniter_state)
+                && (value < redline.min.value || value.is_nan());
+            let above_max = redline.max.is_armed(igniter_state)
+                && (value > redline.max.value || value.is_nan());
+
+            if !below_min && !above_max {
+                self.redline_monitor.elapsed_out_of_limits[index] = 0.0;
+                self.redline_monitor.tripped[index] = false;
+                continue;
+            }
+
+            if self.redline_monitor.tripped[index] {
+                continue;
+            }
+
+            self.redline_monitor.elapsed_out_of_limits[index] += elapsed;
+
+            if self.redline_monitor.elapsed_out_of_limits[index]
+                >= f32::from(redline.debounce_ms) * 1e-3
+            {
+                log::error!("{:?} redlined at {}, aborting", sensor, value);
+
+                self.redline_monitor.tripped[index] = true;
+                self.abort(
+                    hals,
+                    AbortReason::Redline {
+                        sensor: *sensor,
+                        value,
+                    },
+                );
+            }
+        }
+    }
+
+    pub fn configure_redline(&mut self, sensor: ECUSensor, redline: SensorRedline) {
+        self.redline_monitor.redlines[sensor as usize] = redline;
+        self.redline_monitor.elapsed_out_of_limits[sensor as usize] = 0.0;
+        self.redline_monitor.tripped[sensor as usize] = false;
+    }
+
+    pub fn get_redline(&self, sensor: ECUSensor) -> SensorRedline {
+        self.redline_monitor.redlines[sensor as usize]
+    }
+}
+
+impl RedlineMonitor {
+    pub fn new() -> RedlineMonitor {
+        RedlineMonitor {
+            redlines: [SensorRedline::default(); MAX_ECU_SENSORS],
+            elapsed_out_of_limits: [0.0; MAX_ECU_SENSORS],
+            tripped: [false; MAX_ECU_SENSORS],
+        }
+    }
+}
+
+impl Default for RedlineMonitor {
+    fn default() -> Self {
+        RedlineMonitor::new()
+    }
+}
@@ -0,0 +1,216 @@
+use ecu::{ack::COMMAND_RETRY_WINDOW, comms_watchdog::CommsLossConfig, Ecu, HALs};
+use hal::{
+    comms_hal::{Command, NakReason, NetworkAddress, Packet, ARM_CONFIRMATION_CODE},
+    comms_mock::CommsMock,
+    ecu_hal::{IgniterState, IgniterTimingConfig, IgnitionVerificationConfig},
+    ecu_mock::ECUHardwareMock,
+};
+
+macro_rules! hals {
+    ($hardware:ident, $comms:ident) => {
+        &mut HALs {
+            hardware: &mut $hardware,
+            comms: &mut $comms,
+        }
+    };
+}
+
+const TIMESTEP: f32 = 0.001;
+
+#[test]
+fn accepted_command_is_acked() {
+    let (mut ecu, mut ecu_hardware, mut comms) = setup();
+
+    send(&mut comms, 7, Command::FireIgniter);
+    run(&mut ecu, &mut ecu_hardware, &mut comms, TIMESTEP);
+
+    assert!(ecu.get_igniter().get_current_state() == IgniterState::Prefire);
+    assert!(replies(&comms) == vec![Packet::Ack { seq: 7 }]);
+}
+
+#[test]
+fn wrong_state_is_nakked() {
+    let (mut ecu, mut ecu_hardware, mut comms) = setup();
+
+    send(&mut comms, 1, Command::FireIgniter);
+    run(&mut ecu, &mut ecu_hardware, &mut comms, 0.5);
+    send(&mut comms, 2, Command::FireIgniter);
+    send(
+        &mut comms,
+        3,
+        Command::ConfigureIgniterTiming(IgniterTimingConfig {
+            prefire_duration_ms: 100,
+            fire_duration_ms: 100,
+            shutdown_duration_ms: 100,
+            purge_duration_ms: 100,
+        }),
+    );
+    run(&mut ecu, &mut ecu_hardware, &mut comms, TIMESTEP);
+
+    assert!(
+        replies(&comms)
+            == vec![
+                Packet::Ack { seq: 1 },
+                Packet::Nak {
+                    seq: 2,
+                    reason: NakReason::WrongState,
+                },
+                Packet::Nak {
+                    seq: 3,
+                    reason: NakReason::WrongState,
+                },
+            ]
+    );
+}
+
+#[test]
+fn disarmed_is_nakked() {
+    let mut ecu_hardware = ECUHardwareMock::new();
+    let mut comms = CommsMock::new();
+    let mut ecu = Ecu::new(0, hals!(ecu_hardware, comms));
+
+    send(&mut comms, 1, Command::FireIgniter);
+    run(&mut ecu, &mut ecu_hardware, &mut comms, TIMESTEP);
+
+    assert!(
+        replies(&comms)
+            == vec![Packet::Nak {
+                seq: 1,
+                reason: NakReason::NotArmed,
+            }]
+    );
+}
+
+#[test]
+fn invalid_parameters_are_nakked() {
+    let (mut ecu, mut ecu_hardware, mut comms) = setup();
+
+    send(&mut comms, 1, Command::SetSparking(-1.0));
+    send(
+        &mut comms,
+        2,
+        Command::ConfigureIgnitionVerification(IgnitionVerificationConfig {
+            enabled: true,
+            window_ms: 0,
+            chamber_pressure_threshold: 42.0,
+            throat_temp_threshold: None,
+        }),
+    );
+    run(&mut ecu, &mut ecu_hardware, &mut comms, TIMESTEP);
+
+    assert!(!ecu_hardware.sparking);
+    assert!(
+        replies(&comms)
+            == vec![
+                Packet::Nak {
+                    seq: 1,
+                    reason: NakReason::InvalidParameter,
+                },
+                Packet::Nak {
+                    seq: 2,
+                    reason: NakReason::InvalidParameter,
+                },
+            ]
+    );
+}
+
+#[test]
+fn retry_is_answered_without_rerunning() {
+    let (mut ecu, mut ecu_hardware, mut comms) = setup();
+
+    // The first fire is acked, the ack gets lost and mission control retries while firing
+    send(&mut comms, 9, Command::FireIgniter);
+    run(&mut ecu, &mut ecu_hardware, &mut comms, 0.3);
+    send(&mut comms, 9, Command::FireIgniter);
+    run(&mut ecu, &mut ecu_hardware, &mut comms, TIMESTEP);
+
+    assert!(ecu.get_igniter().get_current_state() == IgniterState::Firing);
+    assert!(replies(&comms) == vec![Packet::Ack { seq: 9 }, Packet::Ack { seq: 9 }]);
+}
+
+#[test]
+fn different_command_with_same_seq_runs() {
+    let (mut ecu, mut ecu_hardware, mut comms) = setup();
+
+    // ctrl-view and ctrl-send count seqs separately, so they can land on the same one
+    send(&mut comms, 5, Command::SetRecording(true));
+    run(&mut ecu, &mut ecu_hardware, &mut comms, TIMESTEP);
+    send(&mut comms, 5, Command::FireIgniter);
+    run(&mut ecu, &mut ecu_hardware, &mut comms, TIMESTEP);
+
+    assert!(ecu.get_igniter().get_current_state() == IgniterState::Prefire);
+    assert!(replies(&comms) == vec![Packet::Ack { seq: 5 }, Packet::Ack { seq: 5 }]);
+}
+
+#[test]
+fn reused_seq_runs_after_retry_window() {
+    let (mut ecu, mut ecu_hardware, mut comms) = setup();
+
+    send(&mut comms, 3, Command::SetRecording(true));
+    run(
+        &mut ecu,
+        &mut ecu_hardware,
+        &mut comms,
+        COMMAND_RETRY_WINDOW + 0.1,
+    );
+    send(&mut comms, 3, Command::FireIgniter);
+    run(&mut ecu, &mut ecu_hardware, &mut comms, TIMESTEP);
+
+    assert!(ecu.get_igniter().get_current_state() == IgniterState::Prefire);
+}
+
+fn setup() -> (Ecu, ECUHardwareMock, CommsMock) {
+    let mut ecu_hardware = ECUHardwareMock::new();
+    let mut comms = CommsMock::new();
+    let mut ecu = Ecu::new(0, hals!(ecu_hardware, comms));
+    ecu.on_command(
+        hals!(ecu_hardware, comms),
+        &Command::Arm {
+            code: ARM_CONFIRMATION_CODE,
+        },
+    )
+    .unwrap();
+
+    // Nothing here sends heartbeats
+    ecu.set_comms_loss_config(CommsLossConfig {
+        enabled: false,
+        ..CommsLossConfig::default()
+    });
+
+    (ecu, ecu_hardware, comms)
+}
+
+fn send(comms: &mut CommsMock, seq: u16, command: Command) {
+    comms
+        .queue_incoming(
+            Packet::Command { seq, command },
+            NetworkAddress::MissionControl,
+        )
+        .unwrap();
+}
+
+fn run(ecu: &mut Ecu, ecu_hardware: &mut ECUHardwareMock, comms: &mut CommsMock, duration: f32) {
+    let mut elapsed = 0.0;
+
+    while elapsed < duration {
+        ecu.update(
+            &mut HALs {
+                hardware: ecu_hardware,
+                comms,
+            },
+            TIMESTEP,
+        );
+        elapsed += TIMESTEP;
+    }
+}
+
+fn replies(comms: &CommsMock) -> Vec<Packet> {
+    comms
+        .transmitted()
+        .filter(|transmitted| matches!(transmitted.packet, Packet::Ack { .. } | Packet::Nak { .. }))
+        .map(|transmitted| {
+            assert!(transmitted.address == NetworkAddress::MissionControl);
+            transmitted.packet.clone()
+        })
+        .collect()
+}
@@ -0,0 +1,212 @@
+use ecu::{arming::DEFAULT_ARM_TIMEOUT, Ecu, HALs};
+use hal::{
+    comms_hal::{Command, NakReason, Packet, ARM_CONFIRMATION_CODE},
+    comms_mock::CommsMock,
+    ecu_hal::{ECUValve, IgniterState},
+    ecu_mock::ECUHardwareMock,
+};
+
+macro_rules! hals {
+    ($hardware:ident, $comms:ident) => {
+        &mut HALs {
+            hardware: &mut $hardware,
+            comms: &mut $comms,
+        }
+    };
+}
+
+const TIMESTEP: f32 = 0.001;
+
+#[test]
+fn safe_rejects_hazardous_commands() {
+    let mut ecu_hardware = ECUHardwareMock::new();
+    let mut comms = CommsMock::new();
+    let mut ecu = Ecu::new(0, hals!(ecu_hardware, comms));
+
+    assert!(!ecu.is_armed());
+
+    assert!(
+        ecu.on_command(hals!(ecu_hardware, comms), &Command::FireIgniter)
+            == Err(NakReason::NotArmed)
+    );
+    assert!(
+        ecu.on_command(hals!(ecu_hardware, comms), &Command::SetSparking(1.0))
+            == Err(NakReason::NotArmed)
+    );
+    for valve in [
+        ECUValve::IgniterFuelMain,
+        ECUValve::IgniterGOxMain,
+        ECUValve::FuelPress,
+    ]
+    .iter()
+    {
+        let result = ecu.on_command(
+            hals!(ecu_hardware, comms),
+            &Command::SetValve {
+                valve: *valve,
+                state: 255,
+            },
+        );
+        assert!(result == Err(NakReason::NotArmed));
+    }
+
+    assert!(ecu.get_igniter().get_current_state() == IgniterState::Idle);
+    assert!(!ecu_hardware.sparking);
+    assert!(ecu_hardware.valve_states[ECUValve::IgniterFuelMain as usize] == 0);
+    assert!(ecu_hardware.valve_states[ECUValve::IgniterGOxMain as usize] == 0);
+    assert!(ecu_hardware.valve_states[ECUValve::FuelPress as usize] == 0);
+}
+
+#[test]
+fn safe_allows_safing_commands() {
+    let mut ecu_hardware = ECUHardwareMock::new();
+    let mut comms = CommsMock::new();
+    let mut ecu = Ecu::new(0, hals!(ecu_hardware, comms));
+
+    ecu.on_command(
+        hals!(ecu_hardware, comms),
+        &Command::SetValve {
+            valve: ECUValve::FuelVent,
+            state: 255,
+        },
+    )
+    .unwrap();
+    ecu.on_command(
+        hals!(ecu_hardware, comms),
+        &Command::SetValve {
+            valve: ECUValve::FuelPress,
+            state: 0,
+        },
+    )
+    .unwrap();
+    ecu.on_command(hals!(ecu_hardware, comms), &Command::SetRecording(true))
+        .unwrap();
+    ecu.on_command(hals!(ecu_hardware, comms), &Command::Abort)
+        .unwrap();
+
+    assert!(ecu_hardware.valve_states[ECUValve::FuelVent as usize] == 255);
+}
+
+#[test]
+fn arm_requires_confirmation_code() {
+    let mut ecu_hardware = ECUHardwareMock::new();
+    let mut comms = CommsMock::new();
+    let mut ecu = Ecu::new(0, hals!(ecu_hardware, comms));
+
+    let result = ecu.on_command(
+        hals!(ecu_hardware, comms),
+        &Command::Arm {
+            code: ARM_CONFIRMATION_CODE ^ 1,
+        },
+    );
+
+    assert!(result == Err(NakReason::InvalidArmCode));
+    assert!(!ecu.is_armed());
+
+    arm(&mut ecu, &mut ecu_hardware, &mut comms);
+    assert!(ecu.is_armed());
+
+    ecu.on_command(hals!(ecu_hardware, comms), &Command::FireIgniter)
+        .unwrap();
+    assert!(ecu.get_igniter().get_current_state() == IgniterState::Prefire);
+}
+
+#[test]
+fn arming_times_out() {
+    let mut ecu_hardware = ECUHardwareMock::new();
+    let mut comms = CommsMock::new();
+    let mut ecu = Ecu::new(0, hals!(ecu_hardware, comms));
+
+    arm(&mut ecu, &mut ecu_hardware, &mut comms);
+    run(
+        &mut ecu,
+        &mut ecu_hardware,
+        &mut comms,
+        DEFAULT_ARM_TIMEOUT - 0.1,
+    );
+    assert!(ecu.is_armed());
+    assert!(last_telemetry_armed(&comms) == Some(true));
+
+    run(&mut ecu, &mut ecu_hardware, &mut comms, 0.2);
+    assert!(!ecu.is_armed());
+    assert!(last_telemetry_armed(&comms) == Some(false));
+
+    // Re-arming restarts the timeout
+    ecu.set_arm_timeout(1.0);
+    arm(&mut ecu, &mut ecu_hardware, &mut comms);
+    run(&mut ecu, &mut ecu_hardware, &mut comms, 0.9);
+    arm(&mut ecu, &mut ecu_hardware, &mut comms);
+    run(&mut ecu, &mut ecu_hardware, &mut comms, 0.9);
+    assert!(ecu.is_armed());
+}
+
+#[test]
+fn disarm_doesnt_interrupt_sequence() {
+    let mut ecu_hardware = ECUHardwareMock::new();
+    let mut comms = CommsMock::new();
+    let mut ecu = Ecu::new(0, hals!(ecu_hardware, comms));
+
+    arm(&mut ecu, &mut ecu_hardware, &mut comms);
+    ecu.on_command(hals!(ecu_hardware, comms), &Command::FireIgniter)
+        .unwrap();
+    run(&mut ecu, &mut ecu_hardware, &mut comms, 0.5);
+    assert!(ecu.get_igniter().get_current_state() == IgniterState::Firing);
+
+    ecu.on_command(hals!(ecu_hardware, comms), &Command::Disarm)
+        .unwrap();
+    assert!(!ecu.is_armed());
+
+    run(&mut ecu, &mut ecu_hardware, &mut comms, 0.1);
+    assert!(ecu.get_igniter().get_current_state() == IgniterState::Firing);
+}
+
+#[test]
+fn abort_disarms() {
+    let mut ecu_hardware = ECUHardwareMock::new();
+    let mut comms = CommsMock::new();
+    let mut ecu = Ecu::new(0, hals!(ecu_hardware, comms));
+
+    arm(&mut ecu, &mut ecu_hardware, &mut comms);
+    ecu.on_command(hals!(ecu_hardware, comms), &Command::Abort)
+        .unwrap();
+
+    assert!(!ecu.is_armed());
+}
+
+fn arm(ecu: &mut Ecu, ecu_hardware: &mut ECUHardwareMock, comms: &mut CommsMock) {
+    ecu.on_command(
+        &mut HALs {
+            hardware: ecu_hardware,
+            comms,
+        },
+        &Command::Arm {
+            code: ARM_CONFIRMATION_CODE,
+        },
+    )
+    .unwrap();
+}
+
+fn run(ecu: &mut Ecu, ecu_hardware: &mut ECUHardwareMock, comms: &mut CommsMock, duration: f32) {
+    let mut elapsed = 0.0;
+
+    while elapsed < duration {
+        ecu.update(
+            &mut HALs {
+                hardware: ecu_hardware,
+                comms,
+            },
+            TIMESTEP,
+        );
+        elapsed += TIMESTEP;
+    }
+}
+
+fn last_telemetry_armed(comms: &CommsMock) -> Option<bool> {
+    comms
+        .transmitted()
+        .filter_map(|transmitted| match &transmitted.packet {
+            Packet::ECUTelemtry(telemetry) => Some(telemetry.armed),
+            _ => None,
+        })
+        .last()
+}
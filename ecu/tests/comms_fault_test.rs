@@ -0,0 +1,294 @@
+use std::sync::atomic::{AtomicU16, Ordering};
+
+use ecu::{
+    igniter::{
+        DEFAULT_IGNITER_FIRE_DURATION_MS, DEFAULT_IGNITER_PREFIRE_DURATION_MS,
+        DEFAULT_IGNITER_PURGE_DURATION_MS, DEFAULT_IGNITER_SHUTDOWN_DURATION_MS,
+    },
+    Ecu, HALs,
+};
+use hal::{
+    comms_fault::{FaultConfig, FaultyComms},
+    comms_hal::{Command, NetworkAddress, Packet, ARM_CONFIRMATION_CODE, HEARTBEAT_RATE},
+    comms_mock::CommsMock,
+    ecu_hal::{ECUValve, IgniterState},
+    ecu_mock::ECUHardwareMock,
+};
+
+macro_rules! hals {
+    ($hardware:ident, $comms:ident) => {
+        &mut HALs {
+            hardware: &mut $hardware,
+            comms: &mut $comms,
+        }
+    };
+}
+
+const TIMESTEP: f32 = 0.001;
+
+static NEXT_SEQ: AtomicU16 = AtomicU16::new(0);
+const FIRE_SEQUENCE_STEPS: u16 = DEFAULT_IGNITER_PREFIRE_DURATION_MS
+    + DEFAULT_IGNITER_FIRE_DURATION_MS
+    + DEFAULT_IGNITER_SHUTDOWN_DURATION_MS
+    + DEFAULT_IGNITER_PURGE_DURATION_MS
+    + 10;
+
+#[test]
+fn duplicated_fire_command_fires_once() {
+    let mut ecu_hardware = ECUHardwareMock::new();
+    let mut comms = FaultyComms::new(CommsMock::new(), 1);
+    comms.set_receive_faults(FaultConfig {
+        duplicate_probability: 1.0,
+        ..FaultConfig::none()
+    });
+    let mut ecu = Ecu::new(0, hals!(ecu_hardware, comms));
+    ecu.on_command(
+        hals!(ecu_hardware, comms),
+        &Command::Arm {
+            code: ARM_CONFIRMATION_CODE,
+        },
+    )
+    .unwrap();
+
+    send_command(&mut comms, Command::FireIgniter);
+    let states = run(
+        &mut ecu,
+        &mut ecu_hardware,
+        &mut comms,
+        FIRE_SEQUENCE_STEPS * 2,
+    );
+
+    assert!(comms.get_receive_stats().duplicated > 0);
+    assert!(count_transitions(&states, IgniterState::Firing) == 1);
+    assert_safe(&mut ecu, &ecu_hardware);
+}
+
+#[test]
+fn lost_fire_command_never_fires() {
+    let mut ecu_hardware = ECUHardwareMock::new();
+    let mut comms = FaultyComms::new(CommsMock::new(), 2);
+    comms.set_receive_faults(FaultConfig {
+        loss_probability: 1.0,
+        ..FaultConfig::none()
+    });
+    let mut ecu = Ecu::new(0, hals!(ecu_hardware, comms));
+    ecu.on_command(
+        hals!(ecu_hardware, comms),
+        &Command::Arm {
+            code: ARM_CONFIRMATION_CODE,
+        },
+    )
+    .unwrap();
+
+    send_command(&mut comms, Command::FireIgniter);
+    let states = run(&mut ecu, &mut ecu_hardware, &mut comms, FIRE_SEQUENCE_STEPS);
+
+    assert!(comms.get_receive_stats().dropped > 0);
+    assert!(states.iter().all(|state| *state == IgniterState::Idle));
+    assert_safe(&mut ecu, &ecu_hardware);
+}
+
+#[test]
+fn delayed_fire_command_fires_late() {
+    let mut ecu_hardware = ECUHardwareMock::new();
+    let mut comms = FaultyComms::new(CommsMock::new(), 3);
+    comms.set_receive_faults(FaultConfig {
+        min_latency: 0.1,
+        max_latency: 0.1,
+        ..FaultConfig::none()
+    });
+    let mut ecu = Ecu::new(0, hals!(ecu_hardware, comms));
+    ecu.on_command(
+        hals!(ecu_hardware, comms),
+        &Command::Arm {
+            code: ARM_CONFIRMATION_CODE,
+        },
+    )
+    .unwrap();
+
+    send_command(&mut comms, Command::FireIgniter);
+    let states = run(
+        &mut ecu,
+        &mut ecu_hardware,
+        &mut comms,
+        FIRE_SEQUENCE_STEPS + 100,
+    );
+
+    let first_prefire = states
+        .iter()
+        .position(|state| *state == IgniterState::Prefire)
+        .unwrap();
+    assert!((99..=101).contains(&first_prefire));
+    assert!(count_transitions(&states, IgniterState::Firing) == 1);
+    assert_safe(&mut ecu, &ecu_hardware);
+}
+
+#[test]
+fn delayed_abort_still_aborts() {
+    let mut ecu_hardware = ECUHardwareMock::new();
+    let mut comms = FaultyComms::new(CommsMock::new(), 4);
+    comms.set_receive_faults(FaultConfig {
+        min_latency: 0.0,
+        max_latency: 0.05,
+        ..FaultConfig::none()
+    });
+    let mut ecu = Ecu::new(0, hals!(ecu_hardware, comms));
+    ecu.on_command(
+        hals!(ecu_hardware, comms),
+        &Command::Arm {
+            code: ARM_CONFIRMATION_CODE,
+        },
+    )
+    .unwrap();
+
+    send_command(&mut comms, Command::FireIgniter);
+    run(&mut ecu, &mut ecu_hardware, &mut comms, 100);
+    send_command(&mut comms, Command::Abort);
+    run(&mut ecu, &mut ecu_hardware, &mut comms, 100);
+
+    assert!(ecu.get_igniter().get_current_state() == IgniterState::Purge);
+
+    run(
+        &mut ecu,
+        &mut ecu_hardware,
+        &mut comms,
+        DEFAULT_IGNITER_PURGE_DURATION_MS,
+    );
+    assert_safe(&mut ecu, &ecu_hardware);
+}
+
+#[test]
+fn transmit_backpressure_doesnt_stall_firing() {
+    let mut ecu_hardware = ECUHardwareMock::new();
+    let mut comms = FaultyComms::new(CommsMock::new(), 5);
+    comms.set_transmit_faults(FaultConfig {
+        would_block_probability: 1.0,
+        ..FaultConfig::none()
+    });
+    let mut ecu = Ecu::new(0, hals!(ecu_hardware, comms));
+    ecu.on_command(
+        hals!(ecu_hardware, comms),
+        &Command::Arm {
+            code: ARM_CONFIRMATION_CODE,
+        },
+    )
+    .unwrap();
+
+    send_command(&mut comms, Command::FireIgniter);
+    let states = run(&mut ecu, &mut ecu_hardware, &mut comms, FIRE_SEQUENCE_STEPS);
+
+    assert!(comms.get_transmit_stats().blocked > 0);
+    assert!(comms.inner().transmit_count() == 0);
+    assert!(count_transitions(&states, IgniterState::Firing) == 1);
+    assert_safe(&mut ecu, &ecu_hardware);
+}
+
+#[test]
+fn randomized_link_faults() {
+    for seed in 1..=25 {
+        let mut ecu_hardware = ECUHardwareMock::new();
+        let mut comms = FaultyComms::new(CommsMock::new(), seed);
+        let faults = FaultConfig {
+            loss_probability: 0.2,
+            duplicate_probability: 0.2,
+            bit_flip_probability: 0.2,
+            min_latency: 0.0,
+            max_latency: 0.02,
+            would_block_probability: 0.2,
+        };
+        comms.set_receive_faults(faults);
+        comms.set_transmit_faults(faults);
+        let mut ecu = Ecu::new(0, hals!(ecu_hardware, comms));
+        ecu.on_command(
+            hals!(ecu_hardware, comms),
+            &Command::Arm {
+                code: ARM_CONFIRMATION_CODE,
+            },
+        )
+        .unwrap();
+
+        // Keep asking until the igniter starts, like an operator would
+        let mut states = Vec::new();
+        while !states.contains(&IgniterState::Prefire) && states.len() < 1000 {
+            send_command(&mut comms, Command::FireIgniter);
+            states.append(&mut run(&mut ecu, &mut ecu_hardware, &mut comms, 50));
+        }
+
+        states.append(&mut run(
+            &mut ecu,
+            &mut ecu_hardware,
+            &mut comms,
+            FIRE_SEQUENCE_STEPS,
+        ));
+
+        println!(
+            "Seed {}: rx {:?}, tx {:?}",
+            seed,
+            comms.get_receive_stats(),
+            comms.get_transmit_stats()
+        );
+
+        assert!(states.contains(&IgniterState::Firing));
+        // A fire command arriving late can restart the sequence, but it must always run to
+        // completion and leave the igniter safe afterwards
+        while ecu.get_igniter().get_current_state() != IgniterState::Idle {
+            run(&mut ecu, &mut ecu_hardware, &mut comms, FIRE_SEQUENCE_STEPS);
+        }
+        assert_safe(&mut ecu, &ecu_hardware);
+    }
+}
+
+fn send_command(comms: &mut FaultyComms<CommsMock>, command: Command) {
+    let seq = NEXT_SEQ.fetch_add(1, Ordering::Relaxed);
+    send(comms, Packet::Command { seq, command });
+}
+
+fn send(comms: &mut FaultyComms<CommsMock>, packet: Packet) {
+    comms
+        .inner_mut()
+        .queue_incoming(packet, NetworkAddress::MissionControl)
+        .unwrap();
+}
+
+fn run(
+    ecu: &mut Ecu,
+    ecu_hardware: &mut ECUHardwareMock,
+    comms: &mut FaultyComms<CommsMock>,
+    steps: u16,
+) -> Vec<IgniterState> {
+    let mut states = Vec::new();
+    let heartbeat_steps = (HEARTBEAT_RATE / TIMESTEP) as u16;
+
+    for step in 0..steps {
+        // Mission control keeps the link alive, so only the injected faults can safe the ECU
+        if step % heartbeat_steps == 0 {
+            send(comms, Packet::Heartbeat);
+        }
+
+        comms.advance_time(TIMESTEP);
+        ecu.update(
+            &mut HALs {
+                hardware: ecu_hardware,
+                comms,
+            },
+            TIMESTEP,
+        );
+        states.push(ecu.get_igniter().get_current_state());
+    }
+
+    states
+}
+
+fn count_transitions(states: &[IgniterState], to_state: IgniterState) -> usize {
+    states
+        .windows(2)
+        .filter(|pair| pair[0] != to_state && pair[1] == to_state)
+        .count()
+}
+
+fn assert_safe(ecu: &mut Ecu, ecu_hardware: &ECUHardwareMock) {
+    assert!(ecu.get_igniter().get_current_state() == IgniterState::Idle);
+    assert!(ecu_hardware.valve_states[ECUValve::IgniterFuelMain as usize] == 0);
+    assert!(ecu_hardware.valve_states[ECUValve::IgniterGOxMain as usize] == 0);
+    assert!(!ecu_hardware.sparking);
+}
@@ -0,0 +1,281 @@
+use std::sync::atomic::{AtomicU16, Ordering};
+
+use ecu::{
+    comms_watchdog::{CommsLossConfig, DEFAULT_COMMS_LOSS_TIMEOUT},
+    igniter::DEFAULT_IGNITER_PURGE_DURATION_MS,
+    Ecu, HALs,
+};
+use hal::{
+    comms_hal::{
+        AbortReason, Command, NetworkAddress, Packet, ARM_CONFIRMATION_CODE, HEARTBEAT_RATE,
+    },
+    comms_mock::CommsMock,
+    ecu_hal::{ECUValve, IgniterState},
+    ecu_mock::ECUHardwareMock,
+};
+
+macro_rules! hals {
+    ($hardware:ident, $comms:ident) => {
+        &mut HALs {
+            hardware: &mut $hardware,
+            comms: &mut $comms,
+        }
+    };
+}
+
+const TIMESTEP: f32 = 0.001;
+
+static NEXT_SEQ: AtomicU16 = AtomicU16::new(0);
+
+#[test]
+fn no_safing_before_first_contact() {
+    let mut ecu_hardware = ECUHardwareMock::new();
+    let mut comms = CommsMock::new();
+    let mut ecu = Ecu::new(0, hals!(ecu_hardware, comms));
+
+    run(
+        &mut ecu,
+        &mut ecu_hardware,
+        &mut comms,
+        DEFAULT_COMMS_LOSS_TIMEOUT * 3.0,
+    );
+
+    assert!(count_comms_loss_aborts(&comms) == 0);
+    assert!(ecu_hardware.valve_states[ECUValve::FuelVent as usize] == 0);
+}
+
+#[test]
+fn heartbeats_keep_link_alive() {
+    let mut ecu_hardware = ECUHardwareMock::new();
+    let mut comms = CommsMock::new();
+    let mut ecu = Ecu::new(0, hals!(ecu_hardware, comms));
+
+    for _ in 0..50 {
+        send(&mut comms, Packet::Heartbeat);
+        run(&mut ecu, &mut ecu_hardware, &mut comms, HEARTBEAT_RATE);
+    }
+
+    assert!(count_comms_loss_aborts(&comms) == 0);
+}
+
+#[test]
+fn comms_loss_mid_fire_safes() {
+    let mut ecu_hardware = ECUHardwareMock::new();
+    let mut comms = CommsMock::new();
+    let mut ecu = Ecu::new(0, hals!(ecu_hardware, comms));
+    ecu.on_command(
+        hals!(ecu_hardware, comms),
+        &Command::Arm {
+            code: ARM_CONFIRMATION_CODE,
+        },
+    )
+    .unwrap();
+
+    send_command(
+        &mut comms,
+        Command::SetValve {
+            valve: ECUValve::FuelPress,
+            state: 255,
+        },
+    );
+    send_command(&mut comms, Command::FireIgniter);
+    run(&mut ecu, &mut ecu_hardware, &mut comms, 0.5);
+
+    assert!(ecu.get_igniter().get_current_state() == IgniterState::Firing);
+    assert!(count_comms_loss_aborts(&comms) == 0);
+
+    run(
+        &mut ecu,
+        &mut ecu_hardware,
+        &mut comms,
+        DEFAULT_COMMS_LOSS_TIMEOUT - 0.5 + 2.0 * TIMESTEP,
+    );
+
+    assert!(count_comms_loss_aborts(&comms) == 1);
+    assert!(ecu.get_igniter().get_current_state() == IgniterState::Purge);
+    assert!(ecu_hardware.valve_states[ECUValve::IgniterFuelMain as usize] == 0);
+    assert!(ecu_hardware.valve_states[ECUValve::IgniterGOxMain as usize] == 255);
+    assert!(ecu_hardware.valve_states[ECUValve::FuelPress as usize] == 0);
+
+    run(
+        &mut ecu,
+        &mut ecu_hardware,
+        &mut comms,
+        f32::from(DEFAULT_IGNITER_PURGE_DURATION_MS) * 1e-3 + 2.0 * TIMESTEP,
+    );
+    assert!(ecu.get_igniter().get_current_state() == IgniterState::Idle);
+    assert!(ecu_hardware.valve_states[ECUValve::IgniterGOxMain as usize] == 0);
+    assert!(ecu_hardware.valve_states[ECUValve::FuelVent as usize] == 255);
+    assert!(!ecu_hardware.sparking);
+
+    // Only safes once per loss of signal
+    run(
+        &mut ecu,
+        &mut ecu_hardware,
+        &mut comms,
+        DEFAULT_COMMS_LOSS_TIMEOUT * 2.0,
+    );
+    assert!(count_comms_loss_aborts(&comms) == 1);
+}
+
+#[test]
+fn comms_loss_without_venting() {
+    let mut ecu_hardware = ECUHardwareMock::new();
+    let mut comms = CommsMock::new();
+    let mut ecu = Ecu::new(0, hals!(ecu_hardware, comms));
+    ecu.on_command(
+        hals!(ecu_hardware, comms),
+        &Command::Arm {
+            code: ARM_CONFIRMATION_CODE,
+        },
+    )
+    .unwrap();
+    ecu.set_comms_loss_config(CommsLossConfig {
+        timeout: 0.25,
+        open_fuel_vent: false,
+        ..CommsLossConfig::default()
+    });
+
+    send_command(
+        &mut comms,
+        Command::SetValve {
+            valve: ECUValve::FuelPress,
+            state: 255,
+        },
+    );
+    run(&mut ecu, &mut ecu_hardware, &mut comms, 0.3);
+
+    assert!(count_comms_loss_aborts(&comms) == 1);
+    assert!(ecu_hardware.valve_states[ECUValve::FuelPress as usize] == 0);
+    assert!(ecu_hardware.valve_states[ECUValve::FuelVent as usize] == 0);
+}
+
+#[test]
+fn comms_loss_disabled() {
+    let mut ecu_hardware = ECUHardwareMock::new();
+    let mut comms = CommsMock::new();
+    let mut ecu = Ecu::new(0, hals!(ecu_hardware, comms));
+    ecu.set_comms_loss_config(CommsLossConfig {
+        enabled: false,
+        ..CommsLossConfig::default()
+    });
+
+    send(&mut comms, Packet::Heartbeat);
+    run(
+        &mut ecu,
+        &mut ecu_hardware,
+        &mut comms,
+        DEFAULT_COMMS_LOSS_TIMEOUT * 3.0,
+    );
+
+    assert!(count_comms_loss_aborts(&comms) == 0);
+}
+
+#[test]
+fn comms_loss_rearms_after_contact() {
+    let mut ecu_hardware = ECUHardwareMock::new();
+    let mut comms = CommsMock::new();
+    let mut ecu = Ecu::new(0, hals!(ecu_hardware, comms));
+
+    send(&mut comms, Packet::Heartbeat);
+    run(
+        &mut ecu,
+        &mut ecu_hardware,
+        &mut comms,
+        DEFAULT_COMMS_LOSS_TIMEOUT + 0.1,
+    );
+    assert!(count_comms_loss_aborts(&comms) == 1);
+
+    send(&mut comms, Packet::Heartbeat);
+    run(
+        &mut ecu,
+        &mut ecu_hardware,
+        &mut comms,
+        DEFAULT_COMMS_LOSS_TIMEOUT + 0.1,
+    );
+    assert!(count_comms_loss_aborts(&comms) == 2);
+}
+
+#[test]
+fn goodbye_disarms_until_next_contact() {
+    let mut ecu_hardware = ECUHardwareMock::new();
+    let mut comms = CommsMock::new();
+    let mut ecu = Ecu::new(0, hals!(ecu_hardware, comms));
+
+    send(&mut comms, Packet::Heartbeat);
+    run(&mut ecu, &mut ecu_hardware, &mut comms, HEARTBEAT_RATE);
+    send(&mut comms, Packet::Goodbye);
+    run(
+        &mut ecu,
+        &mut ecu_hardware,
+        &mut comms,
+        DEFAULT_COMMS_LOSS_TIMEOUT * 3.0,
+    );
+
+    assert!(count_comms_loss_aborts(&comms) == 0);
+    assert!(ecu_hardware.valve_states[ECUValve::FuelVent as usize] == 0);
+
+    send(&mut comms, Packet::Heartbeat);
+    run(
+        &mut ecu,
+        &mut ecu_hardware,
+        &mut comms,
+        DEFAULT_COMMS_LOSS_TIMEOUT + 0.1,
+    );
+    assert!(count_comms_loss_aborts(&comms) == 1);
+}
+
+#[test]
+fn other_controllers_dont_feed_watchdog() {
+    let mut ecu_hardware = ECUHardwareMock::new();
+    let mut comms = CommsMock::new();
+    let mut ecu = Ecu::new(0, hals!(ecu_hardware, comms));
+
+    send(&mut comms, Packet::Heartbeat);
+    for _ in 0..20 {
+        comms
+            .queue_incoming(Packet::Heartbeat, NetworkAddress::EngineController(1))
+            .unwrap();
+        run(&mut ecu, &mut ecu_hardware, &mut comms, HEARTBEAT_RATE);
+    }
+
+    assert!(count_comms_loss_aborts(&comms) == 1);
+}
+
+fn send_command(comms: &mut CommsMock, command: Command) {
+    let seq = NEXT_SEQ.fetch_add(1, Ordering::Relaxed);
+    send(comms, Packet::Command { seq, command });
+}
+
+fn send(comms: &mut CommsMock, packet: Packet) {
+    comms
+        .queue_incoming(packet, NetworkAddress::MissionControl)
+        .unwrap();
+}
+
+fn run(ecu: &mut Ecu, ecu_hardware: &mut ECUHardwareMock, comms: &mut CommsMock, duration: f32) {
+    let mut elapsed = 0.0;
+
+    while elapsed < duration {
+        ecu.update(
+            &mut HALs {
+                hardware: ecu_hardware,
+                comms,
+            },
+            TIMESTEP,
+        );
+        elapsed += TIMESTEP;
+    }
+}
+
+fn count_comms_loss_aborts(comms: &CommsMock) -> usize {
+    comms.count_transmitted(|packet| {
+        matches!(
+            packet,
+            Packet::ControllerAborted {
+                reason: AbortReason::CommsLoss,
+                ..
+            }
+        )
+    })
+}
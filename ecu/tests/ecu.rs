@@ -1,6 +1,10 @@
-use ecu::{Ecu, HALs};
+use ecu::{Ecu, HALs, DEFAULT_TELEMETRY_RATE, RECORD_RATE};
 use hal::{
-    comms_mock::CommsMock,
+    comms_hal::{
+        Command, CommsInterface, NetworkAddress, Packet, ARM_CONFIRMATION_CODE,
+        RECORDED_DATA_BATCH_LENGTH,
+    },
+    comms_mock::{CommsLoopback, CommsMock},
     ecu_hal::{ECUHardware, ECUValve, ECU_VALVES},
     ecu_mock::ECUHardwareMock,
 };
@@ -33,3 +37,116 @@ fn test_valve_startup_states() {
     assert!(ecu_hardware.get_valve_states()[ECUValve::FuelPress as usize] == 0);
     assert!(ecu_hardware.get_valve_states()[ECUValve::FuelVent as usize] == 0);
 }
+
+#[test]
+fn test_telemetry_rate() {
+    let mut ecu_hardware = ECUHardwareMock::new();
+    let mut comms = CommsMock::new();
+    let mut ecu = Ecu::new(0, hals!(ecu_hardware, comms));
+
+    for _ in 0..1000 {
+        ecu.update(hals!(ecu_hardware, comms), 0.001);
+        comms.advance_time(0.001);
+    }
+
+    let telemetry: Vec<_> = comms
+        .transmitted()
+        .filter(|transmitted| matches!(transmitted.packet, Packet::ECUTelemtry(_)))
+        .collect();
+
+    // Allow for a frame of float error at either end of the second
+    let expected = (1.0 / DEFAULT_TELEMETRY_RATE) as usize;
+    assert!((expected - 1..=expected).contains(&telemetry.len()));
+
+    for transmitted in &telemetry {
+        assert!(transmitted.address == NetworkAddress::MissionControl);
+    }
+
+    for pair in telemetry.windows(2) {
+        let spacing = pair[1].timestamp - pair[0].timestamp;
+        assert!((spacing - DEFAULT_TELEMETRY_RATE).abs() < 0.0015);
+    }
+}
+
+#[test]
+fn test_recorded_data_transfer() {
+    let mut ecu_hardware = ECUHardwareMock::new();
+    let mut comms = CommsMock::new();
+    let mut ecu = Ecu::new(0, hals!(ecu_hardware, comms));
+
+    ecu.on_command(hals!(ecu_hardware, comms), &Command::SetRecording(true))
+        .unwrap();
+    for _ in 0..100 {
+        ecu.update(hals!(ecu_hardware, comms), RECORD_RATE);
+    }
+    ecu.on_command(hals!(ecu_hardware, comms), &Command::SetRecording(false))
+        .unwrap();
+    assert!(comms.count_transmitted(|packet| matches!(packet, Packet::RecordedData(_))) == 0);
+
+    ecu.on_command(hals!(ecu_hardware, comms), &Command::TransferData)
+        .unwrap();
+    for _ in 0..200 {
+        ecu.update(hals!(ecu_hardware, comms), RECORD_RATE);
+    }
+
+    // Full batches, then whatever's left a frame at a time
+    let batches = comms.count_transmitted(|packet| matches!(packet, Packet::RecordedDataBatch(_)));
+    let singles = comms.count_transmitted(|packet| matches!(packet, Packet::RecordedData(_)));
+    assert!(batches == 12);
+    assert!(singles < RECORDED_DATA_BATCH_LENGTH);
+    assert!((99..=100).contains(&(batches * RECORDED_DATA_BATCH_LENGTH + singles)));
+}
+
+#[test]
+fn test_loopback_commands() {
+    let link = CommsLoopback::new(
+        NetworkAddress::MissionControl,
+        NetworkAddress::EngineController(0),
+    );
+    let mut mission_control = link.endpoint_a();
+    let mut comms = link.endpoint_b();
+    let mut ecu_hardware = ECUHardwareMock::new();
+    let mut ecu = Ecu::new(0, hals!(ecu_hardware, comms));
+    ecu.on_command(
+        hals!(ecu_hardware, comms),
+        &Command::Arm {
+            code: ARM_CONFIRMATION_CODE,
+        },
+    )
+    .unwrap();
+
+    mission_control
+        .transmit(
+            &Packet::Command {
+                seq: 42,
+                command: Command::SetValve {
+                    valve: ECUValve::FuelPress,
+                    state: 255,
+                },
+            },
+            NetworkAddress::EngineController(0),
+        )
+        .unwrap();
+    ecu.update(hals!(ecu_hardware, comms), 0.001);
+    assert!(ecu_hardware.get_valve_states()[ECUValve::FuelPress as usize] == 255);
+
+    for _ in 0..20 {
+        ecu.update(hals!(ecu_hardware, comms), 0.001);
+    }
+
+    let mut telemetry_count = 0;
+    let mut acks = Vec::new();
+    while let Some((packet, from)) = mission_control.receive() {
+        assert!(from == NetworkAddress::EngineController(0));
+        match packet {
+            Packet::ECUTelemtry(telemetry) => {
+                assert!(telemetry.ecu_data.valve_states[ECUValve::FuelPress as usize] == 255);
+                telemetry_count += 1;
+            }
+            Packet::Ack { seq } => acks.push(seq),
+            _ => {}
+        }
+    }
+    assert!(telemetry_count >= 1);
+    assert!(acks == vec![42]);
+}
@@ -0,0 +1,173 @@
+use ecu::{comms_watchdog::DEFAULT_COMMS_LOSS_TIMEOUT, Ecu, HALs};
+use hal::{
+    comms_hal::{
+        AbortReason, Command, ControllerKind, NetworkAddress, Packet, ARM_CONFIRMATION_CODE,
+        PROTOCOL_VERSION,
+    },
+    comms_mock::CommsMock,
+    ecu_mock::ECUHardwareMock,
+};
+
+macro_rules! hals {
+    ($hardware:ident, $comms:ident) => {
+        &mut HALs {
+            hardware: &mut $hardware,
+            comms: &mut $comms,
+        }
+    };
+}
+
+const TIMESTEP: f32 = 0.001;
+
+#[test]
+fn hello_sent_on_startup() {
+    let mut ecu_hardware = ECUHardwareMock::new();
+    let mut comms = CommsMock::new();
+    let _ecu = Ecu::new(0, hals!(ecu_hardware, comms));
+
+    let transmitted = comms.pop_transmitted().unwrap();
+
+    assert!(transmitted.address == NetworkAddress::MissionControl);
+    assert!(matches!(
+        transmitted.packet,
+        Packet::Hello {
+            protocol_version: PROTOCOL_VERSION,
+            kind: ControllerKind::EngineController,
+            ..
+        }
+    ));
+}
+
+#[test]
+fn hello_is_answered() {
+    let (mut ecu, mut ecu_hardware, mut comms) = setup();
+
+    send_hello(&mut comms, PROTOCOL_VERSION);
+    run(&mut ecu, &mut ecu_hardware, &mut comms, TIMESTEP);
+
+    assert!(hellos(&comms) == 1);
+    assert!(ecu.get_mission_control_version() == Some(PROTOCOL_VERSION));
+    assert!(ecu.is_compatible(NetworkAddress::MissionControl));
+}
+
+#[test]
+fn mismatched_version_is_ignored() {
+    let (mut ecu, mut ecu_hardware, mut comms) = setup();
+
+    send_hello(&mut comms, PROTOCOL_VERSION + 1);
+    send(
+        &mut comms,
+        1,
+        Command::Arm {
+            code: ARM_CONFIRMATION_CODE,
+        },
+    );
+    run(&mut ecu, &mut ecu_hardware, &mut comms, TIMESTEP);
+
+    // Still answers the hello so mission control can see the mismatch too
+    assert!(hellos(&comms) == 1);
+    assert!(replies(&comms) == 0);
+    assert!(!ecu.is_armed());
+    assert!(!ecu.is_compatible(NetworkAddress::MissionControl));
+
+    // Once mission control is updated it's listened to again
+    send_hello(&mut comms, PROTOCOL_VERSION);
+    send(
+        &mut comms,
+        2,
+        Command::Arm {
+            code: ARM_CONFIRMATION_CODE,
+        },
+    );
+    run(&mut ecu, &mut ecu_hardware, &mut comms, TIMESTEP);
+
+    assert!(replies(&comms) == 1);
+    assert!(ecu.is_armed());
+}
+
+#[test]
+fn mismatched_heartbeats_dont_feed_watchdog() {
+    let (mut ecu, mut ecu_hardware, mut comms) = setup();
+
+    comms
+        .queue_incoming(Packet::Heartbeat, NetworkAddress::MissionControl)
+        .unwrap();
+    run(&mut ecu, &mut ecu_hardware, &mut comms, TIMESTEP);
+
+    send_hello(&mut comms, PROTOCOL_VERSION + 1);
+
+    let mut elapsed = 0.0;
+    while elapsed < DEFAULT_COMMS_LOSS_TIMEOUT + 0.1 {
+        comms
+            .queue_incoming(Packet::Heartbeat, NetworkAddress::MissionControl)
+            .unwrap();
+        run(&mut ecu, &mut ecu_hardware, &mut comms, 0.01);
+        elapsed += 0.01;
+    }
+
+    assert!(
+        comms.count_transmitted(|packet| matches!(
+            packet,
+            Packet::ControllerAborted {
+                reason: AbortReason::CommsLoss,
+                ..
+            }
+        )) == 1
+    );
+}
+
+fn setup() -> (Ecu, ECUHardwareMock, CommsMock) {
+    let mut ecu_hardware = ECUHardwareMock::new();
+    let mut comms = CommsMock::new();
+    let ecu = Ecu::new(0, hals!(ecu_hardware, comms));
+
+    // Only count what's sent after startup
+    while comms.pop_transmitted().is_some() {}
+
+    (ecu, ecu_hardware, comms)
+}
+
+fn send_hello(comms: &mut CommsMock, protocol_version: u16) {
+    comms
+        .queue_incoming(
+            Packet::Hello {
+                protocol_version,
+                kind: ControllerKind::MissionControl,
+                build_id: *b"testtest",
+            },
+            NetworkAddress::MissionControl,
+        )
+        .unwrap();
+}
+
+fn send(comms: &mut CommsMock, seq: u16, command: Command) {
+    comms
+        .queue_incoming(
+            Packet::Command { seq, command },
+            NetworkAddress::MissionControl,
+        )
+        .unwrap();
+}
+
+fn run(ecu: &mut Ecu, ecu_hardware: &mut ECUHardwareMock, comms: &mut CommsMock, duration: f32) {
+    let mut elapsed = 0.0;
+
+    while elapsed < duration {
+        ecu.update(
+            &mut HALs {
+                hardware: ecu_hardware,
+                comms,
+            },
+            TIMESTEP,
+        );
+        elapsed += TIMESTEP;
+    }
+}
+
+fn hellos(comms: &CommsMock) -> usize {
+    comms.count_transmitted(|packet| matches!(packet, Packet::Hello { .. }))
+}
+
+fn replies(comms: &CommsMock) -> usize {
+    comms.count_transmitted(|packet| matches!(packet, Packet::Ack { .. } | Packet::Nak { .. }))
+}
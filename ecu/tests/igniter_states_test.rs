@@ -1,12 +1,15 @@
 use ecu::{
-    igniter::{DEFAULT_IGNITER_FIRE_DURATION_MS, DEFAULT_IGNITER_PREFIRE_DURATION_MS},
+    igniter::{
+        DEFAULT_IGNITER_FIRE_DURATION_MS, DEFAULT_IGNITER_PREFIRE_DURATION_MS,
+        DEFAULT_IGNITER_PURGE_DURATION_MS, DEFAULT_IGNITER_SHUTDOWN_DURATION_MS,
+    },
     Ecu, HALs,
 };
 
 use hal::{
-    comms_hal::Packet,
+    comms_hal::{Command, NakReason, ARM_CONFIRMATION_CODE},
     comms_mock::CommsMock,
-    ecu_hal::{ECUValve, IgniterState},
+    ecu_hal::{ECUValve, IgniterState, IgniterTimingConfig},
     ecu_mock::ECUHardwareMock,
 };
 
@@ -39,10 +42,18 @@ fn fire_igniter_test() {
     let mut ecu_hardware = ECUHardwareMock::new();
     let mut comms = CommsMock::new();
     let mut ecu = Ecu::new(0, hals!(ecu_hardware, comms));
+    ecu.on_command(
+        hals!(ecu_hardware, comms),
+        &Command::Arm {
+            code: ARM_CONFIRMATION_CODE,
+        },
+    )
+    .unwrap();
 
     assert_idle_state(&mut ecu, &mut ecu_hardware);
 
-    ecu.on_packet(hals!(ecu_hardware, comms), &Packet::FireIgniter);
+    ecu.on_command(hals!(ecu_hardware, comms), &Command::FireIgniter)
+        .unwrap();
     assert_prefire_state(&mut ecu, &mut ecu_hardware);
 
     for _ in 0..DEFAULT_IGNITER_PREFIRE_DURATION_MS - 1 {
@@ -58,6 +69,22 @@ fn fire_igniter_test() {
         assert_firing_state(&mut ecu, &mut ecu_hardware);
     }
 
+    ecu.update(hals!(ecu_hardware, comms), 0.001);
+    assert_shutdown_state(&mut ecu, &mut ecu_hardware);
+
+    for _ in 0..DEFAULT_IGNITER_SHUTDOWN_DURATION_MS {
+        ecu.update(hals!(ecu_hardware, comms), 0.001);
+        assert_shutdown_state(&mut ecu, &mut ecu_hardware);
+    }
+
+    ecu.update(hals!(ecu_hardware, comms), 0.001);
+    assert_purge_state(&mut ecu, &mut ecu_hardware);
+
+    for _ in 0..DEFAULT_IGNITER_PURGE_DURATION_MS {
+        ecu.update(hals!(ecu_hardware, comms), 0.001);
+        assert_purge_state(&mut ecu, &mut ecu_hardware);
+    }
+
     ecu.update(hals!(ecu_hardware, comms), 0.001);
 
     for _ in 0..100 {
@@ -67,23 +94,169 @@ fn fire_igniter_test() {
     }
 }
 
+#[test]
+fn configured_purge_timing_test() {
+    let mut ecu_hardware = ECUHardwareMock::new();
+    let mut comms = CommsMock::new();
+    let mut ecu = Ecu::new(0, hals!(ecu_hardware, comms));
+    ecu.on_command(
+        hals!(ecu_hardware, comms),
+        &Command::Arm {
+            code: ARM_CONFIRMATION_CODE,
+        },
+    )
+    .unwrap();
+
+    ecu.on_command(
+        hals!(ecu_hardware, comms),
+        &Command::ConfigureIgniterTiming(IgniterTimingConfig {
+            prefire_duration_ms: 10,
+            fire_duration_ms: 20,
+            shutdown_duration_ms: 5,
+            purge_duration_ms: 2000,
+        }),
+    )
+    .unwrap();
+    ecu.on_command(hals!(ecu_hardware, comms), &Command::FireIgniter)
+        .unwrap();
+
+    for _ in 0..40 {
+        ecu.update(hals!(ecu_hardware, comms), 0.001);
+    }
+    assert_purge_state(&mut ecu, &mut ecu_hardware);
+
+    for _ in 0..1900 {
+        ecu.update(hals!(ecu_hardware, comms), 0.001);
+    }
+    assert_purge_state(&mut ecu, &mut ecu_hardware);
+
+    for _ in 0..200 {
+        ecu.update(hals!(ecu_hardware, comms), 0.001);
+    }
+    assert_idle_state(&mut ecu, &mut ecu_hardware);
+}
+
+#[test]
+fn abort_during_purge_test() {
+    let mut ecu_hardware = ECUHardwareMock::new();
+    let mut comms = CommsMock::new();
+    let mut ecu = Ecu::new(0, hals!(ecu_hardware, comms));
+    ecu.on_command(
+        hals!(ecu_hardware, comms),
+        &Command::Arm {
+            code: ARM_CONFIRMATION_CODE,
+        },
+    )
+    .unwrap();
+
+    ecu.on_command(hals!(ecu_hardware, comms), &Command::FireIgniter)
+        .unwrap();
+
+    let time_to_purge = DEFAULT_IGNITER_PREFIRE_DURATION_MS
+        + DEFAULT_IGNITER_FIRE_DURATION_MS
+        + DEFAULT_IGNITER_SHUTDOWN_DURATION_MS
+        + 10;
+    for _ in 0..time_to_purge {
+        ecu.update(hals!(ecu_hardware, comms), 0.001);
+    }
+    assert_purge_state(&mut ecu, &mut ecu_hardware);
+
+    // Firing again has to wait for the purge to finish
+    assert!(
+        ecu.on_command(hals!(ecu_hardware, comms), &Command::FireIgniter)
+            == Err(NakReason::WrongState)
+    );
+    assert_purge_state(&mut ecu, &mut ecu_hardware);
+
+    // Already purging, so aborting cuts the purge short
+    ecu.on_command(hals!(ecu_hardware, comms), &Command::Abort)
+        .unwrap();
+    assert_idle_state(&mut ecu, &mut ecu_hardware);
+}
+
+#[test]
+fn abort_during_firing_test() {
+    let mut ecu_hardware = ECUHardwareMock::new();
+    let mut comms = CommsMock::new();
+    let mut ecu = Ecu::new(0, hals!(ecu_hardware, comms));
+    ecu.on_command(
+        hals!(ecu_hardware, comms),
+        &Command::Arm {
+            code: ARM_CONFIRMATION_CODE,
+        },
+    )
+    .unwrap();
+
+    ecu.on_command(hals!(ecu_hardware, comms), &Command::FireIgniter)
+        .unwrap();
+
+    for _ in 0..(DEFAULT_IGNITER_PREFIRE_DURATION_MS + 10) {
+        ecu.update(hals!(ecu_hardware, comms), 0.001);
+    }
+    assert_firing_state(&mut ecu, &mut ecu_hardware);
+
+    // The chamber still gets purged
+    ecu.on_command(hals!(ecu_hardware, comms), &Command::Abort)
+        .unwrap();
+    assert_purge_state(&mut ecu, &mut ecu_hardware);
+
+    for _ in 0..(DEFAULT_IGNITER_PURGE_DURATION_MS + 10) {
+        ecu.update(hals!(ecu_hardware, comms), 0.001);
+    }
+    assert_idle_state(&mut ecu, &mut ecu_hardware);
+
+    // A second abort doesn't wait for the purge
+    ecu.on_command(
+        hals!(ecu_hardware, comms),
+        &Command::Arm {
+            code: ARM_CONFIRMATION_CODE,
+        },
+    )
+    .unwrap();
+    ecu.on_command(hals!(ecu_hardware, comms), &Command::FireIgniter)
+        .unwrap();
+    for _ in 0..(DEFAULT_IGNITER_PREFIRE_DURATION_MS + 10) {
+        ecu.update(hals!(ecu_hardware, comms), 0.001);
+    }
+    ecu.on_command(hals!(ecu_hardware, comms), &Command::Abort)
+        .unwrap();
+    ecu.on_command(hals!(ecu_hardware, comms), &Command::Abort)
+        .unwrap();
+    assert_idle_state(&mut ecu, &mut ecu_hardware);
+}
+
 fn assert_idle_state(ecu: &mut Ecu, ecu_hardware: &mut ECUHardwareMock) {
     assert!(ecu.get_igniter().get_current_state() == IgniterState::Idle);
     assert!(ecu_hardware.valve_states[ECUValve::IgniterFuelMain as usize] == 0);
     assert!(ecu_hardware.valve_states[ECUValve::IgniterGOxMain as usize] == 0);
-    assert!(ecu_hardware.sparking == false);
+    assert!(!ecu_hardware.sparking);
 }
 
 fn assert_prefire_state(ecu: &mut Ecu, ecu_hardware: &mut ECUHardwareMock) {
     assert!(ecu.get_igniter().get_current_state() == IgniterState::Prefire);
     assert!(ecu_hardware.valve_states[ECUValve::IgniterFuelMain as usize] == 0);
     assert!(ecu_hardware.valve_states[ECUValve::IgniterGOxMain as usize] > 0);
-    assert!(ecu_hardware.sparking == false);
+    assert!(!ecu_hardware.sparking);
 }
 
 fn assert_firing_state(ecu: &mut Ecu, ecu_hardware: &mut ECUHardwareMock) {
     assert!(ecu.get_igniter().get_current_state() == IgniterState::Firing);
     assert!(ecu_hardware.valve_states[ECUValve::IgniterFuelMain as usize] > 0);
     assert!(ecu_hardware.valve_states[ECUValve::IgniterGOxMain as usize] > 0);
-    assert!(ecu_hardware.sparking == true);
+    assert!(ecu_hardware.sparking);
+}
+
+fn assert_shutdown_state(ecu: &mut Ecu, ecu_hardware: &mut ECUHardwareMock) {
+    assert!(ecu.get_igniter().get_current_state() == IgniterState::Shutdown);
+    assert!(ecu_hardware.valve_states[ECUValve::IgniterFuelMain as usize] == 0);
+    assert!(ecu_hardware.valve_states[ECUValve::IgniterGOxMain as usize] > 0);
+    assert!(ecu_hardware.valve_states[ECUValve::FuelPress as usize] == 0);
+    assert!(!ecu_hardware.sparking);
+}
+
+fn assert_purge_state(ecu: &mut Ecu, ecu_hardware: &mut ECUHardwareMock) {
+    assert!(ecu.get_igniter().get_current_state() == IgniterState::Purge);
+    assert!(ecu_hardware.valve_states[ECUValve::IgniterFuelMain as usize] == 0);
+    assert!(ecu_hardware.valve_states[ECUValve::IgniterGOxMain as usize] > 0);
+    assert!(!ecu_hardware.sparking);
 }
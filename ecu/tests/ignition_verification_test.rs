@@ -0,0 +1,239 @@
+use ecu::{
+    igniter::{
+        DEFAULT_IGNITER_FIRE_DURATION_MS, DEFAULT_IGNITER_PREFIRE_DURATION_MS,
+        DEFAULT_IGNITER_PURGE_DURATION_MS, DEFAULT_IGNITER_SHUTDOWN_DURATION_MS,
+        DEFAULT_IGNITION_WINDOW_MS,
+    },
+    Ecu, HALs,
+};
+use ecu_sim::{adc::DEFAULT_SENSOR_ENCODINGS, igniter_model::IgniterModelConfig, ECUHardwareSim};
+use hal::{
+    comms_hal::{Command, Packet, ARM_CONFIRMATION_CODE},
+    comms_mock::CommsMock,
+    ecu_hal::{
+        ECUHardware, ECUValve, IgniterState, IgnitionResult, IgnitionVerificationConfig,
+        ECU_SENSORS,
+    },
+};
+
+macro_rules! hals {
+    ($hardware:ident, $comms:ident) => {
+        &mut HALs {
+            hardware: &mut $hardware,
+            comms: &mut $comms,
+        }
+    };
+}
+
+const TIMESTEP: f32 = 0.001;
+
+#[test]
+fn verified_ignition() {
+    let (mut ecu, mut ecu_hardware, mut comms) = setup(IgniterModelConfig::default());
+    verify_ignition(&mut ecu, &mut ecu_hardware, &mut comms, None);
+
+    ecu.on_command(hals!(ecu_hardware, comms), &Command::FireIgniter)
+        .unwrap();
+    assert!(ecu.get_igniter().get_ignition_result() == IgnitionResult::NotFired);
+
+    step(
+        &mut ecu,
+        &mut ecu_hardware,
+        &mut comms,
+        prefire_duration() + window_duration(),
+    );
+    assert!(ecu.get_igniter().get_current_state() == IgniterState::Firing);
+    assert!(ecu.get_igniter().get_ignition_result() == IgnitionResult::Ignited);
+
+    step(
+        &mut ecu,
+        &mut ecu_hardware,
+        &mut comms,
+        f32::from(DEFAULT_IGNITER_FIRE_DURATION_MS) * 1e-3
+            + shutdown_duration()
+            + f32::from(DEFAULT_IGNITER_PURGE_DURATION_MS) * 1e-3,
+    );
+    assert!(ecu.get_igniter().get_current_state() == IgniterState::Idle);
+    assert!(last_telemetry_result(&comms) == Some(IgnitionResult::Ignited));
+}
+
+#[test]
+fn no_ignition_shuts_down_and_purges() {
+    let (mut ecu, mut ecu_hardware, mut comms) = setup(IgniterModelConfig {
+        ignition_enabled: false,
+        ..IgniterModelConfig::default()
+    });
+    verify_ignition(&mut ecu, &mut ecu_hardware, &mut comms, None);
+
+    ecu.on_command(hals!(ecu_hardware, comms), &Command::FireIgniter)
+        .unwrap();
+    step(
+        &mut ecu,
+        &mut ecu_hardware,
+        &mut comms,
+        prefire_duration() + window_duration(),
+    );
+
+    assert!(!ecu_hardware.model().is_ignited());
+    assert!(ecu.get_igniter().get_current_state() == IgniterState::Shutdown);
+    assert!(ecu.get_igniter().get_ignition_result() == IgnitionResult::NoIgnition);
+    assert!(ecu_hardware.get_valve_states()[ECUValve::IgniterFuelMain as usize] == 0);
+    assert!(ecu_hardware.get_valve_states()[ECUValve::IgniterGOxMain as usize] == 255);
+    assert!(!ecu_hardware.get_sparking());
+
+    step(&mut ecu, &mut ecu_hardware, &mut comms, shutdown_duration());
+    assert!(ecu.get_igniter().get_current_state() == IgniterState::Purge);
+    assert!(ecu_hardware.get_valve_states()[ECUValve::IgniterGOxMain as usize] == 255);
+
+    step(
+        &mut ecu,
+        &mut ecu_hardware,
+        &mut comms,
+        f32::from(DEFAULT_IGNITER_PURGE_DURATION_MS) * 1e-3 + 0.01,
+    );
+
+    assert!(ecu.get_igniter().get_current_state() == IgniterState::Idle);
+    assert!(ecu_hardware.get_valve_states()[ECUValve::IgniterGOxMain as usize] == 0);
+    assert!(last_telemetry_result(&comms) == Some(IgnitionResult::NoIgnition));
+
+    // The result sticks around until the next firing
+    ecu.on_command(hals!(ecu_hardware, comms), &Command::FireIgniter)
+        .unwrap();
+    step(&mut ecu, &mut ecu_hardware, &mut comms, prefire_duration());
+    assert!(ecu.get_igniter().get_ignition_result() == IgnitionResult::Pending);
+}
+
+#[test]
+fn throat_temp_must_also_rise() {
+    let (mut ecu, mut ecu_hardware, mut comms) = setup(IgniterModelConfig::default());
+    // Hotter than the igniter gets in the verification window
+    verify_ignition(&mut ecu, &mut ecu_hardware, &mut comms, Some(5000.0));
+
+    ecu.on_command(hals!(ecu_hardware, comms), &Command::FireIgniter)
+        .unwrap();
+    step(
+        &mut ecu,
+        &mut ecu_hardware,
+        &mut comms,
+        prefire_duration() + window_duration(),
+    );
+
+    assert!(ecu.get_igniter().get_current_state() == IgniterState::Shutdown);
+    assert!(ecu.get_igniter().get_ignition_result() == IgnitionResult::NoIgnition);
+}
+
+#[test]
+fn unverified_without_config() {
+    let (mut ecu, mut ecu_hardware, mut comms) = setup(IgniterModelConfig {
+        ignition_enabled: false,
+        ..IgniterModelConfig::default()
+    });
+
+    ecu.on_command(hals!(ecu_hardware, comms), &Command::FireIgniter)
+        .unwrap();
+    step(
+        &mut ecu,
+        &mut ecu_hardware,
+        &mut comms,
+        prefire_duration() + window_duration(),
+    );
+
+    assert!(ecu.get_igniter().get_current_state() == IgniterState::Firing);
+    assert!(ecu.get_igniter().get_ignition_result() == IgnitionResult::Unverified);
+}
+
+fn setup(model_config: IgniterModelConfig) -> (Ecu, ECUHardwareSim, CommsMock) {
+    let mut ecu_hardware = ECUHardwareSim::new(model_config);
+    let mut comms = CommsMock::new();
+    let mut ecu = Ecu::new(0, hals!(ecu_hardware, comms));
+    ecu.on_command(
+        hals!(ecu_hardware, comms),
+        &Command::Arm {
+            code: ARM_CONFIRMATION_CODE,
+        },
+    )
+    .unwrap();
+
+    for (sensor, encoding) in ECU_SENSORS.iter().zip(DEFAULT_SENSOR_ENCODINGS.iter()) {
+        ecu.on_command(
+            hals!(ecu_hardware, comms),
+            &Command::ConfigureSensor {
+                sensor: *sensor,
+                config: encoding.to_sensor_config(),
+            },
+        )
+        .unwrap();
+    }
+
+    ecu.on_command(
+        hals!(ecu_hardware, comms),
+        &Command::SetValve {
+            valve: ECUValve::FuelPress,
+            state: 255,
+        },
+    )
+    .unwrap();
+    step(&mut ecu, &mut ecu_hardware, &mut comms, 3.0);
+
+    (ecu, ecu_hardware, comms)
+}
+
+fn verify_ignition(
+    ecu: &mut Ecu,
+    ecu_hardware: &mut ECUHardwareSim,
+    comms: &mut CommsMock,
+    throat_temp_threshold: Option<f32>,
+) {
+    ecu.on_command(
+        &mut HALs {
+            hardware: ecu_hardware,
+            comms,
+        },
+        &Command::ConfigureIgnitionVerification(IgnitionVerificationConfig {
+            enabled: true,
+            window_ms: DEFAULT_IGNITION_WINDOW_MS,
+            // Above what the chamber sees from cold flow alone
+            chamber_pressure_threshold: 90.0,
+            throat_temp_threshold,
+        }),
+    )
+    .unwrap();
+}
+
+fn prefire_duration() -> f32 {
+    f32::from(DEFAULT_IGNITER_PREFIRE_DURATION_MS) * 1e-3 + 0.01
+}
+
+fn shutdown_duration() -> f32 {
+    f32::from(DEFAULT_IGNITER_SHUTDOWN_DURATION_MS) * 1e-3 + 0.01
+}
+
+fn window_duration() -> f32 {
+    f32::from(DEFAULT_IGNITION_WINDOW_MS) * 1e-3 + 0.01
+}
+
+fn step(ecu: &mut Ecu, ecu_hardware: &mut ECUHardwareSim, comms: &mut CommsMock, duration: f32) {
+    let mut elapsed = 0.0;
+
+    while elapsed < duration {
+        ecu_hardware.update(TIMESTEP);
+        ecu.update(
+            &mut HALs {
+                hardware: ecu_hardware,
+                comms,
+            },
+            TIMESTEP,
+        );
+        elapsed += TIMESTEP;
+    }
+}
+
+fn last_telemetry_result(comms: &CommsMock) -> Option<IgnitionResult> {
+    comms
+        .transmitted()
+        .filter_map(|transmitted| match &transmitted.packet {
+            Packet::ECUTelemtry(telemetry) => Some(telemetry.ignition_result),
+            _ => None,
+        })
+        .last()
+}
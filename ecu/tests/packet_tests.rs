@@ -1,6 +1,6 @@
 use ecu::{Ecu, HALs};
 use hal::{
-    comms_hal::Packet,
+    comms_hal::{AbortReason, Command, NetworkAddress, Packet, ARM_CONFIRMATION_CODE},
     comms_mock::CommsMock,
     ecu_hal::{ECUHardware, ECUValve},
     ecu_mock::ECUHardwareMock,
@@ -20,6 +20,13 @@ fn test_set_valve_packet() {
     let mut ecu_hardware = ECUHardwareMock::new();
     let mut comms = CommsMock::new();
     let mut ecu = Ecu::new(0, hals!(ecu_hardware, comms));
+    ecu.on_command(
+        hals!(ecu_hardware, comms),
+        &Command::Arm {
+            code: ARM_CONFIRMATION_CODE,
+        },
+    )
+    .unwrap();
 
     let valves = [
         ECUValve::FuelPress,
@@ -38,13 +45,14 @@ fn test_set_valve_packet() {
             assert!(*valve_state == 0);
         }
 
-        ecu.on_packet(
+        ecu.on_command(
             hals!(ecu_hardware, comms),
-            &Packet::SetValve {
+            &Command::SetValve {
                 valve: *valve,
                 state: 255,
             },
-        );
+        )
+        .unwrap();
 
         for (index, valve_state) in ecu_hardware.valve_states.iter().enumerate() {
             if index == *valve as usize {
@@ -54,16 +62,50 @@ fn test_set_valve_packet() {
             }
         }
 
-        ecu.on_packet(
+        ecu.on_command(
             hals!(ecu_hardware, comms),
-            &Packet::SetValve {
+            &Command::SetValve {
                 valve: *valve,
                 state: 0,
             },
-        );
+        )
+        .unwrap();
 
         for valve_state in ecu_hardware.valve_states.iter() {
             assert!(*valve_state == 0);
         }
     }
 }
+
+#[test]
+fn test_abort_packet_broadcasts() {
+    let mut ecu_hardware = ECUHardwareMock::new();
+    let mut comms = CommsMock::new();
+    let mut ecu = Ecu::new(0, hals!(ecu_hardware, comms));
+
+    comms
+        .queue_incoming(
+            Packet::Command {
+                seq: 0,
+                command: Command::Abort,
+            },
+            NetworkAddress::MissionControl,
+        )
+        .unwrap();
+    ecu.update(hals!(ecu_hardware, comms), 0.001);
+
+    let aborts: Vec<_> = comms
+        .transmitted()
+        .filter(|transmitted| matches!(transmitted.packet, Packet::ControllerAborted { .. }))
+        .collect();
+
+    assert!(aborts.len() == 1);
+    assert!(
+        aborts[0].packet
+            == Packet::ControllerAborted {
+                address: NetworkAddress::EngineController(0),
+                reason: AbortReason::Commanded,
+            }
+    );
+    assert!(aborts[0].address == NetworkAddress::Broadcast);
+}
@@ -0,0 +1,266 @@
+use ecu::{igniter::DEFAULT_IGNITER_PREFIRE_DURATION_MS, Ecu, HALs};
+use hal::{
+    comms_hal::{AbortReason, Command, NetworkAddress, Packet, ARM_CONFIRMATION_CODE},
+    comms_mock::CommsMock,
+    ecu_hal::{ECUHardware, ECUSensor, IgniterState, RedlineLimit, SensorRedline, ECU_SENSORS},
+    ecu_mock::ECUHardwareMock,
+    SensorConfig,
+};
+
+macro_rules! hals {
+    ($hardware:ident, $comms:ident) => {
+        &mut HALs {
+            hardware: &mut $hardware,
+            comms: &mut $comms,
+        }
+    };
+}
+
+const TIMESTEP: f32 = 0.001;
+
+#[test]
+fn max_redline_aborts_after_debounce() {
+    let (mut ecu, mut ecu_hardware, mut comms) = setup();
+    ecu.configure_redline(ECUSensor::IgniterChamberPressure, chamber_redline());
+
+    fire(&mut ecu, &mut ecu_hardware, &mut comms);
+    ecu_hardware.sensor_readings[ECUSensor::IgniterChamberPressure as usize] = 250;
+
+    run(&mut ecu, &mut ecu_hardware, &mut comms, 19);
+    assert!(ecu.get_igniter().get_current_state() == IgniterState::Firing);
+    assert!(redline_aborts(&comms).is_empty());
+
+    run(&mut ecu, &mut ecu_hardware, &mut comms, 2);
+    assert!(ecu.get_igniter().get_current_state() == IgniterState::Purge);
+
+    let aborts = redline_aborts(&comms);
+    assert!(aborts.len() == 1);
+    assert!(
+        aborts[0]
+            == AbortReason::Redline {
+                sensor: ECUSensor::IgniterChamberPressure,
+                value: 250.0,
+            }
+    );
+
+    // Doesn't keep aborting while the sensor stays out of limits
+    run(&mut ecu, &mut ecu_hardware, &mut comms, 100);
+    assert!(redline_aborts(&comms).len() == 1);
+}
+
+#[test]
+fn short_breach_is_debounced() {
+    let (mut ecu, mut ecu_hardware, mut comms) = setup();
+    ecu.configure_redline(ECUSensor::IgniterChamberPressure, chamber_redline());
+
+    fire(&mut ecu, &mut ecu_hardware, &mut comms);
+
+    for _ in 0..10 {
+        ecu_hardware.sensor_readings[ECUSensor::IgniterChamberPressure as usize] = 250;
+        run(&mut ecu, &mut ecu_hardware, &mut comms, 10);
+        ecu_hardware.sensor_readings[ECUSensor::IgniterChamberPressure as usize] = 100;
+        run(&mut ecu, &mut ecu_hardware, &mut comms, 5);
+    }
+
+    assert!(ecu.get_igniter().get_current_state() == IgniterState::Firing);
+    assert!(redline_aborts(&comms).is_empty());
+}
+
+#[test]
+fn nan_reading_breaches_armed_redline() {
+    let (mut ecu, mut ecu_hardware, mut comms) = setup();
+    ecu.configure_redline(ECUSensor::IgniterChamberPressure, chamber_redline());
+
+    fire(&mut ecu, &mut ecu_hardware, &mut comms);
+
+    // An empty input range scales the reading to 0 / 0
+    ecu_hardware.sensor_readings[ECUSensor::IgniterChamberPressure as usize] = 0;
+    ecu_hardware.configure_sensor(
+        ECUSensor::IgniterChamberPressure,
+        &SensorConfig {
+            premin: 0.0,
+            premax: 0.0,
+            ..SensorConfig::default()
+        },
+    );
+    assert!(ecu_hardware
+        .get_sensor_value(ECUSensor::IgniterChamberPressure)
+        .is_nan());
+
+    run(&mut ecu, &mut ecu_hardware, &mut comms, 21);
+    assert!(ecu.get_igniter().get_current_state() != IgniterState::Firing);
+
+    let aborts = redline_aborts(&comms);
+    assert!(aborts.len() == 1);
+    assert!(matches!(
+        aborts[0],
+        AbortReason::Redline {
+            sensor: ECUSensor::IgniterChamberPressure,
+            value,
+        } if value.is_nan()
+    ));
+}
+
+#[test]
+fn unarmed_limit_is_ignored() {
+    let (mut ecu, mut ecu_hardware, mut comms) = setup();
+    ecu.configure_redline(ECUSensor::IgniterChamberPressure, chamber_redline());
+
+    ecu_hardware.sensor_readings[ECUSensor::IgniterChamberPressure as usize] = 250;
+    run(&mut ecu, &mut ecu_hardware, &mut comms, 100);
+
+    assert!(redline_aborts(&comms).is_empty());
+
+    // Arms as soon as the igniter enters an armed state
+    ecu.on_command(hals!(ecu_hardware, comms), &Command::FireIgniter)
+        .unwrap();
+    run(&mut ecu, &mut ecu_hardware, &mut comms, 100);
+
+    assert!(redline_aborts(&comms).is_empty());
+
+    run(
+        &mut ecu,
+        &mut ecu_hardware,
+        &mut comms,
+        DEFAULT_IGNITER_PREFIRE_DURATION_MS,
+    );
+
+    assert!(redline_aborts(&comms).len() == 1);
+    assert!(ecu.get_igniter().get_current_state() == IgniterState::Purge);
+}
+
+#[test]
+fn min_redline_aborts() {
+    let (mut ecu, mut ecu_hardware, mut comms) = setup();
+    ecu_hardware.sensor_readings[ECUSensor::FuelTankPressure as usize] = 200;
+
+    ecu.on_command(
+        hals!(ecu_hardware, comms),
+        &Command::ConfigureRedline {
+            sensor: ECUSensor::FuelTankPressure,
+            redline: SensorRedline {
+                min: RedlineLimit {
+                    value: 150.0,
+                    armed_states: IgniterState::Prefire.mask() | IgniterState::Firing.mask(),
+                },
+                max: RedlineLimit::disabled(),
+                debounce_ms: 0,
+            },
+        },
+    )
+    .unwrap();
+
+    fire(&mut ecu, &mut ecu_hardware, &mut comms);
+    assert!(redline_aborts(&comms).is_empty());
+
+    ecu_hardware.sensor_readings[ECUSensor::FuelTankPressure as usize] = 100;
+    run(&mut ecu, &mut ecu_hardware, &mut comms, 1);
+
+    assert!(ecu.get_igniter().get_current_state() == IgniterState::Purge);
+    assert!(
+        redline_aborts(&comms)
+            == vec![AbortReason::Redline {
+                sensor: ECUSensor::FuelTankPressure,
+                value: 100.0,
+            }]
+    );
+}
+
+#[test]
+fn redline_packet_configures_limits() {
+    let (mut ecu, mut ecu_hardware, mut comms) = setup();
+
+    assert!(ecu.get_redline(ECUSensor::IgniterChamberPressure) == SensorRedline::default());
+
+    ecu.on_command(
+        hals!(ecu_hardware, comms),
+        &Command::ConfigureRedline {
+            sensor: ECUSensor::IgniterChamberPressure,
+            redline: chamber_redline(),
+        },
+    )
+    .unwrap();
+
+    assert!(ecu.get_redline(ECUSensor::IgniterChamberPressure) == chamber_redline());
+}
+
+fn chamber_redline() -> SensorRedline {
+    SensorRedline {
+        min: RedlineLimit::disabled(),
+        max: RedlineLimit {
+            value: 200.0,
+            armed_states: IgniterState::Firing.mask(),
+        },
+        debounce_ms: 20,
+    }
+}
+
+fn setup() -> (Ecu, ECUHardwareMock, CommsMock) {
+    let mut ecu_hardware = ECUHardwareMock::new();
+    let mut comms = CommsMock::new();
+
+    // Pass raw readings straight through as sensor values
+    for sensor in ECU_SENSORS.iter() {
+        ecu_hardware.configure_sensor(*sensor, &SensorConfig::default());
+    }
+
+    let mut ecu = Ecu::new(0, hals!(ecu_hardware, comms));
+    ecu.on_command(
+        hals!(ecu_hardware, comms),
+        &Command::Arm {
+            code: ARM_CONFIRMATION_CODE,
+        },
+    )
+    .unwrap();
+
+    (ecu, ecu_hardware, comms)
+}
+
+fn fire(ecu: &mut Ecu, ecu_hardware: &mut ECUHardwareMock, comms: &mut CommsMock) {
+    ecu.on_command(
+        &mut HALs {
+            hardware: ecu_hardware,
+            comms,
+        },
+        &Command::FireIgniter,
+    )
+    .unwrap();
+    run(
+        ecu,
+        ecu_hardware,
+        comms,
+        DEFAULT_IGNITER_PREFIRE_DURATION_MS + 1,
+    );
+
+    assert!(ecu.get_igniter().get_current_state() == IgniterState::Firing);
+}
+
+fn run(ecu: &mut Ecu, ecu_hardware: &mut ECUHardwareMock, comms: &mut CommsMock, steps: u16) {
+    for _ in 0..steps {
+        ecu.update(
+            &mut HALs {
+                hardware: ecu_hardware,
+                comms,
+            },
+            TIMESTEP,
+        );
+    }
+}
+
+fn redline_aborts(comms: &CommsMock) -> Vec<AbortReason> {
+    comms
+        .transmitted()
+        .filter_map(|transmitted| match transmitted.packet {
+            Packet::ControllerAborted { address, reason } => {
+                assert!(address == NetworkAddress::EngineController(0));
+
+                if matches!(reason, AbortReason::Redline { .. }) {
+                    Some(reason)
+                } else {
+                    None
+                }
+            }
+            _ => None,
+        })
+        .collect()
+}
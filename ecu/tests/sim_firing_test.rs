@@ -0,0 +1,128 @@
+use ecu::{
+    igniter::{
+        DEFAULT_IGNITER_FIRE_DURATION_MS, DEFAULT_IGNITER_PREFIRE_DURATION_MS,
+        DEFAULT_IGNITER_PURGE_DURATION_MS, DEFAULT_IGNITER_SHUTDOWN_DURATION_MS,
+    },
+    Ecu, HALs,
+};
+use ecu_sim::{igniter_model::IgniterModelConfig, ECUHardwareSim};
+use hal::{
+    comms_hal::{Command, ARM_CONFIRMATION_CODE},
+    comms_mock::CommsMock,
+    ecu_hal::{ECUSensor, ECUValve, IgniterState},
+};
+
+macro_rules! hals {
+    ($hardware:ident, $comms:ident) => {
+        &mut HALs {
+            hardware: &mut $hardware,
+            comms: &mut $comms,
+        }
+    };
+}
+
+const TIMESTEP: f32 = 0.001;
+
+#[test]
+fn sim_full_firing() {
+    let mut ecu_hardware = ECUHardwareSim::new(IgniterModelConfig::default());
+    let mut comms = CommsMock::new();
+    let mut ecu = Ecu::new(0, hals!(ecu_hardware, comms));
+    ecu.on_command(
+        hals!(ecu_hardware, comms),
+        &Command::Arm {
+            code: ARM_CONFIRMATION_CODE,
+        },
+    )
+    .unwrap();
+
+    // Pressurize the fuel tank before firing
+    ecu.on_command(
+        hals!(ecu_hardware, comms),
+        &Command::SetValve {
+            valve: ECUValve::FuelPress,
+            state: 255,
+        },
+    )
+    .unwrap();
+    step(&mut ecu, &mut ecu_hardware, &mut comms, 3.0);
+    assert!(
+        ecu_hardware
+            .model()
+            .get_sensor_value(ECUSensor::FuelTankPressure)
+            > 200.0
+    );
+
+    ecu.on_command(hals!(ecu_hardware, comms), &Command::FireIgniter)
+        .unwrap();
+    step(
+        &mut ecu,
+        &mut ecu_hardware,
+        &mut comms,
+        f32::from(DEFAULT_IGNITER_PREFIRE_DURATION_MS) * 1e-3 - 0.01,
+    );
+    assert!(ecu.get_igniter().get_current_state() == IgniterState::Prefire);
+    assert!(!ecu_hardware.model().is_ignited());
+    let prefire_chamber = ecu_hardware
+        .model()
+        .get_sensor_value(ECUSensor::IgniterChamberPressure);
+
+    step(
+        &mut ecu,
+        &mut ecu_hardware,
+        &mut comms,
+        f32::from(DEFAULT_IGNITER_FIRE_DURATION_MS) * 1e-3 * 0.5,
+    );
+    assert!(ecu.get_igniter().get_current_state() == IgniterState::Firing);
+    assert!(ecu_hardware.model().is_ignited());
+    assert!(
+        ecu_hardware
+            .model()
+            .get_sensor_value(ECUSensor::IgniterChamberPressure)
+            > prefire_chamber * 2.0
+    );
+
+    step(
+        &mut ecu,
+        &mut ecu_hardware,
+        &mut comms,
+        f32::from(DEFAULT_IGNITER_FIRE_DURATION_MS) * 1e-3 * 0.5
+            + f32::from(DEFAULT_IGNITER_SHUTDOWN_DURATION_MS) * 1e-3
+            + 0.01,
+    );
+    // Fuel is off, so the flame goes out while GOx purges the chamber
+    assert!(ecu.get_igniter().get_current_state() == IgniterState::Purge);
+    assert!(!ecu_hardware.model().is_ignited());
+    assert!(ecu_hardware.model().get_fuel_flow() == 0.0);
+    assert!(ecu_hardware.model().get_gox_flow() > 0.0);
+
+    step(
+        &mut ecu,
+        &mut ecu_hardware,
+        &mut comms,
+        f32::from(DEFAULT_IGNITER_PURGE_DURATION_MS) * 1e-3 + 0.1,
+    );
+    assert!(ecu.get_igniter().get_current_state() == IgniterState::Idle);
+    assert!(
+        ecu_hardware
+            .model()
+            .get_sensor_value(ECUSensor::IgniterChamberPressure)
+            < 1.0
+    );
+}
+
+fn step(ecu: &mut Ecu, ecu_hardware: &mut ECUHardwareSim, comms: &mut CommsMock, duration: f32) {
+    let mut elapsed = 0.0;
+
+    while elapsed < duration {
+        ecu_hardware.update(TIMESTEP);
+        ecu.update(
+            &mut HALs {
+                hardware: ecu_hardware,
+                comms,
+            },
+            TIMESTEP,
+        );
+        elapsed += TIMESTEP;
+    }
+}
@@ -0,0 +1,329 @@
+use heapless::Vec;
+
+use crate::comms_hal::{
+    CommsInterface, NetworkAddress, Packet, TransferError, MAX_SERIALIZE_LENGTH,
+};
+
+/// Maximum number of packets that can be held back for latency in each direction
+pub const MAX_DELAYED_PACKETS: usize = 256;
+
+/// Describes the faults applied to packets travelling in one direction. Probabilities are in
+/// the range [0, 1] and latencies are in seconds.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct FaultConfig {
+    /// Chance a packet silently disappears
+    pub loss_probability: f32,
+    /// Chance a packet is delivered twice
+    pub duplicate_probability: f32,
+    /// Chance a single random bit in the serialized packet is flipped. Packets that no longer
+    /// deserialize are dropped like the receiver would drop them.
+    pub bit_flip_probability: f32,
+    /// Each packet is delayed by a random latency in this range. A range wider than the gap
+    /// between packets will reorder them.
+    pub min_latency: f32,
+    pub max_latency: f32,
+    /// Chance a transmit returns `nb::Error::WouldBlock` as if every mailbox were full. Only
+    /// applies to the transmit direction.
+    pub would_block_probability: f32,
+}
+
+impl FaultConfig {
+    pub const fn none() -> FaultConfig {
+        FaultConfig {
+            loss_probability: 0.0,
+            duplicate_probability: 0.0,
+            bit_flip_probability: 0.0,
+            min_latency: 0.0,
+            max_latency: 0.0,
+            would_block_probability: 0.0,
+        }
+    }
+}
+
+impl Default for FaultConfig {
+    fn default() -> FaultConfig {
+        FaultConfig::none()
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct FaultStats {
+    pub dropped: usize,
+    pub duplicated: usize,
+    pub corrupted: usize,
+    pub delayed: usize,
+    pub blocked: usize,
+}
+
+struct DelayedPacket {
+    due: f32,
+    packet: Packet,
+    address: NetworkAddress,
+}
+
+/// Wraps another `CommsInterface` and degrades the link according to a `FaultConfig` for each
+/// direction. All randomness comes from a seeded generator so a failing run can be replayed.
+/// Latency is measured against a clock that has to be driven with `advance_time()`.
+pub struct FaultyComms<C: CommsInterface> {
+    inner: C,
+    transmit_faults: FaultConfig,
+    receive_faults: FaultConfig,
+    rng: XorShift32,
+    time: f32,
+    delayed_transmits: Vec<DelayedPacket, MAX_DELAYED_PACKETS>,
+    delayed_receives: Vec<DelayedPacket, MAX_DELAYED_PACKETS>,
+    transmit_stats: FaultStats,
+    receive_stats: FaultStats,
+}
+
+impl<C: CommsInterface> FaultyComms<C> {
+    pub fn new(inner: C, seed: u32) -> FaultyComms<C> {
+        FaultyComms {
+            inner,
+            transmit_faults: FaultConfig::none(),
+            receive_faults: FaultConfig::none(),
+            rng: XorShift32::new(seed),
+            time: 0.0,
+            delayed_transmits: Vec::new(),
+            delayed_receives: Vec::new(),
+            transmit_stats: FaultStats::default(),
+            receive_stats: FaultStats::default(),
+        }
+    }
+
+    pub fn set_transmit_faults(&mut self, faults: FaultConfig) {
+        self.transmit_faults = faults;
+    }
+
+    pub fn set_receive_faults(&mut self, faults: FaultConfig) {
+        self.receive_faults = faults;
+    }
+
+    /// Advances the latency clock and hands any transmits that are now due to the inner
+    /// interface
+    pub fn advance_time(&mut self, elapsed: f32) {
+        self.time += elapsed;
+        self.flush_transmits();
+    }
+
+    pub fn get_time(&self) -> f32 {
+        self.time
+    }
+
+    pub fn get_transmit_stats(&self) -> FaultStats {
+        self.transmit_stats
+    }
+
+    pub fn get_receive_stats(&self) -> FaultStats {
+        self.receive_stats
+    }
+
+    pub fn inner(&self) -> &C {
+        &self.inner
+    }
+
+    pub fn inner_mut(&mut self) -> &mut C {
+        &mut self.inner
+    }
+
+    pub fn into_inner(self) -> C {
+        self.inner
+    }
+
+    fn flush_transmits(&mut self) {
+        while let Some(index) = next_due(&self.delayed_transmits, self.time) {
+            let delayed = &self.delayed_transmits[index];
+
+            match self.inner.transmit(&delayed.packet, delayed.address) {
+                Ok(()) | Err(nb::Error::Other(_)) => {
+                    self.delayed_transmits.swap_remove(index);
+                }
+                // Leave it queued and try again next time
+                Err(nb::Error::WouldBlock) => break,
+            }
+        }
+    }
+
+    fn poll_inner(&mut self) {
+        while let Some((packet, from)) = self.inner.receive() {
+            let faults = self.receive_faults;
+            let mut stats = self.receive_stats;
+
+            for packet in apply_faults(&mut self.rng, &faults, &mut stats, packet).iter() {
+                let latency = random_latency(&mut self.rng, &faults);
+                if latency > 0.0 {
+                    stats.delayed += 1;
+                }
+
+                let delayed = DelayedPacket {
+                    due: self.time + latency,
+                    packet: packet.clone(),
+                    address: from,
+                };
+
+                if self.delayed_receives.push(delayed).is_err() {
+                    stats.dropped += 1;
+                }
+            }
+
+            self.receive_stats = stats;
+        }
+    }
+}
+
+impl<C: CommsInterface> CommsInterface for FaultyComms<C> {
+    fn transmit(
+        &mut self,
+        packet: &Packet,
+        address: NetworkAddress,
+    ) -> nb::Result<(), TransferError> {
+        let faults = self.transmit_faults;
+
+        if self.rng.chance(faults.would_block_probability) {
+            self.transmit_stats.blocked += 1;
+            return Err(nb::Error::WouldBlock);
+        }
+
+        let mut stats = self.transmit_stats;
+        let mut result = Ok(());
+
+        for packet in apply_faults(&mut self.rng, &faults, &mut stats, packet.clone()).iter() {
+            let latency = random_latency(&mut self.rng, &faults);
+
+            if latency > 0.0 {
+                stats.delayed += 1;
+
+                let delayed = DelayedPacket {
+                    due: self.time + latency,
+                    packet: packet.clone(),
+                    address,
+                };
+
+                if self.delayed_transmits.push(delayed).is_err() {
+                    result = Err(nb::Error::WouldBlock);
+                }
+            } else {
+                result = self.inner.transmit(packet, address);
+            }
+        }
+
+        self.transmit_stats = stats;
+
+        result
+    }
+
+    fn receive(&mut self) -> Option<(Packet, NetworkAddress)> {
+        self.flush_transmits();
+        self.poll_inner();
+
+        next_due(&self.delayed_receives, self.time).map(|index| {
+            let delayed = self.delayed_receives.swap_remove(index);
+
+            (delayed.packet, delayed.address)
+        })
+    }
+}
+
+/// Runs a packet through loss, corruption and duplication, returning the copies that should
+/// actually be delivered
+fn apply_faults(
+    rng: &mut XorShift32,
+    faults: &FaultConfig,
+    stats: &mut FaultStats,
+    packet: Packet,
+) -> Vec<Packet, 2> {
+    let mut output = Vec::new();
+
+    if rng.chance(faults.loss_probability) {
+        stats.dropped += 1;
+        return output;
+    }
+
+    let packet = if rng.chance(faults.bit_flip_probability) {
+        stats.corrupted += 1;
+
+        match flip_random_bit(rng, &packet) {
+            Some(packet) => packet,
+            None => {
+                stats.dropped += 1;
+                return output;
+            }
+        }
+    } else {
+        packet
+    };
+
+    if rng.chance(faults.duplicate_probability) {
+        stats.duplicated += 1;
+        let _ = output.push(packet.clone());
+    }
+
+    let _ = output.push(packet);
+
+    output
+}
+
+fn flip_random_bit(rng: &mut XorShift32, packet: &Packet) -> Option<Packet> {
+    let mut buffer = [0_u8; MAX_SERIALIZE_LENGTH];
+    let len = packet.serialize(&mut buffer).ok()?;
+
+    let bit = rng.next_below(len * 8);
+    buffer[bit / 8] ^= 1 << (bit % 8);
+
+    Packet::deserialize(&mut buffer[0..len]).ok()
+}
+
+fn random_latency(rng: &mut XorShift32, faults: &FaultConfig) -> f32 {
+    faults.min_latency + (faults.max_latency - faults.min_latency) * rng.next_f32()
+}
+
+/// Finds the packet with the earliest due time, if it's due
+fn next_due(packets: &[DelayedPacket], time: f32) -> Option<usize> {
+    packets
+        .iter()
+        .enumerate()
+        .filter(|(_, delayed)| delayed.due <= time)
+        .min_by(|(_, a), (_, b)| {
+            a.due
+                .partial_cmp(&b.due)
+                .unwrap_or(core::cmp::Ordering::Equal)
+        })
+        .map(|(index, _)| index)
+}
+
+/// Marsaglia's xorshift, plenty random enough for fault injection and small enough for no_std
+struct XorShift32 {
+    state: u32,
+}
+
+impl XorShift32 {
+    fn new(seed: u32) -> XorShift32 {
+        // A zero state would get stuck at zero forever
+        XorShift32 {
+            state: if seed == 0 { 0x9E37_79B9 } else { seed },
+        }
+    }
+
+    fn next_u32(&mut self) -> u32 {
+        let mut x = self.state;
+        x ^= x << 13;
+        x ^= x >> 17;
+        x ^= x << 5;
+        self.state = x;
+
+        x
+    }
+
+    #[allow(clippy::cast_precision_loss)]
+    fn next_f32(&mut self) -> f32 {
+        (self.next_u32() >> 8) as f32 / (1_u32 << 24) as f32
+    }
+
+    fn next_below(&mut self, bound: usize) -> usize {
+        self.next_u32() as usize % bound.max(1)
+    }
+
+    fn chance(&mut self, probability: f32) -> bool {
+        probability > 0.0 && self.next_f32() < probability
+    }
+}
@@ -1,21 +1,47 @@
+mod checksum;
+pub mod comms_bridge;
 pub mod comms_canfd_hal;
 pub mod comms_ethernet_hal;
+pub mod fragmentation;
 
 use postcard::{
     flavors::{Cobs, Slice},
-    from_bytes_cobs, serialize_with_flavor, Error,
+    from_bytes, serialize_with_flavor, Error,
 };
 use serde::{Deserialize, Serialize};
 
 use crate::{
-    ecu_hal::{ECUDataFrame, ECUSensor, ECUValve, IgniterTimingConfig},
+    ecu_hal::{
+        ECUDataFrame, ECUSensor, ECUValve, IgniterTimingConfig, IgnitionResult,
+        IgnitionVerificationConfig, SensorRedline,
+    },
     SensorConfig,
 };
 
-use self::comms_canfd_hal::CANFDTransferError;
+use self::{
+    checksum::{Checksum, CHECKSUM_LENGTH},
+    comms_canfd_hal::CANFDTransferError,
+};
 
 pub const MAX_SERIALIZE_LENGTH: usize = 60;
 
+/// Number of frames in `Packet::RecordedDataBatch`
+pub const RECORDED_DATA_BATCH_LENGTH: usize = 8;
+
+/// How often mission control sends `Packet::Heartbeat` while it's connected, in seconds
+pub const HEARTBEAT_RATE: f32 = 0.1;
+
+/// Has to be sent in `Command::Arm` for the ECU to arm, so a corrupted or misdirected packet can't
+/// arm it by accident
+pub const ARM_CONFIRMATION_CODE: u32 = 0x4152_4D21;
+
+/// Bumped whenever the serialized layout of any packet changes. Controllers that announce a
+/// different version in `Packet::Hello` get ignored, since their packets can't be trusted to
+/// decode into what was sent.
+pub const PROTOCOL_VERSION: u16 = 1;
+
+pub const BUILD_ID_LENGTH: usize = 8;
+
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
 pub enum NetworkAddress {
     Broadcast,
@@ -23,6 +49,38 @@ pub enum NetworkAddress {
     MissionControl,
 }
 
+/// What's on the other end of a `Packet::Hello`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ControllerKind {
+    EngineController,
+    MissionControl,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum AbortReason {
+    /// Mission control sent `Command::Abort`
+    Commanded,
+    /// Nothing was heard from mission control within the comms loss timeout
+    CommsLoss,
+    /// A sensor stayed outside one of its armed redlines for longer than the debounce time
+    Redline { sensor: ECUSensor, value: f32 },
+}
+
+/// Why a controller refused a `Command`, sent back in `Packet::Nak`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum NakReason {
+    /// The command isn't allowed in the igniter's current state
+    WrongState,
+    /// The command is hazardous and the ECU isn't armed
+    NotArmed,
+    /// `Command::Arm` didn't carry `ARM_CONFIRMATION_CODE`
+    InvalidArmCode,
+    /// One of the command's parameters is out of range
+    InvalidParameter,
+    /// The controller doesn't handle this command
+    Unsupported,
+}
+
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum TransferError {
     Unknown,
@@ -39,6 +97,10 @@ pub enum SerializationError {
     UnexpectedEnd,
     BadVar,
     BadEncoding,
+    /// A fragment's index, count or length don't fit with the rest of its packet
+    BadFragment,
+    /// The packet's CRC trailer doesn't match its contents, it was corrupted on the way
+    ChecksumMismatch,
 }
 
 pub trait CommsInterface {
@@ -58,9 +120,61 @@ pub trait CommsInterface {
     fn receive(&mut self) -> Option<(Packet, NetworkAddress)>;
 }
 
+/// The discriminant of every variant is pinned by the `discriminant_layout_is_pinned` test in
+/// `hal/tests/comms_hal.rs`. Adding, removing or reordering variants needs a `PROTOCOL_VERSION`
+/// bump so controllers on either side of the change can tell they don't match.
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
 pub enum Packet {
     // -- Commands -- //
+    /// Answered with an `Ack` or `Nak` carrying the same `seq`. Senders should use a new `seq` for
+    /// every command and only reuse it when retrying.
+    Command {
+        seq: u16,
+        command: Command,
+    },
+    Ack {
+        seq: u16,
+    },
+    Nak {
+        seq: u16,
+        reason: NakReason,
+    },
+
+    // -- Telemetry -- //
+    ECUTelemtry(ECUTelemtryData),
+    ControllerAborted {
+        address: NetworkAddress,
+        reason: AbortReason,
+    },
+
+    // -- Data transfer -- //
+    RecordedData(ECUDataFrame),
+
+    // -- Link -- //
+    Heartbeat,
+    /// Sent by mission control when it disconnects on purpose, so the ECU stops expecting
+    /// heartbeats instead of safing itself. The next packet from mission control starts the comms
+    /// loss watchdog again.
+    Goodbye,
+
+    // -- Added since, in the order they were added -- //
+    /// Recorded data sent a batch at a time. Too long for a single frame, has to be sent through
+    /// `fragmentation`.
+    RecordedDataBatch([ECUDataFrame; RECORDED_DATA_BATCH_LENGTH]),
+    /// Sent by every controller when it starts up, and by the ECU in reply to one. The layout of
+    /// this packet can never change between protocol versions, otherwise a mismatch couldn't be
+    /// detected.
+    Hello {
+        protocol_version: u16,
+        kind: ControllerKind,
+        build_id: [u8; BUILD_ID_LENGTH],
+    },
+}
+
+/// Pinned by `discriminant_layout_is_pinned` the same way as `Packet`, any change to the variants
+/// needs a `PROTOCOL_VERSION` bump
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub enum Command {
     SetValve {
         valve: ECUValve,
         state: u8,
@@ -73,22 +187,61 @@ pub enum Packet {
     },
     ConfigureIgniterTiming(IgniterTimingConfig),
     Abort,
-
-    // -- Telemetry -- //
-    ECUTelemtry(ECUTelemtryData),
-    ControllerAborted(NetworkAddress),
     SetRecording(bool),
     TransferData,
-    RecordedData(ECUDataFrame),
-    // -- Data transfer -- //
+    ConfigureRedline {
+        sensor: ECUSensor,
+        redline: SensorRedline,
+    },
+    ConfigureIgnitionVerification(IgnitionVerificationConfig),
+    Arm {
+        code: u32,
+    },
+    Disarm,
+}
+
+impl Command {
+    /// Commands that can open propellant or pressurant valves or light the igniter, which the ECU
+    /// only takes while armed. Closing a valve is always allowed, since that's how the stand gets
+    /// safed.
+    pub fn is_hazardous(&self) -> bool {
+        match self {
+            Command::SetValve { valve, state } => {
+                *state > 0
+                    && matches!(
+                        valve,
+                        ECUValve::IgniterFuelMain | ECUValve::IgniterGOxMain | ECUValve::FuelPress
+                    )
+            }
+            Command::SetSparking(_) | Command::FireIgniter => true,
+            _ => false,
+        }
+    }
 }
 
 impl Packet {
+    /// Builds this controller's `Hello`. `version` is usually a git hash or the crate version, and
+    /// is cut off at `BUILD_ID_LENGTH` bytes.
+    pub fn hello(kind: ControllerKind, version: &str) -> Packet {
+        let mut build_id = [0_u8; BUILD_ID_LENGTH];
+        for (to, from) in build_id.iter_mut().zip(version.bytes()) {
+            *to = from;
+        }
+
+        Packet::Hello {
+            protocol_version: PROTOCOL_VERSION,
+            kind,
+            build_id,
+        }
+    }
+
     pub fn serialize(&self, buffer: &mut [u8]) -> Result<usize, SerializationError> {
         match Cobs::try_new(Slice::new(buffer)) {
             Ok(flavor) => {
-                let serialized =
-                    serialize_with_flavor::<Packet, Cobs<Slice>, &mut [u8]>(&self, flavor);
+                let serialized = serialize_with_flavor::<Packet, Checksum<Cobs<Slice>>, &mut [u8]>(
+                    self,
+                    Checksum::new(flavor),
+                );
 
                 match serialized {
                     Ok(output_buffer) => Ok(output_buffer.len()),
@@ -111,7 +264,27 @@ impl Packet {
     }
 
     pub fn deserialize(buffer: &mut [u8]) -> Result<Packet, SerializationError> {
-        match from_bytes_cobs(buffer) {
+        // The decoder doesn't know about the sentinel, so it has to be cut off first
+        let frame_len = buffer
+            .iter()
+            .position(|byte| *byte == 0)
+            .unwrap_or(buffer.len());
+
+        let len = match cobs::decode_in_place(&mut buffer[0..frame_len]) {
+            Ok(len) => len,
+            Err(()) => return Err(SerializationError::BadEncoding),
+        };
+
+        if len < CHECKSUM_LENGTH {
+            return Err(SerializationError::UnexpectedEnd);
+        }
+
+        let (data, checksum) = buffer[0..len].split_at(len - CHECKSUM_LENGTH);
+        if checksum::crc16(data).to_le_bytes() != checksum {
+            return Err(SerializationError::ChecksumMismatch);
+        }
+
+        match from_bytes(data) {
             Ok(packet) => Ok(packet),
             Err(err) => match err {
                 Error::WontImplement | Error::NotYetImplemented => {
@@ -136,4 +309,6 @@ impl Packet {
 pub struct ECUTelemtryData {
     pub ecu_data: ECUDataFrame,
     pub max_loop_time: f32, // In seconds
+    pub ignition_result: IgnitionResult,
+    pub armed: bool,
 }
@@ -0,0 +1,52 @@
+use postcard::flavors::SerFlavor;
+
+/// Bytes the checksum adds to the end of every serialized packet, before COBS framing
+pub const CHECKSUM_LENGTH: usize = 2;
+
+/// CRC-16/CCITT-FALSE, polynomial 0x1021 and initial value 0xFFFF. Bitwise instead of
+/// table driven, packets are short enough that the speed doesn't matter.
+pub fn crc16(data: &[u8]) -> u16 {
+    data.iter()
+        .fold(0xFFFF, |crc, byte| crc16_update(crc, *byte))
+}
+
+fn crc16_update(crc: u16, byte: u8) -> u16 {
+    let mut crc = crc ^ (u16::from(byte) << 8);
+
+    for _ in 0..8 {
+        crc = if crc & 0x8000 != 0 {
+            (crc << 1) ^ 0x1021
+        } else {
+            crc << 1
+        };
+    }
+
+    crc
+}
+
+/// Postcard flavor that appends the CRC of everything pushed through it once serialization is
+/// done, so it can sit in front of `Cobs` and the checksum ends up inside the COBS frame
+pub struct Checksum<B> {
+    flav: B,
+    crc: u16,
+}
+
+impl<B> Checksum<B> {
+    pub fn new(flav: B) -> Checksum<B> {
+        Checksum { flav, crc: 0xFFFF }
+    }
+}
+
+impl<B: SerFlavor> SerFlavor for Checksum<B> {
+    type Output = B::Output;
+
+    fn try_push(&mut self, data: u8) -> Result<(), ()> {
+        self.crc = crc16_update(self.crc, data);
+        self.flav.try_push(data)
+    }
+
+    fn release(mut self) -> Result<Self::Output, ()> {
+        self.flav.try_extend(&self.crc.to_le_bytes())?;
+        self.flav.release()
+    }
+}
@@ -0,0 +1,335 @@
+use core::slice;
+
+use heapless::Vec;
+use serde::{Deserialize, Serialize};
+
+use super::{
+    comms_canfd_hal::{self, CANFDPacketMetadata, CANFD_BUFFER_SIZE},
+    comms_ethernet_hal::{ETHERNET_BUFFER_SIZE, ETHERNET_HEADER_SIZE},
+    NetworkAddress, MAX_SERIALIZE_LENGTH,
+};
+
+/// Used as both the from and to address of the pulse the bridge sends, so mission control can
+/// tell the bridge is alive even when no controller is talking
+pub const CET_PULSE_ADDRESS: u16 = 255;
+
+/// Mission control sends a header with this as both addresses to have the bridge send everything
+/// addressed to mission control back to wherever the announcement came from
+pub const ANNOUNCE_ADDRESS: u16 = 0xFFFF;
+
+/// Port ctrl-view listens on, and where packets for mission control go until it announces itself
+pub const MISSION_CONTROL_PORT: u16 = 25565;
+
+/// Port the bridge listens on
+pub const BRIDGE_PORT: u16 = 8888;
+
+/// Where packets for mission control go until it announces itself
+pub const BROADCAST_ENDPOINT: EthernetEndpoint = EthernetEndpoint {
+    ip: [10, 0, 0, 255],
+    port: MISSION_CONTROL_PORT,
+};
+
+/// Shortest frame put on the bus, the same as a classic CAN frame
+pub const MIN_CANFD_FRAME_LENGTH: usize = 8;
+
+/// Most addresses a `RoutingTable` can hold routes for
+pub const MAX_ROUTES: usize = 8;
+
+/// Most mission control programs the bridge sends to at once, e.g. ctrl-view and ctrl-send on two
+/// laptops. Announcing past this forgets the one that announced first.
+pub const MAX_MISSION_CONTROL_ENDPOINTS: usize = 4;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct EthernetEndpoint {
+    pub ip: [u8; 4],
+    pub port: u16,
+}
+
+/// A frame as it's sent or received on the CAN-FD bus. `buffer` holds the metadata followed by the
+/// data, same as `comms_canfd_hal::serialize_packet` writes it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CANFDFrame {
+    pub id: u32,
+    pub len: usize,
+    pub buffer: [u8; CANFD_BUFFER_SIZE],
+}
+
+/// Where the controller at an address lives
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Route {
+    /// On the CAN-FD bus, so frames addressed to it from Ethernet get put on the bus
+    CANFD,
+    /// Everywhere mission control has announced itself from
+    MissionControl,
+    /// At a fixed Ethernet endpoint
+    Ethernet(EthernetEndpoint),
+    /// On both sides, frames are always forwarded
+    Everywhere,
+    /// Frames addressed to it aren't forwarded at all
+    Drop,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum BridgeError {
+    /// The frame's from or to address isn't a `NetworkAddress`
+    UnknownAddress,
+    /// The metadata's data length is 0 or longer than `MAX_SERIALIZE_LENGTH`
+    BadLength,
+    /// The frame is shorter than its header and the data length in its metadata
+    FrameTooShort,
+    /// The routing table has no room left for another address
+    RoutingTableFull,
+}
+
+/// Routes per address. Addresses without a route are assumed to be engine controllers on the bus.
+pub struct RoutingTable {
+    routes: Vec<(NetworkAddress, Route), MAX_ROUTES>,
+}
+
+impl RoutingTable {
+    /// Broadcasts go everywhere and mission control gets whatever's addressed to it
+    pub fn new() -> RoutingTable {
+        let mut routes = Vec::new();
+
+        // Can't fail, MAX_ROUTES leaves room for both
+        let _ = routes.push((NetworkAddress::Broadcast, Route::Everywhere));
+        let _ = routes.push((NetworkAddress::MissionControl, Route::MissionControl));
+
+        RoutingTable { routes }
+    }
+
+    /// Sets the route for `address`, replacing any route it already had.
+    ///
+    /// # Errors
+    /// Returns `BridgeError::RoutingTableFull` if `address` is new and there are already
+    /// `MAX_ROUTES` routes
+    pub fn set_route(&mut self, address: NetworkAddress, route: Route) -> Result<(), BridgeError> {
+        if let Some(entry) = self
+            .routes
+            .iter_mut()
+            .find(|(routed_address, _)| *routed_address == address)
+        {
+            entry.1 = route;
+            return Ok(());
+        }
+
+        self.routes
+            .push((address, route))
+            .map_err(|_| BridgeError::RoutingTableFull)
+    }
+
+    pub fn get_route(&self, address: NetworkAddress) -> Route {
+        self.find_route(address).copied().unwrap_or(Route::CANFD)
+    }
+
+    fn find_route(&self, address: NetworkAddress) -> Option<&Route> {
+        self.routes
+            .iter()
+            .find(|(routed_address, _)| *routed_address == address)
+            .map(|(_, route)| route)
+    }
+}
+
+impl Default for RoutingTable {
+    fn default() -> Self {
+        RoutingTable::new()
+    }
+}
+
+/// Translates between CAN-FD frames and the Ethernet framing used by `comms_ethernet_hal`. The
+/// metadata is passed through untouched, so fragmented packets are forwarded frame by frame and
+/// only reassembled at the receiving end.
+///
+/// Mirrors the transceiver firmware in `canfd-ethernet-transceiver`, including sending mission
+/// control's packets to every announced endpoint. The one difference is frames whose metadata
+/// gives a data length of 0 or over `MAX_SERIALIZE_LENGTH`: the firmware clamps the length when
+/// going to Ethernet and drops zero-length datagrams silently, while this rejects both ways with
+/// `BridgeError::BadLength`, since a clamped frame can't decode into what was sent anyway.
+pub struct Bridge {
+    routes: RoutingTable,
+    /// Oldest announcement first
+    mission_control_endpoints: Vec<EthernetEndpoint, MAX_MISSION_CONTROL_ENDPOINTS>,
+}
+
+impl Bridge {
+    pub fn new(routes: RoutingTable) -> Bridge {
+        Bridge {
+            routes,
+            mission_control_endpoints: Vec::new(),
+        }
+    }
+
+    pub fn get_routes(&self) -> &RoutingTable {
+        &self.routes
+    }
+
+    pub fn get_routes_mut(&mut self) -> &mut RoutingTable {
+        &mut self.routes
+    }
+
+    /// Every endpoint mission control announced itself from, or `BROADCAST_ENDPOINT` if it hasn't
+    pub fn get_mission_control_endpoints(&self) -> &[EthernetEndpoint] {
+        if self.mission_control_endpoints.is_empty() {
+            slice::from_ref(&BROADCAST_ENDPOINT)
+        } else {
+            &self.mission_control_endpoints
+        }
+    }
+
+    /// Writes a frame received from the bus into `buffer` in Ethernet framing. Returns its length
+    /// and every endpoint to send it to, or `None` if the receiver isn't on the Ethernet side.
+    ///
+    /// # Errors
+    /// Returns a `BridgeError` if the frame's id or metadata are invalid
+    pub fn canfd_to_ethernet(
+        &self,
+        frame: &CANFDFrame,
+        buffer: &mut [u8; ETHERNET_BUFFER_SIZE],
+    ) -> Result<Option<(usize, &[EthernetEndpoint])>, BridgeError> {
+        let (from_address, to_address) = match comms_canfd_hal::frame_id_addresses(frame.id) {
+            (Some(from_address), Some(to_address)) => (from_address, to_address),
+            _ => return Err(BridgeError::UnknownAddress),
+        };
+
+        let len = data_length(&frame.buffer[0..4])?;
+
+        if frame.len.min(CANFD_BUFFER_SIZE) < len + 4 {
+            return Err(BridgeError::FrameTooShort);
+        }
+
+        let endpoints = match self.routes.find_route(to_address) {
+            Some(Route::MissionControl | Route::Everywhere) => self.get_mission_control_endpoints(),
+            Some(Route::Ethernet(endpoint)) => slice::from_ref(endpoint),
+            Some(Route::CANFD | Route::Drop) | None => return Ok(None),
+        };
+
+        write_header(
+            address_to_u16(from_address),
+            address_to_u16(to_address),
+            buffer,
+        );
+        buffer[4..(ETHERNET_HEADER_SIZE + len)].copy_from_slice(&frame.buffer[0..(len + 4)]);
+
+        Ok(Some((ETHERNET_HEADER_SIZE + len, endpoints)))
+    }
+
+    /// Turns a datagram received from `source` into a frame for the bus, or `None` if it's not
+    /// meant for the bus. Mission control announcing itself is handled here too.
+    ///
+    /// # Errors
+    /// Returns a `BridgeError` if the header is invalid
+    pub fn ethernet_to_canfd(
+        &mut self,
+        buffer: &[u8],
+        source: EthernetEndpoint,
+    ) -> Result<Option<CANFDFrame>, BridgeError> {
+        if buffer.len() < ETHERNET_HEADER_SIZE {
+            return Err(BridgeError::FrameTooShort);
+        }
+
+        // The source port is kept, since ctrl-view and ctrl-send listen on different ports
+        if is_announce(buffer) {
+            if !self.mission_control_endpoints.contains(&source) {
+                if self.mission_control_endpoints.is_full() {
+                    self.mission_control_endpoints.remove(0);
+                }
+
+                // Can't fail, there's room after the removal
+                let _ = self.mission_control_endpoints.push(source);
+            }
+
+            return Ok(None);
+        }
+
+        let from_id = u16::from_le_bytes([buffer[0], buffer[1]]);
+        let to_id = u16::from_le_bytes([buffer[2], buffer[3]]);
+
+        let (from_address, to_address) = match (
+            NetworkAddress::from_id(u32::from(from_id)),
+            NetworkAddress::from_id(u32::from(to_id)),
+        ) {
+            (Some(from_address), Some(to_address)) => (from_address, to_address),
+            _ => return Err(BridgeError::UnknownAddress),
+        };
+
+        let len = data_length(&buffer[4..8])?;
+
+        if buffer.len() < ETHERNET_HEADER_SIZE + len {
+            return Err(BridgeError::FrameTooShort);
+        }
+
+        match self.routes.get_route(to_address) {
+            Route::CANFD | Route::Everywhere => {}
+            _ => return Ok(None),
+        }
+
+        let mut frame = CANFDFrame {
+            id: comms_canfd_hal::frame_id(from_address, to_address, len + 4),
+            len: (len + 4).max(MIN_CANFD_FRAME_LENGTH),
+            buffer: [0_u8; CANFD_BUFFER_SIZE],
+        };
+        frame.buffer[0..(len + 4)].copy_from_slice(&buffer[4..(ETHERNET_HEADER_SIZE + len)]);
+
+        Ok(Some(frame))
+    }
+
+    /// Writes the periodic pulse into `buffer`, returning its length and where to send it
+    pub fn cet_pulse(
+        &self,
+        buffer: &mut [u8; ETHERNET_BUFFER_SIZE],
+    ) -> (usize, &[EthernetEndpoint]) {
+        buffer.fill(0);
+        write_header(CET_PULSE_ADDRESS, CET_PULSE_ADDRESS, buffer);
+
+        (ETHERNET_BUFFER_SIZE, self.get_mission_control_endpoints())
+    }
+}
+
+impl Default for Bridge {
+    fn default() -> Self {
+        Bridge::new(RoutingTable::new())
+    }
+}
+
+/// Whether a datagram received by mission control is the bridge's pulse rather than a packet
+pub fn is_cet_pulse(buffer: &[u8]) -> bool {
+    buffer.len() >= 4
+        && u16::from_le_bytes([buffer[0], buffer[1]]) == CET_PULSE_ADDRESS
+        && u16::from_le_bytes([buffer[2], buffer[3]]) == CET_PULSE_ADDRESS
+}
+
+/// Whether a datagram received by the bridge is mission control announcing itself
+pub fn is_announce(buffer: &[u8]) -> bool {
+    buffer.len() >= 4
+        && u16::from_le_bytes([buffer[0], buffer[1]]) == ANNOUNCE_ADDRESS
+        && u16::from_le_bytes([buffer[2], buffer[3]]) == ANNOUNCE_ADDRESS
+}
+
+/// The header mission control sends the bridge so packets addressed to it come back to the IP and
+/// port it was sent from
+pub fn announce(buffer: &mut [u8; ETHERNET_BUFFER_SIZE]) -> usize {
+    buffer.fill(0);
+    write_header(ANNOUNCE_ADDRESS, ANNOUNCE_ADDRESS, buffer);
+
+    ETHERNET_HEADER_SIZE
+}
+
+fn data_length(metadata: &[u8]) -> Result<usize, BridgeError> {
+    let len = CANFDPacketMetadata::from_byte_slice(metadata).get_true_data_length();
+
+    if len == 0 || len > MAX_SERIALIZE_LENGTH {
+        Err(BridgeError::BadLength)
+    } else {
+        Ok(len)
+    }
+}
+
+fn write_header(from_id: u16, to_id: u16, buffer: &mut [u8; ETHERNET_BUFFER_SIZE]) {
+    buffer[0..2].copy_from_slice(&from_id.to_le_bytes());
+    buffer[2..4].copy_from_slice(&to_id.to_le_bytes());
+}
+
+#[allow(clippy::cast_possible_truncation)]
+fn address_to_u16(address: NetworkAddress) -> u16 {
+    (address.to_standard_id() & 0xFFFF) as u16
+}
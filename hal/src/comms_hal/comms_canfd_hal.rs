@@ -1,12 +1,19 @@
-use super::{NetworkAddress, Packet, SerializationError, TransferError, MAX_SERIALIZE_LENGTH};
+use super::{
+    fragmentation::Fragment, NetworkAddress, Packet, SerializationError, TransferError,
+    MAX_SERIALIZE_LENGTH,
+};
 use serde::{Deserialize, Serialize};
 
 pub const CANFD_BUFFER_SIZE: usize = MAX_SERIALIZE_LENGTH + 4;
 
+const FRAME_ID_ADDRESS_MASK: u32 = 0x1F;
+
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
 pub enum CANFDTransferError {
     MetadataSerialization,
     FrameTooBigForRegion,
+    /// No transmit mailbox freed up in time to send the rest of a fragmented packet
+    MailboxTimeout,
     Unknown,
 }
 
@@ -37,6 +44,24 @@ impl NetworkAddress {
     }
 }
 
+/// Bits 0-4 of a frame id are the receiver's address, bit 5 selects the mailbox region for frames
+/// longer than 32 bytes and bits 6-10 are the sender's address
+pub fn frame_id(from: NetworkAddress, to: NetworkAddress, frame_len: usize) -> u32 {
+    let mb_select: u32 = if frame_len > 32 { 0b1 } else { 0b0 };
+
+    (to.to_standard_id() & FRAME_ID_ADDRESS_MASK)
+        + (mb_select << 5)
+        + ((from.to_standard_id() & FRAME_ID_ADDRESS_MASK) << 6)
+}
+
+/// Splits a frame id back into its sender and receiver addresses
+pub fn frame_id_addresses(id: u32) -> (Option<NetworkAddress>, Option<NetworkAddress>) {
+    (
+        NetworkAddress::from_id((id >> 6) & FRAME_ID_ADDRESS_MASK),
+        NetworkAddress::from_id(id & FRAME_ID_ADDRESS_MASK),
+    )
+}
+
 pub fn serialize_packet(
     packet: &Packet,
     buffer: &mut [u8; CANFD_BUFFER_SIZE],
@@ -62,18 +87,70 @@ pub fn serialize_packet(
     }
 }
 
+/// Only works for packets that fit in a single frame, fragmented packets have to go through
+/// `deserialize_fragment` and a `Reassembler`
 pub fn deserialize_packet(
     buffer: &mut [u8; CANFD_BUFFER_SIZE],
 ) -> Result<Packet, SerializationError> {
     let metadata = CANFDPacketMetadata::from_byte_slice(buffer);
 
+    if metadata.get_fragment_count() != 1 {
+        return Err(SerializationError::BadFragment);
+    }
+
     Packet::deserialize(&mut buffer[4..(metadata.get_true_data_length() + 4)])
 }
 
+pub fn serialize_fragment(
+    fragment: &Fragment,
+    buffer: &mut [u8; CANFD_BUFFER_SIZE],
+) -> Result<usize, TransferError> {
+    let len = fragment.data.len();
+
+    let mut metadata = CANFDPacketMetadata::new();
+    if metadata.set_true_data_length(len).is_err() {
+        return Err(TransferError::Serialization(
+            SerializationError::PacketTooLong,
+        ));
+    }
+    metadata.set_fragment(fragment);
+
+    if metadata.serialize_to_buffer(&mut buffer[0..4]).is_err() {
+        return Err(TransferError::CANFDError(
+            CANFDTransferError::MetadataSerialization,
+        ));
+    }
+
+    buffer[4..(len + 4)].copy_from_slice(fragment.data);
+
+    Ok(len + 4)
+}
+
+pub fn deserialize_fragment(
+    buffer: &[u8; CANFD_BUFFER_SIZE],
+) -> Result<Fragment<'_>, SerializationError> {
+    let metadata = CANFDPacketMetadata::from_byte_slice(buffer);
+    let len = metadata.get_true_data_length();
+
+    if len > MAX_SERIALIZE_LENGTH {
+        return Err(SerializationError::BadFragment);
+    }
+
+    Ok(Fragment {
+        message_id: metadata.get_message_id(),
+        index: metadata.get_fragment_index(),
+        count: metadata.get_fragment_count(),
+        data: &buffer[4..(len + 4)],
+    })
+}
+
 pub struct CANFDPacketMetadata {
     /// Bitfield mapping:
     /// [0,5]     - True data length, u8 in range [0, 60]
-    /// [6, 31]   - Reserved
+    /// [6, 11]   - Fragment index
+    /// [12, 17]  - Fragment count minus one, so unfragmented packets leave it zeroed
+    /// [18, 25]  - Message id, shared by every fragment of a packet
+    /// [26, 31]  - Reserved
     bitfield: u32,
 }
 
@@ -111,6 +188,42 @@ impl CANFDPacketMetadata {
             >> MetadatBitfieldField::TrueDataLength.shift()) as usize
     }
 
+    pub fn set_fragment(&mut self, fragment: &Fragment) {
+        self.set_field(
+            MetadatBitfieldField::FragmentIndex,
+            u32::from(fragment.index),
+        );
+        self.set_field(
+            MetadatBitfieldField::FragmentCount,
+            u32::from(fragment.count.saturating_sub(1)),
+        );
+        self.set_field(
+            MetadatBitfieldField::MessageId,
+            u32::from(fragment.message_id),
+        );
+    }
+
+    pub fn get_fragment_index(&self) -> u8 {
+        u32_to_u8(self.get_field(MetadatBitfieldField::FragmentIndex))
+    }
+
+    pub fn get_fragment_count(&self) -> u8 {
+        u32_to_u8(self.get_field(MetadatBitfieldField::FragmentCount)) + 1
+    }
+
+    pub fn get_message_id(&self) -> u8 {
+        u32_to_u8(self.get_field(MetadatBitfieldField::MessageId))
+    }
+
+    fn set_field(&mut self, field: MetadatBitfieldField, value: u32) {
+        self.bitfield &= !field.mask();
+        self.bitfield |= (value << field.shift()) & field.mask();
+    }
+
+    fn get_field(&self, field: MetadatBitfieldField) -> u32 {
+        (self.bitfield & field.mask()) >> field.shift()
+    }
+
     pub fn serialize_to_buffer(&self, buffer: &mut [u8]) -> Result<(), LengthTooShortError> {
         if buffer.len() < 4 {
             Err(LengthTooShortError {})
@@ -127,18 +240,27 @@ impl CANFDPacketMetadata {
 
 enum MetadatBitfieldField {
     TrueDataLength,
+    FragmentIndex,
+    FragmentCount,
+    MessageId,
 }
 
 impl MetadatBitfieldField {
     fn mask(&self) -> u32 {
         match self {
             MetadatBitfieldField::TrueDataLength => 0x3F,
+            MetadatBitfieldField::FragmentIndex => 0x3F << 6,
+            MetadatBitfieldField::FragmentCount => 0x3F << 12,
+            MetadatBitfieldField::MessageId => 0xFF << 18,
         }
     }
 
     fn shift(&self) -> u32 {
         match self {
             MetadatBitfieldField::TrueDataLength => 0,
+            MetadatBitfieldField::FragmentIndex => 6,
+            MetadatBitfieldField::FragmentCount => 12,
+            MetadatBitfieldField::MessageId => 18,
         }
     }
 }
@@ -1,6 +1,11 @@
-use super::{NetworkAddress, Packet, SerializationError, TransferError, MAX_SERIALIZE_LENGTH};
+use super::{
+    comms_canfd_hal::CANFDPacketMetadata, fragmentation::Fragment, NetworkAddress, Packet,
+    SerializationError, TransferError, MAX_SERIALIZE_LENGTH,
+};
 
-pub const ETHERNET_BUFFER_SIZE: usize = MAX_SERIALIZE_LENGTH + 6;
+/// From address, to address, then the same metadata bitfield that prefixes CAN-FD frames
+pub const ETHERNET_HEADER_SIZE: usize = 8;
+pub const ETHERNET_BUFFER_SIZE: usize = MAX_SERIALIZE_LENGTH + ETHERNET_HEADER_SIZE;
 
 pub fn serialize_packet(
     packet: &Packet,
@@ -8,6 +13,86 @@ pub fn serialize_packet(
     to_address: NetworkAddress,
     buffer: &mut [u8; ETHERNET_BUFFER_SIZE],
 ) -> Result<usize, TransferError> {
+    match packet.serialize(&mut buffer[ETHERNET_HEADER_SIZE..]) {
+        Ok(len) => {
+            let metadata = CANFDPacketMetadata::new();
+            serialize_header(from_address, to_address, metadata, len, buffer)?;
+
+            Ok(len + ETHERNET_HEADER_SIZE)
+        }
+        Err(err) => Err(TransferError::Serialization(err)),
+    }
+}
+
+/// Only works for packets that fit in a single frame, fragmented packets have to go through
+/// `deserialize_fragment` and a `Reassembler`
+pub fn deserialize_packet(
+    buffer: &mut [u8; ETHERNET_BUFFER_SIZE],
+) -> Result<(Packet, NetworkAddress, NetworkAddress), SerializationError> {
+    let (fragment, from_address, to_address) = deserialize_fragment(buffer)?;
+
+    if !fragment.is_complete() {
+        return Err(SerializationError::BadFragment);
+    }
+
+    let len = fragment.data.len();
+    let packet =
+        Packet::deserialize(&mut buffer[ETHERNET_HEADER_SIZE..(ETHERNET_HEADER_SIZE + len)])?;
+
+    Ok((packet, from_address, to_address))
+}
+
+pub fn serialize_fragment(
+    fragment: &Fragment,
+    from_address: NetworkAddress,
+    to_address: NetworkAddress,
+    buffer: &mut [u8; ETHERNET_BUFFER_SIZE],
+) -> Result<usize, TransferError> {
+    let len = fragment.data.len();
+
+    let mut metadata = CANFDPacketMetadata::new();
+    metadata.set_fragment(fragment);
+    serialize_header(from_address, to_address, metadata, len, buffer)?;
+
+    buffer[ETHERNET_HEADER_SIZE..(ETHERNET_HEADER_SIZE + len)].copy_from_slice(fragment.data);
+
+    Ok(len + ETHERNET_HEADER_SIZE)
+}
+
+pub fn deserialize_fragment(
+    buffer: &[u8; ETHERNET_BUFFER_SIZE],
+) -> Result<(Fragment<'_>, NetworkAddress, NetworkAddress), SerializationError> {
+    let from_address = address_from_slice(&buffer[0..2]);
+    let to_address = address_from_slice(&buffer[2..4]);
+    let metadata = CANFDPacketMetadata::from_byte_slice(&buffer[4..8]);
+    let len = metadata.get_true_data_length();
+
+    if len > MAX_SERIALIZE_LENGTH {
+        return Err(SerializationError::BadFragment);
+    }
+
+    match (from_address, to_address) {
+        (Some(from_address), Some(to_address)) => Ok((
+            Fragment {
+                message_id: metadata.get_message_id(),
+                index: metadata.get_fragment_index(),
+                count: metadata.get_fragment_count(),
+                data: &buffer[ETHERNET_HEADER_SIZE..(ETHERNET_HEADER_SIZE + len)],
+            },
+            from_address,
+            to_address,
+        )),
+        _ => Err(SerializationError::BadVar),
+    }
+}
+
+fn serialize_header(
+    from_address: NetworkAddress,
+    to_address: NetworkAddress,
+    mut metadata: CANFDPacketMetadata,
+    len: usize,
+    buffer: &mut [u8; ETHERNET_BUFFER_SIZE],
+) -> Result<(), TransferError> {
     for (to, from) in buffer
         .iter_mut()
         .zip(from_address.to_standard_id().to_le_bytes().iter())
@@ -25,39 +110,16 @@ pub fn serialize_packet(
         *to = *from;
     }
 
-    match packet.serialize(&mut buffer[6..]) {
-        Ok(len) => {
-            for (to, from) in buffer
-                .iter_mut()
-                .skip(4)
-                .zip(len.to_le_bytes().iter())
-                .take(2)
-            {
-                *to = *from;
-            }
-
-            Ok(len + 6)
-        }
-        Err(err) => Err(TransferError::Serialization(err)),
+    if metadata.set_true_data_length(len).is_err() {
+        return Err(TransferError::Serialization(
+            SerializationError::PacketTooLong,
+        ));
     }
-}
 
-pub fn deserialize_packet(
-    buffer: &mut [u8; ETHERNET_BUFFER_SIZE],
-) -> Result<(Packet, NetworkAddress, NetworkAddress), SerializationError> {
-    let from_address = address_from_slice(&buffer[0..2]);
-    let to_address = address_from_slice(&buffer[2..4]);
-    let len = usize::from(buffer[4]) + (usize::from(buffer[5]) << 8);
+    // Can't fail, the slice is always 4 bytes long
+    let _ = metadata.serialize_to_buffer(&mut buffer[4..8]);
 
-    match (from_address, to_address) {
-        (Some(from_address), Some(to_address)) => {
-            match Packet::deserialize(&mut buffer[6..(6 + len)]) {
-                Ok(packet) => Ok((packet, from_address, to_address)),
-                Err(err) => Err(err),
-            }
-        }
-        _ => Err(SerializationError::BadVar),
-    }
+    Ok(())
 }
 
 fn address_from_slice(buffer: &[u8]) -> Option<NetworkAddress> {
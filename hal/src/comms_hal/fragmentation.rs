@@ -0,0 +1,253 @@
+use heapless::Vec;
+
+use super::{NetworkAddress, Packet, SerializationError, MAX_SERIALIZE_LENGTH};
+
+/// Most frames a single packet can be split into. The metadata bitfield has room for 64, this is
+/// kept lower so reassembly buffers stay small enough for the microcontrollers.
+pub const MAX_FRAGMENTS: usize = 16;
+
+/// Longest serialized packet that can be sent once it's been fragmented
+pub const MAX_MESSAGE_LENGTH: usize = MAX_SERIALIZE_LENGTH * MAX_FRAGMENTS;
+
+/// Seconds a partially received packet is kept around waiting for its missing fragments
+pub const REASSEMBLY_TIMEOUT: f32 = 0.5;
+
+/// How many packets from different senders can be reassembled at the same time
+pub const REASSEMBLY_SLOTS: usize = 4;
+
+/// One frame worth of a serialized packet. Every fragment except the last one is exactly
+/// `MAX_SERIALIZE_LENGTH` bytes long, so a fragment's offset in the packet is implied by its index.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Fragment<'a> {
+    /// Shared by all fragments of the same packet. Packets that fit in a single frame use 0.
+    pub message_id: u8,
+    pub index: u8,
+    pub count: u8,
+    pub data: &'a [u8],
+}
+
+impl<'a> Fragment<'a> {
+    /// Whether the fragment holds a whole packet on its own
+    pub fn is_complete(&self) -> bool {
+        self.count == 1
+    }
+}
+
+/// Splits packets into fragments, handing out a new message id for every packet that needs more
+/// than one frame
+pub struct Fragmenter {
+    next_message_id: u8,
+}
+
+impl Fragmenter {
+    pub fn new() -> Fragmenter {
+        Fragmenter { next_message_id: 1 }
+    }
+
+    /// Serializes `packet` into `buffer` and returns an iterator over the frames it has to be sent
+    /// as.
+    ///
+    /// # Errors
+    /// Returns `SerializationError::PacketTooLong` if the packet doesn't fit in `MAX_FRAGMENTS`
+    /// frames
+    pub fn fragment<'a>(
+        &mut self,
+        packet: &Packet,
+        buffer: &'a mut [u8; MAX_MESSAGE_LENGTH],
+    ) -> Result<Fragments<'a>, SerializationError> {
+        let len = packet.serialize(buffer)?;
+        // usize::div_ceil needs Rust 1.73, and hal has to build with the firmware's toolchain
+        #[allow(clippy::manual_div_ceil)]
+        let count = (len + MAX_SERIALIZE_LENGTH - 1) / MAX_SERIALIZE_LENGTH;
+
+        let message_id = if count > 1 {
+            let message_id = self.next_message_id;
+            self.next_message_id = self.next_message_id.wrapping_add(1).max(1);
+
+            message_id
+        } else {
+            0
+        };
+
+        Ok(Fragments {
+            data: &buffer[0..len],
+            message_id,
+            count: usize_to_u8(count),
+            index: 0,
+        })
+    }
+}
+
+impl Default for Fragmenter {
+    fn default() -> Self {
+        Fragmenter::new()
+    }
+}
+
+pub struct Fragments<'a> {
+    data: &'a [u8],
+    message_id: u8,
+    count: u8,
+    index: u8,
+}
+
+impl<'a> Iterator for Fragments<'a> {
+    type Item = Fragment<'a>;
+
+    fn next(&mut self) -> Option<Fragment<'a>> {
+        if self.index >= self.count {
+            return None;
+        }
+
+        let start = usize::from(self.index) * MAX_SERIALIZE_LENGTH;
+        let end = (start + MAX_SERIALIZE_LENGTH).min(self.data.len());
+
+        let fragment = Fragment {
+            message_id: self.message_id,
+            index: self.index,
+            count: self.count,
+            data: &self.data[start..end],
+        };
+        self.index += 1;
+
+        Some(fragment)
+    }
+}
+
+/// Puts fragmented packets back together. Fragments can arrive in any order, but a packet is
+/// dropped if it isn't complete within `REASSEMBLY_TIMEOUT`.
+pub struct Reassembler {
+    partial_messages: Vec<PartialMessage, REASSEMBLY_SLOTS>,
+}
+
+struct PartialMessage {
+    from: NetworkAddress,
+    message_id: u8,
+    count: u8,
+    received: u16,
+    len: usize,
+    elapsed: f32,
+    buffer: [u8; MAX_MESSAGE_LENGTH],
+}
+
+impl Reassembler {
+    pub fn new() -> Reassembler {
+        Reassembler {
+            partial_messages: Vec::new(),
+        }
+    }
+
+    /// Drops partial packets that have been waiting for longer than `REASSEMBLY_TIMEOUT`
+    pub fn update(&mut self, elapsed: f32) {
+        for partial_message in self.partial_messages.iter_mut() {
+            partial_message.elapsed += elapsed;
+        }
+
+        self.partial_messages
+            .retain(|partial_message| partial_message.elapsed < REASSEMBLY_TIMEOUT);
+    }
+
+    /// Takes in a fragment sent from `from`, returning the packet once all of its fragments have
+    /// arrived.
+    ///
+    /// # Errors
+    /// Returns `SerializationError::BadFragment` if the fragment's metadata doesn't make sense, or
+    /// whatever deserializing the reassembled packet failed with
+    pub fn receive(
+        &mut self,
+        from: NetworkAddress,
+        fragment: &Fragment,
+    ) -> Result<Option<Packet>, SerializationError> {
+        let count = usize::from(fragment.count);
+        let index = usize::from(fragment.index);
+        let is_last = index + 1 == count;
+
+        if count == 0
+            || count > MAX_FRAGMENTS
+            || index >= count
+            || fragment.data.len() > MAX_SERIALIZE_LENGTH
+            || (!is_last && fragment.data.len() != MAX_SERIALIZE_LENGTH)
+        {
+            return Err(SerializationError::BadFragment);
+        }
+
+        if fragment.is_complete() {
+            let mut buffer = [0_u8; MAX_SERIALIZE_LENGTH];
+            buffer[0..fragment.data.len()].copy_from_slice(fragment.data);
+
+            return Packet::deserialize(&mut buffer[0..fragment.data.len()]).map(Some);
+        }
+
+        let slot = match self.partial_messages.iter().position(|partial_message| {
+            partial_message.from == from && partial_message.message_id == fragment.message_id
+        }) {
+            Some(slot) => slot,
+            None => self.start_message(from, fragment),
+        };
+
+        let partial_message = &mut self.partial_messages[slot];
+
+        if partial_message.count != fragment.count {
+            self.partial_messages.swap_remove(slot);
+            return Err(SerializationError::BadFragment);
+        }
+
+        let start = index * MAX_SERIALIZE_LENGTH;
+        partial_message.buffer[start..(start + fragment.data.len())].copy_from_slice(fragment.data);
+        partial_message.received |= 1 << index;
+
+        if is_last {
+            partial_message.len = start + fragment.data.len();
+        }
+
+        if partial_message.received.count_ones() == u32::from(partial_message.count) {
+            let mut partial_message = self.partial_messages.swap_remove(slot);
+            let len = partial_message.len;
+
+            Packet::deserialize(&mut partial_message.buffer[0..len]).map(Some)
+        } else {
+            Ok(None)
+        }
+    }
+
+    fn start_message(&mut self, from: NetworkAddress, fragment: &Fragment) -> usize {
+        // Make room by dropping whichever packet has been waiting the longest
+        if self.partial_messages.is_full() {
+            let mut oldest = 0;
+
+            for (slot, partial_message) in self.partial_messages.iter().enumerate() {
+                if partial_message.elapsed > self.partial_messages[oldest].elapsed {
+                    oldest = slot;
+                }
+            }
+
+            self.partial_messages.swap_remove(oldest);
+        }
+
+        let partial_message = PartialMessage {
+            from,
+            message_id: fragment.message_id,
+            count: fragment.count,
+            received: 0,
+            len: 0,
+            elapsed: 0.0,
+            buffer: [0_u8; MAX_MESSAGE_LENGTH],
+        };
+
+        // Can't fail, there's always a free slot by now
+        let _ = self.partial_messages.push(partial_message);
+
+        self.partial_messages.len() - 1
+    }
+}
+
+impl Default for Reassembler {
+    fn default() -> Self {
+        Reassembler::new()
+    }
+}
+
+#[allow(clippy::cast_possible_truncation)]
+fn usize_to_u8(val: usize) -> u8 {
+    (val & 0xFF) as u8
+}
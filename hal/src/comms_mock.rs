@@ -1,23 +1,210 @@
+use core::cell::RefCell;
+
+use heapless::Deque;
+
 use crate::comms_hal::{CommsInterface, NetworkAddress, Packet, TransferError};
 
-pub struct CommsMock {}
+/// How many packets each mock queue can hold before it starts dropping or blocking
+pub const COMMS_MOCK_QUEUE_SIZE: usize = 512;
+
+/// A packet that was handed to `CommsInterface::transmit`, along with where it was going and
+/// when it was sent according to the mock's clock
+#[derive(Debug, Clone, PartialEq)]
+pub struct TransmittedPacket {
+    pub packet: Packet,
+    pub address: NetworkAddress,
+    pub timestamp: f32,
+}
+
+/// A `CommsInterface` that lets tests feed packets to a controller and inspect everything it
+/// sends back out. Nothing is actually transferred anywhere.
+pub struct CommsMock {
+    incoming: Deque<(Packet, NetworkAddress), COMMS_MOCK_QUEUE_SIZE>,
+    transmitted: Deque<TransmittedPacket, COMMS_MOCK_QUEUE_SIZE>,
+    transmit_count: usize,
+    time: f32,
+}
 
 impl CommsMock {
     pub fn new() -> CommsMock {
-        CommsMock {}
+        CommsMock {
+            incoming: Deque::new(),
+            transmitted: Deque::new(),
+            transmit_count: 0,
+            time: 0.0,
+        }
+    }
+
+    /// Queues a packet to be returned by `receive()` as if it was sent from `from`.
+    ///
+    /// # Errors
+    /// Returns `nb::Error::WouldBlock` if the incoming queue is full, same as `CommsLoopback`
+    pub fn queue_incoming(
+        &mut self,
+        packet: Packet,
+        from: NetworkAddress,
+    ) -> nb::Result<(), TransferError> {
+        self.incoming
+            .push_back((packet, from))
+            .map_err(|_| nb::Error::WouldBlock)
+    }
+
+    pub fn incoming_len(&self) -> usize {
+        self.incoming.len()
+    }
+
+    /// Advances the clock used to timestamp transmitted packets
+    pub fn advance_time(&mut self, elapsed: f32) {
+        self.time += elapsed;
+    }
+
+    pub fn get_time(&self) -> f32 {
+        self.time
+    }
+
+    /// Iterates over the recorded transmissions, oldest first. Only the most recent
+    /// `COMMS_MOCK_QUEUE_SIZE` transmissions are kept.
+    pub fn transmitted(&self) -> impl Iterator<Item = &TransmittedPacket> {
+        self.transmitted.iter()
+    }
+
+    pub fn pop_transmitted(&mut self) -> Option<TransmittedPacket> {
+        self.transmitted.pop_front()
+    }
+
+    pub fn clear_transmitted(&mut self) {
+        self.transmitted.clear();
+    }
+
+    /// Total number of packets transmitted since creation, including any that have since been
+    /// dropped from the recording
+    pub fn transmit_count(&self) -> usize {
+        self.transmit_count
+    }
+
+    /// Counts the recorded transmissions whose packet satisfies `filter`
+    pub fn count_transmitted<F>(&self, filter: F) -> usize
+    where
+        F: Fn(&Packet) -> bool,
+    {
+        self.transmitted
+            .iter()
+            .filter(|transmitted| filter(&transmitted.packet))
+            .count()
+    }
+}
+
+impl Default for CommsMock {
+    fn default() -> Self {
+        CommsMock::new()
     }
 }
 
 impl CommsInterface for CommsMock {
     fn transmit(
         &mut self,
-        _packet: &Packet,
-        _address: NetworkAddress,
+        packet: &Packet,
+        address: NetworkAddress,
+    ) -> nb::Result<(), TransferError> {
+        if self.transmitted.is_full() {
+            self.transmitted.pop_front();
+        }
+
+        let _ = self.transmitted.push_back(TransmittedPacket {
+            packet: packet.clone(),
+            address,
+            timestamp: self.time,
+        });
+        self.transmit_count += 1;
+
+        Ok(())
+    }
+
+    fn receive(&mut self) -> Option<(Packet, NetworkAddress)> {
+        self.incoming.pop_front()
+    }
+}
+
+/// Connects two endpoints back to back in the same process, such as mission control and an
+/// engine controller. Packets transmitted by one endpoint show up in the other's `receive()`
+/// if they're addressed to it or broadcast, and anything else is dropped like it would be by
+/// the receiver's mailbox filters.
+pub struct CommsLoopback {
+    address_a: NetworkAddress,
+    address_b: NetworkAddress,
+    a_to_b: RefCell<Deque<(Packet, NetworkAddress), COMMS_MOCK_QUEUE_SIZE>>,
+    b_to_a: RefCell<Deque<(Packet, NetworkAddress), COMMS_MOCK_QUEUE_SIZE>>,
+}
+
+/// One side of a `CommsLoopback`
+pub struct LoopbackEndpoint<'a> {
+    address: NetworkAddress,
+    peer_address: NetworkAddress,
+    outgoing: &'a RefCell<Deque<(Packet, NetworkAddress), COMMS_MOCK_QUEUE_SIZE>>,
+    incoming: &'a RefCell<Deque<(Packet, NetworkAddress), COMMS_MOCK_QUEUE_SIZE>>,
+}
+
+impl CommsLoopback {
+    pub fn new(address_a: NetworkAddress, address_b: NetworkAddress) -> CommsLoopback {
+        CommsLoopback {
+            address_a,
+            address_b,
+            a_to_b: RefCell::new(Deque::new()),
+            b_to_a: RefCell::new(Deque::new()),
+        }
+    }
+
+    pub fn endpoint_a(&self) -> LoopbackEndpoint<'_> {
+        LoopbackEndpoint {
+            address: self.address_a,
+            peer_address: self.address_b,
+            outgoing: &self.a_to_b,
+            incoming: &self.b_to_a,
+        }
+    }
+
+    pub fn endpoint_b(&self) -> LoopbackEndpoint<'_> {
+        LoopbackEndpoint {
+            address: self.address_b,
+            peer_address: self.address_a,
+            outgoing: &self.b_to_a,
+            incoming: &self.a_to_b,
+        }
+    }
+}
+
+impl<'a> LoopbackEndpoint<'a> {
+    pub fn get_address(&self) -> NetworkAddress {
+        self.address
+    }
+
+    /// Number of packets waiting to be received by this endpoint
+    pub fn pending(&self) -> usize {
+        self.incoming.borrow().len()
+    }
+}
+
+impl<'a> CommsInterface for LoopbackEndpoint<'a> {
+    fn transmit(
+        &mut self,
+        packet: &Packet,
+        address: NetworkAddress,
     ) -> nb::Result<(), TransferError> {
+        if address != self.peer_address && address != NetworkAddress::Broadcast {
+            return Ok(());
+        }
+
+        let mut outgoing = self.outgoing.borrow_mut();
+        if outgoing.is_full() {
+            return Err(nb::Error::WouldBlock);
+        }
+
+        let _ = outgoing.push_back((packet.clone(), self.address));
+
         Ok(())
     }
 
     fn receive(&mut self) -> Option<(Packet, NetworkAddress)> {
-        None
+        self.incoming.borrow_mut().pop_front()
     }
 }
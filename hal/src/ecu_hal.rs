@@ -5,6 +5,10 @@ use crate::SensorConfig;
 pub const MAX_ECU_SENSORS: usize = 5;
 pub const MAX_ECU_VALVES: usize = 4;
 
+/// The Teensy 4.1 ADC is configured for 12-bit readings against a 5V sensor supply
+pub const ADC_MAX_READING: u16 = 4095;
+pub const ADC_REFERENCE_VOLTAGE: f32 = 5.0;
+
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
 pub enum ECUSensor {
     IgniterThroatTemp = 0,
@@ -42,6 +46,17 @@ pub enum IgniterState {
     Idle,
     Prefire,
     Firing,
+    /// Fuel and spark off while GOx keeps flowing, so the fuel main is closed before purging
+    Shutdown,
+    /// GOx only, to clear the chamber of fuel
+    Purge,
+}
+
+impl IgniterState {
+    /// This state's bit in an `armed_states` style bitmask
+    pub fn mask(self) -> u8 {
+        1 << (self as u8)
+    }
 }
 
 pub trait ECUHardware {
@@ -71,4 +86,74 @@ pub struct ECUDataFrame {
 pub struct IgniterTimingConfig {
     pub prefire_duration_ms: u16,
     pub fire_duration_ms: u16,
+    pub shutdown_duration_ms: u16,
+    pub purge_duration_ms: u16,
+}
+
+/// Checks that the igniter actually lit after entering `IgniterState::Firing`. Thresholds are in
+/// the units produced by each sensor's `SensorConfig`.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct IgnitionVerificationConfig {
+    pub enabled: bool,
+    /// How long after entering Firing the thresholds have to be crossed by
+    pub window_ms: u16,
+    pub chamber_pressure_threshold: f32,
+    /// If set, the throat temperature also has to cross this threshold
+    pub throat_temp_threshold: Option<f32>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum IgnitionResult {
+    NotFired,
+    /// Firing, but the verification window hasn't passed yet
+    Pending,
+    Ignited,
+    NoIgnition,
+    /// Fired with ignition verification disabled
+    Unverified,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct RedlineLimit {
+    pub value: f32,
+    /// Bitmask of the `IgniterState`s this limit is enforced in, built with `IgniterState::mask`.
+    /// A limit with no armed states is disabled.
+    pub armed_states: u8,
+}
+
+impl RedlineLimit {
+    pub const fn disabled() -> RedlineLimit {
+        RedlineLimit {
+            value: 0.0,
+            armed_states: 0,
+        }
+    }
+
+    pub fn is_armed(&self, state: IgniterState) -> bool {
+        self.armed_states & state.mask() != 0
+    }
+}
+
+/// Limits on a sensor's value, in the units produced by its `SensorConfig`. A reading has to be
+/// outside of an armed limit for `debounce_ms` before the ECU aborts.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct SensorRedline {
+    pub min: RedlineLimit,
+    pub max: RedlineLimit,
+    pub debounce_ms: u16,
+}
+
+impl Default for SensorRedline {
+    fn default() -> SensorRedline {
+        SensorRedline {
+            min: RedlineLimit::disabled(),
+            max: RedlineLimit::disabled(),
+            debounce_ms: 0,
+        }
+    }
+}
+
+/// The voltage on an analog input that reads `raw`
+pub fn raw_to_voltage(raw: u16) -> f32 {
+    ADC_REFERENCE_VOLTAGE * (f32::from(raw) / f32::from(ADC_MAX_READING))
 }
@@ -1,6 +1,7 @@
 #![forbid(unsafe_code)]
 #![no_std]
 
+pub mod comms_fault;
 pub mod comms_hal;
 pub mod comms_mock;
 pub mod ecu_hal;
@@ -16,6 +17,18 @@ pub struct SensorConfig {
     pub postmax: f32,
 }
 
+impl SensorConfig {
+    /// Whether readings can be scaled with this config. An empty or non-finite input range would
+    /// turn every reading into inf or NaN.
+    pub fn is_valid(&self) -> bool {
+        self.premin.is_finite()
+            && self.premax.is_finite()
+            && self.postmin.is_finite()
+            && self.postmax.is_finite()
+            && self.premax != self.premin
+    }
+}
+
 impl Default for SensorConfig {
     fn default() -> SensorConfig {
         SensorConfig {
@@ -0,0 +1,434 @@
+use hal::{
+    comms_hal::{
+        comms_bridge::{
+            self, Bridge, BridgeError, CANFDFrame, EthernetEndpoint, Route, RoutingTable,
+            BROADCAST_ENDPOINT, MAX_MISSION_CONTROL_ENDPOINTS, MAX_ROUTES, MIN_CANFD_FRAME_LENGTH,
+            MISSION_CONTROL_PORT,
+        },
+        comms_canfd_hal::{self, CANFD_BUFFER_SIZE},
+        comms_ethernet_hal::{self, ETHERNET_BUFFER_SIZE},
+        fragmentation::{Fragmenter, Reassembler, MAX_MESSAGE_LENGTH},
+        Command, ControllerKind, NetworkAddress, Packet, RECORDED_DATA_BATCH_LENGTH,
+    },
+    ecu_hal::{ECUDataFrame, ECUValve, IgniterState, MAX_ECU_SENSORS, MAX_ECU_VALVES},
+};
+
+const LAPTOP: EthernetEndpoint = EthernetEndpoint {
+    ip: [10, 0, 0, 42],
+    port: 50000,
+};
+
+#[test]
+fn canfd_to_ethernet_eq() {
+    let bridge = Bridge::default();
+    let mut buffer = [0_u8; ETHERNET_BUFFER_SIZE];
+
+    for packet in get_packets().iter() {
+        let frame = canfd_frame(
+            packet,
+            NetworkAddress::EngineController(0),
+            NetworkAddress::MissionControl,
+        );
+
+        let (len, endpoints) = bridge
+            .canfd_to_ethernet(&frame, &mut buffer)
+            .unwrap()
+            .unwrap();
+        assert!(endpoints == [BROADCAST_ENDPOINT]);
+        assert!(len == frame.len - 4 + comms_ethernet_hal::ETHERNET_HEADER_SIZE);
+
+        let (other_packet, from, to) = comms_ethernet_hal::deserialize_packet(&mut buffer).unwrap();
+
+        assert!(other_packet == *packet);
+        assert!(from == NetworkAddress::EngineController(0));
+        assert!(to == NetworkAddress::MissionControl);
+    }
+}
+
+#[test]
+fn ethernet_to_canfd_eq() {
+    let mut bridge = Bridge::default();
+    let mut buffer = [0_u8; ETHERNET_BUFFER_SIZE];
+
+    for packet in get_packets().iter() {
+        let len = comms_ethernet_hal::serialize_packet(
+            packet,
+            NetworkAddress::MissionControl,
+            NetworkAddress::EngineController(0),
+            &mut buffer,
+        )
+        .unwrap();
+
+        let mut frame = bridge
+            .ethernet_to_canfd(&buffer[0..len], LAPTOP)
+            .unwrap()
+            .unwrap();
+
+        // Same id the ECU would send a reply to mission control with, reversed
+        assert!(
+            frame.id
+                == comms_canfd_hal::frame_id(
+                    NetworkAddress::MissionControl,
+                    NetworkAddress::EngineController(0),
+                    frame.len,
+                )
+        );
+        assert!(
+            comms_canfd_hal::frame_id_addresses(frame.id)
+                == (
+                    Some(NetworkAddress::MissionControl),
+                    Some(NetworkAddress::EngineController(0))
+                )
+        );
+        assert!(frame.len >= MIN_CANFD_FRAME_LENGTH);
+        assert!(frame.len == (len - comms_ethernet_hal::ETHERNET_HEADER_SIZE + 4).max(8));
+
+        let other_packet = comms_canfd_hal::deserialize_packet(&mut frame.buffer).unwrap();
+        assert!(other_packet == *packet);
+    }
+}
+
+#[test]
+fn long_frames_select_upper_mailboxes() {
+    let short = comms_canfd_hal::frame_id(
+        NetworkAddress::MissionControl,
+        NetworkAddress::EngineController(0),
+        32,
+    );
+    let long = comms_canfd_hal::frame_id(
+        NetworkAddress::MissionControl,
+        NetworkAddress::EngineController(0),
+        33,
+    );
+
+    assert!(short == 21 + (1 << 6));
+    assert!(long == short + (1 << 5));
+    assert!(
+        comms_canfd_hal::frame_id_addresses(short) == comms_canfd_hal::frame_id_addresses(long)
+    );
+}
+
+#[test]
+fn fragments_are_forwarded_untouched() {
+    let bridge = Bridge::default();
+    let mut fragmenter = Fragmenter::new();
+    let mut reassembler = Reassembler::new();
+    let mut message_buffer = [0_u8; MAX_MESSAGE_LENGTH];
+    let mut buffer = [0_u8; ETHERNET_BUFFER_SIZE];
+
+    let packet = recorded_data_batch();
+    let mut reassembled = None;
+
+    for fragment in fragmenter.fragment(&packet, &mut message_buffer).unwrap() {
+        let mut frame = CANFDFrame {
+            id: 0,
+            len: 0,
+            buffer: [0_u8; CANFD_BUFFER_SIZE],
+        };
+        frame.len = comms_canfd_hal::serialize_fragment(&fragment, &mut frame.buffer).unwrap();
+        frame.id = comms_canfd_hal::frame_id(
+            NetworkAddress::EngineController(0),
+            NetworkAddress::MissionControl,
+            frame.len,
+        );
+
+        bridge
+            .canfd_to_ethernet(&frame, &mut buffer)
+            .unwrap()
+            .unwrap();
+
+        let (des_fragment, from, _to) = comms_ethernet_hal::deserialize_fragment(&buffer).unwrap();
+        assert!(des_fragment == fragment);
+
+        reassembled = reassembler.receive(from, &des_fragment).unwrap();
+    }
+
+    assert!(reassembled == Some(packet));
+}
+
+#[test]
+fn cet_pulse_is_recognized() {
+    let mut bridge = Bridge::default();
+    let mut buffer = [0_u8; ETHERNET_BUFFER_SIZE];
+
+    let (len, endpoints) = bridge.cet_pulse(&mut buffer);
+
+    assert!(endpoints == [BROADCAST_ENDPOINT]);
+    assert!(comms_bridge::is_cet_pulse(&buffer[0..len]));
+
+    // A pulse looped back to the bridge isn't put on the bus
+    assert!(bridge.ethernet_to_canfd(&buffer[0..len], LAPTOP) == Err(BridgeError::UnknownAddress));
+
+    let len = comms_ethernet_hal::serialize_packet(
+        &Packet::Heartbeat,
+        NetworkAddress::MissionControl,
+        NetworkAddress::EngineController(0),
+        &mut buffer,
+    )
+    .unwrap();
+
+    assert!(!comms_bridge::is_cet_pulse(&buffer[0..len]));
+}
+
+#[test]
+fn announce_sets_mission_control_endpoint() {
+    let mut bridge = Bridge::default();
+    let mut buffer = [0_u8; ETHERNET_BUFFER_SIZE];
+
+    let len = comms_bridge::announce(&mut buffer);
+
+    assert!(bridge.ethernet_to_canfd(&buffer[0..len], LAPTOP) == Ok(None));
+    assert!(bridge.get_mission_control_endpoints() == [LAPTOP]);
+
+    let frame = canfd_frame(
+        &Packet::Heartbeat,
+        NetworkAddress::EngineController(0),
+        NetworkAddress::MissionControl,
+    );
+    let (_len, endpoints) = bridge
+        .canfd_to_ethernet(&frame, &mut buffer)
+        .unwrap()
+        .unwrap();
+    assert!(endpoints == [LAPTOP]);
+
+    let (_len, endpoints) = bridge.cet_pulse(&mut buffer);
+    assert!(endpoints == [LAPTOP]);
+}
+
+#[test]
+fn every_announced_port_gets_replies() {
+    let mut bridge = Bridge::default();
+    let mut buffer = [0_u8; ETHERNET_BUFFER_SIZE];
+
+    let ctrl_view = EthernetEndpoint {
+        ip: LAPTOP.ip,
+        port: MISSION_CONTROL_PORT,
+    };
+    let ctrl_send = EthernetEndpoint {
+        ip: LAPTOP.ip,
+        port: MISSION_CONTROL_PORT + 1,
+    };
+
+    let len = comms_bridge::announce(&mut buffer);
+    bridge
+        .ethernet_to_canfd(&buffer[0..len], ctrl_view)
+        .unwrap();
+    bridge
+        .ethernet_to_canfd(&buffer[0..len], ctrl_send)
+        .unwrap();
+    // Announcing again doesn't send everything twice
+    bridge
+        .ethernet_to_canfd(&buffer[0..len], ctrl_view)
+        .unwrap();
+
+    let frame = canfd_frame(
+        &Packet::Heartbeat,
+        NetworkAddress::EngineController(0),
+        NetworkAddress::MissionControl,
+    );
+    let (_len, endpoints) = bridge
+        .canfd_to_ethernet(&frame, &mut buffer)
+        .unwrap()
+        .unwrap();
+    assert!(endpoints == [ctrl_view, ctrl_send]);
+
+    // The oldest announcement is forgotten once there are too many
+    for port in 0..MAX_MISSION_CONTROL_ENDPOINTS {
+        let len = comms_bridge::announce(&mut buffer);
+        bridge
+            .ethernet_to_canfd(
+                &buffer[0..len],
+                EthernetEndpoint {
+                    ip: LAPTOP.ip,
+                    port: LAPTOP.port + port as u16,
+                },
+            )
+            .unwrap();
+    }
+
+    let endpoints = bridge.get_mission_control_endpoints();
+    assert!(endpoints.len() == MAX_MISSION_CONTROL_ENDPOINTS);
+    assert!(!endpoints.contains(&ctrl_view));
+    assert!(!endpoints.contains(&ctrl_send));
+}
+
+#[test]
+fn routes_are_followed() {
+    let mut bridge = Bridge::default();
+    let mut buffer = [0_u8; ETHERNET_BUFFER_SIZE];
+
+    // Controllers on the bus don't get their traffic echoed to Ethernet
+    let frame = canfd_frame(
+        &Packet::Heartbeat,
+        NetworkAddress::EngineController(0),
+        NetworkAddress::EngineController(1),
+    );
+    assert!(bridge.canfd_to_ethernet(&frame, &mut buffer) == Ok(None));
+
+    // Broadcasts go both ways
+    let frame = canfd_frame(
+        &Packet::Heartbeat,
+        NetworkAddress::EngineController(0),
+        NetworkAddress::Broadcast,
+    );
+    assert!(bridge
+        .canfd_to_ethernet(&frame, &mut buffer)
+        .unwrap()
+        .is_some());
+
+    let len = comms_ethernet_hal::serialize_packet(
+        &Packet::Heartbeat,
+        NetworkAddress::MissionControl,
+        NetworkAddress::Broadcast,
+        &mut buffer,
+    )
+    .unwrap();
+    assert!(bridge
+        .ethernet_to_canfd(&buffer[0..len], LAPTOP)
+        .unwrap()
+        .is_some());
+
+    // Nothing addressed to mission control is put on the bus
+    let len = comms_ethernet_hal::serialize_packet(
+        &Packet::Heartbeat,
+        NetworkAddress::EngineController(0),
+        NetworkAddress::MissionControl,
+        &mut buffer,
+    )
+    .unwrap();
+    assert!(bridge.ethernet_to_canfd(&buffer[0..len], LAPTOP) == Ok(None));
+
+    bridge
+        .get_routes_mut()
+        .set_route(NetworkAddress::EngineController(1), Route::Ethernet(LAPTOP))
+        .unwrap();
+    bridge
+        .get_routes_mut()
+        .set_route(NetworkAddress::EngineController(2), Route::Drop)
+        .unwrap();
+
+    let frame = canfd_frame(
+        &Packet::Heartbeat,
+        NetworkAddress::EngineController(0),
+        NetworkAddress::EngineController(1),
+    );
+    assert!(
+        bridge
+            .canfd_to_ethernet(&frame, &mut buffer)
+            .unwrap()
+            .unwrap()
+            .1
+            == [LAPTOP]
+    );
+
+    let len = comms_ethernet_hal::serialize_packet(
+        &Packet::Heartbeat,
+        NetworkAddress::MissionControl,
+        NetworkAddress::EngineController(2),
+        &mut buffer,
+    )
+    .unwrap();
+    assert!(bridge.ethernet_to_canfd(&buffer[0..len], LAPTOP) == Ok(None));
+}
+
+#[test]
+fn routing_table_fills_up() {
+    let mut routes = RoutingTable::new();
+
+    assert!(routes.get_route(NetworkAddress::EngineController(0)) == Route::CANFD);
+
+    for index in 0..(MAX_ROUTES - 2) {
+        routes
+            .set_route(NetworkAddress::EngineController(index as u8), Route::Drop)
+            .unwrap();
+    }
+
+    assert!(
+        routes.set_route(NetworkAddress::EngineController(10), Route::Drop)
+            == Err(BridgeError::RoutingTableFull)
+    );
+
+    // Replacing an existing route still works once it's full
+    routes
+        .set_route(NetworkAddress::EngineController(0), Route::CANFD)
+        .unwrap();
+    assert!(routes.get_route(NetworkAddress::EngineController(0)) == Route::CANFD);
+}
+
+#[test]
+fn bad_frames_rejected() {
+    let mut bridge = Bridge::default();
+    let mut buffer = [0_u8; ETHERNET_BUFFER_SIZE];
+
+    let mut frame = canfd_frame(
+        &Packet::Heartbeat,
+        NetworkAddress::EngineController(0),
+        NetworkAddress::MissionControl,
+    );
+
+    let mut unknown_address = frame;
+    unknown_address.id = 2 + (1 << 6);
+    assert!(
+        bridge.canfd_to_ethernet(&unknown_address, &mut buffer) == Err(BridgeError::UnknownAddress)
+    );
+
+    let mut truncated = frame;
+    truncated.len -= 1;
+    assert!(bridge.canfd_to_ethernet(&truncated, &mut buffer) == Err(BridgeError::FrameTooShort));
+
+    frame.buffer[0] &= !0x3F;
+    assert!(bridge.canfd_to_ethernet(&frame, &mut buffer) == Err(BridgeError::BadLength));
+
+    let len = comms_ethernet_hal::serialize_packet(
+        &Packet::Heartbeat,
+        NetworkAddress::MissionControl,
+        NetworkAddress::EngineController(0),
+        &mut buffer,
+    )
+    .unwrap();
+    assert!(
+        bridge.ethernet_to_canfd(&buffer[0..(len - 1)], LAPTOP) == Err(BridgeError::FrameTooShort)
+    );
+    assert!(bridge.ethernet_to_canfd(&buffer[0..4], LAPTOP) == Err(BridgeError::FrameTooShort));
+
+    // The transceiver firmware drops these silently instead
+    buffer[4] &= !0x3F;
+    assert!(bridge.ethernet_to_canfd(&buffer[0..len], LAPTOP) == Err(BridgeError::BadLength));
+}
+
+fn canfd_frame(packet: &Packet, from: NetworkAddress, to: NetworkAddress) -> CANFDFrame {
+    let mut buffer = [0_u8; CANFD_BUFFER_SIZE];
+    let len = comms_canfd_hal::serialize_packet(packet, &mut buffer).unwrap();
+
+    CANFDFrame {
+        id: comms_canfd_hal::frame_id(from, to, len),
+        len,
+        buffer,
+    }
+}
+
+fn recorded_data_batch() -> Packet {
+    Packet::RecordedDataBatch(
+        [ECUDataFrame {
+            igniter_state: IgniterState::Firing,
+            sensor_states: [4095_u16; MAX_ECU_SENSORS],
+            valve_states: [255_u8; MAX_ECU_VALVES],
+            sparking: true,
+        }; RECORDED_DATA_BATCH_LENGTH],
+    )
+}
+
+fn get_packets() -> [Packet; 4] {
+    [
+        Packet::Heartbeat,
+        Packet::Ack { seq: 42 },
+        Packet::Command {
+            seq: 1,
+            command: Command::SetValve {
+                valve: ECUValve::FuelPress,
+                state: 1,
+            },
+        },
+        Packet::hello(ControllerKind::EngineController, "deadbeef"),
+    ]
+}
@@ -0,0 +1,114 @@
+use hal::{
+    comms_fault::{FaultConfig, FaultyComms},
+    comms_hal::{CommsInterface, NetworkAddress, Packet},
+    comms_mock::CommsMock,
+};
+
+#[test]
+fn no_faults_is_transparent() {
+    let mut comms = FaultyComms::new(CommsMock::new(), 42);
+
+    comms
+        .inner_mut()
+        .queue_incoming(Packet::Heartbeat, NetworkAddress::MissionControl)
+        .unwrap();
+    comms
+        .transmit(&Packet::Ack { seq: 2 }, NetworkAddress::MissionControl)
+        .unwrap();
+
+    assert!(comms.receive() == Some((Packet::Heartbeat, NetworkAddress::MissionControl)));
+    assert!(comms.receive().is_none());
+    assert!(comms.inner().transmit_count() == 1);
+}
+
+#[test]
+fn same_seed_same_faults() {
+    let faults = FaultConfig {
+        loss_probability: 0.3,
+        duplicate_probability: 0.3,
+        bit_flip_probability: 0.3,
+        min_latency: 0.0,
+        max_latency: 0.01,
+        would_block_probability: 0.0,
+    };
+
+    let run = |seed| {
+        let mut comms = FaultyComms::new(CommsMock::new(), seed);
+        comms.set_receive_faults(faults);
+
+        let mut received = Vec::new();
+        for index in 0..100 {
+            comms
+                .inner_mut()
+                .queue_incoming(Packet::Ack { seq: index }, NetworkAddress::Broadcast)
+                .unwrap();
+            comms.advance_time(0.001);
+
+            while let Some((packet, _)) = comms.receive() {
+                received.push(packet);
+            }
+        }
+
+        (received, comms.get_receive_stats())
+    };
+
+    let (first, first_stats) = run(7);
+    let (second, second_stats) = run(7);
+    let (other, _) = run(8);
+
+    assert!(first == second);
+    assert!(first_stats == second_stats);
+    assert!(first != other);
+    assert!(first_stats.dropped > 0);
+    assert!(first_stats.duplicated > 0);
+    assert!(first_stats.corrupted > 0);
+}
+
+#[test]
+fn latency_reorders_packets() {
+    let mut comms = FaultyComms::new(CommsMock::new(), 3);
+    comms.set_receive_faults(FaultConfig {
+        min_latency: 0.0,
+        max_latency: 0.1,
+        ..FaultConfig::none()
+    });
+
+    for index in 0..20 {
+        comms
+            .inner_mut()
+            .queue_incoming(Packet::Ack { seq: index }, NetworkAddress::Broadcast)
+            .unwrap();
+    }
+
+    let mut received = Vec::new();
+    for _ in 0..200 {
+        comms.advance_time(0.001);
+        while let Some((Packet::Ack { seq }, _)) = comms.receive() {
+            received.push(seq);
+        }
+    }
+
+    assert!(received.len() == 20);
+    assert!(received.windows(2).any(|pair| pair[0] > pair[1]));
+}
+
+#[test]
+fn delayed_transmits_arrive_later() {
+    let mut comms = FaultyComms::new(CommsMock::new(), 9);
+    comms.set_transmit_faults(FaultConfig {
+        min_latency: 0.05,
+        max_latency: 0.05,
+        ..FaultConfig::none()
+    });
+
+    comms
+        .transmit(&Packet::Ack { seq: 1 }, NetworkAddress::Broadcast)
+        .unwrap();
+    assert!(comms.inner().transmit_count() == 0);
+
+    comms.advance_time(0.049);
+    assert!(comms.inner().transmit_count() == 0);
+
+    comms.advance_time(0.002);
+    assert!(comms.inner().transmit_count() == 1);
+}
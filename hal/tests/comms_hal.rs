@@ -2,13 +2,20 @@ use hal::{
     comms_hal::{
         comms_canfd_hal::{self, CANFDPacketMetadata, CANFD_BUFFER_SIZE},
         comms_ethernet_hal::{self, ETHERNET_BUFFER_SIZE},
-        ECUTelemtryData, NetworkAddress, Packet, MAX_SERIALIZE_LENGTH,
+        fragmentation::{
+            Fragment, Fragmenter, Reassembler, MAX_MESSAGE_LENGTH, REASSEMBLY_TIMEOUT,
+        },
+        AbortReason, Command, ControllerKind, ECUTelemtryData, NakReason, NetworkAddress, Packet,
+        SerializationError, ARM_CONFIRMATION_CODE, MAX_SERIALIZE_LENGTH, PROTOCOL_VERSION,
+        RECORDED_DATA_BATCH_LENGTH,
     },
     ecu_hal::{
-        ECUDataFrame, ECUSensor, ECUValve, IgniterTimingConfig, MAX_ECU_SENSORS, MAX_ECU_VALVES,
+        ECUDataFrame, ECUSensor, ECUValve, IgniterState, IgniterTimingConfig, IgnitionResult,
+        IgnitionVerificationConfig, RedlineLimit, SensorRedline, MAX_ECU_SENSORS, MAX_ECU_VALVES,
     },
     SensorConfig,
 };
+use serde::Serialize;
 
 #[test]
 fn serialize_packet_size_check() {
@@ -82,27 +89,500 @@ fn ethernet_serialize_deserialize_eq() {
     }
 }
 
-fn get_all_packets() -> [Packet; 11] {
-    let set_valve = Packet::SetValve {
-        valve: ECUValve::FuelPress,
-        state: 42,
+#[test]
+fn every_bit_flip_is_rejected() {
+    let mut buffer = [0_u8; MAX_SERIALIZE_LENGTH];
+
+    for packet in get_all_packets().iter() {
+        let len = packet.serialize(&mut buffer).unwrap();
+
+        for bit in 0..(len * 8) {
+            let mut corrupted = buffer;
+            corrupted[bit / 8] ^= 1 << (bit % 8);
+
+            let result = Packet::deserialize(&mut corrupted[0..len]);
+            if result.is_ok() {
+                println!("{:?} survived flipping bit {}", packet, bit);
+            }
+
+            assert!(result.is_err());
+        }
+    }
+}
+
+#[test]
+fn corrupted_valve_state_checksum_mismatch() {
+    let mut buffer = [0_u8; MAX_SERIALIZE_LENGTH];
+    let packet = closed_valve_packet();
+
+    let len = packet.serialize(&mut buffer).unwrap();
+
+    // Corrupt a payload byte without touching the COBS framing
+    let mut corrupted = buffer;
+    corrupted[len - 5] ^= 0xFE;
+    assert!(
+        Packet::deserialize(&mut corrupted[0..len]) == Err(SerializationError::ChecksumMismatch)
+    );
+
+    assert!(Packet::deserialize(&mut buffer[0..len]) == Ok(packet));
+}
+
+#[test]
+fn canfd_corruption_checksum_mismatch() {
+    let mut buffer = [0_u8; CANFD_BUFFER_SIZE];
+    let len = comms_canfd_hal::serialize_packet(&closed_valve_packet(), &mut buffer).unwrap();
+
+    buffer[len - 5] ^= 0xFE;
+
+    assert!(
+        comms_canfd_hal::deserialize_packet(&mut buffer)
+            == Err(SerializationError::ChecksumMismatch)
+    );
+}
+
+#[test]
+fn ethernet_corruption_checksum_mismatch() {
+    let mut buffer = [0_u8; ETHERNET_BUFFER_SIZE];
+    let len = comms_ethernet_hal::serialize_packet(
+        &closed_valve_packet(),
+        NetworkAddress::MissionControl,
+        NetworkAddress::EngineController(0),
+        &mut buffer,
+    )
+    .unwrap();
+
+    buffer[len - 5] ^= 0xFE;
+
+    assert!(
+        comms_ethernet_hal::deserialize_packet(&mut buffer).err()
+            == Some(SerializationError::ChecksumMismatch)
+    );
+}
+
+#[test]
+fn truncated_packet_rejected() {
+    let mut buffer = [0_u8; MAX_SERIALIZE_LENGTH];
+    let len = Packet::Heartbeat.serialize(&mut buffer).unwrap();
+
+    assert!(Packet::deserialize(&mut [0x01, 0x00]) == Err(SerializationError::UnexpectedEnd));
+    assert!(Packet::deserialize(&mut buffer[1..len]).is_err());
+}
+
+/// Fails if the variants of `Packet` or the enums it carries change. If that was on purpose,
+/// bump `PROTOCOL_VERSION` along with the numbers here.
+#[test]
+fn discriminant_layout_is_pinned() {
+    for packet in get_all_packets()
+        .iter()
+        .chain(get_fragmented_packets().iter())
+    {
+        let expected = match packet {
+            Packet::Command { command, .. } => {
+                let expected = match command {
+                    Command::SetValve { .. } => 0,
+                    Command::SetSparking(_) => 1,
+                    Command::FireIgniter => 2,
+                    Command::ConfigureSensor { .. } => 3,
+                    Command::ConfigureIgniterTiming(_) => 4,
+                    Command::Abort => 5,
+                    Command::SetRecording(_) => 6,
+                    Command::TransferData => 7,
+                    Command::ConfigureRedline { .. } => 8,
+                    Command::ConfigureIgnitionVerification(_) => 9,
+                    Command::Arm { .. } => 10,
+                    Command::Disarm => 11,
+                };
+                assert!(discriminant(command) == expected);
+
+                0
+            }
+            Packet::Ack { .. } => 1,
+            Packet::Nak { .. } => 2,
+            Packet::ECUTelemtry(_) => 3,
+            Packet::ControllerAborted { .. } => 4,
+            Packet::RecordedData(_) => 5,
+            Packet::Heartbeat => 6,
+            Packet::Goodbye => 7,
+            Packet::RecordedDataBatch(_) => 8,
+            Packet::Hello { .. } => 9,
+        };
+
+        assert!(discriminant(packet) == expected);
+    }
+
+    let nak_reasons = [
+        (NakReason::WrongState, 0),
+        (NakReason::NotArmed, 1),
+        (NakReason::InvalidArmCode, 2),
+        (NakReason::InvalidParameter, 3),
+        (NakReason::Unsupported, 4),
+    ];
+    for (reason, expected) in nak_reasons.iter() {
+        assert!(discriminant(reason) == *expected);
+    }
+
+    let controller_kinds = [
+        (ControllerKind::EngineController, 0),
+        (ControllerKind::MissionControl, 1),
+    ];
+    for (kind, expected) in controller_kinds.iter() {
+        assert!(discriminant(kind) == *expected);
+    }
+}
+
+/// `Hello` has to decode the same on every protocol version, so its whole layout is pinned
+#[test]
+fn hello_layout_is_pinned() {
+    let mut buffer = [0_u8; MAX_SERIALIZE_LENGTH];
+    let hello = Packet::Hello {
+        protocol_version: 0x1234,
+        kind: ControllerKind::MissionControl,
+        build_id: *b"deadbeef",
     };
-    let set_sparking = Packet::SetSparking(0.42);
-    let fire_igniter = Packet::FireIgniter;
-    let configure_sensor = Packet::ConfigureSensor {
-        sensor: ECUSensor::FuelTankPressure,
-        config: SensorConfig {
-            premin: 42.69,
-            premax: 420.42,
-            postmin: 69.420,
-            postmax: 42.42,
+
+    let serialized = postcard::to_slice(&hello, &mut buffer).unwrap();
+
+    assert!(serialized == [9, 0x34, 0x12, 1, b'd', b'e', b'a', b'd', b'b', b'e', b'e', b'f']);
+}
+
+#[test]
+fn hello_build_id_is_truncated() {
+    let short = Packet::hello(ControllerKind::EngineController, "0.1.0");
+    let long = Packet::hello(ControllerKind::EngineController, "0123456789abcdef");
+
+    assert!(
+        short
+            == Packet::Hello {
+                protocol_version: PROTOCOL_VERSION,
+                kind: ControllerKind::EngineController,
+                build_id: *b"0.1.0\0\0\0",
+            }
+    );
+    assert!(
+        long == Packet::Hello {
+            protocol_version: PROTOCOL_VERSION,
+            kind: ControllerKind::EngineController,
+            build_id: *b"01234567",
+        }
+    );
+}
+
+#[test]
+fn hazardous_commands() {
+    let open = |valve| Command::SetValve { valve, state: 255 };
+    let close = |valve| Command::SetValve { valve, state: 0 };
+
+    assert!(open(ECUValve::IgniterFuelMain).is_hazardous());
+    assert!(open(ECUValve::IgniterGOxMain).is_hazardous());
+    assert!(open(ECUValve::FuelPress).is_hazardous());
+    assert!(Command::SetSparking(1.0).is_hazardous());
+    assert!(Command::FireIgniter.is_hazardous());
+
+    assert!(!close(ECUValve::IgniterFuelMain).is_hazardous());
+    assert!(!open(ECUValve::FuelVent).is_hazardous());
+    assert!(!Command::Abort.is_hazardous());
+    assert!(!Command::Arm {
+        code: ARM_CONFIRMATION_CODE
+    }
+    .is_hazardous());
+}
+
+#[test]
+fn unfragmented_metadata_is_unchanged() {
+    let mut buffer = [0_u8; CANFD_BUFFER_SIZE];
+
+    // Single frame packets have to stay readable by controllers that don't know about fragments
+    for packet in get_all_packets().iter() {
+        comms_canfd_hal::serialize_packet(packet, &mut buffer).unwrap();
+        let metadata = CANFDPacketMetadata::from_byte_slice(&buffer);
+
+        assert!(metadata.get_bitfield() >> 6 == 0);
+        assert!(metadata.get_fragment_count() == 1);
+    }
+}
+
+#[test]
+fn fragment_single_frame_packets() {
+    let mut fragmenter = Fragmenter::new();
+    let mut buffer = [0_u8; MAX_MESSAGE_LENGTH];
+
+    for packet in get_all_packets().iter() {
+        let fragments: Vec<Fragment> = fragmenter.fragment(packet, &mut buffer).unwrap().collect();
+
+        assert!(fragments.len() == 1);
+        assert!(fragments[0].is_complete());
+        assert!(fragments[0].message_id == 0);
+    }
+}
+
+#[test]
+fn canfd_fragment_reassemble_eq() {
+    let mut fragmenter = Fragmenter::new();
+    let mut reassembler = Reassembler::new();
+    let mut message_buffer = [0_u8; MAX_MESSAGE_LENGTH];
+    let mut buffer = [0_u8; CANFD_BUFFER_SIZE];
+
+    for packet in get_all_packets()
+        .iter()
+        .chain(get_fragmented_packets().iter())
+    {
+        println!("Before: {:?}", *packet);
+
+        let fragments: Vec<Fragment> = fragmenter
+            .fragment(packet, &mut message_buffer)
+            .unwrap()
+            .collect();
+        let mut reassembled = None;
+
+        for (index, fragment) in fragments.iter().enumerate() {
+            let len = comms_canfd_hal::serialize_fragment(fragment, &mut buffer).unwrap();
+            let metadata = CANFDPacketMetadata::from_byte_slice(&buffer);
+            assert!((len - 4) == metadata.get_true_data_length());
+
+            let des_fragment = comms_canfd_hal::deserialize_fragment(&buffer).unwrap();
+            assert!(*fragment == des_fragment);
+
+            let result = reassembler
+                .receive(NetworkAddress::EngineController(0), &des_fragment)
+                .unwrap();
+
+            if index + 1 < fragments.len() {
+                assert!(result.is_none());
+            } else {
+                reassembled = result;
+            }
+        }
+
+        println!("After {:?}\n", reassembled);
+
+        assert!(reassembled.as_ref() == Some(packet));
+    }
+}
+
+#[test]
+fn ethernet_fragment_reassemble_eq() {
+    let mut fragmenter = Fragmenter::new();
+    let mut reassembler = Reassembler::new();
+    let mut message_buffer = [0_u8; MAX_MESSAGE_LENGTH];
+    let mut buffer = [0_u8; ETHERNET_BUFFER_SIZE];
+
+    for packet in get_fragmented_packets().iter() {
+        let from_address = NetworkAddress::EngineController(4);
+        let to_address = NetworkAddress::MissionControl;
+
+        let fragments: Vec<Fragment> = fragmenter
+            .fragment(packet, &mut message_buffer)
+            .unwrap()
+            .collect();
+        assert!(fragments.len() > 1);
+
+        // Fragments can arrive out of order
+        let mut reassembled = None;
+        for fragment in fragments.iter().rev() {
+            comms_ethernet_hal::serialize_fragment(fragment, from_address, to_address, &mut buffer)
+                .unwrap();
+
+            // Has to go through a reassembler
+            assert!(
+                comms_ethernet_hal::deserialize_packet(&mut buffer).err()
+                    == Some(SerializationError::BadFragment)
+            );
+
+            let (des_fragment, des_from, des_to) =
+                comms_ethernet_hal::deserialize_fragment(&buffer).unwrap();
+            assert!(*fragment == des_fragment);
+            assert!(from_address == des_from);
+            assert!(to_address == des_to);
+
+            reassembled = reassembler.receive(des_from, &des_fragment).unwrap();
+        }
+
+        assert!(reassembled.as_ref() == Some(packet));
+    }
+}
+
+#[test]
+fn fragmented_packets_get_new_message_ids() {
+    let mut fragmenter = Fragmenter::new();
+    let mut buffer = [0_u8; MAX_MESSAGE_LENGTH];
+    let packet = &get_fragmented_packets()[0];
+
+    let first_id = fragmenter
+        .fragment(packet, &mut buffer)
+        .unwrap()
+        .next()
+        .unwrap()
+        .message_id;
+    let second_id = fragmenter
+        .fragment(packet, &mut buffer)
+        .unwrap()
+        .next()
+        .unwrap()
+        .message_id;
+
+    assert!(first_id != 0);
+    assert!(second_id != 0);
+    assert!(first_id != second_id);
+}
+
+#[test]
+fn interleaved_senders_reassemble() {
+    let mut fragmenter = Fragmenter::new();
+    let mut reassembler = Reassembler::new();
+    let mut first_buffer = [0_u8; MAX_MESSAGE_LENGTH];
+    let mut second_buffer = [0_u8; MAX_MESSAGE_LENGTH];
+    let packets = get_fragmented_packets();
+
+    let first: Vec<Fragment> = fragmenter
+        .fragment(&packets[0], &mut first_buffer)
+        .unwrap()
+        .collect();
+    let second: Vec<Fragment> = Fragmenter::new()
+        .fragment(&packets[1], &mut second_buffer)
+        .unwrap()
+        .collect();
+    assert!(first.len() == second.len());
+
+    let mut first_reassembled = None;
+    let mut second_reassembled = None;
+    for (first_fragment, second_fragment) in first.iter().zip(second.iter()) {
+        first_reassembled = reassembler
+            .receive(NetworkAddress::EngineController(0), first_fragment)
+            .unwrap();
+        second_reassembled = reassembler
+            .receive(NetworkAddress::EngineController(1), second_fragment)
+            .unwrap();
+    }
+
+    assert!(first_reassembled.as_ref() == Some(&packets[0]));
+    assert!(second_reassembled.as_ref() == Some(&packets[1]));
+}
+
+#[test]
+fn reassembly_times_out() {
+    let mut fragmenter = Fragmenter::new();
+    let mut reassembler = Reassembler::new();
+    let mut buffer = [0_u8; MAX_MESSAGE_LENGTH];
+    let packet = &get_fragmented_packets()[0];
+
+    let fragments: Vec<Fragment> = fragmenter.fragment(packet, &mut buffer).unwrap().collect();
+    let (last, rest) = fragments.split_last().unwrap();
+
+    for fragment in rest {
+        assert!(reassembler
+            .receive(NetworkAddress::EngineController(0), fragment)
+            .unwrap()
+            .is_none());
+    }
+
+    reassembler.update(REASSEMBLY_TIMEOUT);
+
+    assert!(reassembler
+        .receive(NetworkAddress::EngineController(0), last)
+        .unwrap()
+        .is_none());
+}
+
+#[test]
+fn reassembly_rejects_bad_fragments() {
+    let mut reassembler = Reassembler::new();
+    let data = [0_u8; MAX_SERIALIZE_LENGTH];
+
+    let bad_fragments = [
+        // Index past the end
+        Fragment {
+            message_id: 1,
+            index: 2,
+            count: 2,
+            data: &data,
         },
-    };
-    let configure_igniter_timing = Packet::ConfigureIgniterTiming(IgniterTimingConfig {
-        prefire_duration_ms: 42,
-        fire_duration_ms: 420,
-    });
-    let abort = Packet::Abort;
+        // More fragments than a reassembler can hold
+        Fragment {
+            message_id: 1,
+            index: 0,
+            count: 63,
+            data: &data,
+        },
+        // Only the last fragment is allowed to be short
+        Fragment {
+            message_id: 1,
+            index: 0,
+            count: 2,
+            data: &data[0..10],
+        },
+    ];
+
+    for fragment in bad_fragments.iter() {
+        assert!(
+            reassembler.receive(NetworkAddress::MissionControl, fragment)
+                == Err(SerializationError::BadFragment)
+        );
+    }
+}
+
+/// Short enough that `len - 5` lands on a payload byte instead of the COBS framing, which is what
+/// the corruption tests flip
+fn closed_valve_packet() -> Packet {
+    Packet::Command {
+        seq: 1,
+        command: Command::SetValve {
+            valve: ECUValve::FuelPress,
+            state: 0,
+        },
+    }
+}
+
+fn get_fragmented_packets() -> [Packet; 2] {
+    let mut frames = [ECUDataFrame {
+        igniter_state: IgniterState::Firing,
+        sensor_states: [4095_u16; MAX_ECU_SENSORS],
+        valve_states: [255_u8; MAX_ECU_VALVES],
+        sparking: true,
+    }; RECORDED_DATA_BATCH_LENGTH];
+
+    let recorded_data_batch = Packet::RecordedDataBatch(frames);
+
+    for (index, frame) in frames.iter_mut().enumerate() {
+        frame.igniter_state = IgniterState::Purge;
+        frame.sensor_states[index % MAX_ECU_SENSORS] = 42;
+        frame.sparking = false;
+    }
+
+    [recorded_data_batch, Packet::RecordedDataBatch(frames)]
+}
+
+fn get_all_packets() -> [Packet; 20] {
+    let set_valve = command(
+        0,
+        Command::SetValve {
+            valve: ECUValve::FuelPress,
+            state: 42,
+        },
+    );
+    let set_sparking = command(1, Command::SetSparking(0.42));
+    let fire_igniter = command(2, Command::FireIgniter);
+    let configure_sensor = command(
+        3,
+        Command::ConfigureSensor {
+            sensor: ECUSensor::FuelTankPressure,
+            config: SensorConfig {
+                premin: 42.69,
+                premax: 420.42,
+                postmin: 69.420,
+                postmax: 42.42,
+            },
+        },
+    );
+    let configure_igniter_timing = command(
+        4,
+        Command::ConfigureIgniterTiming(IgniterTimingConfig {
+            prefire_duration_ms: 42,
+            fire_duration_ms: 420,
+            shutdown_duration_ms: 42,
+            purge_duration_ms: 69,
+        }),
+    );
+    let abort = command(5, Command::Abort);
     let ecu_telemetry = Packet::ECUTelemtry(ECUTelemtryData {
         ecu_data: ECUDataFrame {
             igniter_state: hal::ecu_hal::IgniterState::Idle,
@@ -111,30 +591,94 @@ fn get_all_packets() -> [Packet; 11] {
             sparking: false,
         },
         max_loop_time: 69.96,
+        ignition_result: IgnitionResult::NoIgnition,
+        armed: true,
     });
-    let controller_aborted = Packet::ControllerAborted(NetworkAddress::MissionControl);
-    let set_recording = Packet::SetRecording(true);
-    let transfer_data = Packet::TransferData;
+    let controller_aborted = Packet::ControllerAborted {
+        address: NetworkAddress::MissionControl,
+        reason: AbortReason::Redline {
+            sensor: ECUSensor::IgniterChamberPressure,
+            value: 420.69,
+        },
+    };
+    let set_recording = command(8, Command::SetRecording(true));
+    let transfer_data = command(9, Command::TransferData);
     let recorded_data = Packet::RecordedData(ECUDataFrame {
         igniter_state: hal::ecu_hal::IgniterState::Idle,
         sensor_states: [69_u16; MAX_ECU_SENSORS],
         valve_states: [42_u8; MAX_ECU_VALVES],
         sparking: false,
     });
+    let heartbeat = Packet::Heartbeat;
+    let goodbye = Packet::Goodbye;
+    let configure_redline = command(
+        12,
+        Command::ConfigureRedline {
+            sensor: ECUSensor::IgniterChamberPressure,
+            redline: SensorRedline {
+                min: RedlineLimit {
+                    value: -42.0,
+                    armed_states: IgniterState::Firing.mask(),
+                },
+                max: RedlineLimit {
+                    value: 420.69,
+                    armed_states: IgniterState::Prefire.mask() | IgniterState::Firing.mask(),
+                },
+                debounce_ms: 42,
+            },
+        },
+    );
+    let configure_ignition_verification = command(
+        13,
+        Command::ConfigureIgnitionVerification(IgnitionVerificationConfig {
+            enabled: true,
+            window_ms: 420,
+            chamber_pressure_threshold: 42.69,
+            throat_temp_threshold: Some(69.42),
+        }),
+    );
+    let arm = command(
+        14,
+        Command::Arm {
+            code: ARM_CONFIRMATION_CODE,
+        },
+    );
+    let disarm = command(15, Command::Disarm);
+    let ack = Packet::Ack { seq: 42 };
+    let nak = Packet::Nak {
+        seq: 69,
+        reason: NakReason::NotArmed,
+    };
+    let hello = Packet::hello(ControllerKind::MissionControl, "deadbeef");
 
     // Remember to create a packet so its serialization/deserialization can be tested
-    match set_valve {
-        Packet::SetValve { .. } => println!("test"),
-        Packet::SetSparking(_) => println!("test"),
-        Packet::FireIgniter => println!("test"),
-        Packet::ConfigureSensor { .. } => println!("test"),
-        Packet::ConfigureIgniterTiming(_) => println!("test"),
-        Packet::Abort => println!("test"),
+    match ack {
+        Packet::Command { .. } => println!("test"),
+        Packet::Ack { .. } => println!("test"),
+        Packet::Nak { .. } => println!("test"),
         Packet::ECUTelemtry(_) => println!("test"),
-        Packet::ControllerAborted(_) => println!("test"),
-        Packet::SetRecording(_) => println!("test"),
-        Packet::TransferData => println!("test"),
+        Packet::ControllerAborted { .. } => println!("test"),
         Packet::RecordedData(_) => println!("test"),
+        Packet::RecordedDataBatch(_) => println!("test, in get_fragmented_packets"),
+        Packet::Heartbeat => println!("test"),
+        Packet::Goodbye => println!("test"),
+        Packet::Hello { .. } => println!("test"),
+    }
+
+    // Same for every command carried by Packet::Command
+    match Command::Abort {
+        Command::SetValve { .. } => println!("test"),
+        Command::SetSparking(_) => println!("test"),
+        Command::FireIgniter => println!("test"),
+        Command::ConfigureSensor { .. } => println!("test"),
+        Command::ConfigureIgniterTiming(_) => println!("test"),
+        Command::Abort => println!("test"),
+        Command::SetRecording(_) => println!("test"),
+        Command::TransferData => println!("test"),
+        Command::ConfigureRedline { .. } => println!("test"),
+        Command::ConfigureIgnitionVerification(_) => println!("test"),
+        Command::Arm { .. } => println!("test"),
+        Command::Disarm => println!("test"),
     }
 
     [
@@ -149,5 +693,25 @@ fn get_all_packets() -> [Packet; 11] {
         set_recording,
         transfer_data,
         recorded_data,
+        heartbeat,
+        goodbye,
+        configure_redline,
+        configure_ignition_verification,
+        arm,
+        disarm,
+        ack,
+        nak,
+        hello,
     ]
 }
+
+/// The first byte postcard writes for an enum is its variant index
+fn discriminant<T: Serialize>(value: &T) -> u8 {
+    let mut buffer = [0_u8; MAX_MESSAGE_LENGTH];
+
+    postcard::to_slice(value, &mut buffer).unwrap()[0]
+}
+
+fn command(seq: u16, command: Command) -> Packet {
+    Packet::Command { seq, command }
+}
@@ -0,0 +1,148 @@
+use hal::{
+    comms_hal::{AbortReason, CommsInterface, NetworkAddress, Packet},
+    comms_mock::{CommsLoopback, CommsMock, COMMS_MOCK_QUEUE_SIZE},
+};
+
+#[test]
+fn mock_receive_order() {
+    let mut comms = CommsMock::new();
+
+    assert!(comms.receive().is_none());
+
+    comms
+        .queue_incoming(Packet::Heartbeat, NetworkAddress::MissionControl)
+        .unwrap();
+    comms
+        .queue_incoming(Packet::Ack { seq: 1 }, NetworkAddress::Broadcast)
+        .unwrap();
+    assert!(comms.incoming_len() == 2);
+
+    assert!(comms.receive() == Some((Packet::Heartbeat, NetworkAddress::MissionControl)));
+    assert!(comms.receive() == Some((Packet::Ack { seq: 1 }, NetworkAddress::Broadcast)));
+    assert!(comms.receive().is_none());
+}
+
+#[test]
+fn mock_incoming_blocks_when_full() {
+    let mut comms = CommsMock::new();
+
+    for _ in 0..COMMS_MOCK_QUEUE_SIZE {
+        comms
+            .queue_incoming(Packet::Heartbeat, NetworkAddress::MissionControl)
+            .unwrap();
+    }
+
+    assert!(
+        comms.queue_incoming(Packet::Heartbeat, NetworkAddress::MissionControl)
+            == Err(nb::Error::WouldBlock)
+    );
+    assert!(comms.incoming_len() == COMMS_MOCK_QUEUE_SIZE);
+}
+
+#[test]
+fn mock_records_transmissions() {
+    let mut comms = CommsMock::new();
+
+    comms
+        .transmit(&Packet::Ack { seq: 2 }, NetworkAddress::MissionControl)
+        .unwrap();
+    comms.advance_time(0.5);
+    comms
+        .transmit(
+            &Packet::ControllerAborted {
+                address: NetworkAddress::EngineController(0),
+                reason: AbortReason::Commanded,
+            },
+            NetworkAddress::Broadcast,
+        )
+        .unwrap();
+
+    let transmitted: Vec<_> = comms.transmitted().cloned().collect();
+    assert!(transmitted.len() == 2);
+    assert!(transmitted[0].packet == Packet::Ack { seq: 2 });
+    assert!(transmitted[0].address == NetworkAddress::MissionControl);
+    assert!(transmitted[0].timestamp == 0.0);
+    assert!(transmitted[1].address == NetworkAddress::Broadcast);
+    assert!(transmitted[1].timestamp == 0.5);
+
+    assert!(
+        comms.count_transmitted(|packet| matches!(packet, Packet::ControllerAborted { .. })) == 1
+    );
+
+    assert!(comms.pop_transmitted().unwrap().packet == Packet::Ack { seq: 2 });
+    comms.clear_transmitted();
+    assert!(comms.transmitted().count() == 0);
+    assert!(comms.transmit_count() == 2);
+}
+
+#[test]
+fn mock_keeps_most_recent_transmissions() {
+    let mut comms = CommsMock::new();
+
+    for _ in 0..COMMS_MOCK_QUEUE_SIZE {
+        comms
+            .transmit(&Packet::Ack { seq: 1 }, NetworkAddress::Broadcast)
+            .unwrap();
+    }
+    comms
+        .transmit(&Packet::Heartbeat, NetworkAddress::Broadcast)
+        .unwrap();
+
+    assert!(comms.transmit_count() == COMMS_MOCK_QUEUE_SIZE + 1);
+    assert!(comms.transmitted().count() == COMMS_MOCK_QUEUE_SIZE);
+    assert!(comms.transmitted().last().unwrap().packet == Packet::Heartbeat);
+}
+
+#[test]
+fn loopback_delivers_between_endpoints() {
+    let link = CommsLoopback::new(
+        NetworkAddress::MissionControl,
+        NetworkAddress::EngineController(0),
+    );
+    let mut mission_control = link.endpoint_a();
+    let mut ecu = link.endpoint_b();
+
+    mission_control
+        .transmit(&Packet::Heartbeat, NetworkAddress::EngineController(0))
+        .unwrap();
+    mission_control
+        .transmit(&Packet::Ack { seq: 1 }, NetworkAddress::Broadcast)
+        .unwrap();
+    // Not addressed to the other endpoint, so it never arrives
+    mission_control
+        .transmit(&Packet::Ack { seq: 2 }, NetworkAddress::EngineController(1))
+        .unwrap();
+
+    assert!(ecu.pending() == 2);
+    assert!(mission_control.pending() == 0);
+    assert!(ecu.receive() == Some((Packet::Heartbeat, NetworkAddress::MissionControl)));
+    assert!(ecu.receive() == Some((Packet::Ack { seq: 1 }, NetworkAddress::MissionControl)));
+    assert!(ecu.receive().is_none());
+
+    ecu.transmit(&Packet::Ack { seq: 2 }, NetworkAddress::MissionControl)
+        .unwrap();
+    assert!(
+        mission_control.receive()
+            == Some((Packet::Ack { seq: 2 }, NetworkAddress::EngineController(0)))
+    );
+}
+
+#[test]
+fn loopback_blocks_when_full() {
+    let link = CommsLoopback::new(
+        NetworkAddress::MissionControl,
+        NetworkAddress::EngineController(0),
+    );
+    let mut mission_control = link.endpoint_a();
+
+    for _ in 0..COMMS_MOCK_QUEUE_SIZE {
+        mission_control
+            .transmit(&Packet::Ack { seq: 1 }, NetworkAddress::Broadcast)
+            .unwrap();
+    }
+
+    assert!(
+        mission_control.transmit(&Packet::Ack { seq: 1 }, NetworkAddress::Broadcast)
+            == Err(nb::Error::WouldBlock)
+    );
+}
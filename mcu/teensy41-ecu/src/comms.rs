@@ -5,6 +5,7 @@ use nb::Error::{Other, WouldBlock};
 
 use hal::comms_hal::comms_canfd_hal::CANFDTransferError;
 use hal::comms_hal::comms_canfd_hal::{self, CANFD_BUFFER_SIZE};
+use hal::comms_hal::fragmentation::{Fragmenter, Reassembler, MAX_MESSAGE_LENGTH};
 use hal::comms_hal::{CommsInterface, NetworkAddress, Packet, TransferError};
 use teensy4_canfd::can_error::RxTxError;
 use teensy4_canfd::{
@@ -14,10 +15,16 @@ use teensy4_canfd::{
 
 use self::receive::pop_rx_frame;
 
+/// How many times sending a follow-up fragment is retried while waiting for a transmit mailbox
+/// before the rest of the packet is given up on
+const MAX_FRAGMENT_RETRIES: u32 = 10_000;
+
 #[allow(clippy::module_name_repetitions)]
 pub struct Teensy41ECUComms {
     host_address: NetworkAddress,
     canfd: CAN3FD,
+    fragmenter: Fragmenter,
+    reassembler: Reassembler,
 }
 
 impl Teensy41ECUComms {
@@ -135,8 +142,15 @@ impl Teensy41ECUComms {
         Teensy41ECUComms {
             host_address: NetworkAddress::EngineController(ecu_index),
             canfd,
+            fragmenter: Fragmenter::new(),
+            reassembler: Reassembler::new(),
         }
     }
+
+    /// Drops partially received packets whose remaining fragments never showed up
+    pub fn update(&mut self, elapsed: f32) {
+        self.reassembler.update(elapsed);
+    }
 }
 
 impl CommsInterface for Teensy41ECUComms {
@@ -145,72 +159,96 @@ impl CommsInterface for Teensy41ECUComms {
         packet: &Packet,
         address: NetworkAddress,
     ) -> nb::Result<(), TransferError> {
-        let mut buffer = [0_u8; CANFD_BUFFER_SIZE];
-
-        match comms_canfd_hal::serialize_packet(packet, &mut buffer) {
-            Ok(data_len) => {
-                let tx_frame = TxFDFrame {
-                    id: self.get_outgoing_id(address, data_len),
-                    buffer: &buffer[0..data_len],
-                    priority: None,
-                };
-
-                let transfer_result = interrupt::free(|cs| self.canfd.transfer_nb(cs, &tx_frame));
-
-                match transfer_result {
-                    Ok(_) => Ok(()),
-                    Err(err) => match err {
-                        RxTxError::MailboxUnavailable => Err(WouldBlock),
-                        RxTxError::FrameTooBigForRegions => Err(Other(TransferError::CANFDError(
+        let mut message_buffer = [0_u8; MAX_MESSAGE_LENGTH];
+
+        let fragments = self
+            .fragmenter
+            .fragment(packet, &mut message_buffer)
+            .map_err(|err| Other(TransferError::Serialization(err)))?;
+
+        for (index, fragment) in fragments.enumerate() {
+            let mut buffer = [0_u8; CANFD_BUFFER_SIZE];
+            let data_len =
+                comms_canfd_hal::serialize_fragment(&fragment, &mut buffer).map_err(Other)?;
+
+            let tx_frame = TxFDFrame {
+                id: self.get_outgoing_id(address, data_len),
+                buffer: &buffer[0..data_len],
+                priority: None,
+            };
+
+            let mut retries = 0;
+            loop {
+                match interrupt::free(|cs| self.canfd.transfer_nb(cs, &tx_frame)) {
+                    Ok(_) => break,
+                    // Once part of a packet is out the rest has to follow, or it can't be
+                    // reassembled. A mailbox frees up as soon as its frame is on the bus, unless
+                    // the bus is down.
+                    Err(RxTxError::MailboxUnavailable) if index > 0 => {
+                        retries += 1;
+
+                        if retries > MAX_FRAGMENT_RETRIES {
+                            return Err(Other(TransferError::CANFDError(
+                                CANFDTransferError::MailboxTimeout,
+                            )));
+                        }
+                    }
+                    Err(RxTxError::MailboxUnavailable) => return Err(WouldBlock),
+                    Err(RxTxError::FrameTooBigForRegions) => {
+                        return Err(Other(TransferError::CANFDError(
                             CANFDTransferError::FrameTooBigForRegion,
-                        ))),
-                        RxTxError::Unknown => Err(Other(TransferError::CANFDError(
+                        )))
+                    }
+                    Err(RxTxError::Unknown) => {
+                        return Err(Other(TransferError::CANFDError(
                             CANFDTransferError::Unknown,
-                        ))),
-                    },
+                        )))
+                    }
                 }
             }
-            Err(err) => Err(Other(err)),
         }
+
+        Ok(())
     }
 
     fn receive(&mut self) -> Option<(Packet, NetworkAddress)> {
-        let rx_frame = interrupt::free(|cs| pop_rx_frame(cs));
-
-        match rx_frame {
-            Some(mut rx_frame) => {
-                let packet = comms_canfd_hal::deserialize_packet(&mut rx_frame.buffer);
-                let from_id = Teensy41ECUComms::get_incoming_id(rx_frame.id);
-
-                if packet.is_err() || from_id.is_none() {
-                    log::error!("Failed to parse incoming packet. Packet was {:?} and from_addr was ({:?}, {:?})", packet, from_id, rx_frame.id);
-                }
+        // Fragments of a larger packet don't give back anything on their own, so keep going
+        // until a packet is complete or there's nothing left to read
+        while let Some(rx_frame) = interrupt::free(|cs| pop_rx_frame(cs)) {
+            let from_id = Teensy41ECUComms::get_incoming_id(rx_frame.id);
+            let fragment = comms_canfd_hal::deserialize_fragment(&rx_frame.buffer);
+            let packet = match (fragment, from_id) {
+                (Ok(fragment), Some(from_addr)) => self.reassembler.receive(from_addr, &fragment),
+                (Err(err), _) => Err(err),
+                (Ok(_), None) => Ok(None),
+            };
+
+            if packet.is_err() || from_id.is_none() {
+                log::error!("Failed to parse incoming packet. Packet was {:?} and from_addr was ({:?}, {:?})", packet, from_id, rx_frame.id);
+            }
 
-                match (packet, from_id) {
-                    (Ok(packet), Some(from_addr)) => Some((packet, from_addr)),
-                    _ => None,
-                }
+            if let (Ok(Some(packet)), Some(from_addr)) = (packet, from_id) {
+                return Some((packet, from_addr));
             }
-            None => None,
         }
+
+        None
     }
 }
 
 impl Teensy41ECUComms {
     fn get_outgoing_id(&self, target: NetworkAddress, data_len: usize) -> Id {
-        let mb_select: u32 = if data_len > 32 { 0b1 } else { 0b0 };
-
-        Id::Standard(
-            (target.to_standard_id() as u32)
-                + (mb_select << 5)
-                + ((self.host_address.to_standard_id() as u32) << 6),
-        )
+        Id::Standard(comms_canfd_hal::frame_id(
+            self.host_address,
+            target,
+            data_len,
+        ))
     }
 
     fn get_incoming_id(id: Id) -> Option<NetworkAddress> {
-        NetworkAddress::from_id(match id {
-            Id::Standard(val) => val & 0x1F,
-            Id::Extended(_val) => 0,
-        })
+        match id {
+            Id::Standard(val) => comms_canfd_hal::frame_id_addresses(val).0,
+            Id::Extended(_val) => NetworkAddress::from_id(0),
+        }
     }
 }
@@ -95,6 +95,7 @@ fn main() -> ! {
 
             teensy41_hardware.read_sensors();
             teensy41_hardware.update_spark(delta);
+            teensy41_comms.update(delta);
 
             ecu.update(
                 &mut HALs {